@@ -0,0 +1,74 @@
+// Play a ROM with random actions in the reinforcement learning environment:
+// cargo run --release --example random_agent -- <PATH_TO_ROM>
+
+use std::env;
+use std::process::exit;
+
+use nesmulator_core::utils::ARGBColor;
+use nesmulator_gui::Environment;
+
+const EPISODES: u32 = 3;
+
+fn main() {
+    let rom_path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: random_agent <PATH_TO_ROM>");
+            exit(1);
+        }
+    };
+
+    // Reward the agent when the screen changes, a crude incentive to explore
+    let mut previous: Vec<ARGBColor> = vec![];
+    let mut environment = Environment::new(&rom_path)
+        .with_frame_skip(4)
+        .with_max_frames(60 * 60)
+        .with_reward(move |observation| {
+            let changed = previous
+                .iter()
+                .zip(observation.iter())
+                .filter(|(a, b)| a.red != b.red || a.green != b.green || a.blue != b.blue)
+                .count();
+            previous = observation.to_vec();
+            changed as f64 / observation.len() as f64
+        });
+
+    // Small xorshift generator, to avoid a dependency for the example
+    let mut seed: u32 = 0x2545_F491;
+    let mut random = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed
+    };
+
+    for episode in 0..EPISODES {
+        if let Err(e) = environment.reset() {
+            eprintln!("Cannot start the episode: {}", e);
+            exit(1);
+        }
+        let mut total_reward = 0.0;
+        let mut steps = 0;
+        loop {
+            let action = (random() & 0xFF) as u8;
+            let step = match environment.step(action) {
+                Ok(step) => step,
+                Err(e) => {
+                    eprintln!("Cannot step the environment: {}", e);
+                    exit(1);
+                }
+            };
+            total_reward += step.reward;
+            steps += 1;
+            if step.done {
+                break;
+            }
+        }
+        println!(
+            "Episode {}: {} steps, total reward {:.3}",
+            episode + 1,
+            steps,
+            total_reward
+        );
+    }
+}
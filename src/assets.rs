@@ -0,0 +1,92 @@
+use std::env;
+use std::fs;
+
+use log::{info, warn};
+
+// Name given to --palette for the palette shipped with the emulator
+pub const BUILTIN_PALETTE: &str = "builtin";
+// Embedded so that the executable works on its own, as the themes and the font
+const BUILTIN_PALETTE_DATA: &[u8] = include_bytes!("../palette.pal");
+// Sizes of the .pal files: 64 colors of 3 bytes, or 512 colors with the 8 combinations
+// of the color emphasis bits, the only layout read by the core
+const PALETTE_SIZE: usize = 64 * 3;
+const EMPHASIS_PALETTE_SIZE: usize = 512 * 3;
+// Brightness of the channels that are not emphasized when another one is, out of 256
+const EMPHASIS_DARKENING: u32 = 191;
+
+// File of the palette given to the core. The core only reads palettes of 512 colors from
+// files, so the builtin palette and the palettes of 64 colors are written to the temporary
+// folder. An invalid palette is replaced by the builtin one, as the core cannot start with
+// it. None keeps the palette of the core.
+pub fn palette_file(name: &str) -> Option<String> {
+    if name == BUILTIN_PALETTE {
+        return write_temporary("nesmulator-builtin.pal", BUILTIN_PALETTE_DATA);
+    }
+    match read_palette(name) {
+        Ok(data) if data.len() == EMPHASIS_PALETTE_SIZE => Some(String::from(name)),
+        Ok(data) => {
+            info!(
+                "Palette {} has 64 colors, adding the emphasized ones.",
+                name
+            );
+            write_temporary("nesmulator-palette.pal", &add_emphasis(&data))
+        }
+        Err(e) => {
+            warn!(
+                "Invalid palette {}: {}, using the builtin palette.",
+                name, e
+            );
+            palette_file(BUILTIN_PALETTE)
+        }
+    }
+}
+
+// Whether a palette given to --palette can be used, to warn the player
+pub fn check_palette(name: &str) -> Result<(), String> {
+    if name == BUILTIN_PALETTE {
+        return Ok(());
+    }
+    read_palette(name).map(|_| ())
+}
+
+fn read_palette(path: &str) -> Result<Vec<u8>, String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    match data.len() {
+        PALETTE_SIZE | EMPHASIS_PALETTE_SIZE => Ok(data),
+        size => Err(format!(
+            "{} bytes instead of {} (64 colors) or {} (512 colors)",
+            size, PALETTE_SIZE, EMPHASIS_PALETTE_SIZE
+        )),
+    }
+}
+
+// The 512 colors of a palette of 64, the emphasis of a color darkening the other two as
+// on the NTSC PPU. Bit 0 of the emphasis is red, bit 1 green and bit 2 blue.
+fn add_emphasis(colors: &[u8]) -> Vec<u8> {
+    let mut palette = Vec::with_capacity(EMPHASIS_PALETTE_SIZE);
+    for emphasis in 0..8 {
+        for color in colors.chunks_exact(3) {
+            for (channel, value) in color.iter().enumerate() {
+                let darkened = emphasis != 0 && emphasis & (1 << channel) == 0;
+                palette.push(if darkened {
+                    (*value as u32 * EMPHASIS_DARKENING / 256) as u8
+                } else {
+                    *value
+                });
+            }
+        }
+    }
+    palette
+}
+
+fn write_temporary(file_name: &str, data: &[u8]) -> Option<String> {
+    let path = env::temp_dir().join(file_name);
+    let written = fs::read(&path).ok().as_deref() == Some(data);
+    if !written {
+        if let Err(e) = fs::write(&path, data) {
+            warn!("Cannot write the palette {}: {}", path.display(), e);
+            return None;
+        }
+    }
+    Some(path.to_string_lossy().into_owned())
+}
@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+use crate::backend::EmulatorCore;
+use crate::movie::Movie;
+use crate::snapshot::ScratchFile;
+
+pub const DEFAULT_IDLE_TIME: Duration = Duration::from_secs(60);
+
+// Demo movies played in rotation when nobody plays, as on arcade cabinets.
+// The movies are played from the state the emulator started in, which must be the
+// state they were recorded from, and the game is restored when a demo is stopped.
+pub struct AttractMode {
+    movies: Vec<Movie>,
+    idle_time: Duration,
+    last_activity: Instant,
+    // Movie played next
+    next: usize,
+    // Movie being played and its frame
+    demo: Option<(usize, usize)>,
+    start_snapshot: Vec<u8>,
+    // Game interrupted by the demos
+    game_snapshot: Vec<u8>,
+    scratch: ScratchFile,
+}
+
+impl AttractMode {
+    // The NES must be in its starting state
+    pub fn new(
+        nes: &mut impl EmulatorCore,
+        movies: Vec<Movie>,
+        idle_time: Duration,
+    ) -> Result<Self, String> {
+        let scratch = ScratchFile::new("attract");
+        Ok(AttractMode {
+            movies,
+            idle_time,
+            last_activity: Instant::now(),
+            next: 0,
+            demo: None,
+            start_snapshot: scratch.save(nes)?,
+            game_snapshot: vec![],
+            scratch,
+        })
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.demo.is_some()
+    }
+
+    // Whether nobody played for long enough to start the demos
+    pub fn is_idle(&self) -> bool {
+        !self.is_playing() && self.last_activity.elapsed() >= self.idle_time
+    }
+
+    // A player pressed a button
+    pub fn activity(&mut self) {
+        self.last_activity = Instant::now();
+    }
+
+    // Start the demos when idle and play the inputs of the current one, once per frame.
+    // Returns whether a demo is playing.
+    pub fn frame(&mut self, nes: &mut impl EmulatorCore, rom_path: &str) -> Result<bool, String> {
+        if self.is_idle() {
+            self.game_snapshot = self.scratch.save(nes)?;
+            self.demo = Some((self.start_demo(nes, rom_path)?, 0));
+        }
+        let (movie, frame) = match self.demo {
+            Some((movie, frame)) if self.movies[movie].input(frame).is_some() => (movie, frame),
+            // The demo is over, play the next one
+            Some(_) => (self.start_demo(nes, rom_path)?, 0),
+            None => return Ok(false),
+        };
+        nes.input(0, self.movies[movie].input(frame).unwrap_or(0))?;
+        self.demo = Some((movie, frame + 1));
+        Ok(true)
+    }
+
+    // Restore the starting state for the next movie, returns its index
+    fn start_demo(&mut self, nes: &mut impl EmulatorCore, rom_path: &str) -> Result<usize, String> {
+        self.scratch.load(nes, &self.start_snapshot, rom_path)?;
+        release_buttons(nes)?;
+        let movie = self.next;
+        self.next = (self.next + 1) % self.movies.len();
+        Ok(movie)
+    }
+
+    // Go back to the game left when the demos started, with the buttons released
+    pub fn stop(&mut self, nes: &mut impl EmulatorCore, rom_path: &str) -> Result<(), String> {
+        self.demo = None;
+        self.activity();
+        self.scratch.load(nes, &self.game_snapshot, rom_path)?;
+        release_buttons(nes)
+    }
+}
+
+fn release_buttons(nes: &mut impl EmulatorCore) -> Result<(), String> {
+    nes.input(0, 0)?;
+    nes.input(1, 0)
+}
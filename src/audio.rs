@@ -0,0 +1,287 @@
+use std::collections::VecDeque;
+
+use log::{info, warn};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::Sdl;
+
+use crate::error::FrontendError;
+
+const SAMPLE_RATE: i32 = 44100;
+// Length of the fades applied when pausing and resuming, to avoid clicks
+const FADE_SAMPLES: usize = SAMPLE_RATE as usize * 5 / 1000;
+// Samples kept ahead of the audio device, 100ms
+const TARGET_BUFFERED: usize = SAMPLE_RATE as usize / 10;
+// Beyond this, the sound is skipped, which happens when fast-forwarding
+const MAX_BUFFERED: usize = TARGET_BUFFERED * 2;
+// Largest change of the resampling ratio applied to keep the buffer around its target,
+// small enough to not be heard
+const MAX_RATE_ADJUSTMENT: f64 = 0.005;
+// Slower speeds are resampled, changing the pitch, faster ones skip parts of the sound
+// to keep it
+const SKIP_SPEED: f64 = 1.05;
+// Decay of the last sample when the buffer runs dry, to avoid clicks
+const UNDERRUN_DECAY: f32 = 0.99;
+
+// Ring buffer of samples, played from the SDL audio thread
+pub struct Playback {
+    buffer: VecDeque<f32>,
+    volume: f32,
+    last_sample: f32,
+}
+
+impl AudioCallback for Playback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            self.last_sample = match self.buffer.pop_front() {
+                Some(sample) => sample,
+                None => self.last_sample * UNDERRUN_DECAY,
+            };
+            *sample = self.last_sample * self.volume;
+        }
+    }
+}
+
+// Linear interpolation of the samples, keeping its position between two calls
+#[derive(Default)]
+pub struct Resampler {
+    // Position of the next output sample, relative to the first input sample
+    position: f64,
+    previous: f32,
+}
+
+impl Resampler {
+    // ratio is the number of output samples for each input sample
+    fn resample(&mut self, input: &[f32], ratio: f64) -> Vec<f32> {
+        let mut output = Vec::with_capacity((input.len() as f64 * ratio) as usize + 1);
+        let last = input.len() as f64 - 1.0;
+        let sample = |index: isize| match index {
+            -1 => self.previous,
+            i => input[i as usize],
+        };
+        while self.position < last {
+            let index = self.position.floor();
+            let fraction = (self.position - index) as f32;
+            let index = index as isize;
+            output.push(sample(index) * (1.0 - fraction) + sample(index + 1) * fraction);
+            self.position += 1.0 / ratio;
+        }
+        if let Some(sample) = input.last() {
+            self.position -= input.len() as f64;
+            self.previous = *sample;
+        }
+        output
+    }
+}
+
+// Where the samples produced by the NES go
+pub enum AudioOutput {
+    Sdl {
+        device: AudioDevice<Playback>,
+        resampler: Resampler,
+        volume: f32,
+        last_sample: f32,
+        // Number of samples left to fade in after resuming
+        fade_in: usize,
+        // Whether the last samples were skipped
+        skipped: bool,
+    },
+    // Samples are dropped, the emulation is only paced by the frame timer
+    Null,
+}
+
+impl AudioOutput {
+    // Open the SDL audio device, falling back to a null sink if audio is disabled or unavailable
+    pub fn new(sdl_context: Option<&Sdl>, enabled: bool, volume: f32) -> Self {
+        if !enabled {
+            info!("Audio disabled.");
+            return AudioOutput::Null;
+        }
+        let sdl_context = match sdl_context {
+            Some(context) => context,
+            None => {
+                warn!("SDL is not available, running without audio.");
+                return AudioOutput::Null;
+            }
+        };
+        match open_device(sdl_context, volume) {
+            Ok(device) => {
+                device.resume();
+                AudioOutput::Sdl {
+                    device,
+                    resampler: Resampler::default(),
+                    volume,
+                    last_sample: 0.0,
+                    fade_in: 0,
+                    skipped: false,
+                }
+            }
+            Err(e) => {
+                warn!(
+                    "Cannot initialize audio, running without audio: {}",
+                    FrontendError::Audio(e)
+                );
+                AudioOutput::Null
+            }
+        }
+    }
+
+    // Size of the buffered audio in bytes, None for the null sink
+    pub fn queued_size(&mut self) -> Option<u32> {
+        match self {
+            AudioOutput::Sdl { device, .. } => {
+                Some((device.lock().buffer.len() * std::mem::size_of::<f32>()) as u32)
+            }
+            AudioOutput::Null => None,
+        }
+    }
+
+    pub fn set_volume(&mut self, new_volume: f32) {
+        if let AudioOutput::Sdl { device, volume, .. } = self {
+            if *volume != new_volume {
+                *volume = new_volume;
+                device.lock().volume = new_volume;
+            }
+        }
+    }
+
+    // Add the samples of an emulation running at the given speed. They are resampled to
+    // play at the speed of the audio device, slightly faster or slower to keep the buffer
+    // from running dry or growing. With keep_pitch, an emulation producing less sound than
+    // its speed tells, e.g. a game slowed down to PAL timing, has parts of its sound
+    // repeated instead of a lower pitch.
+    #[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
+    pub fn queue(&mut self, samples: &[f32], speed: f64, keep_pitch: bool) {
+        if let AudioOutput::Sdl {
+            device,
+            resampler,
+            last_sample,
+            fade_in,
+            skipped,
+            ..
+        } = self
+        {
+            // A stopped emulation has no sound
+            if speed <= 0.0 {
+                return;
+            }
+            let mut playback = device.lock();
+            let buffered = playback.buffer.len();
+            if buffered >= MAX_BUFFERED {
+                *skipped = true;
+                return;
+            }
+            let stretch = if speed < SKIP_SPEED { 1.0 / speed } else { 1.0 };
+            let fill = (TARGET_BUFFERED as f64 - buffered as f64) / TARGET_BUFFERED as f64;
+            let ratio = stretch * (1.0 + MAX_RATE_ADJUSTMENT * fill.clamp(-1.0, 1.0));
+            let mut output = resampler.resample(samples, ratio);
+            smooth(&mut output, last_sample, fade_in, skipped);
+            let repeat = keep_pitch && buffered + output.len() < TARGET_BUFFERED / 2;
+            playback.buffer.extend(output.iter().copied());
+            if repeat {
+                // Blended with the end of the first copy as after a skip
+                *skipped = true;
+                smooth(&mut output, last_sample, fade_in, skipped);
+                playback.buffer.extend(output);
+            }
+        }
+    }
+
+    // Add the samples as they are produced, without following the speed of the emulation.
+    // They are dropped when the buffer is full.
+    #[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
+    pub fn queue_unadjusted(&mut self, samples: &[f32]) {
+        if let AudioOutput::Sdl {
+            device,
+            last_sample,
+            fade_in,
+            skipped,
+            ..
+        } = self
+        {
+            let mut playback = device.lock();
+            if playback.buffer.len() >= MAX_BUFFERED {
+                *skipped = true;
+                return;
+            }
+            let mut output = samples.to_vec();
+            smooth(&mut output, last_sample, fade_in, skipped);
+            playback.buffer.extend(output);
+        }
+    }
+
+    // Drop the buffered audio when the emulation pauses, replaced by a short fade to silence
+    pub fn pause(&mut self) {
+        if let AudioOutput::Sdl {
+            device,
+            last_sample,
+            fade_in,
+            ..
+        } = self
+        {
+            let mut playback = device.lock();
+            playback.buffer.clear();
+            let played = playback.last_sample;
+            playback.buffer.extend(
+                (1..=FADE_SAMPLES).map(|i| played * (1.0 - i as f32 / FADE_SAMPLES as f32)),
+            );
+            *last_sample = 0.0;
+            *fade_in = 0;
+        }
+    }
+
+    // Drop the buffered audio of a game state that was left, e.g. by a reset or by loading
+    // a state, so that it does not play over the new one
+    pub fn flush(&mut self) {
+        self.pause();
+        if let AudioOutput::Sdl { resampler, .. } = self {
+            *resampler = Resampler::default();
+        }
+        self.resume();
+    }
+
+    // Fade in the first samples queued after resuming the emulation
+    pub fn resume(&mut self) {
+        if let AudioOutput::Sdl { fade_in, .. } = self {
+            *fade_in = FADE_SAMPLES;
+        }
+    }
+}
+
+// Blend the start of the samples with the last ones queued after a skip, and fade them in
+// after resuming
+fn smooth(output: &mut [f32], last_sample: &mut f32, fade_in: &mut usize, skipped: &mut bool) {
+    let blend = if *skipped { FADE_SAMPLES } else { 0 };
+    *skipped = false;
+    for (i, sample) in output.iter_mut().take(blend).enumerate() {
+        let weight = i as f32 / blend as f32;
+        *sample = *sample * weight + *last_sample * (1.0 - weight);
+    }
+    for (i, sample) in output.iter_mut().take(*fade_in).enumerate() {
+        *sample *= (FADE_SAMPLES - *fade_in + i) as f32 / FADE_SAMPLES as f32;
+    }
+    *fade_in = fade_in.saturating_sub(output.len());
+
+    if let Some(sample) = output.last() {
+        *last_sample = *sample;
+    }
+}
+
+fn open_device(sdl_context: &Sdl, volume: f32) -> Result<AudioDevice<Playback>, String> {
+    let audio_subsystem = sdl_context.audio()?;
+
+    let desired_audio_specs = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE),
+        channels: Some(1),
+        samples: Some(1024),
+    };
+
+    // SDL converts the samples if the device does not support this format
+    audio_subsystem.open_playback(None, &desired_audio_specs, |_| Playback {
+        // Allocated once, to not allocate from the audio thread
+        buffer: VecDeque::with_capacity(MAX_BUFFERED * 2),
+        volume,
+        last_sample: 0.0,
+    })
+}
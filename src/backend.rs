@@ -0,0 +1,225 @@
+use std::time::Duration;
+#[cfg(feature = "instrumented-core")]
+use std::time::Instant;
+
+#[cfg(feature = "instrumented-core")]
+use log::debug;
+use nesmulator_core::nes::NES;
+use nesmulator_core::utils::ARGBColor;
+use nesmulator_core::Config;
+
+use crate::assets;
+
+// Core used by the frontend, selected at compile time
+#[cfg(not(feature = "instrumented-core"))]
+pub type Core = NES;
+#[cfg(feature = "instrumented-core")]
+pub type Core = Instrumented<NES>;
+
+// Everything the frontend needs from an emulation core.
+// Another core (e.g. a different version of nesmulator-core) only has to implement this trait.
+pub trait EmulatorCore: Send {
+    fn new(palette_path: Option<&str>, display_cpu_logs: bool) -> Self
+    where
+        Self: Sized;
+    fn insert_cartdrige(&mut self, rom_path: &str) -> Result<(), String>;
+    fn load_state(&mut self, state_path: &str, rom_path: &str) -> Result<(), String>;
+    fn save_state(&mut self, path: &str) -> Result<(), String>;
+    fn load_save(&mut self, path: &str) -> Result<(), String>;
+    fn save(&mut self, path: &str) -> Result<(), String>;
+    fn clock(&mut self);
+    fn reset(&mut self);
+    fn input(&mut self, port: usize, input: u8) -> Result<(), String>;
+    fn get_frame_buffer(&mut self) -> Option<[ARGBColor; 61_440]>;
+    fn get_pattern_tables(&self) -> Result<[Vec<ARGBColor>; 2], String>;
+    fn get_palette_colors(&self) -> Result<Vec<ARGBColor>, String>;
+    fn set_debug_palette_id(&mut self, id: u8) -> Result<(), String>;
+    fn get_one_frame_duration(&self) -> Duration;
+    fn produce_samples(&mut self, produce: bool);
+    fn get_samples(&mut self) -> Vec<f32>;
+}
+
+impl EmulatorCore for NES {
+    fn new(palette_path: Option<&str>, display_cpu_logs: bool) -> Self {
+        let palette_path = palette_path.and_then(assets::palette_file);
+        NES::from_config(Config::new(palette_path.as_deref(), display_cpu_logs))
+    }
+
+    fn insert_cartdrige(&mut self, rom_path: &str) -> Result<(), String> {
+        NES::insert_cartdrige(self, rom_path).map_err(|e| e.to_string())
+    }
+
+    fn load_state(&mut self, state_path: &str, rom_path: &str) -> Result<(), String> {
+        NES::load_state(self, state_path, rom_path).map_err(|e| e.to_string())
+    }
+
+    fn save_state(&mut self, path: &str) -> Result<(), String> {
+        NES::save_state(self, path).map_err(|e| e.to_string())
+    }
+
+    fn load_save(&mut self, path: &str) -> Result<(), String> {
+        NES::load_save(self, path).map_err(|e| e.to_string())
+    }
+
+    fn save(&mut self, path: &str) -> Result<(), String> {
+        NES::save(self, path).map_err(|e| e.to_string())
+    }
+
+    fn clock(&mut self) {
+        NES::clock(self)
+    }
+
+    fn reset(&mut self) {
+        NES::reset(self)
+    }
+
+    fn input(&mut self, port: usize, input: u8) -> Result<(), String> {
+        NES::input(self, port, input).map_err(|e| e.to_string())
+    }
+
+    fn get_frame_buffer(&mut self) -> Option<[ARGBColor; 61_440]> {
+        NES::get_frame_buffer(self)
+    }
+
+    fn get_pattern_tables(&self) -> Result<[Vec<ARGBColor>; 2], String> {
+        let table = |index| {
+            NES::get_pattern_table(self, index)
+                .map(|table| table.to_vec())
+                .map_err(|e| e.to_string())
+        };
+        Ok([table(0)?, table(1)?])
+    }
+
+    fn get_palette_colors(&self) -> Result<Vec<ARGBColor>, String> {
+        NES::get_palette(self)
+            .map(|palette| palette.to_vec())
+            .map_err(|e| e.to_string())
+    }
+
+    fn set_debug_palette_id(&mut self, id: u8) -> Result<(), String> {
+        NES::set_debug_palette_id(self, id).map_err(|e| e.to_string())
+    }
+
+    fn get_one_frame_duration(&self) -> Duration {
+        NES::get_one_frame_duration(self)
+    }
+
+    fn produce_samples(&mut self, produce: bool) {
+        NES::produce_samples(self, produce)
+    }
+
+    fn get_samples(&mut self) -> Vec<f32> {
+        NES::get_samples(self).to_vec()
+    }
+}
+
+// Number of frames between two reports of the instrumented core
+#[cfg(feature = "instrumented-core")]
+const REPORT_FRAMES: u64 = 600;
+
+// Wrap a core to log how many clocks and how much time its frames take
+#[cfg(feature = "instrumented-core")]
+pub struct Instrumented<C> {
+    inner: C,
+    clocks: u64,
+    frames: u64,
+    clock_time: Duration,
+    since: Instant,
+}
+
+#[cfg(feature = "instrumented-core")]
+impl<C: EmulatorCore> EmulatorCore for Instrumented<C> {
+    fn new(palette_path: Option<&str>, display_cpu_logs: bool) -> Self {
+        Instrumented {
+            inner: C::new(palette_path, display_cpu_logs),
+            clocks: 0,
+            frames: 0,
+            clock_time: Duration::ZERO,
+            since: Instant::now(),
+        }
+    }
+
+    fn insert_cartdrige(&mut self, rom_path: &str) -> Result<(), String> {
+        debug!("Core: inserting cartridge {}", rom_path);
+        self.inner.insert_cartdrige(rom_path)
+    }
+
+    fn load_state(&mut self, state_path: &str, rom_path: &str) -> Result<(), String> {
+        debug!("Core: loading state {}", state_path);
+        self.inner.load_state(state_path, rom_path)
+    }
+
+    fn save_state(&mut self, path: &str) -> Result<(), String> {
+        debug!("Core: saving state {}", path);
+        self.inner.save_state(path)
+    }
+
+    fn load_save(&mut self, path: &str) -> Result<(), String> {
+        self.inner.load_save(path)
+    }
+
+    fn save(&mut self, path: &str) -> Result<(), String> {
+        self.inner.save(path)
+    }
+
+    fn clock(&mut self) {
+        let start = Instant::now();
+        self.inner.clock();
+        self.clock_time += start.elapsed();
+        self.clocks += 1;
+    }
+
+    fn reset(&mut self) {
+        debug!("Core: reset after {} clocks", self.clocks);
+        self.inner.reset()
+    }
+
+    fn input(&mut self, port: usize, input: u8) -> Result<(), String> {
+        self.inner.input(port, input)
+    }
+
+    fn get_frame_buffer(&mut self) -> Option<[ARGBColor; 61_440]> {
+        let frame = self.inner.get_frame_buffer();
+        if frame.is_some() {
+            self.frames += 1;
+            if self.frames == REPORT_FRAMES {
+                debug!(
+                    "Core: {} frames in {:?}, {} clocks and {:?} of emulation per frame",
+                    REPORT_FRAMES,
+                    self.since.elapsed(),
+                    self.clocks / REPORT_FRAMES,
+                    self.clock_time / REPORT_FRAMES as u32
+                );
+                self.frames = 0;
+                self.clocks = 0;
+                self.clock_time = Duration::ZERO;
+                self.since = Instant::now();
+            }
+        }
+        frame
+    }
+
+    fn get_pattern_tables(&self) -> Result<[Vec<ARGBColor>; 2], String> {
+        self.inner.get_pattern_tables()
+    }
+
+    fn get_palette_colors(&self) -> Result<Vec<ARGBColor>, String> {
+        self.inner.get_palette_colors()
+    }
+
+    fn set_debug_palette_id(&mut self, id: u8) -> Result<(), String> {
+        self.inner.set_debug_palette_id(id)
+    }
+
+    fn get_one_frame_duration(&self) -> Duration {
+        self.inner.get_one_frame_duration()
+    }
+
+    fn produce_samples(&mut self, produce: bool) {
+        self.inner.produce_samples(produce)
+    }
+
+    fn get_samples(&mut self) -> Vec<f32> {
+        self.inner.get_samples()
+    }
+}
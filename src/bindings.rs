@@ -0,0 +1,831 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use nesmulator_core::utils::ControllerInput;
+use winit::event::VirtualKeyCode;
+
+use crate::input::MergePolicy;
+
+// NES buttons in the order they are asked by the binding wizard
+pub const BUTTONS: [(u8, &str); 8] = [
+    (ControllerInput::Up as u8, "up"),
+    (ControllerInput::Left as u8, "left"),
+    (ControllerInput::Down as u8, "down"),
+    (ControllerInput::Right as u8, "right"),
+    (ControllerInput::Start as u8, "start"),
+    (ControllerInput::Select as u8, "select"),
+    (ControllerInput::A as u8, "a"),
+    (ControllerInput::B as u8, "b"),
+];
+
+// Emulator functions triggered by a key of the keyboard
+#[derive(Clone, Copy, PartialEq)]
+pub enum Hotkey {
+    Debug,
+    Reset,
+    PreviousPalette,
+    NextPalette,
+    SpeedUp,
+    SpeedDown,
+    SaveState,
+    Save,
+    PauseMenu,
+    BindKeys,
+    RateCompatibility,
+    Retry,
+    CompareReference,
+    NextMonitor,
+    CaptureRepro,
+    Rewind,
+    Pause,
+    FrameAdvance,
+    FastForward,
+    IntegerScaling,
+    AspectCorrection,
+    CropOverscan,
+    Fullscreen,
+    Filter,
+    VolumeUp,
+    VolumeDown,
+    SetCover,
+    Screenshot,
+    Record,
+    Cheats,
+    SkipIdle,
+    FrameBlending,
+    SaveReplay,
+    PreviousGame,
+    NextGame,
+    PushBookmark,
+    PopBookmark,
+    PreviousChr,
+    NextChr,
+    Profiler,
+    // Loads the state slot, or saves it while Shift is held
+    StateSlot(usize),
+}
+
+// Number of state slots, each with its hotkey
+pub const STATE_SLOTS: usize = 8;
+
+// Hotkeys with their name in the configuration file and their default key
+const HOTKEYS: [(Hotkey, &str, VirtualKeyCode); 40 + STATE_SLOTS] = [
+    (Hotkey::Debug, "debug", VirtualKeyCode::E),
+    (Hotkey::Reset, "reset", VirtualKeyCode::R),
+    (
+        Hotkey::PreviousPalette,
+        "previous-palette",
+        VirtualKeyCode::Left,
+    ),
+    (Hotkey::NextPalette, "next-palette", VirtualKeyCode::Right),
+    (Hotkey::SpeedUp, "speed-up", VirtualKeyCode::Up),
+    (Hotkey::SpeedDown, "speed-down", VirtualKeyCode::Down),
+    (Hotkey::SaveState, "save-state", VirtualKeyCode::M),
+    (Hotkey::Save, "save", VirtualKeyCode::L),
+    (Hotkey::PauseMenu, "pause-menu", VirtualKeyCode::F1),
+    (Hotkey::BindKeys, "bind-keys", VirtualKeyCode::F2),
+    (Hotkey::RateCompatibility, "rate", VirtualKeyCode::F3),
+    (Hotkey::Retry, "retry", VirtualKeyCode::F5),
+    (
+        Hotkey::CompareReference,
+        "compare-reference",
+        VirtualKeyCode::F6,
+    ),
+    (Hotkey::NextMonitor, "next-monitor", VirtualKeyCode::F10),
+    (Hotkey::CaptureRepro, "capture", VirtualKeyCode::F12),
+    (Hotkey::Rewind, "rewind", VirtualKeyCode::Back),
+    (Hotkey::Pause, "pause", VirtualKeyCode::P),
+    (Hotkey::FrameAdvance, "frame-advance", VirtualKeyCode::N),
+    (Hotkey::FastForward, "fast-forward", VirtualKeyCode::Tab),
+    (
+        Hotkey::IntegerScaling,
+        "integer-scaling",
+        VirtualKeyCode::F7,
+    ),
+    (Hotkey::AspectCorrection, "aspect-ratio", VirtualKeyCode::F8),
+    (Hotkey::CropOverscan, "crop-overscan", VirtualKeyCode::F9),
+    (Hotkey::Fullscreen, "fullscreen", VirtualKeyCode::F11),
+    (Hotkey::Filter, "filter", VirtualKeyCode::F4),
+    (Hotkey::VolumeUp, "volume-up", VirtualKeyCode::Equals),
+    (Hotkey::VolumeDown, "volume-down", VirtualKeyCode::Minus),
+    (Hotkey::SetCover, "set-cover", VirtualKeyCode::K),
+    (Hotkey::Screenshot, "screenshot", VirtualKeyCode::G),
+    (Hotkey::Record, "record", VirtualKeyCode::V),
+    (Hotkey::Cheats, "cheats", VirtualKeyCode::H),
+    (Hotkey::SkipIdle, "skip-idle", VirtualKeyCode::J),
+    (Hotkey::FrameBlending, "frame-blending", VirtualKeyCode::B),
+    (Hotkey::SaveReplay, "save-replay", VirtualKeyCode::Y),
+    (Hotkey::PreviousGame, "previous-game", VirtualKeyCode::Comma),
+    (Hotkey::NextGame, "next-game", VirtualKeyCode::Period),
+    (Hotkey::PushBookmark, "push-bookmark", VirtualKeyCode::T),
+    (Hotkey::PopBookmark, "pop-bookmark", VirtualKeyCode::U),
+    (Hotkey::PreviousChr, "previous-chr", VirtualKeyCode::PageUp),
+    (Hotkey::NextChr, "next-chr", VirtualKeyCode::PageDown),
+    (Hotkey::Profiler, "profiler", VirtualKeyCode::F),
+    (Hotkey::StateSlot(0), "state-slot-1", VirtualKeyCode::Key1),
+    (Hotkey::StateSlot(1), "state-slot-2", VirtualKeyCode::Key2),
+    (Hotkey::StateSlot(2), "state-slot-3", VirtualKeyCode::Key3),
+    (Hotkey::StateSlot(3), "state-slot-4", VirtualKeyCode::Key4),
+    (Hotkey::StateSlot(4), "state-slot-5", VirtualKeyCode::Key5),
+    (Hotkey::StateSlot(5), "state-slot-6", VirtualKeyCode::Key6),
+    (Hotkey::StateSlot(6), "state-slot-7", VirtualKeyCode::Key7),
+    (Hotkey::StateSlot(7), "state-slot-8", VirtualKeyCode::Key8),
+];
+
+// Environment variables overriding the settings of the configuration file,
+// NESMULATOR_<SECTION>_<SETTING> with the dashes as underscores, e.g.
+// NESMULATOR_HOTKEYS_SAVE_STATE=F9 or NESMULATOR_DISPLAY_SCALE=3
+const ENV_PREFIX: &str = "NESMULATOR";
+
+fn env_name(section: &str, name: &str) -> String {
+    format!("{}_{}_{}", ENV_PREFIX, section, name)
+        .to_uppercase()
+        .replace('-', "_")
+}
+
+// Settings given by the environment, as a configuration file would give them
+fn env_table() -> toml::value::Table {
+    let mut sections = toml::value::Table::new();
+    for (section, name, _) in KeyBindings::default().settings() {
+        let value = match env::var(env_name(section, name)) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        // The scale is the only number, an invalid one being reported by apply
+        let value = match value.parse::<i64>() {
+            Ok(number) if section == "display" => toml::Value::Integer(number),
+            _ => toml::Value::String(value),
+        };
+        if let toml::Value::Table(entries) = sections
+            .entry(section)
+            .or_insert_with(|| toml::Value::Table(toml::value::Table::new()))
+        {
+            entries.insert(String::from(name), value);
+        }
+    }
+    sections
+}
+
+// Configuration file used when --config is not given:
+// $XDG_CONFIG_HOME/nesmulator/config.toml, ~/.config/nesmulator/config.toml on Unix,
+// %APPDATA%\nesmulator\config.toml on Windows, or nesmulator.toml in the working directory
+pub fn default_config_path() -> PathBuf {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from));
+    match config_dir {
+        Some(dir) => dir.join("nesmulator").join("config.toml"),
+        None => PathBuf::from("nesmulator.toml"),
+    }
+}
+
+// Keys that can be bound to a NES button or a hotkey, with the name used in the configuration file.
+// Escape is missing as it cancels the binding wizard and opens the pause menu, and Shift as it
+// makes the state slot hotkeys save the state.
+const KEYS: [(VirtualKeyCode, &str); 74] = [
+    (VirtualKeyCode::A, "A"),
+    (VirtualKeyCode::B, "B"),
+    (VirtualKeyCode::C, "C"),
+    (VirtualKeyCode::D, "D"),
+    (VirtualKeyCode::E, "E"),
+    (VirtualKeyCode::F, "F"),
+    (VirtualKeyCode::G, "G"),
+    (VirtualKeyCode::H, "H"),
+    (VirtualKeyCode::I, "I"),
+    (VirtualKeyCode::J, "J"),
+    (VirtualKeyCode::K, "K"),
+    (VirtualKeyCode::L, "L"),
+    (VirtualKeyCode::M, "M"),
+    (VirtualKeyCode::N, "N"),
+    (VirtualKeyCode::O, "O"),
+    (VirtualKeyCode::P, "P"),
+    (VirtualKeyCode::Q, "Q"),
+    (VirtualKeyCode::R, "R"),
+    (VirtualKeyCode::S, "S"),
+    (VirtualKeyCode::T, "T"),
+    (VirtualKeyCode::U, "U"),
+    (VirtualKeyCode::V, "V"),
+    (VirtualKeyCode::W, "W"),
+    (VirtualKeyCode::X, "X"),
+    (VirtualKeyCode::Y, "Y"),
+    (VirtualKeyCode::Z, "Z"),
+    (VirtualKeyCode::Key0, "0"),
+    (VirtualKeyCode::Key1, "1"),
+    (VirtualKeyCode::Key2, "2"),
+    (VirtualKeyCode::Key3, "3"),
+    (VirtualKeyCode::Key4, "4"),
+    (VirtualKeyCode::Key5, "5"),
+    (VirtualKeyCode::Key6, "6"),
+    (VirtualKeyCode::Key7, "7"),
+    (VirtualKeyCode::Key8, "8"),
+    (VirtualKeyCode::Key9, "9"),
+    (VirtualKeyCode::Numpad0, "Numpad0"),
+    (VirtualKeyCode::Numpad1, "Numpad1"),
+    (VirtualKeyCode::Numpad2, "Numpad2"),
+    (VirtualKeyCode::Numpad3, "Numpad3"),
+    (VirtualKeyCode::Numpad4, "Numpad4"),
+    (VirtualKeyCode::Numpad5, "Numpad5"),
+    (VirtualKeyCode::Numpad6, "Numpad6"),
+    (VirtualKeyCode::Numpad7, "Numpad7"),
+    (VirtualKeyCode::Numpad8, "Numpad8"),
+    (VirtualKeyCode::Numpad9, "Numpad9"),
+    (VirtualKeyCode::Up, "Up"),
+    (VirtualKeyCode::Left, "Left"),
+    (VirtualKeyCode::Down, "Down"),
+    (VirtualKeyCode::Right, "Right"),
+    (VirtualKeyCode::Space, "Space"),
+    (VirtualKeyCode::Return, "Return"),
+    (VirtualKeyCode::Tab, "Tab"),
+    (VirtualKeyCode::Back, "Backspace"),
+    (VirtualKeyCode::LControl, "LControl"),
+    (VirtualKeyCode::RControl, "RControl"),
+    (VirtualKeyCode::LAlt, "LAlt"),
+    (VirtualKeyCode::RAlt, "RAlt"),
+    (VirtualKeyCode::Comma, "Comma"),
+    (VirtualKeyCode::Period, "Period"),
+    (VirtualKeyCode::Minus, "Minus"),
+    (VirtualKeyCode::Equals, "Equals"),
+    (VirtualKeyCode::F1, "F1"),
+    (VirtualKeyCode::F2, "F2"),
+    (VirtualKeyCode::F3, "F3"),
+    (VirtualKeyCode::F4, "F4"),
+    (VirtualKeyCode::F5, "F5"),
+    (VirtualKeyCode::F6, "F6"),
+    (VirtualKeyCode::F7, "F7"),
+    (VirtualKeyCode::F8, "F8"),
+    (VirtualKeyCode::F9, "F9"),
+    (VirtualKeyCode::F10, "F10"),
+    (VirtualKeyCode::F11, "F11"),
+    (VirtualKeyCode::F12, "F12"),
+];
+
+// First bindable key pressed since the last update of the event loop
+pub fn pressed_key(key_pressed: impl Fn(VirtualKeyCode) -> bool) -> Option<VirtualKeyCode> {
+    KEYS.iter()
+        .map(|(key, _)| *key)
+        .find(|key| key_pressed(*key))
+}
+
+fn key_name(key: VirtualKeyCode) -> &'static str {
+    KEYS.iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, name)| *name)
+        .unwrap_or("?")
+}
+
+fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+    KEYS.iter()
+        .find(|(_, n)| n.eq_ignore_ascii_case(name))
+        .map(|(key, _)| *key)
+}
+
+// Tables of the configuration file shared by the presets
+const PRESET_SECTIONS: [&str; 2] = ["controller", "hotkeys"];
+
+fn read_toml(path: &str) -> Result<toml::value::Table, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    match content.parse::<toml::Value>().map_err(|e| e.to_string())? {
+        toml::Value::Table(sections) => Ok(sections),
+        _ => Err(String::from("Invalid configuration")),
+    }
+}
+
+fn write_file(path: &str, content: &str) -> io::Result<()> {
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, content)
+}
+
+// Scale of the window at startup, from the size of the NES screen
+pub const DEFAULT_WINDOW_SCALE: u32 = 2;
+pub const MAX_WINDOW_SCALE: u32 = 6;
+
+// Keys of the keyboard driving the first controller, in the order of BUTTONS,
+// and keys of the hotkeys, in the order of HOTKEYS, with the settings of the setup wizard
+#[derive(Clone)]
+pub struct KeyBindings {
+    keys: [VirtualKeyCode; 8],
+    hotkeys: [VirtualKeyCode; HOTKEYS.len()],
+    window_scale: u32,
+    // Folder of the states, saves and cheats, instead of next to the ROMs
+    save_dir: Option<String>,
+    // Whether the keyboard still drives the first controller while a gamepad is connected
+    input_merge: MergePolicy,
+    // Settings given by the environment, kept out of the configuration file
+    env: Vec<EnvSetting>,
+}
+
+// Setting of the environment, with the value it replaced
+#[derive(Clone)]
+struct EnvSetting {
+    section: &'static str,
+    name: &'static str,
+    file_value: String,
+    env_value: String,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let mut hotkeys = [VirtualKeyCode::Escape; HOTKEYS.len()];
+        for (key, (_, _, default)) in hotkeys.iter_mut().zip(HOTKEYS.iter()) {
+            *key = *default;
+        }
+        KeyBindings {
+            keys: [
+                VirtualKeyCode::Z,
+                VirtualKeyCode::Q,
+                VirtualKeyCode::S,
+                VirtualKeyCode::D,
+                VirtualKeyCode::X,
+                VirtualKeyCode::C,
+                VirtualKeyCode::I,
+                VirtualKeyCode::O,
+            ],
+            hotkeys,
+            window_scale: DEFAULT_WINDOW_SCALE,
+            save_dir: None,
+            input_merge: MergePolicy::Priority,
+            env: vec![],
+        }
+    }
+}
+
+impl KeyBindings {
+    // Read a TOML configuration file with a [controller] table of "button = key" entries,
+    // a [hotkeys] table of "function = key" entries, a [display] table with the window scale,
+    // a [files] table with the folder of the saves and an [input] table with the merge policy
+    // of the devices. Missing entries keep their default. A key bound to two functions is
+    // refused.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let mut bindings = KeyBindings::default();
+        bindings.apply(&read_toml(path)?)?;
+        bindings.check_conflicts()?;
+        Ok(bindings)
+    }
+
+    // Apply the NESMULATOR_* variables of the environment over the configuration file. They
+    // only last as long as the emulator runs: the file keeps its values when it is saved,
+    // unless the setting is changed in the meantime, e.g. by the binding wizard.
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        let file_settings = self.settings();
+        self.apply(&env_table())
+            .and_then(|()| self.check_conflicts())
+            .map_err(|e| format!("{} (environment)", e))?;
+        self.env = file_settings
+            .into_iter()
+            .zip(self.settings())
+            .filter(|((section, name, _), _)| env::var_os(env_name(section, name)).is_some())
+            .map(
+                |((section, name, file_value), (_, _, env_value))| EnvSetting {
+                    section,
+                    name,
+                    file_value,
+                    env_value,
+                },
+            )
+            .collect();
+        Ok(())
+    }
+
+    // Settings to write to the configuration file, the ones of the environment left
+    // unchanged being set back to the value they replaced
+    fn file_layer(&self) -> KeyBindings {
+        let settings = self.settings();
+        let mut sections = toml::value::Table::new();
+        for setting in self.env.iter() {
+            let unchanged = settings.iter().any(|(section, name, value)| {
+                (*section, *name) == (setting.section, setting.name) && *value == setting.env_value
+            });
+            if !unchanged {
+                continue;
+            }
+            // The values are written as in the file, e.g. "F9" with its quotes
+            let entry = format!("{} = {}", setting.name, setting.file_value)
+                .parse::<toml::Value>()
+                .ok()
+                .and_then(|entry| entry.as_table().cloned())
+                .unwrap_or_default();
+            if let toml::Value::Table(entries) = sections
+                .entry(setting.section)
+                .or_insert_with(|| toml::Value::Table(toml::value::Table::new()))
+            {
+                entries.extend(entry);
+            }
+        }
+        let mut file = self.clone();
+        // The values were read from the file or are the defaults, so they are valid
+        let _ = file.apply(&sections);
+        file.env.clear();
+        file
+    }
+
+    fn apply(&mut self, sections: &toml::value::Table) -> Result<(), String> {
+        for (section, entries) in sections {
+            let entries = entries
+                .as_table()
+                .ok_or(format!("[{}] must be a table", section))?;
+            for (name, value) in entries {
+                let key = || {
+                    value
+                        .as_str()
+                        .and_then(key_from_name)
+                        .ok_or(format!("Unknown key {} for {:?}", value, name))
+                };
+                match section.as_str() {
+                    "controller" => {
+                        let key = key()?;
+                        let index = BUTTONS
+                            .iter()
+                            .position(|(_, button)| *button == name.as_str())
+                            .ok_or(format!("Unknown NES button {:?}", name))?;
+                        self.keys[index] = key;
+                    }
+                    "hotkeys" => {
+                        let key = key()?;
+                        let index = HOTKEYS
+                            .iter()
+                            .position(|(_, hotkey, _)| *hotkey == name.as_str())
+                            .ok_or(format!("Unknown hotkey {:?}", name))?;
+                        self.hotkeys[index] = key;
+                    }
+                    "display" if name == "scale" => {
+                        self.window_scale = value
+                            .as_integer()
+                            .filter(|scale| (1..=MAX_WINDOW_SCALE as i64).contains(scale))
+                            .ok_or(format!(
+                                "The scale must be between 1 and {}",
+                                MAX_WINDOW_SCALE
+                            ))? as u32;
+                    }
+                    "files" if name == "saves" => {
+                        let dir = value.as_str().ok_or("The saves folder must be a string")?;
+                        self.save_dir = Some(String::from(dir)).filter(|dir| !dir.is_empty());
+                    }
+                    "input" if name == "merge" => {
+                        self.input_merge = value
+                            .as_str()
+                            .and_then(MergePolicy::from_name)
+                            .ok_or("The merge policy must be \"priority\" or \"combine\"")?;
+                    }
+                    "display" | "files" | "input" => {
+                        return Err(format!("Unknown setting {:?} in [{}]", name, section))
+                    }
+                    _ => return Err(format!("Unknown section [{}]", section)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Replace the keys with the ones of a preset written by export_preset, e.g. a layout
+    // shared for a game or an arcade stick. The preset only has [controller] and [hotkeys]
+    // tables, and must not bind a key to two functions.
+    pub fn import_preset(&mut self, path: &str) -> Result<(), String> {
+        let sections = read_toml(path)?;
+        if let Some(section) = sections
+            .keys()
+            .find(|section| !PRESET_SECTIONS.contains(&section.as_str()))
+        {
+            return Err(format!("Presets only have keys, found [{}]", section));
+        }
+        let mut imported = self.clone();
+        imported.apply(&sections)?;
+        imported.check_conflicts()?;
+        *self = imported;
+        Ok(())
+    }
+
+    // Write the keys of the controller and of the hotkeys, without the settings of this
+    // computer such as the saves folder
+    pub fn export_preset(&self, path: &str) -> io::Result<()> {
+        let content = format!("# nesmulator key bindings preset\n\n{}", self.keys_toml());
+        write_file(path, &content)
+    }
+
+    fn check_conflicts(&self) -> Result<(), String> {
+        let functions = BUTTONS
+            .iter()
+            .map(|(_, button)| *button)
+            .zip(self.keys.iter())
+            .chain(
+                HOTKEYS
+                    .iter()
+                    .map(|(_, hotkey, _)| *hotkey)
+                    .zip(self.hotkeys.iter()),
+            );
+        let mut bound: Vec<(&str, VirtualKeyCode)> = vec![];
+        for (function, key) in functions {
+            if let Some((other, _)) = bound.iter().find(|(_, k)| k == key) {
+                return Err(format!(
+                    "{} is bound to both {} and {}",
+                    key_name(*key),
+                    other,
+                    function
+                ));
+            }
+            bound.push((function, *key));
+        }
+        Ok(())
+    }
+
+    fn keys_toml(&self) -> String {
+        let mut content = String::from("# Keys of the first controller\n[controller]\n");
+        for ((_, button), key) in BUTTONS.iter().zip(self.keys.iter()) {
+            content.push_str(&format!("{} = \"{}\"\n", button, key_name(*key)));
+        }
+        content.push_str("\n# Keys of the emulator functions\n[hotkeys]\n");
+        for ((_, hotkey, _), key) in HOTKEYS.iter().zip(self.hotkeys.iter()) {
+            content.push_str(&format!("{} = \"{}\"\n", hotkey, key_name(*key)));
+        }
+        content
+    }
+
+    // Every setting with its value as written in the configuration file, by section
+    fn settings(&self) -> Vec<(&'static str, &'static str, String)> {
+        let mut settings = vec![];
+        for ((_, button), key) in BUTTONS.iter().zip(self.keys.iter()) {
+            settings.push(("controller", *button, format!("\"{}\"", key_name(*key))));
+        }
+        for ((_, hotkey, _), key) in HOTKEYS.iter().zip(self.hotkeys.iter()) {
+            settings.push(("hotkeys", *hotkey, format!("\"{}\"", key_name(*key))));
+        }
+        settings.push(("display", "scale", self.window_scale.to_string()));
+        settings.push((
+            "files",
+            "saves",
+            format!("{:?}", self.save_dir.as_deref().unwrap_or("")),
+        ));
+        settings.push(("input", "merge", format!("\"{}\"", self.input_merge.name())));
+        settings
+    }
+
+    // The effective settings, from the defaults overridden by the configuration file, the
+    // environment then the command line, each with where its value comes from.
+    // command_line lists the settings given by an option, as (section, setting).
+    pub fn describe(&self, path: &str, command_line: &[(&str, &str)]) -> String {
+        let file = read_toml(path).unwrap_or_default();
+        let settings = self.settings();
+        let width = settings
+            .iter()
+            .map(|(_, name, value)| name.len() + value.len())
+            .max()
+            .unwrap_or(0);
+        let mut content = String::new();
+        let mut current_section = "";
+        for (section, name, value) in settings.iter() {
+            if *section != current_section {
+                if !current_section.is_empty() {
+                    content.push('\n');
+                }
+                content.push_str(&format!("[{}]\n", section));
+                current_section = section;
+            }
+            let source = if command_line.contains(&(*section, *name)) {
+                String::from("command line")
+            } else if env::var_os(env_name(section, name)).is_some() {
+                env_name(section, name)
+            } else if file
+                .get(*section)
+                .and_then(|entries| entries.get(*name))
+                .is_some()
+            {
+                String::from("configuration file")
+            } else {
+                String::from("default")
+            };
+            let padding = width - name.len() - value.len();
+            content.push_str(&format!(
+                "{} = {}{:padding$}  # {}\n",
+                name,
+                value,
+                "",
+                source,
+                padding = padding
+            ));
+        }
+        content
+    }
+
+    // Write the configuration file, without the settings of the environment left unchanged
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let file = self.file_layer();
+        let mut content = file.keys_toml();
+        content.push_str("\n# Scale of the window at startup\n[display]\n");
+        content.push_str(&format!("scale = {}\n", file.window_scale));
+        content.push_str(
+            "\n# Folder of the states, saves and cheats, empty for next to the ROMs\n[files]\n",
+        );
+        content.push_str(&format!(
+            "saves = {:?}\n",
+            file.save_dir.as_deref().unwrap_or("")
+        ));
+        content.push_str(
+            "\n# \"combine\" for the keyboard and a gamepad to drive the first controller together,\n# \"priority\" for the gamepad only while it is connected\n[input]\n",
+        );
+        content.push_str(&format!("merge = \"{}\"\n", file.input_merge.name()));
+        write_file(path, &content)
+    }
+
+    pub fn window_scale(&self) -> u32 {
+        self.window_scale
+    }
+
+    pub fn save_dir(&self) -> Option<&str> {
+        self.save_dir.as_deref()
+    }
+
+    pub fn input_merge(&self) -> MergePolicy {
+        self.input_merge
+    }
+
+    pub fn set_input_merge(&mut self, input_merge: MergePolicy) {
+        self.input_merge = input_merge;
+    }
+
+    pub fn hotkey(&self, hotkey: Hotkey) -> VirtualKeyCode {
+        let index = HOTKEYS.iter().position(|(h, _, _)| *h == hotkey).unwrap();
+        self.hotkeys[index]
+    }
+
+    // Name of the key of a hotkey, as written in the configuration file
+    pub fn hotkey_name(&self, hotkey: Hotkey) -> &'static str {
+        key_name(self.hotkey(hotkey))
+    }
+
+    // Name of the key of each NES button
+    pub fn controller_keys(&self) -> Vec<(u8, &'static str)> {
+        BUTTONS
+            .iter()
+            .zip(self.keys.iter())
+            .map(|((button, _), key)| (*button, key_name(*key)))
+            .collect()
+    }
+
+    // NES buttons held on the keyboard
+    pub fn input(&self, key_held: impl Fn(VirtualKeyCode) -> bool) -> u8 {
+        BUTTONS
+            .iter()
+            .zip(self.keys.iter())
+            .filter(|(_, key)| key_held(**key))
+            .fold(0, |input, ((button, _), _)| input | button)
+    }
+}
+
+// Interactive flow asking for the key of each NES button in turn
+pub struct BindingWizard {
+    bindings: KeyBindings,
+    next_button: usize,
+    // Function already bound to the last key pressed, which was refused
+    conflict: Option<(VirtualKeyCode, &'static str)>,
+}
+
+impl BindingWizard {
+    pub fn new(current: KeyBindings) -> Self {
+        BindingWizard {
+            bindings: current,
+            next_button: 0,
+            conflict: None,
+        }
+    }
+
+    pub fn prompt(&self) -> String {
+        let button = BUTTONS[self.next_button].1.to_uppercase();
+        match self.conflict {
+            Some((key, function)) => format!(
+                "{} is bound to {}, press another key for {}",
+                key_name(key),
+                function,
+                button
+            ),
+            None => format!("Press the key for {} (Esc to cancel)", button),
+        }
+    }
+
+    // Bind the pressed key to the current button, returns the new bindings once all buttons are bound.
+    // A key of a hotkey or of a button bound before is refused, the prompt asking for another one.
+    pub fn press(&mut self, key: VirtualKeyCode) -> Option<KeyBindings> {
+        self.conflict = BUTTONS[..self.next_button]
+            .iter()
+            .map(|(_, button)| *button)
+            .zip(self.bindings.keys.iter())
+            .chain(
+                HOTKEYS
+                    .iter()
+                    .map(|(_, hotkey, _)| *hotkey)
+                    .zip(self.bindings.hotkeys.iter()),
+            )
+            .find(|(_, bound)| **bound == key)
+            .map(|(function, _)| (key, function));
+        if self.conflict.is_some() {
+            return None;
+        }
+        self.bindings.keys[self.next_button] = key;
+        self.next_button += 1;
+        if self.next_button == BUTTONS.len() {
+            Some(self.bindings.clone())
+        } else {
+            None
+        }
+    }
+}
+
+// Keyboard layouts offered by the setup wizard, with the keys of the D-pad
+const LAYOUTS: [(&str, [VirtualKeyCode; 4]); 2] = [
+    (
+        "AZERTY (ZQSD)",
+        [
+            VirtualKeyCode::Z,
+            VirtualKeyCode::Q,
+            VirtualKeyCode::S,
+            VirtualKeyCode::D,
+        ],
+    ),
+    (
+        "QWERTY (WASD)",
+        [
+            VirtualKeyCode::W,
+            VirtualKeyCode::A,
+            VirtualKeyCode::S,
+            VirtualKeyCode::D,
+        ],
+    ),
+];
+const SETUP_STEPS: [&str; 3] = ["keyboard", "window", "saves"];
+
+// Questions asked on the first run, before the configuration file is written
+pub struct SetupWizard {
+    bindings: KeyBindings,
+    step: usize,
+    layout: usize,
+    // Saves folder offered instead of next to the ROMs, in the configuration folder
+    config_save_dir: String,
+    in_config_dir: bool,
+}
+
+impl SetupWizard {
+    pub fn new(config_path: &str) -> Self {
+        let config_save_dir = Path::new(config_path)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join("saves");
+        SetupWizard {
+            bindings: KeyBindings::default(),
+            step: 0,
+            layout: 0,
+            config_save_dir: config_save_dir.to_string_lossy().into_owned(),
+            in_config_dir: false,
+        }
+    }
+
+    pub fn prompt(&self) -> String {
+        let choice = match self.step {
+            0 => String::from(LAYOUTS[self.layout].0),
+            1 => format!("{}x", self.bindings.window_scale),
+            _ if self.in_config_dir => String::from("config folder"),
+            _ => String::from("next to ROMs"),
+        };
+        format!(
+            "Setup {}/{}, {}: < {} > Enter",
+            self.step + 1,
+            SETUP_STEPS.len(),
+            SETUP_STEPS[self.step],
+            choice
+        )
+    }
+
+    // Change the answer of the current question
+    pub fn previous(&mut self) {
+        match self.step {
+            0 => self.layout = (self.layout + LAYOUTS.len() - 1) % LAYOUTS.len(),
+            1 => self.bindings.window_scale = (self.bindings.window_scale - 1).max(1),
+            _ => self.in_config_dir = !self.in_config_dir,
+        }
+    }
+
+    pub fn next(&mut self) {
+        match self.step {
+            0 => self.layout = (self.layout + 1) % LAYOUTS.len(),
+            1 => {
+                self.bindings.window_scale = (self.bindings.window_scale + 1).min(MAX_WINDOW_SCALE)
+            }
+            _ => self.in_config_dir = !self.in_config_dir,
+        }
+    }
+
+    // Go to the next question, returns the bindings once all questions are answered
+    pub fn confirm(&mut self) -> Option<KeyBindings> {
+        self.step += 1;
+        if self.step < SETUP_STEPS.len() {
+            return None;
+        }
+        self.bindings.keys[..4].copy_from_slice(&LAYOUTS[self.layout].1);
+        if self.in_config_dir {
+            self.bindings.save_dir = Some(self.config_save_dir.clone());
+        }
+        Some(self.bindings.clone())
+    }
+}
@@ -0,0 +1,113 @@
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use nesmulator_core::utils::ARGBColor;
+
+use crate::backend::EmulatorCore;
+use crate::error::FrontendError;
+
+pub const FRAME_WIDTH: u32 = 256;
+pub const FRAME_HEIGHT: u32 = 240;
+
+// Informations about the running session written alongside a repro capture
+pub struct ReproContext<'a> {
+    pub rom_path: &'a str,
+    pub palette_path: Option<&'a str>,
+    pub frame_number: u64,
+    pub speed: f64,
+}
+
+// Write an ARGB buffer to a PNG file
+pub fn write_screenshot(
+    path: &Path,
+    buffer: &[ARGBColor],
+    width: u32,
+    height: u32,
+) -> Result<(), FrontendError> {
+    let file = File::create(path).map_err(save_error)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut data = Vec::with_capacity(buffer.len() * 4);
+    for color in buffer.iter() {
+        data.extend_from_slice(&[color.red, color.green, color.blue, color.alpha]);
+    }
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&data))
+        .map_err(save_error)
+}
+
+// Write a screenshot, a savestate and a context.json file in a new folder of base_dir
+pub fn capture_repro(
+    base_dir: &Path,
+    nes: &mut impl EmulatorCore,
+    frame: &[ARGBColor],
+    context: &ReproContext,
+) -> Result<PathBuf, FrontendError> {
+    let dir = base_dir.join(format!("frame-{}", context.frame_number));
+    fs::create_dir_all(&dir).map_err(save_error)?;
+
+    write_screenshot(
+        &dir.join("screenshot.png"),
+        frame,
+        FRAME_WIDTH,
+        FRAME_HEIGHT,
+    )?;
+    nes.save_state(
+        dir.join("state.data")
+            .to_str()
+            .ok_or_else(|| FrontendError::Save(String::from("Invalid repro path")))?,
+    )
+    .map_err(FrontendError::Save)?;
+
+    let rom = fs::read(context.rom_path).map_err(|e| FrontendError::Rom(e.to_string()))?;
+    let rom_crc32 = crc32(&rom);
+    let json = format!(
+        "{{\n  \"rom\": \"{}\",\n  \"rom_crc32\": \"{:08X}\",\n  \"frame\": {},\n  \"speed\": {},\n  \"palette\": {}\n}}\n",
+        escape_json(context.rom_path),
+        rom_crc32,
+        context.frame_number,
+        context.speed,
+        match context.palette_path {
+            Some(path) => format!("\"{}\"", escape_json(path)),
+            None => String::from("null"),
+        },
+    );
+    fs::write(dir.join("context.json"), json).map_err(save_error)?;
+
+    Ok(dir)
+}
+
+fn save_error(e: impl ToString) -> FrontendError {
+    FrontendError::Save(e.to_string())
+}
+
+// CRC32 (IEEE), the checksum commonly used to identify NES ROMs
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,376 @@
+use std::fs;
+use std::io::ErrorKind;
+
+// Letters of the Game Genie codes, each one standing for 4 bits
+const GAME_GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+const INES_HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+// The CPU sees the PRG ROM from $8000, in banks of at least 8 KB
+const PRG_START: u16 = 0x8000;
+const PRG_WINDOW_SIZE: usize = 0x8000;
+const MIN_BANK_SIZE: usize = 0x2000;
+
+// Value returned when the CPU reads an address of the PRG ROM, only if the original
+// value is the compare value when there is one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RomPatch {
+    pub address: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CheatEffect {
+    Rom(RomPatch),
+    // Value written to the RAM at each frame
+    Freeze { address: u16, value: u8 },
+}
+
+// Decode a Game Genie code (e.g. SXIOPO or YEUZUGAA), or a raw code written
+// AAAA:VV or AAAA?CC:VV with hexadecimal address, compare and value
+fn decode(code: &str) -> Result<CheatEffect, String> {
+    let code = code.to_ascii_uppercase();
+    if code.contains(':') {
+        return decode_raw(&code);
+    }
+    let n = code
+        .chars()
+        .map(|c| GAME_GENIE_LETTERS.find(c).map(|n| n as u16))
+        .collect::<Option<Vec<u16>>>()
+        .filter(|n| n.len() == 6 || n.len() == 8)
+        .ok_or(format!("Invalid code {:?}", code))?;
+    let address = PRG_START
+        | ((n[3] & 7) << 12)
+        | ((n[5] & 7) << 8)
+        | ((n[4] & 8) << 8)
+        | ((n[2] & 7) << 4)
+        | ((n[1] & 8) << 4)
+        | (n[4] & 7)
+        | (n[3] & 8);
+    let last = if n.len() == 8 { n[7] } else { n[5] };
+    let value = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7) | (last & 8);
+    let compare = if n.len() == 8 {
+        Some(((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8))
+    } else {
+        None
+    };
+    Ok(CheatEffect::Rom(RomPatch {
+        address,
+        value: value as u8,
+        compare: compare.map(|compare| compare as u8),
+    }))
+}
+
+fn decode_raw(code: &str) -> Result<CheatEffect, String> {
+    let invalid = || format!("Invalid code {:?}, expected AAAA:VV or AAAA?CC:VV", code);
+    let (target, value) = code.split_once(':').ok_or_else(invalid)?;
+    let (address, compare) = match target.split_once('?') {
+        Some((address, compare)) => (address, Some(compare)),
+        None => (target, None),
+    };
+    let address = u16::from_str_radix(address, 16).map_err(|_| invalid())?;
+    let value = u8::from_str_radix(value, 16).map_err(|_| invalid())?;
+    let compare = match compare {
+        Some(compare) => Some(u8::from_str_radix(compare, 16).map_err(|_| invalid())?),
+        None => None,
+    };
+    if address >= PRG_START {
+        return Ok(CheatEffect::Rom(RomPatch {
+            address,
+            value,
+            compare,
+        }));
+    }
+    if compare.is_some() {
+        return Err(format!(
+            "Invalid code {:?}, RAM codes have no compare value",
+            code
+        ));
+    }
+    Ok(CheatEffect::Freeze { address, value })
+}
+
+pub struct Cheat {
+    pub code: String,
+    pub description: String,
+    pub enabled: bool,
+    effect: CheatEffect,
+}
+
+// Cheats of a ROM, one per line written "on|off <code> <description>"
+pub struct CheatList {
+    path: String,
+    cheats: Vec<Cheat>,
+    selected: usize,
+}
+
+impl CheatList {
+    // No file is an empty list
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.to_string()),
+        };
+        let mut cheats = vec![];
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.splitn(3, char::is_whitespace);
+            let enabled = match fields.next() {
+                Some("on") => true,
+                Some("off") => false,
+                _ => return Err(format!("Line {}: expected on or off", number + 1)),
+            };
+            let code = fields
+                .next()
+                .ok_or(format!("Line {}: missing code", number + 1))?;
+            let effect = decode(code).map_err(|e| format!("Line {}: {}", number + 1, e))?;
+            cheats.push(Cheat {
+                code: String::from(code),
+                description: String::from(fields.next().unwrap_or("").trim()),
+                enabled,
+                effect,
+            });
+        }
+        Ok(CheatList {
+            path: String::from(path),
+            cheats,
+            selected: 0,
+        })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let mut content = String::new();
+        for cheat in self.cheats.iter() {
+            let state = if cheat.enabled { "on" } else { "off" };
+            content.push_str(&format!("{} {} {}\n", state, cheat.code, cheat.description));
+        }
+        fs::write(&self.path, content).map_err(|e| e.to_string())
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    pub fn previous(&mut self) {
+        if !self.cheats.is_empty() {
+            self.selected = (self.selected + self.cheats.len() - 1) % self.cheats.len();
+        }
+    }
+
+    pub fn next(&mut self) {
+        if !self.cheats.is_empty() {
+            self.selected = (self.selected + 1) % self.cheats.len();
+        }
+    }
+
+    // Enable or disable the selected cheat. The RAM freezes cannot be enabled as
+    // nesmulator-core does not give write access to the CPU memory.
+    pub fn toggle_selected(&mut self) -> Result<(), String> {
+        let cheat = match self.cheats.get_mut(self.selected) {
+            Some(cheat) => cheat,
+            None => return Ok(()),
+        };
+        if let (CheatEffect::Freeze { .. }, false) = (cheat.effect, cheat.enabled) {
+            return Err(String::from("RAM codes are not supported yet"));
+        }
+        cheat.enabled = !cheat.enabled;
+        Ok(())
+    }
+
+    pub fn prompt(&self) -> String {
+        match self.cheats.get(self.selected) {
+            Some(cheat) => format!(
+                "< [{}] {} {} > {}/{}, Enter to toggle",
+                if cheat.enabled { "on" } else { "off" },
+                cheat.code,
+                cheat.description,
+                self.selected + 1,
+                self.cheats.len()
+            ),
+            None => String::from("No cheat for this game"),
+        }
+    }
+
+    // Patches of the enabled cheats
+    pub fn rom_patches(&self) -> Vec<RomPatch> {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .filter_map(|cheat| match cheat.effect {
+                CheatEffect::Rom(patch) => Some(patch),
+                CheatEffect::Freeze { .. } => None,
+            })
+            .collect()
+    }
+
+    // Enabled cheats that cannot be applied, i.e. the RAM freezes
+    pub fn unsupported(&self) -> Vec<&Cheat> {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.enabled && matches!(cheat.effect, CheatEffect::Freeze { .. }))
+            .collect()
+    }
+}
+
+// Apply the patches to the PRG ROM of an iNES file. The mapper is not known, so a code
+// with a compare value patches every bank holding the compare value at its address, as the
+// Game Genie does, and a code without one is only applied when the PRG ROM is not banked.
+pub fn patch_rom(rom: &mut [u8], patches: &[RomPatch]) -> Result<(), String> {
+    if rom.len() < INES_HEADER_SIZE || &rom[0..4] != b"NES\x1a" {
+        return Err(String::from("not an iNES file"));
+    }
+    let prg_start = INES_HEADER_SIZE + if rom[6] & 0x04 != 0 { TRAINER_SIZE } else { 0 };
+    let prg_size = rom[4] as usize * 0x4000;
+    if prg_size == 0 {
+        return Err(String::from("no PRG ROM"));
+    }
+    let prg = rom
+        .get_mut(prg_start..prg_start + prg_size)
+        .ok_or("truncated PRG ROM")?;
+    for patch in patches.iter() {
+        let offset = (patch.address - PRG_START) as usize;
+        match patch.compare {
+            Some(compare) => {
+                for byte in prg
+                    .iter_mut()
+                    .skip(offset % MIN_BANK_SIZE)
+                    .step_by(MIN_BANK_SIZE)
+                    .filter(|byte| **byte == compare)
+                {
+                    *byte = patch.value;
+                }
+            }
+            None if prg_size <= PRG_WINDOW_SIZE => prg[offset % prg_size] = patch.value,
+            None => {
+                return Err(format!(
+                    "the code for ${:04X} needs a compare value on this ROM, use an 8 letter code",
+                    patch.address
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom_patch(code: &str) -> RomPatch {
+        match decode(code).unwrap() {
+            CheatEffect::Rom(patch) => patch,
+            effect => panic!("{:?} is not a ROM patch", effect),
+        }
+    }
+
+    // iNES file with the given PRG ROM banks of 16 KB, filled with their index
+    fn ines(banks: u8) -> Vec<u8> {
+        let mut rom = b"NES\x1a".to_vec();
+        rom.extend([banks, 0, 0, 0]);
+        rom.resize(INES_HEADER_SIZE, 0);
+        for bank in 0..banks {
+            rom.extend(vec![bank; 0x4000]);
+        }
+        rom
+    }
+
+    #[test]
+    fn decodes_six_letter_codes() {
+        let patch = |address, value| RomPatch {
+            address,
+            value,
+            compare: None,
+        };
+        assert_eq!(rom_patch("SXIOPO"), patch(0x91D9, 0xAD));
+        assert_eq!(rom_patch("gossip"), patch(0xD1DD, 0x14));
+        // Every letter stands for 4 bits, A for 0 and N for 15
+        assert_eq!(rom_patch("AAAAAA"), patch(0x8000, 0x00));
+        assert_eq!(rom_patch("NNNNNN"), patch(0xFFFF, 0xFF));
+    }
+
+    #[test]
+    fn decodes_eight_letter_codes() {
+        assert_eq!(
+            rom_patch("ZEXPYGLA"),
+            RomPatch {
+                address: 0x94A7,
+                value: 0x02,
+                compare: Some(0x03),
+            }
+        );
+    }
+
+    #[test]
+    fn refuses_invalid_codes() {
+        assert!(decode("SXIOP").is_err());
+        assert!(decode("SXIOPOA").is_err());
+        assert!(decode("SXIOPB").is_err());
+        assert!(decode("0300?01:02").is_err());
+    }
+
+    #[test]
+    fn decodes_raw_codes() {
+        assert_eq!(
+            rom_patch("C000?01:02"),
+            RomPatch {
+                address: 0xC000,
+                value: 0x02,
+                compare: Some(0x01),
+            }
+        );
+        assert_eq!(
+            decode("075a:09").unwrap(),
+            CheatEffect::Freeze {
+                address: 0x075A,
+                value: 0x09,
+            }
+        );
+    }
+
+    #[test]
+    fn patches_every_bank_holding_the_compare_value() {
+        let mut rom = ines(4);
+        let patch = RomPatch {
+            address: 0x8010,
+            value: 0xEA,
+            compare: Some(2),
+        };
+        patch_rom(&mut rom, &[patch]).unwrap();
+        let prg = &rom[INES_HEADER_SIZE..];
+        // Bank 2 holds the compare value in both of its 8 KB halves
+        assert_eq!(prg[2 * 0x4000 + 0x10], 0xEA);
+        assert_eq!(prg[2 * 0x4000 + 0x2010], 0xEA);
+        assert_eq!(prg[0x10], 0);
+        assert_eq!(prg[3 * 0x4000 + 0x10], 3);
+        assert_eq!(prg.iter().filter(|byte| **byte == 0xEA).count(), 2);
+    }
+
+    #[test]
+    fn patches_codes_without_compare_value_only_when_not_banked() {
+        let patch = RomPatch {
+            address: 0xC001,
+            value: 0xEA,
+            compare: None,
+        };
+        // A single 16 KB bank is mirrored at $C000
+        let mut rom = ines(1);
+        patch_rom(&mut rom, &[patch]).unwrap();
+        assert_eq!(rom[INES_HEADER_SIZE + 1], 0xEA);
+
+        let mut rom = ines(4);
+        assert!(patch_rom(&mut rom, &[patch]).is_err());
+        assert!(patch_rom(&mut [0; 8], &[patch]).is_err());
+    }
+}
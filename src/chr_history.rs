@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+
+use nesmulator_core::utils::ARGBColor;
+
+use crate::backend::EmulatorCore;
+use crate::font::{self, GLYPH_HEIGHT};
+use crate::osd::argb;
+
+const PATTERN_TABLE_SIZE: usize = 128;
+pub const CHR_HISTORY_WIDTH: usize = 2 * PATTERN_TABLE_SIZE;
+// The pattern tables, then a line telling which snapshot is shown
+pub const CHR_HISTORY_HEIGHT: usize = PATTERN_TABLE_SIZE + GLYPH_HEIGHT + 2;
+// Different pattern tables kept, the ones seen the longest time ago being dropped
+const CHR_HISTORY_DEPTH: usize = 64;
+
+// Pattern tables seen at the end of a frame
+struct ChrSnapshot {
+    tables: [Vec<ARGBColor>; 2],
+    frame: u64,
+}
+
+// Pattern tables seen since the panel was opened, a snapshot being taken each time they
+// change, e.g. when a mapper such as the MMC3 switches its CHR banks, so that the graphics
+// held by each bank can be browsed. The core does not tell when the banks are switched, so
+// the tables are compared at the end of each frame, and the switches made and undone
+// within a frame are not seen.
+pub struct ChrHistory {
+    snapshots: VecDeque<ChrSnapshot>,
+    // Snapshot browsed, the latest one being shown when none is
+    selected: Option<usize>,
+}
+
+impl ChrHistory {
+    pub fn new() -> Self {
+        ChrHistory {
+            snapshots: VecDeque::with_capacity(CHR_HISTORY_DEPTH),
+            selected: None,
+        }
+    }
+
+    // Forget the snapshots, e.g. of the previous game or drawn with another palette
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.selected = None;
+    }
+
+    // Compare the pattern tables with the latest snapshot. Tables seen before are moved to
+    // the end rather than kept twice, so that the banks switched back and forth for an
+    // animation do not fill the history. Tables that cannot be read are skipped, the
+    // error being reported with the other panels of the debugging window.
+    pub fn frame(&mut self, nes: &impl EmulatorCore, frame: u64) {
+        let tables = match nes.get_pattern_tables() {
+            Ok(tables) => tables,
+            Err(_) => return,
+        };
+        if matches!(self.snapshots.back(), Some(s) if same_tables(&s.tables, &tables)) {
+            return;
+        }
+        if let Some(index) = self
+            .snapshots
+            .iter()
+            .position(|s| same_tables(&s.tables, &tables))
+        {
+            self.snapshots.remove(index);
+            self.selected = match self.selected {
+                Some(selected) if selected == index => None,
+                Some(selected) if selected > index => Some(selected - 1),
+                selected => selected,
+            };
+        } else if self.snapshots.len() == CHR_HISTORY_DEPTH {
+            self.snapshots.pop_front();
+            self.selected = self.selected.and_then(|s| s.checked_sub(1));
+        }
+        self.snapshots.push_back(ChrSnapshot { tables, frame });
+    }
+
+    // Browse the snapshots offset positions away, the latest one being followed again
+    // when reached
+    pub fn browse(&mut self, offset: isize) {
+        let last = self.snapshots.len().saturating_sub(1);
+        let current = self.selected.unwrap_or(last) as isize;
+        let index = (current + offset).clamp(0, last as isize) as usize;
+        self.selected = if index == last { None } else { Some(index) };
+    }
+
+    // Pixels of the panel: the two pattern tables of the browsed snapshot side by side,
+    // above e.g. "3/12 frame 1234", followed by "live" when it is the latest one
+    pub fn draw(&self) -> Vec<ARGBColor> {
+        let mut pixels = vec![ARGBColor::black(); CHR_HISTORY_WIDTH * CHR_HISTORY_HEIGHT];
+        let index = match self.selected {
+            Some(index) => index,
+            None if self.snapshots.is_empty() => return pixels,
+            None => self.snapshots.len() - 1,
+        };
+        let snapshot = &self.snapshots[index];
+        for (i, table) in snapshot.tables.iter().enumerate() {
+            for (y, row) in table.chunks(PATTERN_TABLE_SIZE).enumerate() {
+                let start = y * CHR_HISTORY_WIDTH + i * PATTERN_TABLE_SIZE;
+                pixels[start..start + row.len()].copy_from_slice(row);
+            }
+        }
+        let mut label = format!(
+            "{}/{} frame {}",
+            index + 1,
+            self.snapshots.len(),
+            snapshot.frame
+        );
+        if self.selected.is_none() {
+            label.push_str(" live");
+        }
+        font::draw_text(
+            &mut pixels,
+            CHR_HISTORY_WIDTH,
+            1,
+            PATTERN_TABLE_SIZE + 1,
+            &label,
+            argb(0xFF, 0xFF, 0xFF, 0xFF),
+        );
+        pixels
+    }
+}
+
+fn same_tables(a: &[Vec<ARGBColor>; 2], b: &[Vec<ARGBColor>; 2]) -> bool {
+    let channels = |c: &ARGBColor| (c.alpha, c.red, c.green, c.blue);
+    a.iter().zip(b.iter()).all(|(a, b)| {
+        a.len() == b.len()
+            && a.iter()
+                .zip(b.iter())
+                .all(|(a, b)| channels(a) == channels(b))
+    })
+}
@@ -0,0 +1,194 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::hex::{from_hex, to_hex};
+use crate::osd::{Osd, OsdLevel};
+
+// Commands of the control socket, one per line:
+//   checkpoint <NAME>  keep the current state in memory under a name
+//   restore <NAME>     go back to a checkpoint
+//   delete <NAME>      forget a checkpoint
+//   list               names of the checkpoints, separated by spaces
+//   send-state <ADDRESS>  send the current state to the control socket of another
+//                      emulator running the same game, to hand off a situation, answered
+//                      once the other emulator has loaded it
+//   receive-state <HEX>   load a state sent by send-state, in hexadecimal
+// Each command is answered with a line, "ok" followed by the result if any, or "error <MESSAGE>".
+pub enum ControlCommand {
+    Checkpoint(String),
+    Restore(String),
+    Delete(String),
+    List,
+    SendState(String),
+    ReceiveState(Vec<u8>),
+}
+
+impl ControlCommand {
+    fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let argument = words.next();
+        if words.next().is_some() {
+            return Err(String::from("Too many arguments"));
+        }
+        let name = || match argument {
+            Some(name) => Ok(String::from(name)),
+            None => Err(format!("Missing checkpoint name for {}", command)),
+        };
+        match command {
+            "checkpoint" => Ok(ControlCommand::Checkpoint(name()?)),
+            "restore" => Ok(ControlCommand::Restore(name()?)),
+            "delete" => Ok(ControlCommand::Delete(name()?)),
+            "list" if argument.is_none() => Ok(ControlCommand::List),
+            "list" => Err(String::from("Too many arguments")),
+            "send-state" => match argument {
+                Some(address) => Ok(ControlCommand::SendState(String::from(address))),
+                None => Err(String::from("Missing address for send-state")),
+            },
+            "receive-state" => match argument {
+                Some(hex) => from_hex(hex)
+                    .map(ControlCommand::ReceiveState)
+                    .ok_or_else(|| String::from("Invalid state")),
+                None => Err(String::from("Missing state for receive-state")),
+            },
+            c => Err(format!("Unknown command: {:?}", c)),
+        }
+    }
+}
+
+// Command sent by a client, waiting for the emulation thread to answer it
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    reply: Sender<Result<String, String>>,
+}
+
+impl ControlRequest {
+    pub fn reply(self, result: Result<String, String>) {
+        // The client may have disconnected in the meantime
+        let _ = self.reply.send(result);
+    }
+}
+
+// TCP socket external tools connect to, to control the emulation
+pub struct ControlServer {
+    requests: Receiver<ControlRequest>,
+    // Address listened on, which send-state refuses as it would wait for itself
+    address: SocketAddr,
+}
+
+impl ControlServer {
+    pub fn bind(address: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        info!("Control socket listening on {}.", address);
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let sender = sender.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = serve_client(stream, sender) {
+                                warn!("Control client disconnected: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => warn!("Cannot accept a control client: {}", e),
+                }
+            }
+        });
+        Ok(ControlServer { requests, address })
+    }
+
+    // Requests received since the last call
+    pub fn poll(&self) -> Vec<ControlRequest> {
+        self.requests.try_iter().collect()
+    }
+
+    // Answer a send-state request from a thread of its own, as the other emulator can take
+    // up to twice PEER_TIMEOUT to connect and answer, which would freeze the game
+    pub fn send_state(
+        &self,
+        request: ControlRequest,
+        address: String,
+        snapshot: Vec<u8>,
+        osd: Osd,
+    ) {
+        let own_address = self.address;
+        thread::spawn(move || {
+            let result = send_state(&address, own_address, &snapshot).map(|_| {
+                osd.notify(OsdLevel::Info, format!("State sent to {}", address));
+                String::new()
+            });
+            if let Err(e) = &result {
+                warn!("Control command failed: {}", e);
+            }
+            request.reply(result);
+        });
+    }
+}
+
+fn serve_client(stream: TcpStream, sender: Sender<ControlRequest>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = match ControlCommand::parse(&line) {
+            Ok(command) => {
+                let (reply, answer) = mpsc::channel();
+                if sender.send(ControlRequest { command, reply }).is_err() {
+                    // The emulation stopped
+                    return Ok(());
+                }
+                answer
+                    .recv()
+                    .unwrap_or_else(|_| Err(String::from("Emulation stopped")))
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(text) if text.is_empty() => writeln!(writer, "ok")?,
+            Ok(text) => writeln!(writer, "ok {}", text)?,
+            Err(e) => writeln!(writer, "error {}", e)?,
+        }
+    }
+    Ok(())
+}
+
+// Time given to the other emulator to answer send-state, which it does between two frames
+const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Send a state to the control socket of another emulator
+fn send_state(address: &str, own_address: SocketAddr, snapshot: &[u8]) -> Result<(), String> {
+    let error = |e: io::Error| format!("Cannot send the state to {}: {}", address, e);
+    let peer = address
+        .to_socket_addrs()
+        .map_err(error)?
+        .next()
+        .ok_or_else(|| format!("Unknown address {}", address))?;
+    // The state would only be received once the emulation answers this very request
+    if peer.port() == own_address.port()
+        && (peer.ip() == own_address.ip() || own_address.ip().is_unspecified())
+    {
+        return Err(format!("{} is the address of this emulator", address));
+    }
+    let mut stream = TcpStream::connect_timeout(&peer, PEER_TIMEOUT).map_err(error)?;
+    stream.set_read_timeout(Some(PEER_TIMEOUT)).map_err(error)?;
+    writeln!(stream, "receive-state {}", to_hex(snapshot)).map_err(error)?;
+    let mut reply = String::new();
+    BufReader::new(stream)
+        .read_line(&mut reply)
+        .map_err(error)?;
+    match reply.trim_end().strip_prefix("error ") {
+        Some(e) => Err(format!("{} refused the state: {}", address, e)),
+        None if reply.starts_with("ok") => Ok(()),
+        None => Err(format!("No answer from {}", address)),
+    }
+}
@@ -0,0 +1,122 @@
+use nesmulator_core::utils::ControllerInput;
+
+use crate::input::PORTS;
+
+// Frames each turbo button stays pressed then released, 15 presses per second
+const TURBO_FRAMES: u32 = 2;
+const TURBO_BUTTONS: [u8; 2] = [ControllerInput::A as u8, ControllerInput::B as u8];
+
+// How A and B repeat, the defaults of the kinds of games
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Turbo {
+    Off,
+    // The buttons repeat while held, e.g. for shooters
+    Hold,
+    // A press starts repeating the button and the next one stops it, for the games that
+    // are played by mashing for a long time
+    Tap,
+}
+
+impl Turbo {
+    const ALL: [Turbo; 3] = [Turbo::Off, Turbo::Hold, Turbo::Tap];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Turbo::Off => "off",
+            Turbo::Hold => "hold",
+            Turbo::Tap => "tap",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Turbo::ALL.iter().copied().find(|t| t.name() == name)
+    }
+
+    pub fn next(&self) -> Self {
+        let index = Turbo::ALL.iter().position(|t| t == self).unwrap();
+        Turbo::ALL[(index + 1) % Turbo::ALL.len()]
+    }
+}
+
+// Controls of the current game applied to the buttons of the players, not to the
+// inputs played by a movie
+pub struct GameControls {
+    pub swap_ab: bool,
+    pub turbo: Turbo,
+    // Buttons of each port before the controls, to find the new presses
+    previous: [u8; PORTS],
+    // Buttons repeating in tap mode on each port
+    tapped: [u8; PORTS],
+    pressed_phase: bool,
+    phase_frames: u32,
+}
+
+impl GameControls {
+    pub fn new(swap_ab: bool, turbo: Turbo) -> Self {
+        GameControls {
+            swap_ab,
+            turbo,
+            previous: [0; PORTS],
+            tapped: [0; PORTS],
+            pressed_phase: true,
+            phase_frames: 0,
+        }
+    }
+
+    pub fn set_turbo(&mut self, turbo: Turbo) {
+        self.turbo = turbo;
+        self.tapped = [0; PORTS];
+    }
+
+    // Whether the buttons change without input, so that they must be given again at
+    // each frame
+    pub fn is_repeating(&self) -> bool {
+        self.turbo != Turbo::Off
+    }
+
+    // Called at the end of each frame
+    pub fn next_frame(&mut self) {
+        self.phase_frames += 1;
+        if self.phase_frames >= TURBO_FRAMES {
+            self.phase_frames = 0;
+            self.pressed_phase = !self.pressed_phase;
+        }
+    }
+
+    // Buttons given to the core for the buttons held on each port
+    pub fn apply(&mut self, inputs: [u8; PORTS]) -> [u8; PORTS] {
+        let mut applied = inputs;
+        for (port, input) in applied.iter_mut().enumerate() {
+            if self.swap_ab {
+                *input = swap_ab(*input);
+            }
+            let pressed = *input & !self.previous[port];
+            self.previous[port] = *input;
+            for button in TURBO_BUTTONS {
+                let repeating = match self.turbo {
+                    Turbo::Off => false,
+                    Turbo::Hold => *input & button != 0,
+                    Turbo::Tap => {
+                        if pressed & button != 0 {
+                            self.tapped[port] ^= button;
+                        }
+                        self.tapped[port] & button != 0
+                    }
+                };
+                if repeating && self.pressed_phase {
+                    *input |= button;
+                } else if repeating || self.turbo == Turbo::Tap {
+                    *input &= !button;
+                }
+            }
+        }
+        applied
+    }
+}
+
+fn swap_ab(input: u8) -> u8 {
+    let a = ControllerInput::A as u8;
+    let b = ControllerInput::B as u8;
+    let swapped = if input & a != 0 { b } else { 0 } | if input & b != 0 { a } else { 0 };
+    input & !(a | b) | swapped
+}
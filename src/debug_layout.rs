@@ -0,0 +1,92 @@
+use nesmulator_core::utils::ARGBColor;
+
+// An image of a panel of the debugging window, e.g. a pattern table, scaled by a whole
+// number
+pub struct Tile<'a> {
+    pub pixels: &'a [ARGBColor],
+    pub width: usize,
+    pub scale: usize,
+}
+
+impl<'a> Tile<'a> {
+    pub fn new(pixels: &'a [ARGBColor], width: usize, scale: usize) -> Self {
+        Tile {
+            pixels,
+            width,
+            scale,
+        }
+    }
+
+    fn size(&self) -> TileSize {
+        TileSize::new(
+            self.width,
+            self.pixels.len() / self.width.max(1),
+            self.scale,
+        )
+    }
+}
+
+// Size of a tile in the debugging window, known before its pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileSize {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl TileSize {
+    pub fn new(width: usize, height: usize, scale: usize) -> Self {
+        TileSize {
+            width: width * scale,
+            height: height * scale,
+        }
+    }
+}
+
+// Tiles of a panel placed from left to right, separated by gap pixels, and wrapped to
+// a new row when the window is too narrow for the next one
+pub struct PanelLayout {
+    pub width: usize,
+    pub gap: usize,
+}
+
+impl PanelLayout {
+    // Top left corner of each tile, and the height of the panel
+    pub fn place(&self, sizes: &[TileSize]) -> (Vec<(usize, usize)>, usize) {
+        let mut positions = Vec::with_capacity(sizes.len());
+        let (mut x, mut y, mut row_height) = (0, 0, 0);
+        for size in sizes.iter() {
+            if x > 0 && x + self.gap + size.width > self.width {
+                x = 0;
+                y += row_height + self.gap;
+                row_height = 0;
+            }
+            if x > 0 {
+                x += self.gap;
+            }
+            positions.push((x, y));
+            x += size.width;
+            row_height = row_height.max(size.height);
+        }
+        (positions, y + row_height)
+    }
+
+    pub fn height(&self, sizes: &[TileSize]) -> usize {
+        self.place(sizes).1
+    }
+
+    // Draw the tiles to the buffer of the panel, cropping what goes beyond it
+    pub fn draw(&self, buffer: &mut [ARGBColor], tiles: &[Tile]) {
+        let sizes: Vec<TileSize> = tiles.iter().map(Tile::size).collect();
+        let (positions, _) = self.place(&sizes);
+        let height = buffer.len() / self.width;
+        for ((tile, size), (left, top)) in tiles.iter().zip(sizes.iter()).zip(positions) {
+            for y in 0..size.height.min(height.saturating_sub(top)) {
+                let source = (y / tile.scale) * tile.width;
+                let row = (top + y) * self.width;
+                for x in 0..size.width.min(self.width.saturating_sub(left)) {
+                    buffer[row + left + x] = tile.pixels[source + x / tile.scale];
+                }
+            }
+        }
+    }
+}
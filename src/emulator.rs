@@ -0,0 +1,99 @@
+use nesmulator_core::utils::ARGBColor;
+
+use crate::backend::{Core, EmulatorCore};
+
+// Emulator stepped frame by frame by the caller, without window, audio or pacing,
+// e.g. to drive it from scripts, bots or reinforcement learning agents
+pub struct HeadlessEmulator {
+    nes: Box<dyn EmulatorCore>,
+    palette_path: Option<String>,
+    rom_path: Option<String>,
+    frame: Box<[ARGBColor; 61_440]>,
+    frame_count: u64,
+}
+
+impl HeadlessEmulator {
+    pub fn new(palette_path: Option<&str>) -> Self {
+        HeadlessEmulator {
+            nes: power_on(palette_path),
+            palette_path: palette_path.map(String::from),
+            rom_path: None,
+            frame: Box::new([ARGBColor::black(); 61_440]),
+            frame_count: 0,
+        }
+    }
+
+    // Power on a new NES with the given ROM
+    pub fn load_rom(&mut self, rom_path: &str) -> Result<(), String> {
+        let mut nes = power_on(self.palette_path.as_deref());
+        nes.insert_cartdrige(rom_path)?;
+        self.nes = nes;
+        self.rom_path = Some(String::from(rom_path));
+        *self.frame = [ARGBColor::black(); 61_440];
+        self.frame_count = 0;
+        Ok(())
+    }
+
+    // Run the emulation until the next frame is produced
+    pub fn step_frame(&mut self) -> Result<(), String> {
+        while !self.step_frame_within(u64::MAX)? {}
+        Ok(())
+    }
+
+    // Run the emulation until the next frame is produced or the clocks are spent, e.g.
+    // when the core is stuck, returning whether the frame was produced
+    pub fn step_frame_within(&mut self, clocks: u64) -> Result<bool, String> {
+        if self.rom_path.is_none() {
+            return Err(String::from("No ROM loaded"));
+        }
+        for _ in 0..clocks {
+            self.nes.clock();
+            if let Some(frame) = self.nes.get_frame_buffer() {
+                *self.frame = frame;
+                self.frame_count += 1;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // Last frame produced, 256x240 pixels
+    pub fn frame(&self) -> &[ARGBColor] {
+        &self.frame[..]
+    }
+
+    // Colors of the 32 entries of the palette RAM, as they are now
+    pub fn palette(&self) -> Result<Vec<ARGBColor>, String> {
+        self.nes.get_palette_colors()
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    // Buttons held on a controller port, see ControllerInput for the bits
+    pub fn set_input(&mut self, port: usize, input: u8) -> Result<(), String> {
+        self.nes.input(port, input)
+    }
+
+    pub fn reset(&mut self) {
+        self.nes.reset();
+    }
+
+    pub fn save_state(&mut self, path: &str) -> Result<(), String> {
+        self.nes.save_state(path)
+    }
+
+    pub fn load_state(&mut self, path: &str) -> Result<(), String> {
+        match &self.rom_path {
+            Some(rom_path) => self.nes.load_state(path, rom_path),
+            None => Err(String::from("No ROM loaded")),
+        }
+    }
+}
+
+fn power_on(palette_path: Option<&str>) -> Box<dyn EmulatorCore> {
+    let mut nes = <Core as EmulatorCore>::new(palette_path, false);
+    nes.produce_samples(false);
+    Box::new(nes)
+}
@@ -0,0 +1,109 @@
+use nesmulator_core::utils::ARGBColor;
+
+use crate::emulator::HeadlessEmulator;
+
+type RewardHook = Box<dyn FnMut(&[ARGBColor]) -> f64>;
+type DoneHook = Box<dyn FnMut(&[ARGBColor]) -> bool>;
+
+// Result of an action
+pub struct Step<'a> {
+    // Last frame, 256x240 pixels
+    pub observation: &'a [ARGBColor],
+    pub reward: f64,
+    pub done: bool,
+}
+
+// Reinforcement learning environment in the style of Gym: each episode starts from the
+// power on of the ROM (or from a state), and each step holds the buttons of an action
+// on the first controller for a few frames
+pub struct Environment {
+    emulator: HeadlessEmulator,
+    rom_path: String,
+    start_state: Option<String>,
+    frame_skip: u32,
+    max_frames: Option<u64>,
+    reward: Option<RewardHook>,
+    done: Option<DoneHook>,
+}
+
+impl Environment {
+    pub fn new(rom_path: &str) -> Self {
+        Environment {
+            emulator: HeadlessEmulator::new(None),
+            rom_path: String::from(rom_path),
+            start_state: None,
+            frame_skip: 1,
+            max_frames: None,
+            reward: None,
+            done: None,
+        }
+    }
+
+    // Start the episodes from a state file instead of the power on
+    pub fn with_start_state(mut self, path: &str) -> Self {
+        self.start_state = Some(String::from(path));
+        self
+    }
+
+    // Number of frames each action is held for
+    pub fn with_frame_skip(mut self, frame_skip: u32) -> Self {
+        self.frame_skip = frame_skip.max(1);
+        self
+    }
+
+    // End the episodes after the given number of frames
+    pub fn with_max_frames(mut self, max_frames: u64) -> Self {
+        self.max_frames = Some(max_frames);
+        self
+    }
+
+    // Reward of a step, computed from its last frame. The reward is 0 by default.
+    pub fn with_reward(mut self, hook: impl FnMut(&[ARGBColor]) -> f64 + 'static) -> Self {
+        self.reward = Some(Box::new(hook));
+        self
+    }
+
+    // Whether the episode is over after a step, computed from its last frame
+    pub fn with_done(mut self, hook: impl FnMut(&[ARGBColor]) -> bool + 'static) -> Self {
+        self.done = Some(Box::new(hook));
+        self
+    }
+
+    // Start a new episode, returns the first observation
+    pub fn reset(&mut self) -> Result<&[ARGBColor], String> {
+        self.emulator.load_rom(&self.rom_path)?;
+        if let Some(path) = &self.start_state {
+            self.emulator.load_state(path)?;
+        }
+        self.emulator.step_frame()?;
+        Ok(self.emulator.frame())
+    }
+
+    // Hold the buttons of the action (bits of ControllerInput) for frame_skip frames
+    pub fn step(&mut self, action: u8) -> Result<Step<'_>, String> {
+        self.emulator.set_input(0, action)?;
+        for _ in 0..self.frame_skip {
+            self.emulator.step_frame()?;
+        }
+
+        let observation = self.emulator.frame();
+        let reward = match self.reward.as_mut() {
+            Some(hook) => hook(observation),
+            None => 0.0,
+        };
+        let timed_out = match self.max_frames {
+            Some(max) => self.emulator.frame_count() >= max,
+            None => false,
+        };
+        let done = timed_out
+            || match self.done.as_mut() {
+                Some(hook) => hook(observation),
+                None => false,
+            };
+        Ok(Step {
+            observation,
+            reward,
+            done,
+        })
+    }
+}
@@ -0,0 +1,81 @@
+use std::error::Error;
+use std::fmt;
+
+use log::error;
+
+use crate::osd::{Osd, OsdLevel};
+
+// Errors shown to the players, by what they can check to recover from them. Each variant
+// holds the cause given by the library or the core that failed.
+#[derive(Debug)]
+pub enum FrontendError {
+    // The configuration file cannot be read or is invalid
+    Config(String),
+    // The ROM cannot be read or is not supported by the core
+    Rom(String),
+    // The sound device cannot be opened
+    Audio(String),
+    // A window cannot be created or drawn to
+    Video(String),
+    // A save, a state or a capture cannot be written
+    Save(String),
+}
+
+impl FrontendError {
+    pub fn cause(&self) -> &str {
+        match self {
+            FrontendError::Config(cause)
+            | FrontendError::Rom(cause)
+            | FrontendError::Audio(cause)
+            | FrontendError::Video(cause)
+            | FrontendError::Save(cause) => cause,
+        }
+    }
+
+    // What the player can do about it, written to the log and to the terminal
+    pub fn hint(&self) -> &'static str {
+        match self {
+            FrontendError::Config(_) => {
+                "Fix the configuration file, or delete it to write the default one"
+            }
+            FrontendError::Rom(_) => {
+                "Check that the file is an iNES ROM with a mapper supported by nesmulator-core"
+            }
+            FrontendError::Audio(_) => {
+                "Check that a sound device is available, or run with --no-audio"
+            }
+            FrontendError::Video(_) => {
+                "Check that the graphics drivers support Vulkan, Metal, DirectX 12 or OpenGL"
+            }
+            FrontendError::Save(_) => "Check the free space and the permissions of the folder",
+        }
+    }
+
+    // Shorter hint, for the line of the on-screen display, with the key retrying the saves
+    fn short_hint(&self, retry_key: &str) -> String {
+        match self {
+            FrontendError::Config(_) => String::from("check the configuration"),
+            FrontendError::Rom(_) => String::from("check the ROM file"),
+            FrontendError::Audio(_) => String::from("check the sound device"),
+            FrontendError::Video(_) => String::from("check the graphics drivers"),
+            FrontendError::Save(_) => format!("{} to retry", retry_key),
+        }
+    }
+
+    // Log the error and show what failed on screen, e.g. "Failed to save state, F5 to retry"
+    pub fn report(&self, action: &str, osd: &Osd, retry_key: &str) {
+        error!("{}: {}", action, self);
+        osd.notify(
+            OsdLevel::Error,
+            format!("{}, {}", action, self.short_hint(retry_key)),
+        );
+    }
+}
+
+impl fmt::Display for FrontendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}. {}.", self.cause(), self.hint())
+    }
+}
+
+impl Error for FrontendError {}
@@ -0,0 +1,156 @@
+use nesmulator_core::utils::ARGBColor;
+
+// Post-processing of the RGBA frames of pixels, applied on the CPU after the scaling
+
+// Brightness of the dark lines of the scanlines filter, out of 256
+const SCANLINE_BRIGHTNESS: u32 = 160;
+// Strength of the barrel distortion of the CRT filter
+const CRT_CURVATURE: f32 = 0.08;
+// Brightness lost in the corners of the CRT filter
+const CRT_VIGNETTE: f32 = 0.35;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VideoFilter {
+    None,
+    // Darken every other line, as the gaps between the lines of a CRT
+    Scanlines,
+    // Scanlines on a curved screen with darker corners
+    Crt,
+    // Blur the colors horizontally more than the brightness, as the NTSC composite
+    // signal did, which blends dithering patterns and makes colors bleed
+    Ntsc,
+}
+
+impl VideoFilter {
+    const ALL: [VideoFilter; 4] = [
+        VideoFilter::None,
+        VideoFilter::Scanlines,
+        VideoFilter::Crt,
+        VideoFilter::Ntsc,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            VideoFilter::None => "none",
+            VideoFilter::Scanlines => "scanlines",
+            VideoFilter::Crt => "crt",
+            VideoFilter::Ntsc => "ntsc",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        VideoFilter::ALL.iter().copied().find(|f| f.name() == name)
+    }
+
+    pub fn next(&self) -> Self {
+        let index = VideoFilter::ALL.iter().position(|f| f == self).unwrap();
+        VideoFilter::ALL[(index + 1) % VideoFilter::ALL.len()]
+    }
+
+    // Filter a RGBA frame, source_width being the width of the frame before its scaling
+    pub fn apply(&self, frame: &mut [u8], width: usize, height: usize, source_width: usize) {
+        match self {
+            VideoFilter::None => (),
+            VideoFilter::Scanlines => scanlines(frame, width, height),
+            VideoFilter::Crt => {
+                scanlines(frame, width, height);
+                curvature(frame, width, height);
+            }
+            VideoFilter::Ntsc => composite(frame, width, height, width / source_width.max(1)),
+        }
+    }
+}
+
+fn scanlines(frame: &mut [u8], width: usize, height: usize) {
+    for y in (1..height).step_by(2) {
+        for value in frame[y * width * 4..(y + 1) * width * 4].iter_mut() {
+            *value = (*value as u32 * SCANLINE_BRIGHTNESS / 256) as u8;
+        }
+    }
+}
+
+// Barrel distortion and vignette
+fn curvature(frame: &mut [u8], width: usize, height: usize) {
+    let source = frame.to_vec();
+    for y in 0..height {
+        let v = y as f32 / height as f32 * 2.0 - 1.0;
+        for x in 0..width {
+            let u = x as f32 / width as f32 * 2.0 - 1.0;
+            let distance = u * u + v * v;
+            let factor = 1.0 + CRT_CURVATURE * distance;
+            let (su, sv) = (u * factor, v * factor);
+            let pixel = &mut frame[(y * width + x) * 4..(y * width + x) * 4 + 4];
+            if su.abs() > 1.0 || sv.abs() > 1.0 {
+                pixel.copy_from_slice(&[0, 0, 0, 0xFF]);
+                continue;
+            }
+            let sx = (((su + 1.0) / 2.0 * width as f32) as usize).min(width - 1);
+            let sy = (((sv + 1.0) / 2.0 * height as f32) as usize).min(height - 1);
+            let brightness = 1.0 - CRT_VIGNETTE * distance / 2.0;
+            let index = (sy * width + sx) * 4;
+            for channel in 0..3 {
+                pixel[channel] = (source[index + channel] as f32 * brightness) as u8;
+            }
+            pixel[3] = source[index + 3];
+        }
+    }
+}
+
+// Blur the chroma (I and Q of the YIQ colors) over about two NES pixels and the luma over
+// half a pixel, pixel_width being the width of a NES pixel in the frame
+fn composite(frame: &mut [u8], width: usize, height: usize, pixel_width: usize) {
+    let luma_radius = (pixel_width / 2).max(1);
+    let chroma_radius = pixel_width.max(1) * 2;
+    let mut yiq = vec![[0.0f32; 3]; width];
+    let clamp = |value: f32| value.clamp(0.0, 255.0) as u8;
+    for row in frame.chunks_exact_mut(width * 4).take(height) {
+        for (color, pixel) in yiq.iter_mut().zip(row.chunks_exact(4)) {
+            let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+            *color = [
+                0.299 * r + 0.587 * g + 0.114 * b,
+                0.596 * r - 0.274 * g - 0.322 * b,
+                0.211 * r - 0.523 * g + 0.312 * b,
+            ];
+        }
+        let luma = box_blur(&yiq, 0, luma_radius);
+        let in_phase = box_blur(&yiq, 1, chroma_radius);
+        let quadrature = box_blur(&yiq, 2, chroma_radius);
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let (y, i, q) = (luma[x], in_phase[x], quadrature[x]);
+            pixel[0] = clamp(y + 0.956 * i + 0.621 * q);
+            pixel[1] = clamp(y - 0.272 * i - 0.647 * q);
+            pixel[2] = clamp(y - 1.106 * i + 1.703 * q);
+        }
+    }
+}
+
+// Average of each value with its neighbours in the radius, using a running sum
+fn box_blur(colors: &[[f32; 3]], channel: usize, radius: usize) -> Vec<f32> {
+    let len = colors.len();
+    let mut blurred = Vec::with_capacity(len);
+    let mut sum: f32 = colors[..radius.min(len)].iter().map(|c| c[channel]).sum();
+    for x in 0..len {
+        if x + radius < len {
+            sum += colors[x + radius][channel];
+        }
+        if x > radius {
+            sum -= colors[x - radius - 1][channel];
+        }
+        let first = x.saturating_sub(radius);
+        let last = (x + radius).min(len - 1);
+        blurred.push(sum / (last - first + 1) as f32);
+    }
+    blurred
+}
+
+// Average a NES frame with the previous one, as the slowly fading phosphors of a CRT did.
+// Sprites flickering at every other frame, which games use for transparency or to show
+// more sprites on a line, then look transparent instead of blinking.
+pub fn blend_frames(frame: &mut [ARGBColor], previous: &[ARGBColor]) {
+    let average = |a: u8, b: u8| ((a as u16 + b as u16) / 2) as u8;
+    for (pixel, previous) in frame.iter_mut().zip(previous.iter()) {
+        pixel.red = average(pixel.red, previous.red);
+        pixel.green = average(pixel.green, previous.green);
+        pixel.blue = average(pixel.blue, previous.blue);
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use nesmulator_core::utils::ARGBColor;
+use winit::event_loop::EventLoopProxy;
+
+use crate::filter::VideoFilter;
+use crate::rom::PickerInput;
+
+// A frame produced by the emulation thread, ready to be presented by the GUI
+pub struct Frame {
+    pub main: Box<[ARGBColor; 61_440]>,
+    pub debug_open: bool,
+    // Only produced every few frames while the debugging window is open, the window
+    // keeps showing the last data in between
+    pub debug: Option<DebugFrame>,
+    // Chosen with the hotkey or in the pause menu
+    pub filter: VideoFilter,
+}
+
+pub struct DebugFrame {
+    pub pattern_table_0: Vec<ARGBColor>,
+    pub pattern_table_1: Vec<ARGBColor>,
+    pub palette: Vec<ARGBColor>,
+    // Only produced when the pattern table history panel is shown
+    pub chr_history: Option<Vec<ARGBColor>>,
+}
+
+// Create a channel holding at most one frame: a new frame replaces the one not presented yet
+pub fn frame_channel(proxy: EventLoopProxy<()>) -> (FrameSender, FrameReceiver) {
+    let slot = Arc::new(Mutex::new(None));
+    let closed = Arc::new(AtomicBool::new(false));
+    let picker = Arc::new(Mutex::new(vec![]));
+    (
+        FrameSender {
+            slot: Arc::clone(&slot),
+            closed: Arc::clone(&closed),
+            picker: Arc::clone(&picker),
+            proxy,
+        },
+        FrameReceiver {
+            slot,
+            closed,
+            picker,
+        },
+    )
+}
+
+pub struct FrameSender {
+    slot: Arc<Mutex<Option<Frame>>>,
+    // Set when the emulation stopped by itself, e.g. from the pause menu
+    closed: Arc<AtomicBool>,
+    // Gamepad inputs of the ROM picker, which the event loop shows
+    picker: Arc<Mutex<Vec<PickerInput>>>,
+    proxy: EventLoopProxy<()>,
+}
+
+impl FrameSender {
+    // Publish a frame and wake up the event loop, returns true if a frame was dropped
+    pub fn send(&self, frame: Frame) -> bool {
+        let dropped = self.slot.lock().unwrap().replace(frame).is_some();
+        // The event loop is gone when the application is closing
+        let _ = self.proxy.send_event(());
+        dropped
+    }
+
+    // Tell the event loop that no more frames will be sent
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        let _ = self.proxy.send_event(());
+    }
+
+    // Give the event loop an input of the ROM picker, only the emulation thread polling the gamepads
+    pub fn send_picker_input(&self, input: PickerInput) {
+        self.picker.lock().unwrap().push(input);
+        let _ = self.proxy.send_event(());
+    }
+}
+
+pub struct FrameReceiver {
+    slot: Arc<Mutex<Option<Frame>>>,
+    closed: Arc<AtomicBool>,
+    picker: Arc<Mutex<Vec<PickerInput>>>,
+}
+
+impl FrameReceiver {
+    pub fn try_recv(&self) -> Option<Frame> {
+        self.slot.lock().unwrap().take()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    pub fn take_picker_inputs(&self) -> Vec<PickerInput> {
+        std::mem::take(&mut *self.picker.lock().unwrap())
+    }
+}
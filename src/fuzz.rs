@@ -0,0 +1,152 @@
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use nesmulator_core::utils::ControllerInput;
+
+use crate::capture;
+use crate::emulator::HeadlessEmulator;
+use crate::input::PORTS;
+use crate::movie::{MovieRecorder, MovieStart};
+use crate::snapshot::ScratchFile;
+
+// Clocks of the core given to each frame before it is reported as stuck, ten frames of
+// PPU cycles
+const FRAME_CLOCKS: u64 = 10 * 341 * 262;
+// Frames between two checks that the state is restored identically
+const STATE_CHECK_FRAMES: u64 = 600;
+// Longest time the buttons are held, so that the games get inputs they can act on
+const MAX_HOLD_FRAMES: u64 = 30;
+
+// Pseudo-random numbers of a seed, so that a run can be done again (xorshift64*)
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        // Zero is the only seed xorshift cannot leave
+        Random(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // Buttons of a controller, without the opposite directions a pad cannot press together
+    fn buttons(&mut self) -> u8 {
+        let mut buttons = self.next() as u8;
+        for (a, b) in [
+            (ControllerInput::Up as u8, ControllerInput::Down as u8),
+            (ControllerInput::Left as u8, ControllerInput::Right as u8),
+        ] {
+            if buttons & a != 0 && buttons & b != 0 {
+                buttons &= !(if self.next() & 1 == 0 { a } else { b });
+            }
+        }
+        buttons
+    }
+}
+
+// Where a fuzzing run failed, the movie replaying it being written to the reproducer
+pub struct FuzzFailure {
+    pub frame: u64,
+    pub reason: String,
+    pub reproducer: String,
+}
+
+// Play random inputs from the seed on a ROM for a number of frames, to find the games
+// making the core panic, get stuck or write states it cannot restore. The inputs are written
+// to <out_dir>/<ROM>-fuzz-<SEED>.movie as they are played, to be played with --play-movie
+// and attached to a bug report, and the movie is removed when no failure is found. With
+// panic = "abort", as in the release profile, a panic of the core kills the process before
+// it can be reported, but the movie written so far is left as the reproducer: build with
+// --profile fuzz to have the panics reported.
+pub fn fuzz_input(
+    rom_path: &str,
+    palette_path: Option<&str>,
+    seed: u64,
+    frames: u64,
+    out_dir: &str,
+) -> Result<Option<FuzzFailure>, String> {
+    let rom_crc32 = capture::crc32(&fs::read(rom_path).map_err(|e| e.to_string())?);
+    let mut emulator = HeadlessEmulator::new(palette_path);
+    emulator.load_rom(rom_path)?;
+    let scratch = ScratchFile::new("fuzz");
+    let name = Path::new(rom_path)
+        .file_stem()
+        .map_or(String::from("rom"), |stem| {
+            stem.to_string_lossy().into_owned()
+        });
+    let reproducer = Path::new(out_dir)
+        .join(format!("{}-fuzz-{}.movie", name, seed))
+        .to_string_lossy()
+        .into_owned();
+    // Each frame is written to the file before it is played, so that it survives an abort
+    let mut recorder = MovieRecorder::new(&reproducer, rom_crc32, MovieStart::PowerOn)
+        .map_err(|e| format!("Cannot write the reproducer {}: {}", reproducer, e))?;
+    let mut random = Random::new(seed);
+    let mut held = [0; PORTS];
+    let mut hold_frames = [0; PORTS];
+
+    for frame in 1..=frames {
+        for port in 0..PORTS {
+            if hold_frames[port] == 0 {
+                held[port] = random.buttons();
+                hold_frames[port] = 1 + random.next() % MAX_HOLD_FRAMES;
+            }
+            hold_frames[port] -= 1;
+        }
+        recorder.frame(held);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            for (port, buttons) in held.iter().enumerate() {
+                emulator.set_input(port, *buttons)?;
+            }
+            if !emulator.step_frame_within(FRAME_CLOCKS)? {
+                return Err(String::from("no frame produced, the core is stuck"));
+            }
+            if frame % STATE_CHECK_FRAMES == 0 {
+                check_state(&mut emulator, &scratch)?;
+            }
+            Ok(())
+        }));
+        let reason = match result {
+            Ok(Ok(())) => continue,
+            Ok(Err(e)) => e,
+            Err(payload) => panic_message(payload.as_ref()),
+        };
+        return Ok(Some(FuzzFailure {
+            frame,
+            reason,
+            reproducer,
+        }));
+    }
+    drop(recorder);
+    // Nothing to reproduce
+    let _ = fs::remove_file(&reproducer);
+    Ok(None)
+}
+
+// Save the state, load it back and save it again, both states being expected identical
+fn check_state(emulator: &mut HeadlessEmulator, scratch: &ScratchFile) -> Result<(), String> {
+    let path = scratch.path().to_string_lossy().into_owned();
+    let invalid = |e: String| format!("invalid state: {}", e);
+    emulator.save_state(&path).map_err(invalid)?;
+    let saved = fs::read(&path).map_err(|e| invalid(e.to_string()))?;
+    emulator.load_state(&path).map_err(invalid)?;
+    emulator.save_state(&path).map_err(invalid)?;
+    if fs::read(&path).map_err(|e| invalid(e.to_string()))? != saved {
+        return Err(String::from("the state is not restored identically"));
+    }
+    Ok(())
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| String::from(*s))
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| String::from("unknown panic"));
+    format!("the core panicked: {}", message)
+}
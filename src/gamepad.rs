@@ -0,0 +1,320 @@
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use nesmulator_core::utils::ControllerInput;
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::{EventPump, GameControllerSubsystem, Sdl};
+
+// The NES only has two controller ports
+const MAX_GAMEPADS: usize = 2;
+const STICK_DEAD_ZONE: i16 = 16_000;
+const TRIGGER_DEAD_ZONE: i16 = 3_000;
+
+// Fast-forward speeds for a light and a full pull of the right trigger
+const MIN_FAST_FORWARD_SPEED: f64 = 2.0;
+const MAX_FAST_FORWARD_SPEED: f64 = 8.0;
+// Rewind snapshots restored per frame for a light and a full pull of the left trigger
+const MIN_REWIND_RATE: f64 = 1.0;
+const MAX_REWIND_RATE: f64 = 4.0;
+
+// Buttons to hold together to leave the kiosk mode, and for how long
+const KIOSK_EXIT_CHORD: [Button; 3] = [Button::LeftShoulder, Button::RightShoulder, Button::Start];
+const KIOSK_EXIT_TIME: Duration = Duration::from_secs(3);
+
+// Default chords, using Select (Back) as the hotkey modifier
+const DEFAULT_CHORDS: &str =
+    "save-state=back+rightshoulder,reset=back+leftshoulder,menu=back+start";
+
+// Emulator functions that can be triggered with a gamepad button chord
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HotkeyAction {
+    SaveState,
+    Save,
+    Reset,
+    ToggleDebugWindow,
+    CaptureRepro,
+    Screenshot,
+    ToggleRecording,
+    SaveReplay,
+    PushBookmark,
+    PopBookmark,
+    Retry,
+    PauseMenu,
+    Pause,
+    FrameAdvance,
+}
+
+impl HotkeyAction {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "save-state" => Some(HotkeyAction::SaveState),
+            "save" => Some(HotkeyAction::Save),
+            "reset" => Some(HotkeyAction::Reset),
+            "debug" => Some(HotkeyAction::ToggleDebugWindow),
+            "capture" => Some(HotkeyAction::CaptureRepro),
+            "screenshot" => Some(HotkeyAction::Screenshot),
+            "record" => Some(HotkeyAction::ToggleRecording),
+            "replay" => Some(HotkeyAction::SaveReplay),
+            "push-bookmark" => Some(HotkeyAction::PushBookmark),
+            "pop-bookmark" => Some(HotkeyAction::PopBookmark),
+            "retry" => Some(HotkeyAction::Retry),
+            "menu" => Some(HotkeyAction::PauseMenu),
+            "pause" => Some(HotkeyAction::Pause),
+            "frame-advance" => Some(HotkeyAction::FrameAdvance),
+            _ => None,
+        }
+    }
+}
+
+// A set of buttons that must be held together to trigger an action
+pub struct Chord {
+    buttons: Vec<Button>,
+    action: HotkeyAction,
+}
+
+// Parse chords written as "action=button+button,action=button+button",
+// button names being the ones of the SDL game controller mappings
+pub fn parse_chords(spec: Option<&str>) -> Result<Vec<Chord>, String> {
+    let mut chords = vec![];
+    for entry in spec
+        .unwrap_or(DEFAULT_CHORDS)
+        .split(',')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+    {
+        let (action, buttons) = entry.split_once('=').ok_or(format!(
+            "Invalid chord {:?}, expected action=buttons",
+            entry
+        ))?;
+        let action = HotkeyAction::from_name(action.trim())
+            .ok_or(format!("Unknown hotkey action {:?}", action))?;
+        let buttons = buttons
+            .split('+')
+            .map(|name| {
+                Button::from_string(name.trim()).ok_or(format!("Unknown gamepad button {:?}", name))
+            })
+            .collect::<Result<Vec<Button>, String>>()?;
+        chords.push(Chord { buttons, action });
+    }
+    Ok(chords)
+}
+
+pub enum GamepadEvent {
+    // New state of the buttons of the controller plugged in the given port
+    Input(usize, u8),
+    Hotkey(HotkeyAction),
+    // Speed requested by the right trigger, None when released
+    FastForward(Option<f64>),
+    // Rewind rate requested by the left trigger, None when released
+    Rewind(Option<f64>),
+    // The kiosk exit chord was held long enough
+    Exit,
+    // A button pressed or released on a gamepad, for the controller test
+    Host(String),
+}
+
+fn host_event(port: usize, button: Button, change: &str) -> GamepadEvent {
+    GamepadEvent::Host(format!("Pad {} {} {}", port + 1, button.string(), change))
+}
+
+// Gamepads are assigned to the controller ports in the order they are connected
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    event_pump: EventPump,
+    controllers: Vec<GameController>,
+    chords: Vec<Chord>,
+    kiosk: bool,
+    // Since when the kiosk exit chord is held
+    exit_held_since: Option<Instant>,
+}
+
+impl Gamepads {
+    pub fn new(sdl_context: &Sdl, chords: Vec<Chord>) -> Result<Self, String> {
+        Ok(Gamepads {
+            subsystem: sdl_context.game_controller()?,
+            event_pump: sdl_context.event_pump()?,
+            controllers: vec![],
+            chords,
+            kiosk: false,
+            exit_held_since: None,
+        })
+    }
+
+    // In kiosk mode, the emulator can only be closed by holding the exit chord
+    pub fn set_kiosk(&mut self, kiosk: bool) {
+        self.kiosk = kiosk;
+    }
+
+    pub fn connected(&self) -> usize {
+        self.controllers.len()
+    }
+
+    pub fn poll(&mut self) -> Vec<GamepadEvent> {
+        let mut events = vec![];
+        let sdl_events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in sdl_events {
+            match event {
+                Event::ControllerDeviceAdded { which, .. } => self.add(which),
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    if let Some(port) = self.port(which) {
+                        let controller = self.controllers.remove(port);
+                        info!("Gamepad {} disconnected.", controller.name());
+                        // The following gamepads moved to a new port
+                        for port in port..=self.controllers.len() {
+                            events.push(GamepadEvent::Input(port, self.input(port)));
+                        }
+                    }
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    if let Some(port) = self.port(which) {
+                        events.push(host_event(port, button, "pressed"));
+                        events.push(GamepadEvent::Input(port, self.input(port)));
+                        events.extend(
+                            self.completed_chords(port, button)
+                                .into_iter()
+                                .map(GamepadEvent::Hotkey),
+                        );
+                    }
+                }
+                Event::ControllerAxisMotion {
+                    which,
+                    axis: Axis::TriggerRight,
+                    value,
+                    ..
+                } if self.port(which).is_some() => {
+                    events.push(GamepadEvent::FastForward(trigger_speed(
+                        value,
+                        MIN_FAST_FORWARD_SPEED,
+                        MAX_FAST_FORWARD_SPEED,
+                    )));
+                }
+                Event::ControllerAxisMotion {
+                    which,
+                    axis: Axis::TriggerLeft,
+                    value,
+                    ..
+                } if self.port(which).is_some() => {
+                    events.push(GamepadEvent::Rewind(trigger_speed(
+                        value,
+                        MIN_REWIND_RATE,
+                        MAX_REWIND_RATE,
+                    )));
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    if let Some(port) = self.port(which) {
+                        events.push(host_event(port, button, "released"));
+                        events.push(GamepadEvent::Input(port, self.input(port)));
+                    }
+                }
+                Event::ControllerAxisMotion { which, .. } => {
+                    if let Some(port) = self.port(which) {
+                        events.push(GamepadEvent::Input(port, self.input(port)));
+                    }
+                }
+                _ => (),
+            }
+        }
+        if self.kiosk && self.exit_chord_held() {
+            events.push(GamepadEvent::Exit);
+        }
+        events
+    }
+
+    // Whether the kiosk exit chord has been held long enough on any gamepad
+    fn exit_chord_held(&mut self) -> bool {
+        let held = self
+            .controllers
+            .iter()
+            .any(|c| KIOSK_EXIT_CHORD.iter().all(|b| c.button(*b)));
+        if !held {
+            self.exit_held_since = None;
+            return false;
+        }
+        let since = *self.exit_held_since.get_or_insert_with(Instant::now);
+        since.elapsed() >= KIOSK_EXIT_TIME
+    }
+
+    fn add(&mut self, joystick_index: u32) {
+        if self.controllers.len() >= MAX_GAMEPADS {
+            warn!("Only {} gamepads can be used at once.", MAX_GAMEPADS);
+            return;
+        }
+        match self.subsystem.open(joystick_index) {
+            Ok(controller) => {
+                info!(
+                    "Gamepad {} connected as controller {}.",
+                    controller.name(),
+                    self.controllers.len() + 1
+                );
+                self.controllers.push(controller);
+            }
+            Err(e) => warn!("Failed to open gamepad: {}", e),
+        }
+    }
+
+    fn port(&self, instance_id: u32) -> Option<usize> {
+        self.controllers
+            .iter()
+            .position(|c| c.instance_id() == instance_id)
+    }
+
+    // NES buttons held on the gamepad of the given port
+    fn input(&self, port: usize) -> u8 {
+        let controller = match self.controllers.get(port) {
+            Some(controller) => controller,
+            None => return 0,
+        };
+        let x = controller.axis(Axis::LeftX);
+        let y = controller.axis(Axis::LeftY);
+        let mut input = 0;
+        if controller.button(Button::DPadUp) || y < -STICK_DEAD_ZONE {
+            input |= ControllerInput::Up as u8;
+        }
+        if controller.button(Button::DPadLeft) || x < -STICK_DEAD_ZONE {
+            input |= ControllerInput::Left as u8;
+        }
+        if controller.button(Button::DPadDown) || y > STICK_DEAD_ZONE {
+            input |= ControllerInput::Down as u8;
+        }
+        if controller.button(Button::DPadRight) || x > STICK_DEAD_ZONE {
+            input |= ControllerInput::Right as u8;
+        }
+        if controller.button(Button::Start) {
+            input |= ControllerInput::Start as u8;
+        }
+        if controller.button(Button::Back) {
+            input |= ControllerInput::Select as u8;
+        }
+        // Same button positions as on the NES controller
+        if controller.button(Button::B) {
+            input |= ControllerInput::A as u8;
+        }
+        if controller.button(Button::A) {
+            input |= ControllerInput::B as u8;
+        }
+        input
+    }
+
+    // Chords that are completed by pressing the given button
+    fn completed_chords(&self, port: usize, pressed: Button) -> Vec<HotkeyAction> {
+        let controller = &self.controllers[port];
+        self.chords
+            .iter()
+            .filter(|chord| {
+                chord.buttons.contains(&pressed)
+                    && chord.buttons.iter().all(|b| controller.button(*b))
+            })
+            .map(|chord| chord.action)
+            .collect()
+    }
+}
+
+// Map the trigger pressure to a speed, from min for a light pull to max for a full pull
+fn trigger_speed(value: i16, min: f64, max: f64) -> Option<f64> {
+    if value < TRIGGER_DEAD_ZONE {
+        return None;
+    }
+    let pressure = (value - TRIGGER_DEAD_ZONE) as f64 / (i16::MAX - TRIGGER_DEAD_ZONE) as f64;
+    Some(min + pressure * (max - min))
+}
@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+use log::warn;
+use nesmulator_core::utils::ARGBColor;
+
+use crate::backend::EmulatorCore;
+use crate::movie::Movie;
+use crate::osd::blend;
+
+// Opacity of the ghost over the live game
+const GHOST_ALPHA: u8 = 0x60;
+
+// A previous run played back from a movie as a translucent layer over live play.
+// The ghost is a second instance of the ROM driven by the recorded inputs,
+// it disappears when the movie is over.
+pub struct Ghost<C> {
+    live: C,
+    ghost: C,
+    ghost_frame: Box<[ARGBColor; 61_440]>,
+    movie: Option<Movie>,
+    // Frame of the movie played by the ghost
+    frame: usize,
+}
+
+impl<C: EmulatorCore> Ghost<C> {
+    pub fn set_movie(&mut self, movie: Movie) {
+        let input = movie.input(0);
+        self.movie = Some(movie);
+        self.frame = 0;
+        self.ghost_input(input);
+    }
+
+    fn ghost_input(&mut self, input: Option<u8>) {
+        match input {
+            Some(input) => {
+                if let Err(e) = self.ghost.input(0, input) {
+                    warn!("Failed to handle the ghost input: {}", e);
+                }
+            }
+            None => self.movie = None,
+        }
+    }
+}
+
+impl<C: EmulatorCore> EmulatorCore for Ghost<C> {
+    fn new(palette_path: Option<&str>, display_cpu_logs: bool) -> Self {
+        let mut ghost = C::new(palette_path, false);
+        ghost.produce_samples(false);
+        Ghost {
+            live: C::new(palette_path, display_cpu_logs),
+            ghost,
+            ghost_frame: Box::new([ARGBColor::black(); 61_440]),
+            movie: None,
+            frame: 0,
+        }
+    }
+
+    fn insert_cartdrige(&mut self, rom_path: &str) -> Result<(), String> {
+        self.live.insert_cartdrige(rom_path)?;
+        self.ghost.insert_cartdrige(rom_path)
+    }
+
+    // A movie recorded from a state must be played back from the same state
+    fn load_state(&mut self, state_path: &str, rom_path: &str) -> Result<(), String> {
+        self.live.load_state(state_path, rom_path)?;
+        self.ghost.load_state(state_path, rom_path)
+    }
+
+    fn save_state(&mut self, path: &str) -> Result<(), String> {
+        self.live.save_state(path)
+    }
+
+    fn load_save(&mut self, path: &str) -> Result<(), String> {
+        self.live.load_save(path)?;
+        self.ghost.load_save(path)
+    }
+
+    fn save(&mut self, path: &str) -> Result<(), String> {
+        self.live.save(path)
+    }
+
+    fn clock(&mut self) {
+        self.live.clock();
+        if self.movie.is_some() {
+            self.ghost.clock();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.live.reset()
+    }
+
+    fn input(&mut self, port: usize, input: u8) -> Result<(), String> {
+        self.live.input(port, input)
+    }
+
+    fn get_frame_buffer(&mut self) -> Option<[ARGBColor; 61_440]> {
+        if self.movie.is_some() {
+            if let Some(frame) = self.ghost.get_frame_buffer() {
+                *self.ghost_frame = frame;
+                self.frame += 1;
+                let input = self
+                    .movie
+                    .as_ref()
+                    .and_then(|movie| movie.input(self.frame));
+                self.ghost_input(input);
+            }
+        }
+        let mut frame = self.live.get_frame_buffer()?;
+        if self.movie.is_some() {
+            for (pixel, ghost) in frame.iter_mut().zip(self.ghost_frame.iter()) {
+                let mut ghost = *ghost;
+                ghost.alpha = GHOST_ALPHA;
+                *pixel = blend(*pixel, ghost);
+            }
+        }
+        Some(frame)
+    }
+
+    fn get_pattern_tables(&self) -> Result<[Vec<ARGBColor>; 2], String> {
+        self.live.get_pattern_tables()
+    }
+
+    fn get_palette_colors(&self) -> Result<Vec<ARGBColor>, String> {
+        self.live.get_palette_colors()
+    }
+
+    fn set_debug_palette_id(&mut self, id: u8) -> Result<(), String> {
+        self.live.set_debug_palette_id(id)
+    }
+
+    fn get_one_frame_duration(&self) -> Duration {
+        self.live.get_one_frame_duration()
+    }
+
+    // The ghost is silent
+    fn produce_samples(&mut self, produce: bool) {
+        self.live.produce_samples(produce)
+    }
+
+    fn get_samples(&mut self) -> Vec<f32> {
+        self.live.get_samples()
+    }
+}
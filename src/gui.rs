@@ -1,72 +1,410 @@
-use std::error::Error;
-
+use log::{info, warn, Level};
 use nesmulator_core::utils::ARGBColor;
-use pixels::{Pixels, SurfaceTexture};
-use winit::dpi::LogicalSize;
+use pixels::wgpu::PresentMode;
+use pixels::{Pixels, PixelsBuilder, SurfaceTexture};
+use winit::dpi::{LogicalSize, PhysicalPosition, PhysicalSize};
 use winit::event_loop::EventLoop;
-use winit::window::{Window, WindowBuilder};
+use winit::monitor::MonitorHandle;
+use winit::window::{Fullscreen, Window, WindowBuilder, WindowId};
+
+use crate::chr_history::{CHR_HISTORY_HEIGHT, CHR_HISTORY_WIDTH};
+use crate::debug_layout::{PanelLayout, Tile, TileSize};
+use crate::error::FrontendError;
+use crate::filter::VideoFilter;
+use crate::font::{self, GLYPH_HEIGHT};
+use crate::osd::argb;
+use crate::ui_events::{UiAction, UiWindow};
 
 const MAIN_WINDOW_WIDTH: u32 = 256;
 const MAIN_WINDOW_HEIGHT: u32 = 240;
+// Lines hidden at the top and bottom of the frame by most TVs
+const OVERSCAN_LINES: u32 = 8;
+// Width of the NES pixels on a TV, relative to their height
+const PIXEL_ASPECT_RATIO: f64 = 8.0 / 7.0;
 
 const DEBUG_WINDOW_WIDTH: u32 = 256;
-const DEBUG_WINDOW_HEIGHT: u32 = 240 + 2 + 128 + 2 + 6; // From top to bottom: main window | pattern table | palette
+const DEBUG_WINDOW_SCALE: u32 = 2;
+const DEBUG_SEPARATION_HEIGHT: u32 = 2;
+// Pattern tables are 16x16 tiles of 8x8 pixels
+const PATTERN_TABLE_SIZE: usize = 128;
+// The palette has 8 palettes of 4 colors, each color drawn as a square
+const PALETTE_COLORS: usize = 32;
+const PALETTE_SIZE: usize = 4;
+const PALETTE_SWATCH: usize = 6;
+const PALETTE_GAP: usize = 2;
+
+// Number of log records displayed in the log panel
+pub const LOG_PANEL_LINES: usize = 12;
+const LOG_LINE_HEIGHT: usize = GLYPH_HEIGHT + 2;
+
+// Panels that can be displayed in the debugging window, from top to bottom
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugPanel {
+    PatternTables,
+    Palette,
+    Log,
+    // Only shown when asked for, as the pattern tables are compared at each frame
+    ChrHistory,
+}
+
+impl DebugPanel {
+    pub const DEFAULT: [DebugPanel; 3] = [
+        DebugPanel::PatternTables,
+        DebugPanel::Palette,
+        DebugPanel::Log,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "patterns" => Some(DebugPanel::PatternTables),
+            "palette" => Some(DebugPanel::Palette),
+            "log" => Some(DebugPanel::Log),
+            "chr-history" => Some(DebugPanel::ChrHistory),
+            _ => None,
+        }
+    }
+
+    // Size of the images of the panel, e.g. the two pattern tables
+    fn tile_sizes(&self) -> Vec<TileSize> {
+        match self {
+            DebugPanel::PatternTables => {
+                vec![TileSize::new(PATTERN_TABLE_SIZE, PATTERN_TABLE_SIZE, 1); 2]
+            }
+            DebugPanel::Palette => {
+                vec![TileSize::new(PALETTE_SIZE, 1, PALETTE_SWATCH); PALETTE_COLORS / PALETTE_SIZE]
+            }
+            DebugPanel::Log => vec![TileSize::new(
+                DEBUG_WINDOW_WIDTH as usize,
+                LOG_PANEL_LINES * LOG_LINE_HEIGHT + 1,
+                1,
+            )],
+            DebugPanel::ChrHistory => {
+                vec![TileSize::new(CHR_HISTORY_WIDTH, CHR_HISTORY_HEIGHT, 1)]
+            }
+        }
+    }
+
+    fn layout(&self) -> PanelLayout {
+        PanelLayout {
+            width: DEBUG_WINDOW_WIDTH as usize,
+            gap: match self {
+                DebugPanel::Palette => PALETTE_GAP,
+                _ => 0,
+            },
+        }
+    }
+
+    fn height(&self) -> u32 {
+        self.layout().height(&self.tile_sizes()) as u32
+    }
+}
+
+// How the game frame is scaled to the main window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DisplayOptions {
+    // Scale by a whole number only, with black borders around the frame
+    pub integer_scaling: bool,
+    // Display the pixels 8:7 wide as on a TV
+    pub aspect_correction: bool,
+    // Hide the lines at the top and bottom of the frame that TVs did not show
+    pub crop_overscan: bool,
+    pub fullscreen: bool,
+    // Average each frame with the previous one, as the phosphors of a CRT
+    pub frame_blending: bool,
+    // Size of the window at startup, in NES screens
+    pub window_scale: u32,
+}
+
+// Display options that can be switched while playing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DisplayOption {
+    IntegerScaling,
+    AspectCorrection,
+    CropOverscan,
+    Fullscreen,
+    FrameBlending,
+}
+
+fn debug_window_height(panels: &[DebugPanel]) -> u32 {
+    let separations = panels.len().saturating_sub(1) as u32 * DEBUG_SEPARATION_HEIGHT;
+    panels.iter().map(|p| p.height()).sum::<u32>() + separations
+}
 
 #[derive(Debug)]
 pub struct Gui {
     main_window: Window,
     main_pixels: Pixels,
+    // The debugging view has its own window so that it never changes the game size
+    debug_window: Window,
+    debug_pixels: Pixels,
+    debug_panels: Vec<DebugPanel>,
     pub debug: bool,
+    // A minimized window has a zero-size surface that cannot be rendered to
+    minimized: bool,
+    debug_minimized: bool,
+    display: DisplayOptions,
+    surface_size: (u32, u32),
+    // Source pixel of each column and row of the main buffer, when the frame is scaled
+    // on the CPU instead of by pixels
+    scaled_columns: Vec<usize>,
+    scaled_rows: Vec<usize>,
+    filter: VideoFilter,
 }
 
 impl Gui {
-    pub fn new(main_event_loop: &EventLoop<()>) -> Self {
-        let window_size = LogicalSize::new(MAIN_WINDOW_WIDTH * 2, MAIN_WINDOW_HEIGHT * 2);
+    // With vrr, frames are presented as soon as they are ready for variable refresh rate monitors
+    pub fn new(
+        main_event_loop: &EventLoop<()>,
+        debug_panels: Vec<DebugPanel>,
+        debug: bool,
+        vrr: bool,
+        monitor: Option<usize>,
+        display: DisplayOptions,
+        filter: VideoFilter,
+    ) -> Result<Self, FrontendError> {
+        let window_size = LogicalSize::new(
+            MAIN_WINDOW_WIDTH * display.window_scale,
+            MAIN_WINDOW_HEIGHT * display.window_scale,
+        );
         let buffer_size = LogicalSize::new(MAIN_WINDOW_WIDTH, MAIN_WINDOW_HEIGHT);
         let main_window = WindowBuilder::new()
             .with_title("Nesmulator")
             .with_inner_size(window_size)
             .with_min_inner_size(buffer_size)
             .build(main_event_loop)
-            .expect("Cannot create main window");
+            .map_err(video_error)?;
 
         let surface_texture =
             SurfaceTexture::new(window_size.width, window_size.height, &main_window);
+        let present_mode = if vrr {
+            PresentMode::Mailbox
+        } else {
+            PresentMode::Fifo
+        };
         let main_pixels =
-            Pixels::new(buffer_size.width, buffer_size.height, surface_texture).unwrap();
+            PixelsBuilder::new(buffer_size.width, buffer_size.height, surface_texture)
+                .present_mode(present_mode)
+                .build()
+                .map_err(video_error)?;
 
-        Gui {
+        let debug_window_height = debug_window_height(&debug_panels);
+        let debug_window_size = LogicalSize::new(
+            DEBUG_WINDOW_WIDTH * DEBUG_WINDOW_SCALE,
+            debug_window_height * DEBUG_WINDOW_SCALE,
+        );
+        let debug_buffer_size = LogicalSize::new(DEBUG_WINDOW_WIDTH, debug_window_height);
+        let debug_window = WindowBuilder::new()
+            .with_title("Nesmulator - Debug")
+            .with_inner_size(debug_window_size)
+            .with_min_inner_size(debug_buffer_size)
+            .with_visible(debug)
+            .build(main_event_loop)
+            .map_err(video_error)?;
+
+        let surface_texture = SurfaceTexture::new(
+            debug_window_size.width,
+            debug_window_size.height,
+            &debug_window,
+        );
+        let debug_pixels = Pixels::new(
+            debug_buffer_size.width,
+            debug_buffer_size.height,
+            surface_texture,
+        )
+        .map_err(video_error)?;
+
+        if let Some(index) = monitor {
+            match main_window.available_monitors().nth(index) {
+                Some(monitor) => move_to_monitor(&main_window, &monitor),
+                None => warn!("Monitor {} not found, using the default one.", index),
+            }
+        }
+
+        let surface_size = main_window.inner_size();
+        let mut gui = Gui {
             main_window,
             main_pixels,
-            debug: false,
+            debug_window,
+            debug_pixels,
+            debug_panels,
+            debug,
+            minimized: false,
+            debug_minimized: false,
+            display,
+            surface_size: (surface_size.width, surface_size.height),
+            scaled_columns: vec![],
+            scaled_rows: vec![],
+            filter,
+        };
+        if display.fullscreen {
+            gui.main_window
+                .set_fullscreen(Some(Fullscreen::Borderless(None)));
+        }
+        gui.update_layout();
+        Ok(gui)
+    }
+
+    // Switch a display option, returns whether it is now enabled
+    pub fn toggle_display(&mut self, option: DisplayOption) -> bool {
+        let enabled = match option {
+            DisplayOption::IntegerScaling => &mut self.display.integer_scaling,
+            DisplayOption::AspectCorrection => &mut self.display.aspect_correction,
+            DisplayOption::CropOverscan => &mut self.display.crop_overscan,
+            DisplayOption::Fullscreen => &mut self.display.fullscreen,
+            DisplayOption::FrameBlending => &mut self.display.frame_blending,
+        };
+        *enabled = !*enabled;
+        let enabled = *enabled;
+        match option {
+            // The window is resized, which updates the layout
+            DisplayOption::Fullscreen => self
+                .main_window
+                .set_fullscreen(enabled.then(|| Fullscreen::Borderless(None))),
+            // Applied to the next frames
+            DisplayOption::FrameBlending => (),
+            _ => self.update_layout(),
         }
+        enabled
+    }
+
+    pub fn frame_blending(&self) -> bool {
+        self.display.frame_blending
     }
 
-    pub fn toggle_debugging(&mut self) {
-        if self.debug {
-            let width = MAIN_WINDOW_WIDTH;
-            let height = MAIN_WINDOW_HEIGHT;
-            self.main_pixels.resize_buffer(width, height);
-            self.debug = false;
+    pub fn set_filter(&mut self, filter: VideoFilter) {
+        if self.filter != filter {
+            self.filter = filter;
+            self.update_layout();
+        }
+    }
+
+    pub fn hide_cursor(&self) {
+        self.main_window.set_cursor_visible(false);
+    }
+
+    // Lines of the frame hidden at the top
+    pub fn hidden_top_lines(&self) -> usize {
+        if self.display.crop_overscan {
+            OVERSCAN_LINES as usize
         } else {
-            let width = DEBUG_WINDOW_WIDTH;
-            let height = DEBUG_WINDOW_HEIGHT;
-            self.main_pixels.resize_buffer(width, height);
-            self.debug = true;
+            0
         }
     }
 
-    fn add_pattern_tables(
-        &mut self,
-        buffer: &mut [ARGBColor],
-        pattern_table_0: &[ARGBColor],
-        pattern_table_1: &[ARGBColor],
-    ) {
-        for (i, color) in pattern_table_0.iter().enumerate() {
-            buffer[(i / 128) * 256 + i % 128] = *color;
+    // Size the main buffer for the display options and the window size. pixels only scales
+    // by whole numbers, so the other scalings are done on the CPU into a buffer of the
+    // size of the scaled frame, which pixels then displays as is. Filters also need this
+    // buffer, to draw their effects finer than the NES pixels.
+    fn update_layout(&mut self) {
+        let first_row = self.hidden_top_lines();
+        let source_width = MAIN_WINDOW_WIDTH as f64;
+        let source_height = (MAIN_WINDOW_HEIGHT - 2 * self.hidden_top_lines() as u32) as f64;
+        let pixel_aspect_ratio = if self.display.aspect_correction {
+            PIXEL_ASPECT_RATIO
+        } else {
+            1.0
+        };
+
+        let (width, height) = if self.display.integer_scaling
+            && !self.display.aspect_correction
+            && self.filter == VideoFilter::None
+        {
+            self.scaled_columns.clear();
+            self.scaled_rows.clear();
+            (source_width as u32, source_height as u32)
+        } else {
+            let (surface_width, surface_height) = self.surface_size;
+            let scale = (surface_width as f64 / (source_width * pixel_aspect_ratio))
+                .min(surface_height as f64 / source_height);
+            let scale = if self.display.integer_scaling {
+                scale.floor().max(1.0)
+            } else {
+                scale
+            };
+            let width = ((source_width * pixel_aspect_ratio * scale).round() as u32).max(1);
+            let height = ((source_height * scale).round() as u32).max(1);
+            self.scaled_columns = (0..width as usize)
+                .map(|x| x * MAIN_WINDOW_WIDTH as usize / width as usize)
+                .collect();
+            self.scaled_rows = (0..height as usize)
+                .map(|y| first_row + y * source_height as usize / height as usize)
+                .collect();
+            (width, height)
+        };
+        self.main_pixels.resize_buffer(width, height);
+    }
+
+    // Highest refresh rate of the video modes matching the resolution of the monitor,
+    // winit does not report the rate of the current mode
+    pub fn monitor_refresh_rate(&self) -> Option<f64> {
+        let monitor = self.main_window.current_monitor()?;
+        let size = monitor.size();
+        monitor
+            .video_modes()
+            .filter(|mode| mode.size() == size)
+            .map(|mode| mode.refresh_rate())
+            .max()
+            .map(f64::from)
+    }
+
+    // Resize the main window to a whole number of NES screens, unless it is fullscreen
+    pub fn set_window_scale(&mut self, scale: u32) {
+        self.display.window_scale = scale;
+        if !self.display.fullscreen {
+            self.main_window.set_inner_size(LogicalSize::new(
+                MAIN_WINDOW_WIDTH * scale,
+                MAIN_WINDOW_HEIGHT * scale,
+            ));
+        }
+    }
+
+    // Move the main window to the center of the next available monitor
+    pub fn move_to_next_monitor(&self) {
+        let monitors: Vec<MonitorHandle> = self.main_window.available_monitors().collect();
+        if monitors.len() < 2 {
+            return;
+        }
+        let current = self
+            .main_window
+            .current_monitor()
+            .and_then(|current| monitors.iter().position(|m| *m == current))
+            .unwrap_or(0);
+        let next = (current + 1) % monitors.len();
+        move_to_monitor(&self.main_window, &monitors[next]);
+        info!("Window moved to monitor {}.", next);
+    }
+
+    // Apply a recorded window manager event to one of the windows
+    pub fn replay(&self, window: UiWindow, action: &UiAction) {
+        let window = match window {
+            UiWindow::Main => &self.main_window,
+            UiWindow::Debug => &self.debug_window,
+        };
+        match *action {
+            UiAction::Resized(0, _) | UiAction::Resized(_, 0) => window.set_minimized(true),
+            UiAction::Resized(width, height) => {
+                window.set_minimized(false);
+                window.set_inner_size(PhysicalSize::new(width, height));
+            }
+            UiAction::Moved(x, y) => window.set_outer_position(PhysicalPosition::new(x, y)),
+            UiAction::Focused(true) => window.focus_window(),
+            // A window cannot be unfocused, this happens when focusing the other one
+            UiAction::Focused(false) => (),
         }
-        for (i, color) in pattern_table_1.iter().enumerate() {
-            buffer[(i / 128) * 256 + i % 128 + 128] = *color;
+    }
+
+    pub fn debug_window_id(&self) -> WindowId {
+        self.debug_window.id()
+    }
+
+    pub fn has_debug_panel(&self, panel: DebugPanel) -> bool {
+        self.debug_panels.contains(&panel)
+    }
+
+    pub fn set_debugging(&mut self, debug: bool) {
+        if self.debug != debug {
+            self.debug = debug;
+            self.debug_window.set_visible(debug);
         }
     }
 
@@ -76,70 +414,189 @@ impl Gui {
         }
     }
 
-    fn add_palette(&mut self, buffer: &mut [ARGBColor], palette: &[ARGBColor]) {
-        for (offset, color) in palette.iter().enumerate() {
-            for i in 0..6 {
-                for j in 0..6 {
-                    let index = (offset * 6) + (((offset % 4) == 0) as usize) * 2 + i + j * 256;
-                    buffer[index] = *color;
-                }
-            }
+    // Recent log records, colored by level
+    fn log_pixels(&self, log: &[(Level, String)]) -> Vec<ARGBColor> {
+        let width = DEBUG_WINDOW_WIDTH as usize;
+        let mut buffer = vec![ARGBColor::black(); DebugPanel::Log.height() as usize * width];
+        for (i, (level, message)) in log.iter().enumerate() {
+            let color = match level {
+                Level::Error => argb(0xFF, 0xFF, 0x50, 0x50),
+                Level::Warn => argb(0xFF, 0xFF, 0xD0, 0x40),
+                Level::Info => argb(0xFF, 0xFF, 0xFF, 0xFF),
+                Level::Debug | Level::Trace => argb(0xFF, 0xA0, 0xA0, 0xA0),
+            };
+            font::draw_text(
+                &mut buffer,
+                width,
+                1,
+                1 + i * LOG_LINE_HEIGHT,
+                message,
+                color,
+            );
         }
+        buffer
     }
 
+    #[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
     pub fn debug(
         &mut self,
         pattern_table_0: &[ARGBColor],
         pattern_table_1: &[ARGBColor],
         palette: &[ARGBColor],
+        log: &[(Level, String)],
+        chr_history: Option<&[ARGBColor]>,
     ) {
-        const BUFFER_SIZE: usize =
-            ((DEBUG_WINDOW_HEIGHT - MAIN_WINDOW_HEIGHT) * DEBUG_WINDOW_WIDTH) as usize;
-        let mut buffer = [ARGBColor::black(); BUFFER_SIZE];
+        let width = DEBUG_WINDOW_WIDTH as usize;
+        let mut buffer =
+            vec![ARGBColor::black(); debug_window_height(&self.debug_panels) as usize * width];
         let mut offset = 0;
-        self.add_separation(&mut buffer[offset..offset + 512]);
-        offset += 512;
-        self.add_pattern_tables(
-            &mut buffer[offset..offset + 32768],
-            pattern_table_0,
-            pattern_table_1,
-        );
-        offset += 32768;
-        self.add_separation(&mut buffer[offset..offset + 512]);
-        offset += 512;
-        self.add_palette(&mut buffer[offset..offset + 1536], palette);
+        for (i, panel) in self.debug_panels.clone().iter().enumerate() {
+            if i > 0 {
+                let size = DEBUG_SEPARATION_HEIGHT as usize * width;
+                self.add_separation(&mut buffer[offset..offset + size]);
+                offset += size;
+            }
+            let size = panel.height() as usize * width;
+            let panel_buffer = &mut buffer[offset..offset + size];
+            let layout = panel.layout();
+            match panel {
+                DebugPanel::PatternTables => layout.draw(
+                    panel_buffer,
+                    &[
+                        Tile::new(pattern_table_0, PATTERN_TABLE_SIZE, 1),
+                        Tile::new(pattern_table_1, PATTERN_TABLE_SIZE, 1),
+                    ],
+                ),
+                DebugPanel::Palette => {
+                    let tiles: Vec<Tile> = palette
+                        .chunks(PALETTE_SIZE)
+                        .map(|colors| Tile::new(colors, colors.len(), PALETTE_SWATCH))
+                        .collect();
+                    layout.draw(panel_buffer, &tiles);
+                }
+                DebugPanel::Log => {
+                    let pixels = self.log_pixels(log);
+                    layout.draw(panel_buffer, &[Tile::new(&pixels, width, 1)]);
+                }
+                DebugPanel::ChrHistory => {
+                    if let Some(history) = chr_history {
+                        layout.draw(panel_buffer, &[Tile::new(history, CHR_HISTORY_WIDTH, 1)]);
+                    }
+                }
+            }
+            offset += size;
+        }
 
         self.update_debug_buffer(&buffer);
     }
 
     pub fn update_main_buffer(&mut self, buffer: &[ARGBColor; 61_440]) {
-        for (i, color) in buffer.iter().enumerate() {
-            self.update_pixel(i, color);
+        let width = MAIN_WINDOW_WIDTH as usize;
+        if self.scaled_rows.is_empty() {
+            let first_row = self.hidden_top_lines();
+            let last_row = MAIN_WINDOW_HEIGHT as usize - first_row;
+            copy_to_frame(
+                self.main_pixels.get_frame(),
+                &buffer[first_row * width..last_row * width],
+            );
+            return;
         }
+
+        let frame = self.main_pixels.get_frame();
+        let row_size = self.scaled_columns.len() * 4;
+        for (y, row) in self.scaled_rows.iter().enumerate() {
+            // Rows scaled from the same source row are copied
+            if y > 0 && self.scaled_rows[y - 1] == *row {
+                frame.copy_within((y - 1) * row_size..y * row_size, y * row_size);
+                continue;
+            }
+            let source = &buffer[row * width..(row + 1) * width];
+            let pixels = frame[y * row_size..(y + 1) * row_size].chunks_exact_mut(4);
+            for (pixel, column) in pixels.zip(self.scaled_columns.iter()) {
+                let color = source[*column];
+                pixel.copy_from_slice(&[color.red, color.green, color.blue, color.alpha]);
+            }
+        }
+        self.filter.apply(
+            frame,
+            self.scaled_columns.len(),
+            self.scaled_rows.len(),
+            width,
+        );
     }
 
     fn update_debug_buffer(&mut self, buffer: &[ARGBColor]) {
-        let offset = (MAIN_WINDOW_WIDTH * MAIN_WINDOW_HEIGHT) as usize;
-        for (i, color) in buffer.iter().enumerate() {
-            self.update_pixel(offset + i, color);
-        }
+        let frame = self.debug_pixels.get_frame();
+        copy_to_frame(frame, buffer);
+        let width = DEBUG_WINDOW_WIDTH as usize;
+        self.filter.apply(frame, width, buffer.len() / width, width);
     }
 
-    pub fn redraw(&mut self) {
+    pub fn redraw(&self) {
         self.main_window.request_redraw();
     }
 
-    pub fn render(&mut self) -> Result<(), Box<dyn Error>> {
-        self.main_pixels.render()?;
+    #[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
+    pub fn render(&mut self) -> Result<(), FrontendError> {
+        if !self.minimized {
+            self.main_pixels.render().map_err(video_error)?;
+        }
+        if self.debug && !self.debug_minimized {
+            self.debug_pixels.render().map_err(video_error)?;
+        }
         Ok(())
     }
 
+    // Present a black frame in the main window, between two game frames with black frame
+    // insertion
+    pub fn render_black(&mut self) -> Result<(), FrontendError> {
+        if self.minimized {
+            return Ok(());
+        }
+        for pixel in self.main_pixels.get_frame().chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[0, 0, 0, 0xFF]);
+        }
+        self.main_pixels.render().map_err(video_error)
+    }
+
+    pub fn is_minimized(&self) -> bool {
+        self.minimized
+    }
+
     pub fn resize(&mut self, width: u32, height: u32) {
-        self.main_pixels.resize_surface(width, height);
+        self.minimized = width == 0 || height == 0;
+        if !self.minimized {
+            self.main_pixels.resize_surface(width, height);
+            self.surface_size = (width, height);
+            self.update_layout();
+        }
+    }
+
+    pub fn resize_debug(&mut self, width: u32, height: u32) {
+        self.debug_minimized = width == 0 || height == 0;
+        if !self.debug_minimized {
+            self.debug_pixels.resize_surface(width, height);
+        }
     }
+}
+
+fn video_error(e: impl ToString) -> FrontendError {
+    FrontendError::Video(e.to_string())
+}
+
+fn move_to_monitor(window: &Window, monitor: &MonitorHandle) {
+    let position = monitor.position();
+    let monitor_size = monitor.size();
+    let window_size = window.outer_size();
+    window.set_outer_position(PhysicalPosition::new(
+        position.x + (monitor_size.width.saturating_sub(window_size.width) / 2) as i32,
+        position.y + (monitor_size.height.saturating_sub(window_size.height) / 2) as i32,
+    ));
+}
 
-    fn update_pixel(&mut self, offset: usize, color: &ARGBColor) {
-        let pixel = &mut self.main_pixels.get_frame()[offset * 4..offset * 4 + 4];
+// Copy ARGB colors to a RGBA pixels frame
+fn copy_to_frame(frame: &mut [u8], buffer: &[ARGBColor]) {
+    for (pixel, color) in frame.chunks_exact_mut(4).zip(buffer.iter()) {
         pixel[0] = color.red;
         pixel[1] = color.green;
         pixel[2] = color.blue;
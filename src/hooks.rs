@@ -0,0 +1,104 @@
+use std::process::{Command, Stdio};
+use std::thread;
+
+use log::{debug, warn};
+
+// Events of the emulator that can run a command, see --event-hook
+#[derive(Clone, Copy, PartialEq)]
+pub enum HookEvent {
+    GameLoaded,
+    StateSaved,
+    StateLoaded,
+    GameSaved,
+    BugCaptured,
+}
+
+impl HookEvent {
+    const ALL: [HookEvent; 5] = [
+        HookEvent::GameLoaded,
+        HookEvent::StateSaved,
+        HookEvent::StateLoaded,
+        HookEvent::GameSaved,
+        HookEvent::BugCaptured,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::GameLoaded => "game-loaded",
+            HookEvent::StateSaved => "state-saved",
+            HookEvent::StateLoaded => "state-loaded",
+            HookEvent::GameSaved => "game-saved",
+            HookEvent::BugCaptured => "bug-captured",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        HookEvent::ALL.iter().copied().find(|e| e.name() == name)
+    }
+}
+
+// Shell commands run on the events of the emulator, e.g. to call a webhook with curl
+#[derive(Default)]
+pub struct EventHooks {
+    hooks: Vec<(HookEvent, String)>,
+    rom_path: String,
+}
+
+impl EventHooks {
+    // Parse hooks written as "event=command"
+    pub fn parse(specs: &[&str], rom_path: &str) -> Result<Self, String> {
+        let mut hooks = vec![];
+        for spec in specs {
+            let (name, command) = spec
+                .split_once('=')
+                .ok_or(format!("Invalid hook {:?}, expected event=command", spec))?;
+            let event = HookEvent::from_name(name.trim())
+                .ok_or(format!("Unknown event {:?}", name.trim()))?;
+            hooks.push((event, String::from(command.trim())));
+        }
+        Ok(EventHooks {
+            hooks,
+            rom_path: String::from(rom_path),
+        })
+    }
+
+    // The ROM given to the commands, once another game is loaded
+    pub fn set_rom_path(&mut self, rom_path: &str) {
+        self.rom_path = String::from(rom_path);
+    }
+
+    // Run the commands of an event without waiting for them. The event, the ROM and the
+    // file written or read if any are given in the NESMULATOR_* environment variables.
+    pub fn fire(&self, event: HookEvent, path: Option<&str>) {
+        for (_, command) in self.hooks.iter().filter(|(e, _)| *e == event) {
+            debug!("Running the {} hook: {}", event.name(), command);
+            let mut shell = shell(command);
+            shell
+                .env("NESMULATOR_EVENT", event.name())
+                .env("NESMULATOR_ROM", &self.rom_path)
+                .env("NESMULATOR_PATH", path.unwrap_or(""))
+                .stdin(Stdio::null());
+            match shell.spawn() {
+                // Wait for the command in the background, to not leave a zombie process
+                Ok(mut child) => {
+                    thread::spawn(move || child.wait());
+                }
+                Err(e) => warn!("Cannot run the {} hook: {}", event.name(), e),
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.args(["/C", command]);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.args(["-c", command]);
+    shell
+}
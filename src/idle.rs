@@ -0,0 +1,48 @@
+use nesmulator_core::utils::ARGBColor;
+
+// Identical frames without input before the game is considered idle, half a second
+const IDLE_FRAMES: u32 = 30;
+// Speed of the emulation while the game is idle
+pub const IDLE_SPEED: f64 = 8.0;
+
+// Detect a game waiting on a still screen for a number of frames, e.g. before a title
+// screen or between two levels: the frames stay identical while no button is held
+#[derive(Default)]
+pub struct IdleDetector {
+    last_hash: u64,
+    identical_frames: u32,
+}
+
+impl IdleDetector {
+    // Returns whether the game is idle after this frame
+    pub fn frame(&mut self, frame: &[ARGBColor], input: bool) -> bool {
+        let hash = hash_frame(frame);
+        if input || hash != self.last_hash {
+            self.identical_frames = 0;
+        } else {
+            self.identical_frames = self.identical_frames.saturating_add(1);
+        }
+        self.last_hash = hash;
+        self.is_idle()
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.identical_frames >= IDLE_FRAMES
+    }
+
+    pub fn reset(&mut self) {
+        self.identical_frames = 0;
+    }
+}
+
+// FNV-1a hash of the colors of the frame
+fn hash_frame(frame: &[ARGBColor]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for color in frame.iter() {
+        for byte in [color.red, color.green, color.blue] {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
@@ -0,0 +1,177 @@
+// Number of controller ports of the NES
+pub const PORTS: usize = 2;
+
+// Devices and programs driving the controller ports, from the highest priority to the lowest
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SourceKind {
+    Movie,
+    Gamepad,
+    Keyboard,
+}
+
+impl SourceKind {
+    // Programs replace the players even when the inputs are combined
+    fn is_program(&self) -> bool {
+        *self == SourceKind::Movie
+    }
+}
+
+// How a port driven by several sources takes its inputs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergePolicy {
+    // Only the source of highest priority is used, e.g. the gamepad over the keyboard
+    Priority,
+    // The buttons held on every device driving the port are combined
+    Combine,
+}
+
+impl MergePolicy {
+    const ALL: [MergePolicy; 2] = [MergePolicy::Priority, MergePolicy::Combine];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MergePolicy::Priority => "priority",
+            MergePolicy::Combine => "combine",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        MergePolicy::ALL.iter().copied().find(|p| p.name() == name)
+    }
+}
+
+// Inputs of the controller ports coming from a device or a program, e.g. a movie, the
+// network or a script
+pub trait InputSource {
+    fn kind(&self) -> SourceKind;
+
+    // Buttons held on the port, None when the source does not drive it
+    fn input(&self, port: usize) -> Option<u8>;
+
+    // Sources driven by events, like the keyboard and the gamepads, are given the buttons
+    // held on a port, None releasing it
+    fn set_input(&mut self, _port: usize, _input: Option<u8>) {}
+
+    // Called at the end of each frame, returns false once the source is over
+    fn next_frame(&mut self) -> bool {
+        true
+    }
+}
+
+// Buttons last reported by the events of a device
+pub struct HeldButtons {
+    kind: SourceKind,
+    ports: [Option<u8>; PORTS],
+}
+
+impl HeldButtons {
+    pub fn new(kind: SourceKind) -> Self {
+        HeldButtons {
+            kind,
+            ports: [None; PORTS],
+        }
+    }
+}
+
+impl InputSource for HeldButtons {
+    fn kind(&self) -> SourceKind {
+        self.kind
+    }
+
+    fn input(&self, port: usize) -> Option<u8> {
+        self.ports[port]
+    }
+
+    fn set_input(&mut self, port: usize, input: Option<u8>) {
+        self.ports[port] = input;
+    }
+}
+
+// Sources of the inputs, each port following them as told by the merge policy
+pub struct InputRouter {
+    // Sorted by priority, at most one of each kind
+    sources: Vec<Box<dyn InputSource + Send>>,
+    policy: MergePolicy,
+}
+
+impl InputRouter {
+    // The keyboard and the gamepads are always there, the other sources are added later
+    pub fn new(policy: MergePolicy) -> Self {
+        InputRouter {
+            sources: vec![
+                Box::new(HeldButtons::new(SourceKind::Gamepad)),
+                Box::new(HeldButtons::new(SourceKind::Keyboard)),
+            ],
+            policy,
+        }
+    }
+
+    // Add a source, replacing the one of the same kind if any
+    pub fn add(&mut self, source: Box<dyn InputSource + Send>) {
+        let kind = source.kind();
+        self.remove(kind);
+        let index = self
+            .sources
+            .iter()
+            .position(|s| s.kind() > kind)
+            .unwrap_or(self.sources.len());
+        self.sources.insert(index, source);
+    }
+
+    // Returns whether there was a source of this kind
+    pub fn remove(&mut self, kind: SourceKind) -> bool {
+        let count = self.sources.len();
+        self.sources.retain(|s| s.kind() != kind);
+        self.sources.len() != count
+    }
+
+    // Whether the inputs of the players are ignored, e.g. while a movie plays
+    pub fn is_driven_by_program(&self) -> bool {
+        self.sources.iter().any(|s| s.kind().is_program())
+    }
+
+    pub fn input_of(&self, kind: SourceKind, port: usize) -> Option<u8> {
+        self.sources
+            .iter()
+            .find(|s| s.kind() == kind)
+            .and_then(|s| s.input(port))
+    }
+
+    pub fn set_input(&mut self, kind: SourceKind, port: usize, input: Option<u8>) {
+        if let Some(source) = self.sources.iter_mut().find(|s| s.kind() == kind) {
+            source.set_input(port, input);
+        }
+    }
+
+    // Inputs of each port, released when no source drives it
+    pub fn inputs(&self) -> [u8; PORTS] {
+        let mut inputs = [0; PORTS];
+        for (port, input) in inputs.iter_mut().enumerate() {
+            let mut driving = self
+                .sources
+                .iter()
+                .filter_map(|s| s.input(port).map(|input| (s.kind(), input)));
+            *input = match (self.policy, driving.next()) {
+                (_, None) => 0,
+                (MergePolicy::Priority, Some((_, input))) => input,
+                (MergePolicy::Combine, Some((kind, input))) if kind.is_program() => input,
+                (MergePolicy::Combine, Some((_, input))) => {
+                    driving.fold(input, |all, (_, input)| all | input)
+                }
+            };
+        }
+        inputs
+    }
+
+    // Go to the next frame, returns the sources that are over, which are removed
+    pub fn next_frame(&mut self) -> Vec<SourceKind> {
+        let mut over = vec![];
+        for source in self.sources.iter_mut() {
+            if !source.next_frame() {
+                over.push(source.kind());
+            }
+        }
+        self.sources.retain(|s| !over.contains(&s.kind()));
+        over
+    }
+}
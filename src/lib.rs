@@ -1,77 +1,1452 @@
-use std::sync::mpsc::Receiver;
-use std::thread;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
 use std::time::Instant;
 use std::{process::exit, time::Duration};
 
 use env_logger::Env;
 use log::{error, info, warn};
-use nesmulator_core::{nes::NES, Config};
-use sdl2::audio::AudioSpecDesired;
+use nesmulator_core::utils::{ARGBColor, ControllerInput};
+use winit::event::Event;
 use winit::event_loop::EventLoop;
+use winit::window::WindowId;
 
-use crate::gui::Gui;
+pub use crate::bindings::{
+    default_config_path, pressed_key, BindingWizard, Hotkey, KeyBindings, SetupWizard,
+    DEFAULT_WINDOW_SCALE, STATE_SLOTS,
+};
+pub use crate::cheats::{CheatList, RomPatch};
+pub use crate::controls::Turbo;
+pub use crate::emulator::HeadlessEmulator;
+pub use crate::environment::{Environment, Step};
+pub use crate::error::FrontendError;
+pub use crate::filter::VideoFilter;
+pub use crate::fuzz::{fuzz_input, FuzzFailure};
+pub use crate::gui::DisplayOption;
+pub use crate::input::MergePolicy;
+pub use crate::notes::Rating;
+pub use crate::osd::OsdLevel;
+pub use crate::pacing::FrameSleep;
+pub use crate::palette_capture::capture_palettes;
+pub use crate::paths::describe_paths;
+pub use crate::rom::{is_rom, PickerInput, Playlist, RomPaths, RomPicker};
+pub use crate::state_diff::diff_states;
+pub use crate::states::{export_states_page, list_states};
+pub use crate::sync::SyncStrategy;
+pub use crate::thread_tuning::ThreadTuning;
 
+use crate::attract::AttractMode;
+use crate::audio::AudioOutput;
+use crate::backend::{Core, EmulatorCore};
+use crate::capture::ReproContext;
+use crate::chr_history::ChrHistory;
+use crate::control::{ControlCommand, ControlRequest, ControlServer};
+use crate::controls::GameControls;
+use crate::frame::{DebugFrame, Frame, FrameReceiver, FrameSender};
+use crate::gamepad::{GamepadEvent, Gamepads, HotkeyAction};
+use crate::ghost::Ghost;
+use crate::gui::{DebugPanel, DisplayOptions, Gui, LOG_PANEL_LINES};
+use crate::hooks::{EventHooks, HookEvent};
+use crate::idle::{IdleDetector, IDLE_SPEED};
+use crate::input::{InputRouter, SourceKind, PORTS};
+use crate::log_panel::{BufferedLogger, LogBuffer, LogFilter};
+use crate::menu::{MenuAction, MenuSettings, PauseMenu, KEYBOARD};
+use crate::mini_profiler::{MiniProfiler, Subsystem};
+use crate::movie::{Movie, MoviePlayer, MovieRecorder, MovieStart};
+use crate::notes::NotesDatabase;
+use crate::osd::{Osd, OsdLayer};
+use crate::pacing::{FrameTiming, LagChange, LagMonitor, PacingReport};
+use crate::parental::PlayTimer;
+use crate::passthrough::MoviePassthrough;
+use crate::profiling::TraceCapture;
+use crate::race::{Race, RaceAudio};
+use crate::recorder::VideoRecorder;
+use crate::reference::ReferenceOverlay;
+use crate::replay::ReplayBuffer;
+use crate::rewind::RewindBuffer;
+use crate::save_guard::SaveGuard;
+use crate::schedule::EmulationTime;
+use crate::shared_frame::SharedFrame;
+use crate::snapshot::ScratchFile;
+use crate::speed::{MAX_SPEED, MIN_SPEED, MUTED_SPEED};
+use crate::theme::{SharedTheme, Theme, ThemeWatcher};
+use crate::timer::TimeTrial;
+use crate::ui_events::{UiEventRecorder, UiEventReplay, UiWindow};
+
+mod assets;
+mod attract;
+mod audio;
+mod backend;
+mod bindings;
+mod capture;
+mod cheats;
+mod chr_history;
+mod control;
+mod controls;
+mod debug_layout;
+mod emulator;
+mod environment;
+mod error;
+mod filter;
+mod font;
+mod frame;
+mod fuzz;
+mod gamepad;
+mod ghost;
 mod gui;
+mod hex;
+mod hooks;
+mod idle;
+mod input;
+mod log_panel;
+mod menu;
+mod mini_profiler;
+mod movie;
+mod notes;
+mod osd;
+mod pacing;
+mod palette_capture;
+mod parental;
+mod passthrough;
+mod paths;
+mod profiling;
+#[cfg(feature = "python")]
+mod python;
+mod race;
+mod recorder;
+mod reference;
+mod replay;
+mod rewind;
+mod rom;
+mod save_guard;
+mod schedule;
+mod shared_frame;
+mod snapshot;
+mod speed;
+mod speed_indicator;
+mod state_diff;
+mod states;
+mod sync;
+mod theme;
+mod thread_tuning;
+mod timer;
+mod ui_events;
 
 const DEFAULT_DEBUG_LEVEL: &str = "info";
 const MIN_AUDIO_QUEUE_SIZE: u32 = 4 * 4410;
+// Maximum speed change applied to match the monitor refresh rate
+const MAX_REFRESH_RATE_ADJUSTMENT: f64 = 0.01;
+// Speed of the games set to PAL timing, from the NTSC frame rate to the PAL one
+const PAL_SPEED: f64 = 50.0070 / 60.0988;
+// Largest gap between the refresh rate of the monitor and a multiple of 60 Hz for black
+// frame insertion, in frames of the monitor per NES frame
+const BLACK_FRAME_RATE_TOLERANCE: f64 = 0.05;
+// Time between two redraws of the pause menu, or of the frame while paused
+const MENU_REFRESH_TIME: Duration = Duration::from_millis(16);
+// Frames sent between two refreshes of the debugging window, 15 times per second as the
+// pattern tables and the palette rarely change
+const DEBUG_REFRESH_FRAMES: u32 = 4;
+// Speed of the fast-forward key, running the emulation as fast as possible
+const UNCAPPED_SPEED: f64 = f64::INFINITY;
+// Bookmarks kept in memory, the oldest being dropped past it
+const BOOKMARK_DEPTH: usize = 100;
+// Clocks between two checks of the messages of the main thread, one scanline of PPU cycles,
+// so that the channel is not polled at each clock
+const MESSAGE_POLL_CLOCKS: u64 = 341;
 
 // Different messages that can be thrown at the NES by the event loop
 #[derive(PartialEq)]
 pub enum Message {
     Input(usize, u8),
     Reset,
-    DrawFrame,
+    // Play another ROM, dropped on the window or chosen in the picker
+    LoadRom(RomPaths),
+    // Play another game of the playlist, writing the battery save and the state of the
+    // current one first, and resuming the new one where it was left if it was played before
+    SwitchGame(RomPaths, bool),
     ChangePaletteId(u8),
+    // Show the pattern tables seen offset changes before or after the ones shown
+    BrowseChrHistory(isize),
     ChangeEmulationSpeed(f64),
+    // Go to the next speed step from the current speed, faster or slower
+    SpeedUp,
+    SpeedDown,
+    // Volume in percent
+    ChangeVolume(u8),
     SaveState(String),
+    LoadState(String),
     Save(String),
-    ResizeWindow(u32, u32),
+    CaptureRepro(String),
+    // Play the ROM patched by the enabled cheats
+    ApplyCheats(Vec<RomPatch>),
+    // Write the current frame as the cover of the game
+    SetCover(String),
+    // Write the current frame to a PNG file in the given folder
+    Screenshot(String),
+    // Start recording a video in the given folder, or stop the recording
+    ToggleRecording(String),
+    // Write the last seconds of play to a movie and a state in the given folder
+    SaveReplay(String),
+    // Keep the current state on the stack of bookmarks, or go back to the last one and
+    // remove it, to try again from there
+    PushBookmark,
+    PopBookmark,
+    RateCompatibility(Rating),
+    // Go back in time while the rewind key is held
+    StartRewind,
+    StopRewind,
+    TogglePause,
+    // Fast-forward the still screens of the game or not, remembered for the ROM
+    ToggleSkipIdle,
+    // Run exactly one frame while paused
+    FrameAdvance,
+    // Run as fast as possible while the fast-forward key is held
+    FastForward(bool),
+    // Run again the last message that failed
+    Retry,
+    TogglePauseMenu,
+    // A key pressed or released, for the controller test of the pause menu
+    HostInput(String),
     ToggleDebugWindow,
+    // Show the time spent in each part of the frames or hide it
+    ToggleProfiler,
+    CycleFilter,
+    WindowMinimized(bool),
     CloseApp,
+    // Ctrl+C, SIGTERM or the console being closed, the game is saved before exiting
+    Terminate,
 }
 
 pub struct NESConfig<'a> {
-    pub rom_path: &'a str,
+    // None until a ROM is dropped on the window or chosen in the picker
+    pub rom: Option<&'a RomPaths>,
     pub palette_path: Option<&'a str>,
-    pub save_path: &'a str,
-    pub state_path: &'a str,
     pub load_state: bool,
     pub debug_level: Option<&'a str>,
     pub display_cpu_logs: bool,
+    pub enable_audio: bool,
+    // Volume in percent
+    pub volume: u8,
+    pub pacing_report_path: Option<&'a str>,
+    pub trace_path: Option<&'a str>,
+    pub debug_panels: Option<&'a str>,
+    pub gamepad_hotkeys: Option<&'a str>,
+    pub pause_when_minimized: bool,
+    // Fast-forward the still screens of the games without a setting of their own
+    pub skip_idle: bool,
+    // How the keyboard and a gamepad driving the same port are merged
+    pub input_merge: MergePolicy,
+    // Turbo of the games without a setting of their own
+    pub turbo: Turbo,
+    pub refresh_rate: Option<&'a str>,
+    pub vrr: bool,
+    pub black_frame_insertion: bool,
+    pub thread_tuning: ThreadTuning,
+    pub sync: SyncStrategy,
+    pub frame_sleep: FrameSleep,
+    pub monitor: Option<usize>,
+    pub record_ui_events_path: Option<&'a str>,
+    pub replay_ui_events_path: Option<&'a str>,
+    pub reset_at: Option<&'a str>,
+    pub log_panel_filter: Option<&'a str>,
+    pub notes_path: &'a str,
+    pub config_path: &'a str,
+    pub add_note: Option<&'a str>,
+    pub race: bool,
+    pub race_audio: Option<&'a str>,
+    pub record_movie_path: Option<&'a str>,
+    pub play_movie_path: Option<&'a str>,
+    // Keys pressed for the buttons of the first controller played by the movie, see
+    // --passthrough
+    pub passthrough_keys: Option<Vec<(u8, &'static str)>>,
+    pub ghost_path: Option<&'a str>,
+    pub timer: Option<&'a str>,
+    pub reference_path: Option<&'a str>,
+    pub shared_frame_path: Option<&'a str>,
+    pub deterministic: bool,
+    pub rewind_seconds: Option<u64>,
+    pub rewind_interval: Option<u32>,
+    pub replay_seconds: Option<u64>,
+    pub control_address: Option<&'a str>,
+    pub event_hooks: Vec<&'a str>,
+    pub integer_scaling: bool,
+    pub aspect_correction: bool,
+    pub crop_overscan: bool,
+    pub frame_blending: bool,
+    pub fullscreen: bool,
+    // Size of the window at startup, in NES screens
+    pub window_scale: u32,
+    pub filter: VideoFilter,
+    pub theme: Option<&'a str>,
+    pub kiosk: bool,
+    pub attract_movies: Vec<&'a str>,
+    pub attract_idle_seconds: Option<u64>,
+    // Name of the key of the retry hotkey, shown when a save fails
+    pub retry_key: &'static str,
+}
+
+// Handle on the emulation running in its own thread, presenting its frames in the GUI
+pub struct EmulatorHandle {
+    thread: Option<JoinHandle<()>>,
+    gui: Gui,
+    frames: FrameReceiver,
+    osd: Osd,
+    osd_layer: OsdLayer,
+    last_frame: Box<[ARGBColor; 61_440]>,
+    // Frame received before the last one, blended with it with --frame-blending
+    previous_frame: Box<[ARGBColor; 61_440]>,
+    // Black frames presented after each frame with --black-frame-insertion, and the ones
+    // left to present after the last frame
+    black_frames: u32,
+    black_frames_left: u32,
+    ui_event_recorder: Option<UiEventRecorder>,
+    ui_event_replay: Option<UiEventReplay>,
+    log_buffer: LogBuffer,
+    log_filter: LogFilter,
+    reference: Option<ReferenceOverlay>,
+    theme: SharedTheme,
+    theme_watcher: Option<ThemeWatcher>,
+    trace: Option<TraceCapture>,
+    // Whether the last frame could not be rendered, the error being logged once until a
+    // frame is rendered again
+    render_failed: bool,
+}
+
+impl EmulatorHandle {
+    // Display the latest frame produced by the emulation thread, if any
+    #[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
+    pub fn present(&mut self) {
+        if self.black_frames_left > 0 {
+            self.black_frames_left -= 1;
+            let result = self.gui.render_black();
+            self.check_render(result);
+            if self.black_frames_left > 0 {
+                self.gui.redraw();
+            }
+            return;
+        }
+
+        let received = self.frames.try_recv();
+        let new_frame = received.is_some();
+        if let Some(frame) = received {
+            self.previous_frame = std::mem::replace(&mut self.last_frame, frame.main);
+            self.gui.set_debugging(frame.debug_open);
+            self.gui.set_filter(frame.filter);
+            if let Some(debug) = &frame.debug {
+                let log = self.log_buffer.recent(&self.log_filter, LOG_PANEL_LINES);
+                self.gui.debug(
+                    &debug.pattern_table_0,
+                    &debug.pattern_table_1,
+                    &debug.palette,
+                    &log,
+                    debug.chr_history.as_deref(),
+                );
+            }
+        }
+
+        if let Some(result) = self
+            .theme_watcher
+            .as_mut()
+            .and_then(|w| w.reload_if_changed())
+        {
+            match result {
+                Ok(()) => {
+                    info!("Theme reloaded.");
+                    self.osd.notify(OsdLevel::Info, "Theme reloaded");
+                }
+                Err(e) => {
+                    error!("Cannot reload the theme: {}", e);
+                    self.osd.notify(OsdLevel::Error, "Cannot reload the theme");
+                }
+            }
+        }
+
+        // Draw the reference screenshot and the on-screen display over a copy of the frame
+        self.osd_layer.update();
+        let reference = self.reference.as_ref().filter(|r| r.is_active());
+        let blending = self.gui.frame_blending();
+        if self.osd_layer.is_empty() && reference.is_none() && !blending {
+            self.gui.update_main_buffer(&self.last_frame);
+        } else {
+            let mut frame = self.last_frame.clone();
+            if blending {
+                filter::blend_frames(&mut frame[..], &self.previous_frame[..]);
+            }
+            if let Some(reference) = reference {
+                reference.draw(&mut frame[..]);
+            }
+            // Keep the on-screen display below the cropped overscan
+            let first_pixel = self.gui.hidden_top_lines() * 256;
+            self.osd_layer
+                .draw(&mut frame[first_pixel..], 256, &self.theme.get());
+            self.gui.update_main_buffer(&frame);
+        }
+
+        let result = self.gui.render();
+        self.check_render(result);
+        if new_frame && self.black_frames > 0 {
+            self.black_frames_left = self.black_frames;
+            self.gui.redraw();
+        }
+    }
+
+    // Log a render failure once rather than at each frame, until rendering works again
+    fn check_render(&mut self, result: Result<(), FrontendError>) {
+        match result {
+            Ok(()) if self.render_failed => {
+                info!("Rendering again.");
+                self.render_failed = false;
+            }
+            Err(e) if !self.render_failed => {
+                error!("Failed to render frame: {}", e);
+                self.render_failed = true;
+            }
+            _ => (),
+        }
+    }
+
+    // Whether the emulation thread stopped by itself, e.g. from the pause menu
+    pub fn emulation_stopped(&self) -> bool {
+        self.frames.is_closed()
+    }
+
+    // Inputs of the ROM picker pressed on the gamepads since the last call
+    pub fn picker_inputs(&self) -> Vec<PickerInput> {
+        self.frames.take_picker_inputs()
+    }
+
+    pub fn set_window_scale(&mut self, scale: u32) {
+        self.gui.set_window_scale(scale);
+    }
+
+    // Called when the emulation thread sent a new frame
+    pub fn redraw(&self) {
+        self.gui.redraw();
+    }
+
+    // Returns whether the main window is now minimized
+    pub fn resize(&mut self, width: u32, height: u32) -> bool {
+        self.gui.resize(width, height);
+        self.gui.is_minimized()
+    }
+
+    pub fn resize_debug(&mut self, width: u32, height: u32) {
+        self.gui.resize_debug(width, height);
+    }
+
+    pub fn notify(&self, level: OsdLevel, text: impl Into<String>) {
+        self.osd.notify(level, text);
+    }
+
+    // Display a message until it is replaced or cleared with None
+    pub fn set_prompt(&mut self, prompt: Option<String>) {
+        self.osd_layer.set_prompt(prompt);
+    }
+
+    // Switch between comparing the frame with the reference screenshot in blink mode,
+    // in difference mode or not at all
+    pub fn cycle_reference_mode(&mut self) {
+        match self.reference.as_mut() {
+            Some(reference) => {
+                let name = reference.cycle_mode();
+                self.osd.notify(OsdLevel::Info, name);
+            }
+            None => self.osd.notify(
+                OsdLevel::Warning,
+                "No reference screenshot, see --reference",
+            ),
+        }
+    }
+
+    pub fn toggle_display(&mut self, option: DisplayOption) {
+        let enabled = self.gui.toggle_display(option);
+        let name = match option {
+            DisplayOption::IntegerScaling => "Integer scaling",
+            DisplayOption::AspectCorrection => "8:7 aspect ratio",
+            DisplayOption::CropOverscan => "Overscan cropping",
+            DisplayOption::FrameBlending => "Frame blending",
+            DisplayOption::Fullscreen => return,
+        };
+        let state = if enabled { "on" } else { "off" };
+        self.osd
+            .notify(OsdLevel::Info, format!("{} {}", name, state));
+    }
+
+    pub fn move_to_next_monitor(&self) {
+        self.gui.move_to_next_monitor();
+    }
+
+    // Write the window events to the file given with --record-ui-events
+    pub fn record_ui_event(&mut self, event: &Event<()>) {
+        if let Event::WindowEvent { window_id, event } = event {
+            let window = if self.is_debug_window(*window_id) {
+                UiWindow::Debug
+            } else {
+                UiWindow::Main
+            };
+            if let Some(recorder) = &mut self.ui_event_recorder {
+                recorder.record(window, event);
+            }
+        }
+    }
+
+    // Apply the recorded window events whose time has come, returns the time of the next one
+    pub fn replay_ui_events(&mut self) -> Option<Instant> {
+        let replay = self.ui_event_replay.as_mut()?;
+        for (window, action) in replay.due() {
+            self.gui.replay(window, &action);
+        }
+        let next = replay.next_time();
+        if next.is_none() {
+            info!("UI events replay finished.");
+            self.ui_event_replay = None;
+        }
+        next
+    }
+
+    pub fn is_debug_window(&self, window_id: WindowId) -> bool {
+        self.gui.debug_window_id() == window_id
+    }
+
+    // Wait for the emulation thread to finish writing its files
+    pub fn join(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        // The trace is written when the capture is dropped
+        self.trace = None;
+    }
 }
 
-pub fn run(nes_config: NESConfig, event_loop: &EventLoop<()>, rx: Receiver<Message>) {
-    let config = Config::new(nes_config.palette_path, nes_config.display_cpu_logs);
+pub fn run(
+    nes_config: NESConfig,
+    event_loop: &EventLoop<()>,
+    rx: Receiver<Message>,
+) -> Result<EmulatorHandle, FrontendError> {
+    let log_buffer = init_env_logger(nes_config.debug_level);
+    log_paths(&nes_config);
+    let trace = nes_config.trace_path.and_then(profiling::start_capture);
 
+    let (debug_panels, open_debug) = parse_debug_panels(nes_config.debug_panels);
+    let gui = Gui::new(
+        event_loop,
+        debug_panels,
+        open_debug,
+        nes_config.vrr,
+        nes_config.monitor,
+        DisplayOptions {
+            integer_scaling: nes_config.integer_scaling,
+            aspect_correction: nes_config.aspect_correction,
+            crop_overscan: nes_config.crop_overscan,
+            frame_blending: nes_config.frame_blending,
+            fullscreen: nes_config.fullscreen || nes_config.kiosk,
+            window_scale: nes_config.window_scale,
+        },
+        nes_config.filter,
+    )?;
+    if nes_config.kiosk {
+        gui.hide_cursor();
+    }
+    let (osd, osd_layer) = osd::osd();
+    let (sender, frames) = frame::frame_channel(event_loop.create_proxy());
+    let black_frames = if nes_config.black_frame_insertion {
+        black_frame_count(&gui, nes_config.vrr, &osd)
+    } else {
+        0
+    };
+
+    let ui_event_recorder =
+        nes_config
+            .record_ui_events_path
+            .and_then(|path| match UiEventRecorder::new(path) {
+                Ok(recorder) => Some(recorder),
+                Err(e) => {
+                    error!("Cannot record UI events to {}: {}", path, e);
+                    osd.notify(OsdLevel::Error, "Cannot record UI events");
+                    None
+                }
+            });
+    let ui_event_replay =
+        nes_config
+            .replay_ui_events_path
+            .and_then(|path| match UiEventReplay::load(path) {
+                Ok(replay) => Some(replay),
+                Err(e) => {
+                    error!("Cannot replay UI events from {}: {}", path, e);
+                    osd.notify(OsdLevel::Error, "Cannot replay UI events");
+                    None
+                }
+            });
+
+    let reference = nes_config
+        .reference_path
+        .and_then(|path| match ReferenceOverlay::load(path) {
+            Ok(reference) => Some(reference),
+            Err(e) => {
+                error!("Cannot load the reference screenshot {}: {}", path, e);
+                osd.notify(OsdLevel::Error, "Cannot load the reference");
+                None
+            }
+        });
+
+    let (theme, theme_watcher) = load_theme(nes_config.theme, &osd);
+    let thread = if nes_config.race {
+        let (nes, state) = create_race(&nes_config, osd.clone())?;
+        spawn_nes(nes, state, &nes_config, &gui, theme.clone(), sender, rx)
+    } else if let Some(path) = nes_config.ghost_path {
+        let (nes, state) = create_ghost(&nes_config, path, osd.clone())?;
+        spawn_nes(nes, state, &nes_config, &gui, theme.clone(), sender, rx)
+    } else {
+        let (nes, state) = create_nes::<Core>(&nes_config, osd.clone())?;
+        spawn_nes(nes, state, &nes_config, &gui, theme.clone(), sender, rx)
+    };
+    Ok(EmulatorHandle {
+        thread: Some(thread),
+        gui,
+        frames,
+        osd,
+        osd_layer,
+        last_frame: Box::new([ARGBColor::black(); 61_440]),
+        previous_frame: Box::new([ARGBColor::black(); 61_440]),
+        black_frames,
+        black_frames_left: 0,
+        ui_event_recorder,
+        ui_event_replay,
+        log_buffer,
+        log_filter: LogFilter::parse(nes_config.log_panel_filter),
+        reference,
+        theme,
+        theme_watcher,
+        trace,
+        render_failed: false,
+    })
+}
+
+// Black frames to present after each frame to reduce motion blur, one at 120 Hz, three at
+// 240 Hz, none when the refresh rate of the monitor is not a multiple of the NES one
+fn black_frame_count(gui: &Gui, vrr: bool, osd: &Osd) -> u32 {
+    let refresh_rate = gui.monitor_refresh_rate();
+    let monitor_frames = refresh_rate.map_or(0.0, |rate| rate / 60.0);
+    let count = monitor_frames.round();
+    if vrr || count < 2.0 || (monitor_frames - count).abs() > BLACK_FRAME_RATE_TOLERANCE {
+        let reason = match refresh_rate {
+            _ if vrr => String::from("frames are not paced by the monitor with --vrr"),
+            Some(rate) => format!("the monitor refreshes at {} Hz", rate),
+            None => String::from("the refresh rate of the monitor is unknown"),
+        };
+        warn!(
+            "Black frame insertion needs a monitor refreshing at 120 Hz, 180 Hz or 240 Hz, {}.",
+            reason
+        );
+        osd.notify(OsdLevel::Warning, "Black frame insertion unavailable");
+        return 0;
+    }
+    info!(
+        "Black frame insertion: {} black frames after each frame at {} Hz.",
+        count - 1.0,
+        refresh_rate.unwrap_or_default()
+    );
+    count as u32 - 1
+}
+
+// Bundled theme by name, or theme file reloaded when it is modified
+fn load_theme(spec: Option<&str>, osd: &Osd) -> (SharedTheme, Option<ThemeWatcher>) {
+    let theme = SharedTheme::default();
+    let spec = match spec {
+        Some(spec) => spec,
+        None => return (theme, None),
+    };
+    if let Some(named) = Theme::named(spec) {
+        theme.set(named);
+        return (theme, None);
+    }
+    match Theme::load(Path::new(spec)) {
+        Ok(loaded) => {
+            info!("Theme {} successfully loaded.", spec);
+            theme.set(loaded);
+        }
+        Err(e) => {
+            error!("Cannot load the theme {}: {}", spec, e);
+            osd.notify(OsdLevel::Error, "Cannot load the theme");
+        }
+    }
+    // The file is watched even if invalid, to apply it once fixed
+    let watcher = ThemeWatcher::new(spec, theme.clone());
+    (theme, Some(watcher))
+}
+
+// Spawn a thread to run the NES ROM and give it a channel receiver to handle events from the main loop
+fn spawn_nes<C: EmulatorCore + 'static>(
+    mut nes: C,
+    mut state: EmulationState,
+    nes_config: &NESConfig,
+    gui: &Gui,
+    theme: SharedTheme,
+    sender: FrameSender,
+    rx: Receiver<Message>,
+) -> JoinHandle<()> {
+    state.debug_default = gui.debug;
+    state.debug = rom_debug_window(&state.rom_path, &state.notes_path).unwrap_or(gui.debug);
+    if gui.has_debug_panel(DebugPanel::ChrHistory) {
+        state.chr_history = Some(ChrHistory::new());
+    }
+    state.theme = theme;
+    match nes_config.refresh_rate {
+        Some("auto") => match gui.monitor_refresh_rate() {
+            Some(rate) => sync_to_refresh_rate(&nes, &mut state, rate),
+            None => warn!("Cannot detect the monitor refresh rate."),
+        },
+        Some(rate) => match rate.parse::<f64>() {
+            Ok(rate) if rate > 0.0 => sync_to_refresh_rate(&nes, &mut state, rate),
+            _ => warn!("Invalid refresh rate: {:?}, ignoring it.", rate),
+        },
+        None => (),
+    }
+    let tuning = nes_config.thread_tuning;
+    thread::spawn(move || {
+        tuning.apply();
+        run_nes(&mut nes, Some(sender), &mut state, rx)
+    })
+}
+
+// Run the NES ROM on the current thread without opening any window
+pub fn run_headless(nes_config: NESConfig, rx: Receiver<Message>) -> Result<(), FrontendError> {
     init_env_logger(nes_config.debug_level);
+    log_paths(&nes_config);
+    let _trace = nes_config.trace_path.and_then(profiling::start_capture);
+    nes_config.thread_tuning.apply();
+
+    let (osd, _) = osd::osd();
+    if nes_config.race {
+        let (mut nes, mut state) = create_race(&nes_config, osd)?;
+        run_nes(&mut nes, None, &mut state, rx);
+    } else if let Some(path) = nes_config.ghost_path {
+        let (mut nes, mut state) = create_ghost(&nes_config, path, osd)?;
+        run_nes(&mut nes, None, &mut state, rx);
+    } else {
+        let (mut nes, mut state) = create_nes::<Core>(&nes_config, osd)?;
+        run_nes(&mut nes, None, &mut state, rx);
+    }
+    Ok(())
+}
+
+// Two instances of the ROM side by side, see --race
+fn create_race(
+    nes_config: &NESConfig,
+    osd: Osd,
+) -> Result<(Race<Core>, EmulationState), FrontendError> {
+    let audio = match nes_config.race_audio {
+        Some(name) => RaceAudio::from_name(name).unwrap_or_else(|| {
+            error!(
+                "Invalid race audio: {:?}, value must be left, right or mix.",
+                name
+            );
+            exit(1);
+        }),
+        None => RaceAudio::Mix,
+    };
+    if nes_config.ghost_path.is_some() {
+        warn!("Ghosts are not available in race mode, ignoring the ghost.");
+    }
+    let (mut nes, mut state) = create_nes::<Race<Core>>(nes_config, osd)?;
+    nes.set_audio(audio);
+    if state.rewind.take().is_some() {
+        warn!("Rewind is not available in race mode, ignoring it.");
+    }
+    if state.attract.take().is_some() {
+        warn!("Demos are not available in race mode, ignoring them.");
+    }
+    info!("Race mode: two instances side by side.");
+    Ok((nes, state))
+}
+
+// Play back the movie of a previous run over live play, see --ghost
+fn create_ghost(
+    nes_config: &NESConfig,
+    path: &str,
+    osd: Osd,
+) -> Result<(Ghost<Core>, EmulationState), FrontendError> {
+    let movie = Movie::load(path).unwrap_or_else(|e| {
+        error!("Cannot load the ghost movie {}: {}", path, e);
+        exit(1);
+    });
+    let (mut nes, mut state) = create_nes::<Ghost<Core>>(nes_config, osd)?;
+    nes.set_movie(movie);
+    if state.rewind.take().is_some() {
+        warn!("Rewind is not available with a ghost, ignoring it.");
+    }
+    if state.attract.take().is_some() {
+        warn!("Demos are not available with a ghost, ignoring them.");
+    }
+    info!("Ghost {} successfully loaded.", path);
+    Ok((nes, state))
+}
 
-    let mut gui = Gui::new(event_loop);
+fn create_nes<C: EmulatorCore>(
+    nes_config: &NESConfig,
+    osd: Osd,
+) -> Result<(C, EmulationState), FrontendError> {
+    // The core is given the builtin palette instead of an invalid one
+    if let Some(Err(e)) = nes_config.palette_path.map(assets::check_palette) {
+        error!("Invalid palette: {}", e);
+        osd.notify(OsdLevel::Error, "Invalid palette, using the builtin one");
+    }
 
     // Instantiate a NES and connect a ROM file
-    let mut nes = NES::from_config(config);
-    if nes_config.load_state {
-        if let Err(e) = nes.load_state(&nes_config.state_path, nes_config.rom_path) {
-            error!("Error parsing state: {}", e);
+    let mut nes = C::new(nes_config.palette_path, nes_config.display_cpu_logs);
+    match nes_config.rom {
+        Some(rom) => {
+            if nes_config.load_state {
+                nes.load_state(&rom.state, &rom.rom).map_err(|e| {
+                    FrontendError::Rom(format!("Cannot load the state {}: {}", rom.state, e))
+                })?;
+                info!("State {} successfully loaded.", rom.state);
+            } else {
+                nes.insert_cartdrige(&rom.rom).map_err(|e| {
+                    FrontendError::Rom(format!("Cannot load the ROM {}: {}", rom.rom, e))
+                })?;
+            }
+            info!("ROM {} successfully loaded.", rom.rom);
+
+            // Load a save for the current cartridge, if any
+            if nes.load_save(&rom.save).is_ok() {
+                info!("Save successfully loaded.");
+            }
+        }
+        None => info!("No ROM given, waiting for one to be dropped on the window."),
+    }
+
+    let mut state = EmulationState::new(&nes, nes_config, osd);
+    if let Some(rom) = nes_config.rom {
+        report_fallback_dir(rom, &state.osd);
+        show_rom_notes(nes_config, &state.osd);
+        state.skip_idle =
+            rom_skip_idle(&state.rom_path, &state.notes_path).unwrap_or(nes_config.skip_idle);
+        state.controls = rom_controls(&state.rom_path, &state.notes_path, nes_config.turbo);
+        state.pal_timing = rom_pal_timing(&state.rom_path, &state.notes_path).unwrap_or(false);
+        update_target_time(&nes, &mut state);
+    }
+    if nes_config.deterministic {
+        info!("Deterministic mode: inputs are applied between frames.");
+        if nes_config.sync != SyncStrategy::VideoMaster {
+            warn!("The audio is not synchronized in deterministic mode.");
+        }
+    }
+    if let Some(spec) = nes_config.timer {
+        match timer::parse_triggers(spec) {
+            Ok(triggers) => {
+                let best_time = rom_best_time(&state.rom_path, &state.notes_path);
+                if let Some(best_time) = best_time {
+                    info!("Best time: {}.", timer::format_time(best_time));
+                }
+                state.timer = Some(TimeTrial::new(
+                    triggers,
+                    nes.get_one_frame_duration(),
+                    best_time,
+                ));
+            }
+            Err(e) => {
+                error!("Invalid timer triggers: {}", e);
+                exit(1);
+            }
+        }
+    }
+    if let Some(path) = nes_config.shared_frame_path {
+        match SharedFrame::new(path) {
+            Ok(shared_frame) => state.shared_frame = Some(shared_frame),
+            Err(e) => {
+                error!("Cannot share the frames in {}: {}", path, e);
+                state.osd.notify(OsdLevel::Error, "Cannot share the frames");
+            }
+        }
+    }
+    if let Some(address) = nes_config.control_address {
+        match ControlServer::bind(address) {
+            Ok(control) => state.control = Some(control),
+            Err(e) => {
+                error!("Cannot open the control socket on {}: {}", address, e);
+                state
+                    .osd
+                    .notify(OsdLevel::Error, "Cannot open the control socket");
+            }
+        }
+    }
+    if let Some(seconds) = nes_config.rewind_seconds {
+        state.rewind = Some(RewindBuffer::new(
+            Duration::from_secs(seconds),
+            nes.get_one_frame_duration(),
+            nes_config
+                .rewind_interval
+                .unwrap_or(rewind::DEFAULT_INTERVAL),
+        ));
+    }
+    if let Some(seconds) = nes_config.replay_seconds {
+        let frames = seconds as f64 / nes.get_one_frame_duration().as_secs_f64();
+        state.replay = Some(ReplayBuffer::new(frames as usize));
+    }
+    if let Some(path) = nes_config.record_movie_path {
+        let recorder = rom_identity(&state.rom_path)
+            .and_then(|(crc32, _)| MovieRecorder::new(path, crc32, movie_start(nes_config)?));
+        match recorder {
+            Ok(recorder) => state.movie_recorder = Some(recorder),
+            Err(e) => {
+                error!("Cannot record the movie to {}: {}", path, e);
+                state.osd.notify(OsdLevel::Error, "Cannot record the movie");
+            }
+        }
+    }
+    if let Some(spec) = nes_config.reset_at {
+        match schedule::parse_times(spec) {
+            Ok(times) => state.scheduled_resets = times,
+            Err(e) => {
+                error!("Invalid reset times: {}", e);
+                exit(1);
+            }
+        }
+    }
+    match EventHooks::parse(&nes_config.event_hooks, &state.rom_path) {
+        Ok(hooks) => state.hooks = hooks,
+        Err(e) => {
+            error!("Invalid event hooks: {}", e);
+            exit(1);
+        }
+    }
+    if !nes_config.attract_movies.is_empty() {
+        let movies = nes_config
+            .attract_movies
+            .iter()
+            .map(|path| {
+                Movie::load(path).unwrap_or_else(|e| {
+                    error!("Cannot load the demo movie {}: {}", path, e);
+                    exit(1);
+                })
+            })
+            .collect();
+        let idle_time = nes_config
+            .attract_idle_seconds
+            .map(Duration::from_secs)
+            .unwrap_or(attract::DEFAULT_IDLE_TIME);
+        match AttractMode::new(&mut nes, movies, idle_time) {
+            Ok(attract) => state.attract = Some(attract),
+            Err(e) => {
+                error!("Cannot prepare the demos: {}", e);
+                state
+                    .osd
+                    .notify(OsdLevel::Error, "Cannot prepare the demos");
+            }
+        }
+    }
+    if let Some(path) = nes_config.play_movie_path {
+        let movie = Movie::load(path).unwrap_or_else(|e| {
+            error!("Cannot load the movie {}: {}", path, e);
+            exit(1);
+        });
+        if let Ok((crc32, _)) = rom_identity(&state.rom_path) {
+            let start = movie_start(nes_config).unwrap_or(MovieStart::Unknown);
+            let problems = movie.check(crc32, start);
+            for problem in problems.iter() {
+                warn!("The movie may not play back as recorded: {}.", problem);
+            }
+            if !problems.is_empty() {
+                state
+                    .osd
+                    .notify(OsdLevel::Warning, "The movie may not play back as recorded");
+            }
+        }
+        info!("Playing the movie {} ({} frames).", path, movie.len());
+        state.inputs.add(Box::new(MoviePlayer::new(movie)));
+        apply_inputs(&mut nes, &mut state);
+        state.passthrough = nes_config
+            .passthrough_keys
+            .clone()
+            .map(MoviePassthrough::new);
+        if state.rewind.take().is_some() {
+            warn!("Rewind is not available while playing a movie, ignoring it.");
+        }
+        if state.attract.take().is_some() {
+            warn!("Demos are not available while playing a movie, ignoring them.");
+        }
+    }
+    match PlayTimer::new(&parental::default_path()) {
+        Ok(Some(timer)) => {
+            if let Some(remaining) = timer.remaining() {
+                info!("{} minutes of play left today.", remaining.as_secs() / 60);
+            }
+            state.time_up = timer.is_time_up();
+            state.play_timer = Some(timer);
+        }
+        Ok(None) => (),
+        Err(e) => {
+            error!("Invalid play time limits: {}", e);
             exit(1);
         }
-        info!("State {} successfully loaded.", nes_config.state_path);
-    } else if let Err(e) = nes.insert_cartdrige(nes_config.rom_path) {
-        error!("Error parsing ROM: {}", e);
-        exit(1);
     }
-    info!("ROM {} successfully loaded.", nes_config.rom_path);
+    if state.rom_loaded {
+        state.hooks.fire(HookEvent::GameLoaded, None);
+    }
+    Ok((nes, state))
+}
 
-    // Load a save for the current cartridge, if any
-    if nes.load_save(nes_config.save_path).is_ok() {
-        info!("Save successfully loaded.");
+// The saves of a ROM in a read-only folder go to the data folder
+fn report_fallback_dir(rom: &RomPaths, osd: &Osd) {
+    if rom.save_fallback {
+        let dir = Path::new(&rom.save)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+        warn!(
+            "The folder of the ROM is read-only, the states and the save are written to {}.",
+            dir.display()
+        );
+        osd.notify(
+            OsdLevel::Warning,
+            "Read-only ROM folder, saving to data folder",
+        );
+    }
+}
+
+// Record the note given on the command line, then display the known issues of the ROM
+fn show_rom_notes(nes_config: &NESConfig, osd: &Osd) {
+    let rom_path = nes_config.rom.map_or("", |rom| rom.rom.as_str());
+    let (crc32, rom_name) = match rom_identity(rom_path) {
+        Ok(identity) => identity,
+        Err(e) => {
+            warn!("Cannot read the ROM to look for notes: {}", e);
+            return;
+        }
+    };
+    let mut notes = match NotesDatabase::load(nes_config.notes_path) {
+        Ok(notes) => notes,
+        Err(e) => {
+            warn!("Invalid notes file: {}", e);
+            osd.notify(OsdLevel::Warning, "Invalid notes file");
+            return;
+        }
+    };
+
+    if let Some(text) = nes_config.add_note {
+        notes.add_note(crc32, &rom_name, text);
+        match notes.save(nes_config.notes_path) {
+            Ok(()) => info!("Note saved to {}.", nes_config.notes_path),
+            Err(e) => {
+                error!("Failed to save the note: {}", e);
+                osd.notify(OsdLevel::Error, "Failed to save the note");
+            }
+        }
+    }
+
+    for note in notes.notes(crc32) {
+        info!("Known issue: {}", note);
+        osd.notify(OsdLevel::Info, format!("Note: {}", note));
+    }
+}
+
+// CRC32 and name of a ROM, identifying it in the notes database
+fn rom_identity(rom_path: &str) -> std::io::Result<(u32, String)> {
+    let rom = std::fs::read(rom_path)?;
+    let rom_name = Path::new(rom_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Ok((capture::crc32(&rom), rom_name))
+}
+
+fn rom_best_time(rom_path: &str, notes_path: &str) -> Option<Duration> {
+    let (crc32, _) = rom_identity(rom_path).ok()?;
+    NotesDatabase::load(notes_path).ok()?.best_time(crc32)
+}
+
+fn save_best_time(rom_path: &str, notes_path: &str, time: Duration) -> Result<(), String> {
+    let (crc32, rom_name) = rom_identity(rom_path).map_err(|e| e.to_string())?;
+    let mut notes = NotesDatabase::load(notes_path)?;
+    notes.set_best_time(crc32, &rom_name, time);
+    notes.save(notes_path).map_err(|e| e.to_string())
+}
+
+fn rom_skip_idle(rom_path: &str, notes_path: &str) -> Option<bool> {
+    let (crc32, _) = rom_identity(rom_path).ok()?;
+    NotesDatabase::load(notes_path).ok()?.skip_idle(crc32)
+}
+
+fn save_skip_idle(rom_path: &str, notes_path: &str, skip_idle: bool) -> Result<(), String> {
+    let (crc32, rom_name) = rom_identity(rom_path).map_err(|e| e.to_string())?;
+    let mut notes = NotesDatabase::load(notes_path)?;
+    notes.set_skip_idle(crc32, &rom_name, skip_idle);
+    notes.save(notes_path).map_err(|e| e.to_string())
+}
+
+// Controls of the game, turbo_default being used when it has no turbo setting
+fn rom_controls(rom_path: &str, notes_path: &str, turbo_default: Turbo) -> GameControls {
+    let notes = rom_identity(rom_path)
+        .ok()
+        .and_then(|(crc32, _)| Some((crc32, NotesDatabase::load(notes_path).ok()?)));
+    match notes {
+        Some((crc32, notes)) => GameControls::new(
+            notes.swap_ab(crc32).unwrap_or(false),
+            notes
+                .turbo(crc32)
+                .and_then(Turbo::from_name)
+                .unwrap_or(turbo_default),
+        ),
+        None => GameControls::new(false, turbo_default),
+    }
+}
+
+fn save_controls(rom_path: &str, notes_path: &str, controls: &GameControls) -> Result<(), String> {
+    let (crc32, rom_name) = rom_identity(rom_path).map_err(|e| e.to_string())?;
+    let mut notes = NotesDatabase::load(notes_path)?;
+    notes.set_swap_ab(crc32, &rom_name, controls.swap_ab);
+    notes.set_turbo(crc32, &rom_name, controls.turbo.name());
+    notes.save(notes_path).map_err(|e| e.to_string())
+}
+
+fn rom_pal_timing(rom_path: &str, notes_path: &str) -> Option<bool> {
+    let (crc32, _) = rom_identity(rom_path).ok()?;
+    NotesDatabase::load(notes_path).ok()?.pal_timing(crc32)
+}
+
+fn save_pal_timing(rom_path: &str, notes_path: &str, pal_timing: bool) -> Result<(), String> {
+    let (crc32, rom_name) = rom_identity(rom_path).map_err(|e| e.to_string())?;
+    let mut notes = NotesDatabase::load(notes_path)?;
+    notes.set_pal_timing(crc32, &rom_name, pal_timing);
+    notes.save(notes_path).map_err(|e| e.to_string())
+}
+
+fn rom_debug_window(rom_path: &str, notes_path: &str) -> Option<bool> {
+    let (crc32, _) = rom_identity(rom_path).ok()?;
+    NotesDatabase::load(notes_path).ok()?.debug_window(crc32)
+}
+
+fn save_debug_window(rom_path: &str, notes_path: &str, open: bool) -> Result<(), String> {
+    let (crc32, rom_name) = rom_identity(rom_path).map_err(|e| e.to_string())?;
+    let mut notes = NotesDatabase::load(notes_path)?;
+    notes.set_debug_window(crc32, &rom_name, open);
+    notes.save(notes_path).map_err(|e| e.to_string())
+}
+
+fn rate_compatibility(rom_path: &str, notes_path: &str, rating: Rating) -> Result<(), String> {
+    let (crc32, rom_name) = rom_identity(rom_path).map_err(|e| e.to_string())?;
+    let mut notes = NotesDatabase::load(notes_path)?;
+    notes.set_rating(crc32, &rom_name, rating);
+    notes.save(notes_path).map_err(|e| e.to_string())
+}
+
+// Change the daily play time limit or give more time for today, protected by a PIN
+pub fn configure_parental(
+    pin: &str,
+    daily_limit: Option<u64>,
+    extra_time: Option<u64>,
+) -> Result<String, String> {
+    parental::configure(&parental::default_path(), pin, daily_limit, extra_time)
+}
+
+// Run the ROM without window nor input for a number of frames and write the last frame to
+// a PNG file, e.g. for the thumbnails of a library or to check that a ROM boots
+pub fn snap(
+    rom_path: &str,
+    palette_path: Option<&str>,
+    frames: u64,
+    out: &str,
+) -> Result<(), String> {
+    let mut emulator = HeadlessEmulator::new(palette_path);
+    emulator.load_rom(rom_path)?;
+    for _ in 0..frames {
+        emulator.step_frame()?;
+    }
+    capture::write_screenshot(
+        Path::new(out),
+        emulator.frame(),
+        capture::FRAME_WIDTH,
+        capture::FRAME_HEIGHT,
+    )
+    .map_err(|e| e.to_string())
+}
+
+// Markdown table of the notes recorded for every ROM, to share compatibility lists
+pub fn export_notes(notes_path: &str) -> Result<String, String> {
+    Ok(NotesDatabase::load(notes_path)?.export_notes())
+}
+
+// Markdown table of the ratings given with F3, for the compatibility list of the project
+pub fn export_compat(notes_path: &str) -> Result<String, String> {
+    Ok(NotesDatabase::load(notes_path)?.export_compat())
+}
+
+// State of the emulation loop that is not part of the NES itself
+struct EmulationState {
+    target_time: Duration,
+    speed: f64,
+    // Temporary speed requested by holding a fast-forward control
+    fast_forward_speed: Option<f64>,
+    // Whether the still screens of the game are fast-forwarded, set per game in the notes
+    skip_idle: bool,
+    skip_idle_default: bool,
+    idle: IdleDetector,
+    // Whether the game runs at the PAL frame rate, its sound keeping its pitch, set per
+    // game in the notes
+    pal_timing: bool,
+    // Small speed correction to match the monitor refresh rate
+    refresh_rate_adjustment: f64,
+    frame_count: u64,
+    // Number of NES clocks since the start of the emulation
+    cycle_count: u64,
+    // Resets requested at an exact frame or cycle with --reset-at
+    scheduled_resets: Vec<EmulationTime>,
+    last_frame: Box<[ARGBColor]>,
+    // Whether the debugging window is open
+    debug: bool,
+    // Whether it opens for the games it was not toggled in
+    debug_default: bool,
+    // Frames sent before the next refresh of the debugging window
+    frames_until_debug: u32,
+    // Pattern tables seen since the game was loaded, if their panel is shown
+    chr_history: Option<ChrHistory>,
+    // Whether the data of the debugging window could not be read at the last refresh
+    debug_failed: bool,
+    // Time spent in each part of the frames, while shown
+    profiler: Option<MiniProfiler>,
+    filter: VideoFilter,
+    minimized: bool,
+    pause_when_minimized: bool,
+    // How the emulation and the sound are kept together
+    sync: SyncStrategy,
+    frame_sleep: FrameSleep,
+    // Nothing depending on the wall clock changes the emulation, e.g. the time inputs arrive at
+    deterministic: bool,
+    // Only the gamepads control the emulator, which cannot be closed without the exit chord
+    kiosk: bool,
+    // Nothing is emulated until a ROM is dropped on the window or chosen in the picker
+    rom_loaded: bool,
+    rom_path: String,
+    // ROM inserted in the NES, patched by the cheats if any
+    cartridge_path: String,
+    // ROM patched by the cheats
+    cheat_rom: ScratchFile,
+    palette_path: Option<String>,
+    state_path: String,
+    // State slots, offered by the pause menu after the quick state
+    state_slots: Vec<String>,
+    save_path: String,
+    session_path: String,
+    repro_path: String,
+    // Folder of the screenshots and of the videos
+    screenshot_path: String,
+    notes_path: String,
+    enable_audio: bool,
+    // Volume in percent
+    volume: u8,
+    gamepad_hotkeys: Option<String>,
+    retry_key: &'static str,
+    // Gamepads connected, driving the first controller ports
+    connected_gamepads: usize,
+    pacing_report: Option<PacingReport>,
+    // Frames are skipped when the emulation cannot keep up
+    lag: LagMonitor,
+    osd: Osd,
+    // Failed save or capture that can be retried
+    failed_message: Option<Message>,
+    // The emulation stops while the pause menu is open
+    menu: Option<PauseMenu>,
+    // Buttons held on the gamepads of each port, then on the keyboard
+    held_input: [u8; 3],
+    // Input last given to each controller port of the NES
+    port_input: [u8; 2],
+    movie_recorder: Option<MovieRecorder>,
+    replay: Option<ReplayBuffer>,
+    passthrough: Option<MoviePassthrough>,
+    // Keyboard, gamepads and movie driving the controller ports
+    inputs: InputRouter,
+    // Swap of A and B and turbo of the players, set per game in the notes
+    controls: GameControls,
+    turbo_default: Turbo,
+    video_recorder: Option<VideoRecorder>,
+    // Videos being finished in the background after their recording stopped
+    finishing_videos: Vec<JoinHandle<()>>,
+    timer: Option<TimeTrial>,
+    shared_frame: Option<SharedFrame>,
+    rewind: Option<RewindBuffer>,
+    // Saves wait for the game to finish writing its battery RAM
+    save_guard: SaveGuard,
+    // Whether the rewind key is held
+    rewinding: bool,
+    // Snapshots restored per frame requested by the left trigger, None when released
+    rewind_trigger: Option<f64>,
+    // Snapshots left to restore, the rate of the trigger not being a whole number
+    rewind_steps: f64,
+    // Whether the queued sound belongs to a game state that was left, e.g. by a reset
+    stale_audio: bool,
+    paused: bool,
+    // Whether a frame is run while paused
+    advancing: bool,
+    control: Option<ControlServer>,
+    // States kept in memory by name, from the control socket
+    checkpoints: HashMap<String, Vec<u8>>,
+    checkpoint_scratch: ScratchFile,
+    // Stack of states pushed and popped with hotkeys, the last one pushed at the end
+    bookmarks: Vec<Vec<u8>>,
+    hooks: EventHooks,
+    theme: SharedTheme,
+    // Demos played when nobody plays, see --attract
+    attract: Option<AttractMode>,
+    // Daily play time limit set by the parents
+    play_timer: Option<PlayTimer>,
+    // The emulation stops once the daily play time is over
+    time_up: bool,
+}
+
+impl EmulationState {
+    fn new(nes: &impl EmulatorCore, nes_config: &NESConfig, osd: Osd) -> Self {
+        // The paths stay empty until a ROM is loaded
+        let rom = nes_config.rom.cloned().unwrap_or_default();
+        EmulationState {
+            target_time: nes.get_one_frame_duration(),
+            speed: 1.0,
+            fast_forward_speed: None,
+            skip_idle: nes_config.skip_idle,
+            skip_idle_default: nes_config.skip_idle,
+            pal_timing: false,
+            idle: IdleDetector::default(),
+            refresh_rate_adjustment: 1.0,
+            frame_count: 0,
+            cycle_count: 0,
+            scheduled_resets: vec![],
+            last_frame: vec![ARGBColor::black(); 61_440].into_boxed_slice(),
+            debug: false,
+            debug_default: false,
+            frames_until_debug: 0,
+            chr_history: None,
+            debug_failed: false,
+            profiler: None,
+            filter: nes_config.filter,
+            minimized: false,
+            pause_when_minimized: nes_config.pause_when_minimized,
+            sync: nes_config.sync,
+            frame_sleep: nes_config.frame_sleep,
+            deterministic: nes_config.deterministic,
+            kiosk: nes_config.kiosk,
+            rom_loaded: nes_config.rom.is_some(),
+            cartridge_path: rom.rom.clone(),
+            cheat_rom: ScratchFile::with_extension("cheats", "nes"),
+            rom_path: rom.rom,
+            palette_path: nes_config.palette_path.map(String::from),
+            state_path: rom.state,
+            state_slots: rom.state_slots,
+            save_path: rom.save,
+            session_path: rom.session,
+            repro_path: rom.repro,
+            screenshot_path: rom.screenshots,
+            notes_path: String::from(nes_config.notes_path),
+            enable_audio: nes_config.enable_audio,
+            volume: nes_config.volume,
+            gamepad_hotkeys: nes_config.gamepad_hotkeys.map(String::from),
+            retry_key: nes_config.retry_key,
+            connected_gamepads: 0,
+            pacing_report: nes_config.pacing_report_path.map(PacingReport::new),
+            lag: LagMonitor::default(),
+            osd,
+            failed_message: None,
+            menu: None,
+            held_input: [0; 3],
+            port_input: [0; 2],
+            movie_recorder: None,
+            replay: None,
+            passthrough: None,
+            inputs: InputRouter::new(nes_config.input_merge),
+            controls: GameControls::new(false, nes_config.turbo),
+            turbo_default: nes_config.turbo,
+            video_recorder: None,
+            finishing_videos: vec![],
+            timer: None,
+            shared_frame: None,
+            rewind: None,
+            save_guard: SaveGuard::new(),
+            rewinding: false,
+            rewind_trigger: None,
+            rewind_steps: 0.0,
+            stale_audio: false,
+            paused: false,
+            advancing: false,
+            control: None,
+            checkpoints: HashMap::new(),
+            checkpoint_scratch: ScratchFile::new("checkpoint"),
+            bookmarks: vec![],
+            hooks: EventHooks::default(),
+            theme: SharedTheme::default(),
+            attract: None,
+            play_timer: None,
+            time_up: false,
+        }
+    }
+
+    fn volume(&self) -> f32 {
+        self.volume as f32 / 100.0
+    }
+
+    fn effective_speed(&self) -> f64 {
+        match self.fast_forward_speed {
+            Some(speed) => speed,
+            None if self.idle.is_idle() => self.speed.max(IDLE_SPEED),
+            None => self.speed,
+        }
+    }
+
+    // The game is silent while rewinding, paused or fast-forwarding without limit
+    fn is_muted(&self) -> bool {
+        self.rewinding
+            || self.paused
+            || matches!(self.fast_forward_speed, Some(speed) if speed.is_infinite())
+    }
+
+    // The sound is also muted at high speeds, the emulation still keeping up with them
+    fn is_audible(&self) -> bool {
+        !self.is_muted() && self.effective_speed() <= MUTED_SPEED
     }
+}
+
+// Get the debugging panels from a comma separated list, and whether to open them at startup
+fn parse_debug_panels(names: Option<&str>) -> (Vec<DebugPanel>, bool) {
+    let names = match names {
+        Some(names) => names,
+        None => return (DebugPanel::DEFAULT.to_vec(), false),
+    };
+    let mut panels = vec![];
+    for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        match DebugPanel::from_name(name) {
+            Some(panel) if !panels.contains(&panel) => panels.push(panel),
+            Some(_) => (),
+            None => warn!("Unknown debug panel: {:?}, ignoring it.", name),
+        }
+    }
+    if panels.is_empty() {
+        return (DebugPanel::DEFAULT.to_vec(), false);
+    }
+    (panels, true)
+}
+
+// File of a screenshot or of a video in the given folder, named after the ROM and the frame
+fn capture_path(state: &EmulationState, dir: &str, extension: &str) -> PathBuf {
+    let rom_name = Path::new(&state.rom_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Path::new(dir).join(format!("{}-{}.{}", rom_name, state.frame_count, extension))
+}
 
-    // Spawn a thread to run the NES ROM and give it a channel receiver to handle events from the main loop
-    thread::spawn(move || run_nes(&mut nes, &mut gui, rx));
+// Log where the files are read and written, those of a ROM dropped later being next to it
+fn log_paths(nes_config: &NESConfig) {
+    let paths = describe_paths(
+        nes_config.config_path,
+        nes_config.notes_path,
+        nes_config.rom,
+    );
+    for line in paths.lines() {
+        info!("{}", line);
+    }
 }
 
-fn init_env_logger(debug_level: Option<&str>) {
+// Returns the buffer of the records displayed in the log panel
+fn init_env_logger(debug_level: Option<&str>) -> LogBuffer {
     let debug_level = if let Some(value) = debug_level {
         match value {
             "0" => "error",
@@ -90,115 +1465,1722 @@ fn init_env_logger(debug_level: Option<&str>) {
 
     // Setup logger
     // Logs level from winit and pixels crates are set to warn
-    env_logger::Builder::from_env(Env::default().default_filter_or(
+    let logger = env_logger::Builder::from_env(Env::default().default_filter_or(
         debug_level.to_owned()
             + ",gfx_memory=warn,gfx_backend_vulkan=warn,gfx_descriptor=warn,winit=warn,mio=warn,wgpu_core=warn,wgpu_hal=warn,naga=warn",
     ))
-    .init();
+    .build();
+    let buffer = LogBuffer::default();
+    log::set_max_level(logger.filter());
+    log::set_boxed_logger(Box::new(BufferedLogger::new(logger, buffer.clone())))
+        .expect("Cannot initialize the logger");
+    buffer
 }
 
-fn run_nes(nes: &mut NES, gui: &mut Gui, rx: Receiver<Message>) {
+fn run_nes(
+    nes: &mut impl EmulatorCore,
+    frames: Option<FrameSender>,
+    state: &mut EmulationState,
+    rx: Receiver<Message>,
+) {
     info!("Running NES emulation...");
 
     // Sound
-    let sdl_context = sdl2::init().unwrap();
-    let audio_subsystem = sdl_context.audio().unwrap();
-
-    let desired_audio_specs = AudioSpecDesired {
-        freq: Some(44100),
-        channels: Some(1),
-        samples: Some(1024),
+    let sdl_context = match sdl2::init() {
+        Ok(context) => Some(context),
+        Err(e) => {
+            warn!("Failed to initialize SDL: {}", e);
+            None
+        }
     };
+    let mut audio = AudioOutput::new(sdl_context.as_ref(), state.enable_audio, state.volume());
+    if let AudioOutput::Null = audio {
+        nes.produce_samples(false);
+        if state.enable_audio {
+            state.osd.notify(OsdLevel::Warning, "Audio unavailable");
+        }
+    } else {
+        nes.produce_samples(true);
+    }
 
-    let queue = audio_subsystem
-        .open_queue(None, &desired_audio_specs)
-        .unwrap();
-    queue.resume();
+    // Gamepads
+    let chords = gamepad::parse_chords(state.gamepad_hotkeys.as_deref()).unwrap_or_else(|e| {
+        warn!("Invalid gamepad hotkeys: {}. Using default hotkeys.", e);
+        state
+            .osd
+            .notify(OsdLevel::Warning, "Invalid gamepad hotkeys");
+        gamepad::parse_chords(None).unwrap()
+    });
+    let mut gamepads = sdl_context
+        .as_ref()
+        .and_then(|sdl| match Gamepads::new(sdl, chords) {
+            Ok(mut gamepads) => {
+                gamepads.set_kiosk(state.kiosk);
+                Some(gamepads)
+            }
+            Err(e) => {
+                warn!("Cannot initialize gamepads: {}", e);
+                state.osd.notify(OsdLevel::Warning, "Gamepads unavailable");
+                None
+            }
+        });
 
-    let mut target_time = nes.get_one_frame_duration();
     let mut time = Instant::now();
+    let mut clocks_since_messages = 0;
+    // Time spent on the messages since the last frame, not part of the emulation
+    let mut message_time = Duration::ZERO;
 
     loop {
-        // Run one clock of emulation
-        nes.clock();
-
-        // Handle message from the main thread
-        if let Ok(m) = rx.try_recv() {
-            let keep_running = handle_message(nes, gui, &mut target_time, m);
+        // Show how to load a ROM until one is dropped on the window or chosen in the picker
+        if !state.rom_loaded {
+            audio.pause();
+            let mut keep_running = true;
+            while keep_running && !state.rom_loaded {
+                if let Some(frames) = &frames {
+                    let mut frame = Box::new([ARGBColor::black(); 61_440]);
+                    rom::draw_notice(&mut frame[..], &state.theme.get());
+                    send_frame(nes, frames, state, frame);
+                }
+                keep_running = match rx.recv_timeout(MENU_REFRESH_TIME) {
+                    Ok(
+                        m @ (Message::LoadRom(_)
+                        | Message::CycleFilter
+                        | Message::WindowMinimized(_)
+                        | Message::CloseApp
+                        | Message::Terminate),
+                    ) => handle_message(nes, state, m),
+                    // Nothing else can be done without a game
+                    Ok(_) => true,
+                    Err(RecvTimeoutError::Timeout) => true,
+                    Err(RecvTimeoutError::Disconnected) => false,
+                };
+                if let Some(gamepads) = gamepads.as_mut() {
+                    for event in gamepads.poll() {
+                        keep_running &= picker_gamepad_event(state, frames.as_ref(), event);
+                    }
+                }
+            }
             if !keep_running {
                 break;
             }
+            audio.resume();
+            time = Instant::now();
+            continue;
         }
 
-        // Render frame if ready
-        if let Some(frame) = nes.get_frame_buffer() {
-            gui.update_main_buffer(&frame);
-            if gui.debug {
-                gui.debug(
-                    &nes.get_pattern_table(0).unwrap(),
-                    &nes.get_pattern_table(1).unwrap(),
-                    &nes.get_palette().unwrap(),
-                );
+        // Wait for the window to be restored without running the emulation
+        if state.minimized && state.pause_when_minimized {
+            audio.pause();
+            let mut keep_running = true;
+            while keep_running && state.minimized {
+                keep_running = match rx.recv() {
+                    Ok(m) => handle_message(nes, state, m),
+                    Err(_) => false,
+                };
             }
-            gui.render().unwrap();
-
-            // Synchronize with sound
-            if !nes.is_producing_samples() && queue.size() < MIN_AUDIO_QUEUE_SIZE {
-                nes.produce_samples(true);
-            } else if nes.is_producing_samples() && queue.size() > MIN_AUDIO_QUEUE_SIZE {
-                nes.produce_samples(false);
+            if !keep_running {
+                break;
             }
-            queue.queue_audio(&nes.get_samples()[..]).unwrap();
+            audio.resume();
+            time = Instant::now();
+            continue;
+        }
 
-            // Synchronize the emulation to run at the correct speed
-            let elapsed_time = time.elapsed();
-            if elapsed_time < target_time {
-                spin_sleep::sleep(target_time - elapsed_time);
+        // Stop the emulation once the daily play time is over, until more time is given
+        if state.time_up {
+            audio.pause();
+            let mut keep_running = true;
+            let mut checked_at = Instant::now();
+            while keep_running && state.time_up {
+                if let Some(frames) = &frames {
+                    let mut frame = Box::new([ARGBColor::black(); 61_440]);
+                    frame.copy_from_slice(&state.last_frame);
+                    parental::draw_notice(&mut frame[..], &state.theme.get());
+                    send_frame(nes, frames, state, frame);
+                }
+                keep_running = match rx.recv_timeout(MENU_REFRESH_TIME) {
+                    Ok(m) => handle_message(nes, state, m),
+                    Err(RecvTimeoutError::Timeout) => true,
+                    Err(RecvTimeoutError::Disconnected) => false,
+                };
+                if let Some(gamepads) = gamepads.as_mut() {
+                    for event in gamepads.poll() {
+                        keep_running &= handle_gamepad_event(nes, state, event);
+                    }
+                    set_connected_gamepads(nes, state, gamepads.connected());
+                }
+                if checked_at.elapsed() >= parental::LOCKED_CHECK_TIME {
+                    checked_at = Instant::now();
+                    if let Some(timer) = state.play_timer.as_mut() {
+                        state.time_up = !timer.check_unlocked();
+                    }
+                    if !state.time_up {
+                        info!("More play time was given.");
+                        state.osd.notify(OsdLevel::Info, "More play time given");
+                    }
+                }
+            }
+            if !keep_running {
+                break;
             }
+            audio.resume();
             time = Instant::now();
+            continue;
         }
-    }
-}
 
-fn handle_message(
-    nes: &mut NES,
-    gui: &mut Gui,
-    target_time: &mut Duration,
-    message: Message,
-) -> bool {
-    match message {
-        Message::Input(id, input) => {
-            if let Err(e) = nes.input(id, input) {
-                error!("Failed to handle controller input: {}", e);
-                exit(1);
+        // The pause menu stops the emulation until it is closed
+        if state.menu.is_some() {
+            audio.pause();
+            let mut keep_running = true;
+            while keep_running && state.menu.is_some() {
+                if let (Some(frames), Some(menu)) = (&frames, &state.menu) {
+                    let mut frame = Box::new([ARGBColor::black(); 61_440]);
+                    frame.copy_from_slice(&state.last_frame);
+                    menu.draw(&mut frame[..], &menu_settings(state), &state.theme.get());
+                    send_frame(nes, frames, state, frame);
+                }
+                keep_running = match rx.recv_timeout(MENU_REFRESH_TIME) {
+                    Ok(m) => handle_message(nes, state, m),
+                    Err(RecvTimeoutError::Timeout) => true,
+                    Err(RecvTimeoutError::Disconnected) => false,
+                };
+                if let Some(gamepads) = gamepads.as_mut() {
+                    for event in gamepads.poll() {
+                        keep_running &= handle_gamepad_event(nes, state, event);
+                    }
+                    set_connected_gamepads(nes, state, gamepads.connected());
+                }
+                handle_control_requests(nes, state);
+                // Play the demos when nobody uses the menu
+                if is_attract_idle(state) {
+                    state.menu = None;
+                }
+            }
+            if !keep_running {
+                break;
             }
+            audio.resume();
+            time = Instant::now();
+            continue;
         }
-        Message::Reset => nes.reset(),
-        Message::ResizeWindow(width, height) => gui.resize(width, height),
-        Message::DrawFrame => gui.redraw(),
-        Message::ChangePaletteId(id) => nes.set_debug_palette_id(id).unwrap(),
-        Message::ChangeEmulationSpeed(s) => {
-            *target_time =
-                Duration::from_micros((nes.get_one_frame_duration().as_micros() as f64 / s) as u64)
+
+        // Wait for the emulation to be resumed or advanced by one frame
+        if state.paused && !state.advancing {
+            audio.pause();
+            let mut keep_running = true;
+            while keep_running && state.paused && !state.advancing && state.menu.is_none() {
+                // Keep the on-screen display updated
+                if let Some(frames) = &frames {
+                    let mut frame = Box::new([ARGBColor::black(); 61_440]);
+                    frame.copy_from_slice(&state.last_frame);
+                    send_frame(nes, frames, state, frame);
+                }
+                keep_running = match rx.recv_timeout(MENU_REFRESH_TIME) {
+                    Ok(m) => handle_message(nes, state, m),
+                    Err(RecvTimeoutError::Timeout) => true,
+                    Err(RecvTimeoutError::Disconnected) => false,
+                };
+                if let Some(gamepads) = gamepads.as_mut() {
+                    for event in gamepads.poll() {
+                        keep_running &= handle_gamepad_event(nes, state, event);
+                    }
+                    set_connected_gamepads(nes, state, gamepads.connected());
+                }
+                handle_control_requests(nes, state);
+                if is_attract_idle(state) {
+                    state.paused = false;
+                }
+            }
+            if !keep_running {
+                break;
+            }
+            audio.resume();
+            time = Instant::now();
+            continue;
+        }
+
+        // Run one clock of emulation
+        nes.clock();
+        state.cycle_count += 1;
+        if !state.scheduled_resets.is_empty() {
+            run_scheduled_resets(nes, state, EmulationTime::Cycle(state.cycle_count));
+        }
+
+        // Handle messages from the main thread, only between frames in deterministic mode
+        clocks_since_messages += 1;
+        if !state.deterministic && clocks_since_messages >= MESSAGE_POLL_CLOCKS {
+            clocks_since_messages = 0;
+            let start = Instant::now();
+            let mut keep_running = true;
+            while let Ok(m) = rx.try_recv() {
+                keep_running &= handle_message(nes, state, m);
+            }
+            if !keep_running {
+                break;
+            }
+            message_time += start.elapsed();
+        }
+
+        // Render frame if ready
+        if let Some(mut frame) = nes.get_frame_buffer() {
+            let emulate_time = time.elapsed();
+            profile(
+                state,
+                Subsystem::Emulation,
+                emulate_time.saturating_sub(message_time),
+            );
+            profile(state, Subsystem::Messages, message_time);
+            message_time = Duration::ZERO;
+            state.frame_count += 1;
+            state.advancing = false;
+            state.last_frame.copy_from_slice(&frame);
+            // The videos show that the game did not run at normal speed
+            if let Some(text) = speed_indicator::text(state.effective_speed(), state.rewinding) {
+                speed_indicator::draw(&mut frame, &text);
+            }
+            if let Some(recorder) = state.video_recorder.as_mut() {
+                recorder.frame(&frame);
+            }
+            // The demos are not part of the game
+            let demo = state.attract.is_some() && update_attract(nes, state);
+            if state.rewind.is_some() && !demo {
+                update_rewind(nes, state);
+            }
+            if let Some(recorder) = state.movie_recorder.as_mut().filter(|_| !demo) {
+                recorder.frame(state.port_input);
+            }
+            if state.replay.is_some() && !demo {
+                update_replay(nes, state);
+            }
+            if !demo {
+                update_input_sources(nes, state);
+            }
+            if let Some(shared_frame) = state.shared_frame.as_mut() {
+                shared_frame.write(&frame, state.frame_count);
+            }
+            if state.play_timer.is_some() && !demo {
+                update_play_timer(nes, state);
+            }
+            let debug_start = Instant::now();
+            if state.debug {
+                if let Some(history) = state.chr_history.as_mut() {
+                    history.frame(nes, state.frame_count);
+                }
+            }
+            let debug_time = debug_start.elapsed();
+            profile(state, Subsystem::Debug, debug_time);
+            if state.skip_idle && !demo {
+                update_idle(nes, state, &frame);
+            }
+            if state.timer.is_some() && !demo {
+                update_timer(state, &mut frame);
+            }
+            if state.passthrough.is_some() && !demo {
+                update_passthrough(state, &mut frame);
+            }
+            if !state.scheduled_resets.is_empty() {
+                run_scheduled_resets(nes, state, EmulationTime::Frame(state.frame_count));
+            }
+            if !demo {
+                release_held_saves(nes, state);
+            }
+            if let Some(profiler) = state.profiler.as_ref() {
+                profiler.draw(&mut frame, state.target_time);
+            }
+            let presented = !state.lag.skip_frame();
+            if let Some(frames) = frames.as_ref().filter(|_| presented) {
+                send_frame(nes, frames, state, Box::new(frame));
+            }
+            let render_time = time.elapsed() - emulate_time;
+            profile(
+                state,
+                Subsystem::Frame,
+                render_time.saturating_sub(debug_time),
+            );
+
+            // Handle gamepads inputs and hotkeys
+            let messages_start = Instant::now();
+            if let Some(gamepads) = gamepads.as_mut() {
+                let mut keep_running = true;
+                for event in gamepads.poll() {
+                    keep_running &= handle_gamepad_event(nes, state, event);
+                }
+                set_connected_gamepads(nes, state, gamepads.connected());
+                if !keep_running {
+                    break;
+                }
+            }
+            handle_control_requests(nes, state);
+            if state.deterministic {
+                let mut keep_running = true;
+                while let Ok(m) = rx.try_recv() {
+                    keep_running &= handle_message(nes, state, m);
+                }
+                if !keep_running {
+                    break;
+                }
+            }
+
+            profile(state, Subsystem::Messages, messages_start.elapsed());
+
+            // Synchronize with sound, from scratch when the game state changed
+            let audio_start = Instant::now();
+            let resync = state.stale_audio;
+            if resync {
+                state.stale_audio = false;
+                audio.flush();
+                // The inputs cannot replay the change either
+                if let Some(replay) = state.replay.as_mut() {
+                    replay.clear();
+                }
+            }
+            let audio_queue_size = audio.queued_size();
+            // Audio only drives the pacing at normal speed
+            let audio_paced = !state.deterministic
+                && state.sync.is_audio_paced()
+                && audio_queue_size.is_some()
+                && state.effective_speed() == 1.0;
+            // The samples are produced unless no audio device could be opened, and the audio
+            // output resamples them to the speed of the emulation unless the sync strategy
+            // plays them as they are
+            let samples = nes.get_samples();
+            if let Some(recorder) = state.video_recorder.as_mut() {
+                recorder.samples(&samples);
+            }
+            audio.set_volume(state.volume());
+            if state.deterministic {
+                // The ones the audio queue cannot take are dropped
+                if state.is_audible()
+                    && matches!(audio_queue_size, Some(size) if size <= MIN_AUDIO_QUEUE_SIZE)
+                {
+                    audio.queue(&samples[..], state.effective_speed(), state.pal_timing);
+                }
+            } else if state.is_audible() {
+                if audio_paced || !state.sync.resamples_audio() {
+                    audio.queue_unadjusted(&samples[..]);
+                } else {
+                    audio.queue(
+                        &samples[..],
+                        state.effective_speed()
+                            * state.refresh_rate_adjustment
+                            * state.lag.reached_speed(),
+                        state.pal_timing,
+                    );
+                }
+            }
+
+            profile(state, Subsystem::Audio, audio_start.elapsed());
+
+            // Synchronize the emulation to run at the correct speed
+            let elapsed_time = time.elapsed();
+            // Loading a state is not the emulation being slow
+            if !state.is_muted() && !resync {
+                update_lag(state, elapsed_time);
+            }
+            let sleep_start = Instant::now();
+            wait_for_next_frame(
+                &mut audio,
+                state.frame_sleep,
+                state.target_time,
+                elapsed_time,
+                audio_paced,
+            );
+            if let Some(report) = state.pacing_report.as_mut() {
+                report.record(FrameTiming {
+                    emulate: emulate_time,
+                    render: render_time,
+                    sleep: sleep_start.elapsed(),
+                    audio_queue_size,
+                    presented,
+                });
+            }
+            if !state.is_muted() && !resync {
+                state.lag.frame_period(time.elapsed(), state.target_time);
+            }
+            profile(state, Subsystem::Sleep, sleep_start.elapsed());
+            if let Some(profiler) = state.profiler.as_mut() {
+                profiler.end_frame();
+            }
+            time = Instant::now();
+        }
+    }
+
+    // The event loop must close the application if the emulation stopped by itself
+    if let Some(frames) = &frames {
+        frames.close();
+    }
+
+    if let Some(timer) = state.play_timer.as_mut() {
+        timer.sync();
+    }
+
+    // Wait for the videos to be written
+    if let Some(recorder) = state.video_recorder.take() {
+        state.finishing_videos.push(recorder.stop());
+    }
+    for thread in state.finishing_videos.drain(..) {
+        let _ = thread.join();
+    }
+
+    info!("Pacing: {}.", state.lag.summary());
+    if let Some(report) = &state.pacing_report {
+        match report.write() {
+            Ok(path) => info!("Pacing report written to {}.", path),
+            Err(e) => error!("Failed to write the pacing report: {}", e),
+        }
+    }
+}
+
+#[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
+fn wait_for_next_frame(
+    audio: &mut AudioOutput,
+    sleep: FrameSleep,
+    target_time: Duration,
+    elapsed_time: Duration,
+    audio_paced: bool,
+) {
+    if audio_paced {
+        while audio.queued_size().unwrap_or(0) > MIN_AUDIO_QUEUE_SIZE {
+            sleep.sleep(Duration::from_millis(1));
+        }
+    } else if elapsed_time < target_time {
+        sleep.sleep(target_time - elapsed_time);
+    }
+}
+
+// Run the emulation exactly at the monitor refresh rate when it is close to the NES frame rate
+fn sync_to_refresh_rate(nes: &impl EmulatorCore, state: &mut EmulationState, refresh_rate: f64) {
+    let nes_frame_rate = 1.0 / nes.get_one_frame_duration().as_secs_f64();
+    let adjustment = refresh_rate / nes_frame_rate;
+    if (adjustment - 1.0).abs() > MAX_REFRESH_RATE_ADJUSTMENT {
+        info!(
+            "Monitor refresh rate {} Hz is too far from the NES frame rate {:.4} Hz, not syncing to it.",
+            refresh_rate, nes_frame_rate
+        );
+        return;
+    }
+    info!(
+        "Syncing to the monitor refresh rate {} Hz (speed x{:.5}).",
+        refresh_rate, adjustment
+    );
+    state.refresh_rate_adjustment = adjustment;
+    update_target_time(nes, state);
+}
+
+fn update_target_time(nes: &impl EmulatorCore, state: &mut EmulationState) {
+    let timing = if state.pal_timing { PAL_SPEED } else { 1.0 };
+    let speed = state.effective_speed() * state.refresh_rate_adjustment * timing;
+    state.target_time =
+        Duration::from_micros((nes.get_one_frame_duration().as_micros() as f64 / speed) as u64)
+}
+
+// Skip frames and warn when the emulation cannot keep up with its target speed
+fn update_lag(state: &mut EmulationState, elapsed_time: Duration) {
+    match state.lag.frame(elapsed_time, state.target_time) {
+        Some(LagChange::Lagging) => {
+            warn!(
+                "The emulation cannot keep up with its target speed, skipping frames ({:.1}ms per frame instead of {:.1}ms).",
+                elapsed_time.as_secs_f64() * 1000.0,
+                state.target_time.as_secs_f64() * 1000.0
+            );
+            state
+                .osd
+                .notify(OsdLevel::Warning, "Too slow, skipping frames");
+        }
+        Some(LagChange::Recovered) => {
+            info!("The emulation keeps up with its target speed again.");
+            state.osd.notify(OsdLevel::Info, "Back to full speed");
+        }
+        None => (),
+    }
+}
+
+// Time spent in a part of the frame, counted while the profiler is shown
+fn profile(state: &mut EmulationState, subsystem: Subsystem, time: Duration) {
+    if let Some(profiler) = state.profiler.as_mut() {
+        profiler.add(subsystem, time);
+    }
+}
+
+// Run faster while the game waits on a still screen, until it changes or a button is held
+#[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
+fn update_idle(nes: &impl EmulatorCore, state: &mut EmulationState, frame: &[ARGBColor]) {
+    let was_idle = state.idle.is_idle();
+    let input = state.port_input != [0; 2];
+    if state.idle.frame(frame, input) != was_idle {
+        update_target_time(nes, state);
+    }
+}
+
+// Run the time trial timer and draw it over the frame
+fn update_timer(state: &mut EmulationState, frame: &mut [ARGBColor]) {
+    let timer = match state.timer.as_mut() {
+        Some(timer) => timer,
+        None => return,
+    };
+    if let Some(time) = timer.frame(state.frame_count, state.port_input[0]) {
+        info!("Time trial: {}.", timer::format_time(time));
+        if timer.is_best_time(time) {
+            state.osd.notify(
+                OsdLevel::Info,
+                format!("New best time {}", timer::format_time(time)),
+            );
+            if let Err(e) = save_best_time(&state.rom_path, &state.notes_path, time) {
+                error!("Failed to save the best time: {}", e);
+                state
+                    .osd
+                    .notify(OsdLevel::Error, "Failed to save best time");
+            }
+        }
+    }
+    timer.draw(frame, state.frame_count);
+}
+
+// Show the buttons played by the movie and press their keys, which are released once the
+// movie is over
+fn update_passthrough(state: &mut EmulationState, frame: &mut [ARGBColor]) {
+    let mut inputs = [None; PORTS];
+    for (port, input) in inputs.iter_mut().enumerate() {
+        *input = state.inputs.input_of(SourceKind::Movie, port);
+    }
+    if let Some(passthrough) = state.passthrough.as_mut() {
+        passthrough.frame(inputs[0].unwrap_or(0));
+        passthrough::draw_inputs(frame, inputs);
+    }
+}
+
+// Take a snapshot of the NES, or restore the previous one while the rewind key is held
+#[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
+fn update_rewind(nes: &mut impl EmulatorCore, state: &mut EmulationState) {
+    let rewind = match state.rewind.as_mut() {
+        Some(rewind) => rewind,
+        None => return,
+    };
+    let result = if state.rewinding {
+        // One snapshot per frame, or more with a harder pull of the left trigger
+        state.rewind_steps += state.rewind_trigger.unwrap_or(1.0);
+        let mut result = Ok(());
+        while state.rewinding && state.rewind_steps >= 1.0 && result.is_ok() {
+            state.rewind_steps -= 1.0;
+            result = match rewind.rewind(nes, &state.cartridge_path) {
+                Ok(false) => {
+                    state.rewinding = false;
+                    state
+                        .osd
+                        .notify(OsdLevel::Warning, "Nothing left to rewind");
+                    Ok(())
+                }
+                result => result.map(|_| ()),
+            };
+        }
+        result
+    } else {
+        rewind.frame(nes)
+    };
+    if let Err(e) = result {
+        error!("Rewind failed, disabling it: {}", e);
+        state.osd.notify(OsdLevel::Error, "Rewind failed");
+        state.rewind = None;
+        state.rewinding = false;
+    }
+}
+
+// Keep the inputs of the frame for the replays
+fn update_replay(nes: &mut impl EmulatorCore, state: &mut EmulationState) {
+    let inputs = state.port_input;
+    let result = match state.replay.as_mut() {
+        Some(replay) => replay.frame(nes, inputs),
+        None => return,
+    };
+    if let Err(e) = result {
+        error!("Replay buffer failed, disabling it: {}", e);
+        state.osd.notify(OsdLevel::Error, "Replay buffer failed");
+        state.replay = None;
+    }
+}
+
+// Where the movies start from, the state loaded with --state if any
+fn movie_start(nes_config: &NESConfig) -> std::io::Result<MovieStart> {
+    MovieStart::new(
+        nes_config
+            .rom
+            .map(|rom| rom.state.as_str())
+            .filter(|_| nes_config.load_state),
+    )
+}
+
+// Give the controllers the inputs of their sources
+fn apply_inputs(nes: &mut impl EmulatorCore, state: &mut EmulationState) {
+    let mut inputs = state.inputs.inputs();
+    if !state.inputs.is_driven_by_program() {
+        inputs = state.controls.apply(inputs);
+    }
+    for (port, input) in inputs.iter().enumerate() {
+        if let Err(e) = nes.input(port, *input) {
+            error!("Failed to handle controller input: {}", e);
+            exit(1);
+        }
+        state.port_input[port] = *input;
+    }
+}
+
+// Go to the next frame of the sources playing inputs, e.g. a movie, whose controllers are
+// released for the players once it is over
+#[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
+fn update_input_sources(nes: &mut impl EmulatorCore, state: &mut EmulationState) {
+    let over = state.inputs.next_frame();
+    if over.contains(&SourceKind::Movie) {
+        info!("Movie over.");
+        state.osd.notify(OsdLevel::Info, "Movie over");
+    }
+    // The turbo buttons are pressed and released without input
+    state.controls.next_frame();
+    if state.inputs.is_driven_by_program() || !over.is_empty() || state.controls.is_repeating() {
+        apply_inputs(nes, state);
+    }
+}
+
+// The ports of the connected gamepads ignore the keyboard unless the inputs are combined
+fn set_connected_gamepads(nes: &mut impl EmulatorCore, state: &mut EmulationState, count: usize) {
+    if count == state.connected_gamepads {
+        return;
+    }
+    state.connected_gamepads = count;
+    for port in 0..state.port_input.len() {
+        if port >= count {
+            state.inputs.set_input(SourceKind::Gamepad, port, None);
+        } else if state.inputs.input_of(SourceKind::Gamepad, port).is_none() {
+            state.inputs.set_input(SourceKind::Gamepad, port, Some(0));
+        }
+    }
+    apply_inputs(nes, state);
+}
+
+// Count the play time, and save the game and stop the emulation once it is over
+fn update_play_timer(nes: &mut impl EmulatorCore, state: &mut EmulationState) {
+    let timer = match state.play_timer.as_mut() {
+        Some(timer) => timer,
+        None => return,
+    };
+    if timer.frame() {
+        let minutes = timer.remaining().unwrap_or_default().as_secs() / 60 + 1;
+        state.osd.notify(
+            OsdLevel::Warning,
+            format!("{} minutes of play left", minutes),
+        );
+    }
+    if timer.is_time_up() {
+        info!("Daily play time is over, saving the session.");
+        state.time_up = true;
+        save_session(nes, state);
+    }
+}
+
+// Start or play the demos, returns whether one is playing
+fn update_attract(nes: &mut impl EmulatorCore, state: &mut EmulationState) -> bool {
+    let attract = match state.attract.as_mut() {
+        Some(attract) => attract,
+        None => return false,
+    };
+    let was_playing = attract.is_playing();
+    match attract.frame(nes, &state.cartridge_path) {
+        Ok(playing) => {
+            if playing && !was_playing {
+                info!("Nobody is playing, starting the demos.");
+                state
+                    .osd
+                    .notify(OsdLevel::Info, "Demo - press a button to play");
+            }
+            playing
+        }
+        Err(e) => {
+            error!("Failed to play the demo, disabling the demos: {}", e);
+            state.osd.notify(OsdLevel::Error, "Demo failed");
+            state.attract = None;
+            false
+        }
+    }
+}
+
+fn is_attract_idle(state: &EmulationState) -> bool {
+    matches!(&state.attract, Some(attract) if attract.is_idle())
+}
+
+// Any button stops the demo and opens the pause menu over the restored game.
+// Returns whether the input was used by the demos.
+fn attract_input(nes: &mut impl EmulatorCore, state: &mut EmulationState, input: u8) -> bool {
+    let attract = match state.attract.as_mut() {
+        Some(attract) => attract,
+        None => return false,
+    };
+    if input != 0 {
+        attract.activity();
+    }
+    if !attract.is_playing() {
+        return false;
+    }
+    if input != 0 {
+        if let Err(e) = attract.stop(nes, &state.cartridge_path) {
+            error!("Failed to restore the game after the demo: {}", e);
+            state.osd.notify(OsdLevel::Error, "Cannot restore the game");
+        }
+        state.port_input = [0; 2];
+        state.menu = Some(PauseMenu::new(state.held_input));
+    }
+    true
+}
+
+// Play another ROM without restarting. Like when closing the emulator, the battery save
+// of the previous game is only kept if it was saved before. Returns whether the ROM was
+// loaded.
+fn load_rom(nes: &mut impl EmulatorCore, state: &mut EmulationState, rom: RomPaths) -> bool {
+    if let Err(e) = nes.insert_cartdrige(&rom.rom) {
+        FrontendError::Rom(e).report(
+            &format!("Cannot load {}", file_name(&rom.rom)),
+            &state.osd,
+            state.retry_key,
+        );
+        return false;
+    }
+    nes.reset();
+    info!("ROM {} successfully loaded.", rom.rom);
+    report_fallback_dir(&rom, &state.osd);
+    // Only the standard controllers are emulated, so the game may not be playable
+    if let Some(device) = rom::expansion_device(&rom.rom) {
+        warn!(
+            "The ROM declares a {} as its input device, which is not emulated, using the standard controllers.",
+            device
+        );
+        state.osd.notify(
+            OsdLevel::Warning,
+            format!("{} not emulated, using controllers", device),
+        );
+    }
+    if nes.load_save(&rom.save).is_ok() {
+        info!("Save successfully loaded.");
+    }
+
+    state.rom_loaded = true;
+    state.frame_count = 0;
+    state.cycle_count = 0;
+    state.paused = false;
+    state.rewinding = false;
+    state.stale_audio = true;
+    state.failed_message = None;
+    state.last_frame.fill(ARGBColor::black());
+    // The movies and the demos were made for the previous game
+    if state.movie_recorder.take().is_some() {
+        warn!("Movie recording stopped by the new ROM.");
+    }
+    if state.inputs.remove(SourceKind::Movie) {
+        warn!("Movie playback stopped by the new ROM.");
+        state.passthrough = None;
+        apply_inputs(nes, state);
+    }
+    if state.attract.take().is_some() {
+        warn!("Demos are not available once another ROM is loaded, disabling them.");
+    }
+    if let Some(recorder) = state.video_recorder.take() {
+        info!(
+            "Recording stopped, finishing {}.",
+            recorder.path().display()
+        );
+        state.finishing_videos.push(recorder.stop());
+    }
+    if let Some(rewind) = state.rewind.as_mut() {
+        rewind.clear();
+    }
+    state.checkpoints.clear();
+    state.bookmarks.clear();
+    if let Some(history) = state.chr_history.as_mut() {
+        history.clear();
+    }
+    state.save_guard.clear();
+    state.hooks.set_rom_path(&rom.rom);
+    let best_time = rom_best_time(&rom.rom, &state.notes_path);
+    if let Some(timer) = state.timer.as_mut() {
+        timer.change_game(best_time);
+    }
+    state.skip_idle = rom_skip_idle(&rom.rom, &state.notes_path).unwrap_or(state.skip_idle_default);
+    state.controls = rom_controls(&rom.rom, &state.notes_path, state.turbo_default);
+    state.pal_timing = rom_pal_timing(&rom.rom, &state.notes_path).unwrap_or(false);
+    state.idle.reset();
+    update_target_time(nes, state);
+    state.debug = rom_debug_window(&rom.rom, &state.notes_path).unwrap_or(state.debug_default);
+
+    state.state_path = rom.state;
+    state.state_slots = rom.state_slots;
+    state.save_path = rom.save;
+    state.session_path = rom.session;
+    state.repro_path = rom.repro;
+    state.screenshot_path = rom.screenshots;
+    state
+        .osd
+        .notify(OsdLevel::Info, format!("Loaded {}", file_name(&rom.rom)));
+    if let (Ok((crc32, _)), Ok(notes)) = (
+        rom_identity(&rom.rom),
+        NotesDatabase::load(&state.notes_path),
+    ) {
+        for note in notes.notes(crc32) {
+            info!("Known issue: {}", note);
+            state.osd.notify(OsdLevel::Info, format!("Note: {}", note));
+        }
+    }
+    state.cartridge_path = rom.rom.clone();
+    state.rom_path = rom.rom;
+    state.hooks.fire(HookEvent::GameLoaded, None);
+    true
+}
+
+// Play the ROM patched by the cheats, the game going on from the same state
+fn apply_cheats(nes: &mut impl EmulatorCore, state: &mut EmulationState, patches: &[RomPatch]) {
+    let cartridge_path = if patches.is_empty() {
+        Ok(state.rom_path.clone())
+    } else {
+        fs::read(&state.rom_path)
+            .map_err(|e| e.to_string())
+            .and_then(|mut rom| {
+                cheats::patch_rom(&mut rom, patches)?;
+                fs::write(state.cheat_rom.path(), rom).map_err(|e| e.to_string())
+            })
+            .map(|()| state.cheat_rom.path().to_string_lossy().into_owned())
+    };
+    let result = cartridge_path.and_then(|cartridge_path| {
+        let snapshot = state.checkpoint_scratch.save(nes)?;
+        state
+            .checkpoint_scratch
+            .load(nes, &snapshot, &cartridge_path)?;
+        state.cartridge_path = cartridge_path;
+        Ok(())
+    });
+    match result {
+        Ok(()) => info!("{} cheat codes applied.", patches.len()),
+        Err(e) => {
+            error!("Cannot apply the cheats: {}", e);
+            state.osd.notify(OsdLevel::Error, "Cannot apply the cheats");
         }
+    }
+}
+
+// Name of a file without its folder, to keep the on-screen messages short
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from(path))
+}
+
+// Reset the NES if it was scheduled at the current time
+fn run_scheduled_resets(
+    nes: &mut impl EmulatorCore,
+    state: &mut EmulationState,
+    now: EmulationTime,
+) {
+    if let Some(index) = state.scheduled_resets.iter().position(|time| *time == now) {
+        state.scheduled_resets.remove(index);
+        info!(
+            "Scheduled reset at frame {}, cycle {}.",
+            state.frame_count, state.cycle_count
+        );
+        nes.reset();
+        state.stale_audio = true;
+    }
+}
+
+// Send a frame to the GUI, with the debugging data every few frames while the debugging
+// window is open. There is nothing to debug without a cartridge.
+#[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
+fn send_frame(
+    nes: &impl EmulatorCore,
+    frames: &FrameSender,
+    state: &mut EmulationState,
+    frame: Box<[ARGBColor; 61_440]>,
+) {
+    let debug_open = state.debug && state.rom_loaded;
+    let refresh = debug_open && state.frames_until_debug == 0;
+    state.frames_until_debug = match (debug_open, state.frames_until_debug) {
+        // The data is refreshed as soon as the window opens
+        (false, _) => 0,
+        (true, 0) => DEBUG_REFRESH_FRAMES - 1,
+        (true, frames) => frames - 1,
+    };
+    let debug = if refresh {
+        match (nes.get_pattern_tables(), nes.get_palette_colors()) {
+            (Ok([pattern_table_0, pattern_table_1]), Ok(palette)) => {
+                state.debug_failed = false;
+                Some(DebugFrame {
+                    pattern_table_0,
+                    pattern_table_1,
+                    palette,
+                    chr_history: state.chr_history.as_ref().map(ChrHistory::draw),
+                })
+            }
+            // The window keeps showing the last data, the error being logged once until
+            // the data can be read again
+            (Err(e), _) | (_, Err(e)) => {
+                if !state.debug_failed {
+                    error!("Cannot read the debugging data: {}", e);
+                    state.debug_failed = true;
+                }
+                None
+            }
+        }
+    } else {
+        None
+    };
+    frames.send(Frame {
+        main: frame,
+        debug_open,
+        debug,
+        filter: state.filter,
+    });
+}
+
+// Returns false if the application must close
+#[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
+fn handle_gamepad_event(
+    nes: &mut impl EmulatorCore,
+    state: &mut EmulationState,
+    event: GamepadEvent,
+) -> bool {
+    match event {
+        GamepadEvent::Input(port, input) => {
+            state.held_input[port] = input;
+            if attract_input(nes, state, input) {
+                return true;
+            }
+            if state.menu.is_some() {
+                return menu_input(nes, state, port, input);
+            }
+            state
+                .inputs
+                .set_input(SourceKind::Gamepad, port, Some(input));
+            apply_inputs(nes, state);
+        }
+        GamepadEvent::FastForward(speed) => {
+            state.fast_forward_speed = speed;
+            update_target_time(nes, state);
+        }
+        GamepadEvent::Rewind(rate) => {
+            let pulled = state.rewind_trigger.is_some();
+            state.rewind_trigger = rate;
+            match rate {
+                Some(_) if !pulled => return handle_message(nes, state, Message::StartRewind),
+                None if pulled => return handle_message(nes, state, Message::StopRewind),
+                _ => (),
+            }
+        }
+        GamepadEvent::Hotkey(action) => {
+            let message = hotkey_message(action, state);
+            return handle_message(nes, state, message);
+        }
+        GamepadEvent::Exit => {
+            info!("Kiosk exit chord held, closing application...");
+            return false;
+        }
+        GamepadEvent::Host(event) => {
+            if let Some(menu) = state.menu.as_mut() {
+                menu.host_input(event);
+            }
+        }
+    }
+    true
+}
+
+// Without a game, the gamepads only drive the ROM picker and the kiosk exit chord
+fn picker_gamepad_event(
+    state: &mut EmulationState,
+    frames: Option<&FrameSender>,
+    event: GamepadEvent,
+) -> bool {
+    match event {
+        GamepadEvent::Input(port, input) => {
+            let previous = std::mem::replace(&mut state.held_input[port], input);
+            let pressed = |button: ControllerInput| input & !previous & button as u8 != 0;
+            let picker_input = if pressed(ControllerInput::Left) {
+                Some(PickerInput::Previous)
+            } else if pressed(ControllerInput::Right) {
+                Some(PickerInput::Next)
+            } else if pressed(ControllerInput::A) || pressed(ControllerInput::Start) {
+                Some(PickerInput::Play)
+            } else {
+                None
+            };
+            if let (Some(frames), Some(picker_input)) = (frames, picker_input) {
+                frames.send_picker_input(picker_input);
+            }
+        }
+        GamepadEvent::Exit => {
+            info!("Kiosk exit chord held, closing application...");
+            return false;
+        }
+        _ => (),
+    }
+    true
+}
+
+// Answer the commands received on the control socket, between frames
+#[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
+fn handle_control_requests(nes: &mut impl EmulatorCore, state: &mut EmulationState) {
+    let requests = match &state.control {
+        Some(control) => control.poll(),
+        None => return,
+    };
+    for request in requests {
+        handle_control_request(nes, state, request);
+    }
+}
+
+fn handle_control_request(
+    nes: &mut impl EmulatorCore,
+    state: &mut EmulationState,
+    request: ControlRequest,
+) {
+    let result = match &request.command {
+        ControlCommand::Checkpoint(name) => state.checkpoint_scratch.save(nes).map(|snapshot| {
+            state.checkpoints.insert(name.clone(), snapshot);
+            String::new()
+        }),
+        ControlCommand::Restore(name) => match state.checkpoints.get(name) {
+            Some(snapshot) => state
+                .checkpoint_scratch
+                .load(nes, snapshot, &state.cartridge_path)
+                .map(|_| {
+                    state.stale_audio = true;
+                    String::new()
+                }),
+            None => Err(format!("No checkpoint named {}", name)),
+        },
+        ControlCommand::Delete(name) => match state.checkpoints.remove(name) {
+            Some(_) => Ok(String::new()),
+            None => Err(format!("No checkpoint named {}", name)),
+        },
+        ControlCommand::List => {
+            let mut names: Vec<&str> = state.checkpoints.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            Ok(names.join(" "))
+        }
+        ControlCommand::SendState(address) => {
+            let address = address.clone();
+            match (state.checkpoint_scratch.save(nes), &state.control) {
+                (Ok(snapshot), Some(control)) => {
+                    // Answered once sent, without waiting for the other emulator here
+                    control.send_state(request, address, snapshot, state.osd.clone());
+                    return;
+                }
+                (Ok(_), None) => Err(String::from("No control socket")),
+                (Err(e), _) => Err(e),
+            }
+        }
+        ControlCommand::ReceiveState(snapshot) => state
+            .checkpoint_scratch
+            .load(nes, snapshot, &state.cartridge_path)
+            .map(|_| {
+                state.stale_audio = true;
+                state.osd.notify(OsdLevel::Info, "State received");
+                String::new()
+            }),
+    };
+    if let Err(e) = &result {
+        warn!("Control command failed: {}", e);
+    }
+    request.reply(result);
+}
+
+fn menu_settings(state: &EmulationState) -> MenuSettings {
+    MenuSettings {
+        speed: state.speed,
+        debug: state.debug,
+        filter: state.filter,
+        state_slots: state.state_slots.len(),
+        swap_ab: state.controls.swap_ab,
+        turbo: state.controls.turbo,
+        pal_timing: state.pal_timing,
+        kiosk: state.kiosk,
+    }
+}
+
+// Navigate the pause menu, source being a controller port or the keyboard
+fn menu_input(
+    nes: &mut impl EmulatorCore,
+    state: &mut EmulationState,
+    source: usize,
+    input: u8,
+) -> bool {
+    let settings = menu_settings(state);
+    let action = match state.menu.as_mut() {
+        Some(menu) => menu.input(source, input, &settings),
+        None => None,
+    };
+    match action {
+        Some(MenuAction::Resume) => state.menu = None,
+        Some(MenuAction::SaveState(slot)) => {
+            state.menu = None;
+            let path = state_slot_path(state, slot);
+            return handle_message(nes, state, Message::SaveState(path));
+        }
+        Some(MenuAction::LoadState(slot)) => {
+            state.menu = None;
+            let path = state_slot_path(state, slot);
+            return handle_message(nes, state, Message::LoadState(path));
+        }
+        Some(MenuAction::Reset) => {
+            state.menu = None;
+            return handle_message(nes, state, Message::Reset);
+        }
+        Some(MenuAction::ChangeEmulationSpeed(speed)) => {
+            return handle_message(nes, state, Message::ChangeEmulationSpeed(speed));
+        }
+        Some(MenuAction::ToggleDebugWindow) => {
+            return handle_message(nes, state, Message::ToggleDebugWindow);
+        }
+        Some(MenuAction::CycleFilter) => return handle_message(nes, state, Message::CycleFilter),
+        Some(action @ (MenuAction::ToggleSwapAB | MenuAction::CycleTurbo)) => {
+            if action == MenuAction::ToggleSwapAB {
+                state.controls.swap_ab = !state.controls.swap_ab;
+            } else {
+                let turbo = state.controls.turbo.next();
+                state.controls.set_turbo(turbo);
+            }
+            if let Err(e) = save_controls(&state.rom_path, &state.notes_path, &state.controls) {
+                warn!("Cannot remember the controls for this game: {}", e);
+            }
+        }
+        Some(MenuAction::ToggleTiming) => {
+            state.pal_timing = !state.pal_timing;
+            update_target_time(nes, state);
+            if let Err(e) = save_pal_timing(&state.rom_path, &state.notes_path, state.pal_timing) {
+                warn!("Cannot remember the timing for this game: {}", e);
+            }
+        }
+        Some(MenuAction::Quit) => return false,
+        None => (),
+    }
+    true
+}
+
+// Path of the quick state for slot 0, of a state slot otherwise
+fn state_slot_path(state: &EmulationState, slot: usize) -> String {
+    match slot {
+        0 => state.state_path.clone(),
+        slot => state.state_slots[slot - 1].clone(),
+    }
+}
+
+fn hotkey_message(action: HotkeyAction, state: &EmulationState) -> Message {
+    match action {
+        HotkeyAction::SaveState => Message::SaveState(state.state_path.clone()),
+        HotkeyAction::Save => Message::Save(state.save_path.clone()),
+        HotkeyAction::Reset => Message::Reset,
+        HotkeyAction::ToggleDebugWindow => Message::ToggleDebugWindow,
+        HotkeyAction::CaptureRepro => Message::CaptureRepro(state.repro_path.clone()),
+        HotkeyAction::Screenshot => Message::Screenshot(state.screenshot_path.clone()),
+        HotkeyAction::ToggleRecording => Message::ToggleRecording(state.screenshot_path.clone()),
+        HotkeyAction::SaveReplay => Message::SaveReplay(state.screenshot_path.clone()),
+        HotkeyAction::PushBookmark => Message::PushBookmark,
+        HotkeyAction::PopBookmark => Message::PopBookmark,
+        HotkeyAction::Retry => Message::Retry,
+        HotkeyAction::PauseMenu => Message::TogglePauseMenu,
+        HotkeyAction::Pause => Message::TogglePause,
+        HotkeyAction::FrameAdvance => Message::FrameAdvance,
+    }
+}
+
+// Write a save or a state released by the save guard
+fn write_save(nes: &mut impl EmulatorCore, state: &mut EmulationState, message: Message) {
+    match message {
         Message::SaveState(path) => {
             if let Err(e) = nes.save_state(&path) {
-                error!("Failed to save the emulator state: {}", e);
+                FrontendError::Save(e).report("Failed to save state", &state.osd, state.retry_key);
+                state.failed_message = Some(Message::SaveState(path));
             } else {
-                info!("State successfully saved.");
+                info!("State successfully saved at {}.", path);
+                // Only used to list the states, the state itself was saved
+                if let Err(e) = states::write_metadata(&path, &state.last_frame, state.frame_count)
+                {
+                    warn!("Cannot write the thumbnail of the state: {}", e);
+                }
+                state.hooks.fire(HookEvent::StateSaved, Some(&path));
+                state.osd.notify(
+                    OsdLevel::Info,
+                    format!("State saved to {}", file_name(&path)),
+                );
             }
         }
         Message::Save(path) => {
             if let Err(e) = nes.save(&path) {
-                error!("Failed to save the game: {}", e);
+                FrontendError::Save(e).report("Failed to save game", &state.osd, state.retry_key);
+                state.failed_message = Some(Message::Save(path));
             } else {
                 info!("Game successfully saved at {}.", path);
+                state.hooks.fire(HookEvent::GameSaved, Some(&path));
+                state.osd.notify(OsdLevel::Info, "Game saved");
+            }
+        }
+        _ => (),
+    }
+}
+
+// Write the saves held by the save guard once the battery RAM settled
+fn release_held_saves(nes: &mut impl EmulatorCore, state: &mut EmulationState) {
+    if let Some((messages, settled)) = state.save_guard.frame(nes) {
+        if !settled {
+            warn!("The game is still writing its save, saving anyway.");
+            state.osd.notify(
+                OsdLevel::Warning,
+                "Game still saving, save may be incomplete",
+            );
+        }
+        for message in messages {
+            write_save(nes, state, message);
+        }
+    }
+}
+
+#[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
+fn handle_message(
+    nes: &mut impl EmulatorCore,
+    state: &mut EmulationState,
+    message: Message,
+) -> bool {
+    // A held save belongs to the game as it is now
+    if matches!(
+        message,
+        Message::LoadState(_)
+            | Message::PopBookmark
+            | Message::LoadRom(_)
+            | Message::SwitchGame(..)
+            | Message::Reset
+            | Message::CloseApp
+            | Message::Terminate
+    ) {
+        for save in state.save_guard.take() {
+            write_save(nes, state, save);
+        }
+    }
+    match message {
+        Message::Input(id, input) => {
+            // The keyboard always drives the pause menu
+            if id == 0 {
+                state.held_input[KEYBOARD] = input;
+            }
+            if attract_input(nes, state, input) {
+                return true;
+            }
+            if state.menu.is_some() {
+                return menu_input(nes, state, KEYBOARD, input);
+            }
+            state
+                .inputs
+                .set_input(SourceKind::Keyboard, id, Some(input));
+            apply_inputs(nes, state);
+        }
+        Message::Reset => {
+            nes.reset();
+            state.stale_audio = true;
+        }
+        Message::LoadRom(rom) => {
+            load_rom(nes, state, rom);
+        }
+        Message::SwitchGame(rom, resume) => {
+            save_session(nes, state);
+            let session = rom.session.clone();
+            if load_rom(nes, state, rom) && resume {
+                return handle_message(nes, state, Message::LoadState(session));
+            }
+        }
+        Message::ChangePaletteId(id) => {
+            if let Err(e) = nes.set_debug_palette_id(id) {
+                warn!("Invalid debug palette {}: {}", id, e);
+            }
+            // The tables seen before are drawn with the previous palette
+            if let Some(history) = state.chr_history.as_mut() {
+                history.clear();
+            }
+        }
+        Message::BrowseChrHistory(offset) => match state.chr_history.as_mut() {
+            Some(history) => history.browse(offset),
+            None => warn!("Pattern table history not shown, add chr-history to --debug-panels."),
+        },
+        Message::SpeedUp => {
+            let speed = speed::next_speed(state.speed, true);
+            return handle_message(nes, state, Message::ChangeEmulationSpeed(speed));
+        }
+        Message::SpeedDown => {
+            let speed = speed::next_speed(state.speed, false);
+            return handle_message(nes, state, Message::ChangeEmulationSpeed(speed));
+        }
+        Message::ChangeEmulationSpeed(s) => {
+            state.speed = speed::clamp_speed(s);
+            if state.speed != s {
+                warn!("Speed x{} out of bounds, using x{}.", s, state.speed);
+            }
+            update_target_time(nes, state);
+            let bound = if state.speed >= MAX_SPEED {
+                " (maximum)"
+            } else if state.speed <= MIN_SPEED {
+                " (minimum)"
+            } else if state.speed > MUTED_SPEED {
+                ", muted"
+            } else {
+                ""
+            };
+            state
+                .osd
+                .notify(OsdLevel::Info, format!("Speed x{}{}", state.speed, bound));
+        }
+        Message::ChangeVolume(volume) => {
+            state.volume = volume;
+            state
+                .osd
+                .notify(OsdLevel::Info, format!("Volume {}%", volume));
+        }
+        m @ (Message::SaveState(_) | Message::Save(_)) => {
+            // The emulation does not run to let the battery RAM settle while it is stopped
+            let m = if state.paused || state.time_up {
+                Some(m)
+            } else {
+                state.save_guard.hold(nes, m)
+            };
+            if let Some(m) = m {
+                write_save(nes, state, m);
+            }
+        }
+        Message::LoadState(path) => {
+            if !Path::new(&path).exists() {
+                warn!("No state to load at {}.", path);
+                state.osd.notify(
+                    OsdLevel::Warning,
+                    format!("No state in {}", file_name(&path)),
+                );
+            } else if let Err(e) = nes.load_state(&path, &state.cartridge_path) {
+                error!("Failed to load the emulator state: {}", e);
+                state.osd.notify(OsdLevel::Error, "Failed to load state");
+            } else {
+                info!("State {} successfully loaded.", path);
+                state.stale_audio = true;
+                state.hooks.fire(HookEvent::StateLoaded, Some(&path));
+                state.osd.notify(
+                    OsdLevel::Info,
+                    format!("State loaded from {}", file_name(&path)),
+                );
+            }
+        }
+        Message::CaptureRepro(dir) => {
+            let context = ReproContext {
+                rom_path: &state.rom_path,
+                palette_path: state.palette_path.as_deref(),
+                frame_number: state.frame_count,
+                speed: state.speed,
+            };
+            match capture::capture_repro(Path::new(&dir), nes, &state.last_frame, &context) {
+                Ok(path) => {
+                    info!("Repro capture saved at {}.", path.display());
+                    state
+                        .hooks
+                        .fire(HookEvent::BugCaptured, Some(&path.to_string_lossy()));
+                    state.osd.notify(OsdLevel::Info, "Bug report captured");
+                }
+                Err(e) => {
+                    e.report("Failed to capture", &state.osd, state.retry_key);
+                    state.failed_message = Some(Message::CaptureRepro(dir));
+                }
+            }
+        }
+        Message::RateCompatibility(rating) => {
+            match rate_compatibility(&state.rom_path, &state.notes_path, rating) {
+                Ok(()) => {
+                    info!("Compatibility rated as {}.", rating.name());
+                    state
+                        .osd
+                        .notify(OsdLevel::Info, format!("Rated {}", rating.name()));
+                }
+                Err(e) => {
+                    FrontendError::Save(e).report(
+                        "Failed to save rating",
+                        &state.osd,
+                        state.retry_key,
+                    );
+                    state.failed_message = Some(Message::RateCompatibility(rating));
+                }
+            }
+        }
+        Message::StartRewind => {
+            if state.rewind.is_some() {
+                state.rewinding = true;
+                state.stale_audio = true;
+            } else {
+                state
+                    .osd
+                    .notify(OsdLevel::Warning, "Rewind disabled, see --rewind");
+            }
+        }
+        Message::StopRewind => {
+            state.rewinding = false;
+            state.rewind_steps = 0.0;
+            state.stale_audio = true;
+        }
+        Message::TogglePause => {
+            state.paused = !state.paused;
+            state.advancing = false;
+            let text = if state.paused { "Paused" } else { "Resumed" };
+            state.osd.notify(OsdLevel::Info, text);
+        }
+        Message::ToggleSkipIdle => {
+            state.skip_idle = !state.skip_idle;
+            state.idle.reset();
+            update_target_time(nes, state);
+            let text = if state.skip_idle { "on" } else { "off" };
+            state
+                .osd
+                .notify(OsdLevel::Info, format!("Idle skipping {}", text));
+            if let Err(e) = save_skip_idle(&state.rom_path, &state.notes_path, state.skip_idle) {
+                warn!("Cannot remember idle skipping for this game: {}", e);
+            }
+        }
+        Message::FrameAdvance => {
+            if state.paused {
+                state.advancing = true;
+            } else {
+                state.paused = true;
+                state.osd.notify(OsdLevel::Info, "Paused");
+            }
+        }
+        Message::FastForward(held) => {
+            state.fast_forward_speed = if held { Some(UNCAPPED_SPEED) } else { None };
+            update_target_time(nes, state);
+        }
+        Message::ApplyCheats(patches) => apply_cheats(nes, state, &patches),
+        Message::SetCover(path) => {
+            let result = capture::write_screenshot(
+                Path::new(&path),
+                &state.last_frame,
+                capture::FRAME_WIDTH,
+                capture::FRAME_HEIGHT,
+            );
+            match result {
+                Ok(()) => {
+                    info!("Cover saved to {}.", path);
+                    state.osd.notify(OsdLevel::Info, "Cover saved");
+                }
+                Err(e) => {
+                    e.report("Failed to save cover", &state.osd, state.retry_key);
+                    state.failed_message = Some(Message::SetCover(path));
+                }
+            }
+        }
+        Message::Screenshot(dir) => {
+            let path = capture_path(state, &dir, "png");
+            let mut frame = state.last_frame.clone();
+            if let Some(text) = speed_indicator::text(state.effective_speed(), state.rewinding) {
+                speed_indicator::draw(&mut frame, &text);
+            }
+            let result = fs::create_dir_all(&dir)
+                .map_err(|e| FrontendError::Save(e.to_string()))
+                .and_then(|()| {
+                    capture::write_screenshot(
+                        &path,
+                        &frame,
+                        capture::FRAME_WIDTH,
+                        capture::FRAME_HEIGHT,
+                    )
+                });
+            match result {
+                Ok(()) => {
+                    info!("Screenshot saved to {}.", path.display());
+                    state.osd.notify(
+                        OsdLevel::Info,
+                        format!("Screenshot saved to {}", file_name(&path.to_string_lossy())),
+                    );
+                }
+                Err(e) => {
+                    e.report("Failed to save screenshot", &state.osd, state.retry_key);
+                    state.failed_message = Some(Message::Screenshot(dir));
+                }
+            }
+        }
+        Message::ToggleRecording(dir) => {
+            if let Some(recorder) = state.video_recorder.take() {
+                info!(
+                    "Recording stopped, finishing {}.",
+                    recorder.path().display()
+                );
+                state.osd.notify(OsdLevel::Info, "Recording stopped");
+                state.finishing_videos.push(recorder.stop());
+            } else {
+                let path = capture_path(state, &dir, "mp4");
+                let frame_rate = 1.0 / nes.get_one_frame_duration().as_secs_f64();
+                match VideoRecorder::new(&path, frame_rate) {
+                    Ok(recorder) => {
+                        // The samples are needed even without an audio device
+                        nes.produce_samples(true);
+                        info!("Recording the video to {}.", path.display());
+                        state.osd.notify(OsdLevel::Info, "Recording started");
+                        state.video_recorder = Some(recorder);
+                    }
+                    Err(e) => {
+                        error!("Cannot record the video: {}", e);
+                        state.osd.notify(OsdLevel::Error, "Cannot record the video");
+                    }
+                }
+            }
+        }
+        Message::PushBookmark => match state.checkpoint_scratch.save(nes) {
+            Ok(snapshot) => {
+                if state.bookmarks.len() >= BOOKMARK_DEPTH {
+                    state.bookmarks.remove(0);
+                }
+                state.bookmarks.push(snapshot);
+                state.osd.notify(
+                    OsdLevel::Info,
+                    format!("Bookmark {} pushed", state.bookmarks.len()),
+                );
+            }
+            Err(e) => {
+                error!("Failed to push the bookmark: {}", e);
+                state.osd.notify(OsdLevel::Error, "Failed to push bookmark");
+            }
+        },
+        Message::PopBookmark => {
+            let snapshot = match state.bookmarks.pop() {
+                Some(snapshot) => snapshot,
+                None => {
+                    state
+                        .osd
+                        .notify(OsdLevel::Warning, "No bookmark to go back to");
+                    return true;
+                }
+            };
+            let depth = state.bookmarks.len() + 1;
+            match state
+                .checkpoint_scratch
+                .load(nes, &snapshot, &state.cartridge_path)
+            {
+                Ok(()) => {
+                    state.stale_audio = true;
+                    state
+                        .osd
+                        .notify(OsdLevel::Info, format!("Back to bookmark {}", depth));
+                }
+                Err(e) => {
+                    error!("Failed to load the bookmark: {}", e);
+                    state.bookmarks.push(snapshot);
+                    state.osd.notify(OsdLevel::Error, "Failed to load bookmark");
+                }
+            }
+        }
+        Message::SaveReplay(dir) => {
+            let replay = match state.replay.as_ref() {
+                Some(replay) => replay,
+                None => {
+                    state
+                        .osd
+                        .notify(OsdLevel::Warning, "Replays disabled, see --replay-buffer");
+                    return true;
+                }
+            };
+            let path = capture_path(state, &dir, "movie");
+            let result = fs::create_dir_all(&dir)
+                .map_err(|e| e.to_string())
+                .and_then(|()| rom_identity(&state.rom_path).map_err(|e| e.to_string()))
+                .and_then(|(crc32, _)| replay.write(&path, crc32));
+            match result {
+                Ok(frames) => {
+                    info!("Replay of {} frames saved to {}.", frames, path.display());
+                    state.osd.notify(
+                        OsdLevel::Info,
+                        format!("Replay saved to {}", file_name(&path.to_string_lossy())),
+                    );
+                }
+                Err(e) => {
+                    FrontendError::Save(e).report(
+                        "Failed to save replay",
+                        &state.osd,
+                        state.retry_key,
+                    );
+                    state.failed_message = Some(Message::SaveReplay(dir));
+                }
+            }
+        }
+        Message::Retry => {
+            if let Some(message) = state.failed_message.take() {
+                return handle_message(nes, state, message);
+            }
+        }
+        Message::TogglePauseMenu => {
+            state.menu = match state.menu {
+                Some(_) => None,
+                None => Some(PauseMenu::new(state.held_input)),
+            };
+        }
+        Message::HostInput(event) => {
+            if let Some(menu) = state.menu.as_mut() {
+                menu.host_input(event);
+            }
+        }
+        Message::ToggleDebugWindow => {
+            state.debug = !state.debug;
+            if state.rom_loaded {
+                if let Err(e) = save_debug_window(&state.rom_path, &state.notes_path, state.debug) {
+                    warn!("Cannot remember the debugging window for this game: {}", e);
+                }
+            }
+        }
+        Message::ToggleProfiler => {
+            state.profiler = match state.profiler.take() {
+                Some(profiler) => {
+                    info!("Profiler: {}.", profiler.summary());
+                    state.osd.notify(OsdLevel::Info, "Profiler off");
+                    None
+                }
+                None => {
+                    state.osd.notify(OsdLevel::Info, "Profiler on");
+                    Some(MiniProfiler::new())
+                }
+            };
+        }
+        Message::CycleFilter => {
+            state.filter = state.filter.next();
+            state
+                .osd
+                .notify(OsdLevel::Info, format!("Filter: {}", state.filter.name()));
+        }
+        Message::WindowMinimized(minimized) => {
+            state.minimized = minimized;
+            if minimized && state.pause_when_minimized {
+                info!("Window minimized, emulation paused.");
             }
         }
-        Message::ToggleDebugWindow => gui.toggle_debugging(),
         Message::CloseApp => {
             return false;
         }
+        Message::Terminate => {
+            save_session(nes, state);
+            return false;
+        }
     }
     true
 }
+
+// Write the battery save and the state of the game when the emulator is terminated, as
+// nothing else would be written. The state is resumed with --state <ROM>-session.data.
+fn save_session(nes: &mut impl EmulatorCore, state: &EmulationState) {
+    if !state.rom_loaded {
+        return;
+    }
+    // Games without battery RAM have nothing to save
+    if nes.save(&state.save_path).is_ok() {
+        info!("Game saved at {}.", state.save_path);
+    }
+    match nes.save_state(&state.session_path) {
+        Ok(()) => {
+            info!("Session saved at {}.", state.session_path);
+            if let Err(e) =
+                states::write_metadata(&state.session_path, &state.last_frame, state.frame_count)
+            {
+                warn!("Cannot write the thumbnail of the state: {}", e);
+            }
+        }
+        Err(e) => error!("Failed to save the session: {}", e),
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+// Number of records kept for the log panel
+const MAX_ENTRIES: usize = 200;
+
+struct LogEntry {
+    level: Level,
+    target: String,
+    message: String,
+}
+
+// Recent log records, shared between the logger and the GUI
+#[derive(Clone, Default)]
+pub struct LogBuffer {
+    entries: Arc<Mutex<VecDeque<LogEntry>>>,
+}
+
+impl LogBuffer {
+    fn push(&self, entry: LogEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == MAX_ENTRIES {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    // Last records matching the filter, oldest first
+    pub fn recent(&self, filter: &LogFilter, count: usize) -> Vec<(Level, String)> {
+        let entries = self.entries.lock().unwrap();
+        let mut recent: Vec<(Level, String)> = entries
+            .iter()
+            .rev()
+            .filter(|entry| filter.matches(entry))
+            .take(count)
+            .map(|entry| (entry.level, entry.message.clone()))
+            .collect();
+        recent.reverse();
+        recent
+    }
+}
+
+// Forward the records to env_logger and keep them for the log panel
+pub struct BufferedLogger {
+    inner: env_logger::Logger,
+    buffer: LogBuffer,
+}
+
+impl BufferedLogger {
+    pub fn new(inner: env_logger::Logger, buffer: LogBuffer) -> Self {
+        BufferedLogger { inner, buffer }
+    }
+}
+
+impl Log for BufferedLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        self.inner.log(record);
+        self.buffer.push(LogEntry {
+            level: record.level(),
+            target: String::from(record.target()),
+            message: record.args().to_string(),
+        });
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+// Records displayed in the log panel: a minimum level and target prefixes
+pub struct LogFilter {
+    level: LevelFilter,
+    targets: Vec<String>,
+}
+
+impl LogFilter {
+    // Parse a comma separated list of a level (error, warn, info, debug, trace)
+    // and targets, e.g. "warn,nesmulator_gui". Everything is displayed by default.
+    pub fn parse(spec: Option<&str>) -> Self {
+        let mut filter = LogFilter {
+            level: LevelFilter::Trace,
+            targets: vec![],
+        };
+        for item in spec
+            .unwrap_or("")
+            .split(',')
+            .map(str::trim)
+            .filter(|i| !i.is_empty())
+        {
+            match item.parse::<LevelFilter>() {
+                Ok(level) => filter.level = level,
+                Err(_) => filter.targets.push(String::from(item)),
+            }
+        }
+        filter
+    }
+
+    fn matches(&self, entry: &LogEntry) -> bool {
+        entry.level <= self.level
+            && (self.targets.is_empty() || self.targets.iter().any(|t| entry.target.starts_with(t)))
+    }
+}
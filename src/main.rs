@@ -1,28 +1,297 @@
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::sync::mpsc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use clap::{Arg, Command};
-use log::{error, info};
-use nesmulator_core::utils::ControllerInput;
-use nesmulator_gui::{run, Message, NESConfig};
-use winit::event::{Event, VirtualKeyCode};
+use log::{error, info, warn};
+use nesmulator_gui::{
+    capture_palettes, configure_parental, default_config_path, describe_paths, diff_states,
+    export_compat, export_notes, export_states_page, fuzz_input, is_rom, list_states, pressed_key,
+    run, run_headless, snap, BindingWizard, CheatList, DisplayOption, EmulatorHandle, FrameSleep,
+    FrontendError, Hotkey, KeyBindings, MergePolicy, Message, NESConfig, OsdLevel, PickerInput,
+    Playlist, Rating, RomPaths, RomPicker, SetupWizard, SyncStrategy, ThreadTuning, Turbo,
+    VideoFilter, DEFAULT_WINDOW_SCALE, STATE_SLOTS,
+};
+use winit::event::{ElementState, Event, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit_input_helper::WinitInputHelper;
 
+const DEFAULT_NOTES_PATH: &str = "nesmulator-notes.txt";
+// Keys choosing each compatibility rating after pressing F3
+const RATING_KEYS: [(VirtualKeyCode, Rating); 3] = [
+    (VirtualKeyCode::Key1, Rating::Perfect),
+    (VirtualKeyCode::Key2, Rating::Playable),
+    (VirtualKeyCode::Key3, Rating::Broken),
+];
+// Volume change of the volume hotkeys, in percent
+const VOLUME_STEP: u8 = 10;
+// Hotkeys switching each display option
+const DISPLAY_HOTKEYS: [(Hotkey, DisplayOption); 5] = [
+    (Hotkey::IntegerScaling, DisplayOption::IntegerScaling),
+    (Hotkey::AspectCorrection, DisplayOption::AspectCorrection),
+    (Hotkey::CropOverscan, DisplayOption::CropOverscan),
+    (Hotkey::Fullscreen, DisplayOption::Fullscreen),
+    (Hotkey::FrameBlending, DisplayOption::FrameBlending),
+];
+
 fn main() {
     // CLI creation
     let matches = Command::new("Nesmulator")
         .version("0.1.0")
         .author("AntoineRR")
         .about("nesmulator-gui - CLI to launch a GUI based on the nesmulator-core crate")
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("diff-state")
+                .about("Report the byte regions that differ between two .data state files")
+                .arg(
+                    Arg::new("a")
+                        .index(1)
+                        .value_name("FILE_A")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("b")
+                        .index(2)
+                        .value_name("FILE_B")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("export-notes")
+                .about("Print the notes recorded for every ROM as a markdown table")
+                .arg(
+                    Arg::new("notes-file")
+                        .long("notes-file")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("Specify the file the notes are read from"),
+                ),
+        )
+        .subcommand(
+            Command::new("export-compat")
+                .about("Print the compatibility ratings given with F3 as a markdown table")
+                .arg(
+                    Arg::new("notes-file")
+                        .long("notes-file")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("Specify the file the ratings are read from"),
+                ),
+        )
+        .subcommand(
+            Command::new("parental")
+                .about("Set a daily play time limit, protected by a PIN chosen the first time")
+                .arg(
+                    Arg::new("pin")
+                        .long("pin")
+                        .value_name("PIN")
+                        .takes_value(true)
+                        .required(true)
+                        .help("PIN protecting the limit"),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .long("limit")
+                        .value_name("MINUTES")
+                        .takes_value(true)
+                        .validator(|minutes| minutes.parse::<u64>())
+                        .help("Minutes of play allowed each day, 0 to remove the limit"),
+                )
+                .arg(
+                    Arg::new("extend")
+                        .long("extend")
+                        .value_name("MINUTES")
+                        .takes_value(true)
+                        .validator(|minutes| minutes.parse::<u64>())
+                        .help("Give more minutes of play for today"),
+                ),
+        )
+        .subcommand(
+            Command::new("states")
+                .about("List the states saved for a ROM with their time, frame and thumbnail")
+                .arg(
+                    Arg::new("game")
+                        .index(1)
+                        .value_name("FILE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("html")
+                        .long("html")
+                        .help("Also write a page showing the thumbnails in the temporary folder"),
+                ),
+        )
+        .subcommand(
+            Command::new("paths")
+                .about("Print where the configuration, the notes and the files of a ROM are read and written")
+                .arg(Arg::new("game").index(1).value_name("FILE"))
+                .arg(
+                    Arg::new("notes-file")
+                        .long("notes-file")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("Specify the file the notes are read from"),
+                ),
+        )
+        .subcommand(
+            Command::new("export-bindings")
+                .about("Write the keys of the controller and of the hotkeys to a preset file to share")
+                .arg(
+                    Arg::new("preset")
+                        .index(1)
+                        .value_name("FILE")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("import-bindings")
+                .about("Replace the keys of the configuration file with the ones of a preset file")
+                .arg(
+                    Arg::new("preset")
+                        .index(1)
+                        .value_name("FILE")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("snap")
+                .about("Run a ROM without window for a number of frames and write the last one to a PNG file")
+                .arg(
+                    Arg::new("game")
+                        .index(1)
+                        .value_name("FILE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("frames")
+                        .long("frames")
+                        .value_name("FRAMES")
+                        .takes_value(true)
+                        .default_value("600")
+                        .validator(|frames| frames.parse::<u64>())
+                        .help("Frames to run before writing the last one"),
+                )
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .required(true)
+                        .help("PNG file the frame is written to"),
+                )
+                .arg(
+                    Arg::new("palette")
+                        .short('p')
+                        .long("palette")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("Sets a palette from a .pal file, or builtin for the palette shipped with the emulator"),
+                ),
+        )
+        .subcommand(
+            Command::new("capture-palette")
+                .about("Run a ROM without window and write the colors of the palette RAM at a scanline of each frame to a CSV file")
+                .arg(
+                    Arg::new("game")
+                        .index(1)
+                        .value_name("FILE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("scanline")
+                        .long("scanline")
+                        .value_name("LINE")
+                        .takes_value(true)
+                        .required(true)
+                        .validator(|line| line.parse::<u16>())
+                        .help("Visible scanline at the start of which the palette is captured, from 0 to 239"),
+                )
+                .arg(
+                    Arg::new("frames")
+                        .long("frames")
+                        .value_name("FRAMES")
+                        .takes_value(true)
+                        .default_value("600")
+                        .validator(|frames| frames.parse::<u64>())
+                        .help("Frames to capture"),
+                )
+                .arg(
+                    Arg::new("movie")
+                        .long("movie")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("Play the inputs of a movie recorded with --record-movie"),
+                )
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .required(true)
+                        .help("CSV file the colors are written to"),
+                )
+                .arg(
+                    Arg::new("palette")
+                        .short('p')
+                        .long("palette")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("Sets a palette from a .pal file, or builtin for the palette shipped with the emulator"),
+                ),
+        )
+        .subcommand(
+            Command::new("fuzz-input")
+                .about("Run a ROM without window on random inputs, writing a movie replaying them if the core panics, gets stuck or cannot restore its state")
+                .arg(
+                    Arg::new("game")
+                        .index(1)
+                        .value_name("FILE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("frames")
+                        .long("frames")
+                        .value_name("FRAMES")
+                        .takes_value(true)
+                        .default_value("36000")
+                        .validator(|frames| frames.parse::<u64>())
+                        .help("Frames to run, 10 minutes by default"),
+                )
+                .arg(
+                    Arg::new("seed")
+                        .long("seed")
+                        .value_name("SEED")
+                        .takes_value(true)
+                        .validator(|seed| seed.parse::<u64>())
+                        .help("Seed of the random inputs, to run the same inputs again (default: from the clock)"),
+                )
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .value_name("FOLDER")
+                        .takes_value(true)
+                        .default_value(".")
+                        .help("Folder the movie of a failure is written to"),
+                )
+                .arg(
+                    Arg::new("palette")
+                        .short('p')
+                        .long("palette")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("Sets a palette from a .pal file, or builtin for the palette shipped with the emulator"),
+                ),
+        )
         .arg(
             Arg::new("game")
                 .index(1)
                 .value_name("FILE")
-                .help("Sets the nes file to run in the emulator")
+                .help("Sets the nes file to run in the emulator, several ones to switch between with Comma and Period, or a folder to choose it from (default: the current folder)")
                 .takes_value(true)
-                .required(true),
+                .multiple_values(true),
         )
         .arg(
             Arg::new("debug")
@@ -32,6 +301,25 @@ fn main() {
                 .takes_value(true)
                 .help("Turn debugging information on"),
         )
+        .arg(
+            Arg::new("deterministic")
+                .long("deterministic")
+                .help("Apply the inputs between frames and keep the audio from changing the emulation, so that identical inputs always give identical runs"),
+        )
+        .arg(
+            Arg::new("debug-panels")
+                .long("debug-panels")
+                .value_name("LIST")
+                .takes_value(true)
+                .help("Open the debugging window at startup with the given comma separated panels (patterns, palette, log, chr-history)"),
+        )
+        .arg(
+            Arg::new("log-panel-filter")
+                .long("log-panel-filter")
+                .value_name("FILTER")
+                .takes_value(true)
+                .help("Only display the log records of the given level and targets in the log panel, e.g. \"warn,nesmulator_gui\""),
+        )
         .arg(
             Arg::new("log")
                 .short('l')
@@ -44,7 +332,7 @@ fn main() {
                 .long("palette")
                 .value_name("FILE")
                 .takes_value(true)
-                .help("Sets a palette from a .pal file"),
+                .help("Sets a palette from a .pal file, or builtin for the palette shipped with the emulator"),
         )
         .arg(
             Arg::new("state")
@@ -62,88 +350,1238 @@ fn main() {
                 .takes_value(true)
                 .help("Specify a .sav file to load in the emulator. This works for games that originally provided a save system."),
         )
+        .arg(
+            Arg::new("cheats")
+                .long("cheats")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Specify the file of the cheats, Game Genie or AAAA:VV codes toggled with H (default: <ROM>.cht next to the ROM)"),
+        )
+        .arg(
+            Arg::new("pacing-report")
+                .long("pacing-report")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Write per-frame timing data to a CSV file when closing the emulator"),
+        )
+        .arg(
+            Arg::new("playlist")
+                .long("playlist")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Play the ROMs listed in a file, one per line, switching between them with Comma and Period"),
+        )
+        .arg(
+            Arg::new("pause-when-minimized")
+                .long("pause-when-minimized")
+                .help("Pause the emulation while the window is minimized"),
+        )
+        .arg(
+            Arg::new("input-merge")
+                .long("input-merge")
+                .value_name("POLICY")
+                .takes_value(true)
+                .possible_values(["priority", "combine"])
+                .help("Drive a controller port with its gamepad only (priority, default) or with both its gamepad and the keyboard (combine), overriding the configuration file"),
+        )
+        .arg(
+            Arg::new("turbo")
+                .long("turbo")
+                .value_name("MODE")
+                .takes_value(true)
+                .possible_values(["off", "hold", "tap"])
+                .help("Repeat A and B while held (hold) or from a press to the next one (tap), for the games without their own setting in the pause menu (default off)"),
+        )
+        .arg(
+            Arg::new("skip-idle")
+                .long("skip-idle")
+                .help("Fast-forward the still screens the games wait on without input (J to change it for a game)"),
+        )
+        .arg(
+            Arg::new("refresh-rate")
+                .long("refresh-rate")
+                .value_name("HZ")
+                .takes_value(true)
+                .help("Slightly adjust the emulation speed to match the refresh rate of the monitor when it is close to the NES one (\"auto\" to detect it)"),
+        )
+        .arg(
+            Arg::new("monitor")
+                .long("monitor")
+                .value_name("INDEX")
+                .takes_value(true)
+                .validator(|index| index.parse::<usize>())
+                .help("Open the window on the given monitor, starting from 0"),
+        )
+        .arg(
+            Arg::new("vrr")
+                .long("vrr")
+                .help("Present frames as soon as they are ready for variable refresh rate (G-Sync/FreeSync) monitors, pacing the emulation with the audio by default"),
+        )
+        .arg(
+            Arg::new("black-frame-insertion")
+                .long("black-frame-insertion")
+                .help("Show a black frame after each frame on 120 Hz or faster monitors to reduce motion blur"),
+        )
+        .arg(
+            Arg::new("high-priority")
+                .long("high-priority")
+                .help("Raise the priority of the emulation thread against stutter on busy systems (Linux, thread-tuning feature)"),
+        )
+        .arg(
+            Arg::new("pin-core")
+                .long("pin-core")
+                .value_name("CORE")
+                .takes_value(true)
+                .validator(|core| core.parse::<usize>())
+                .help("Run the emulation thread on a single CPU core, numbered from 0 (thread-tuning feature)"),
+        )
+        .arg(
+            Arg::new("sync")
+                .long("sync")
+                .value_name("STRATEGY")
+                .takes_value(true)
+                .possible_values(["audio-master", "video-master", "off"])
+                .help("Keep the sound in sync by pacing the emulation with the audio (audio-master, default with --vrr), by resampling the sound (video-master, default) or not at all (off)"),
+        )
+        .arg(
+            Arg::new("sleep")
+                .long("sleep")
+                .value_name("MODE")
+                .takes_value(true)
+                .possible_values(["spin", "plain"])
+                .help("Wait for the next frame by sleeping then spinning, regular but keeping a core busy (spin, default), or by sleeping only, to save battery (plain)"),
+        )
+        .arg(
+            Arg::new("sleep-accuracy")
+                .long("sleep-accuracy")
+                .value_name("MICROSECONDS")
+                .takes_value(true)
+                .validator(|accuracy| accuracy.parse::<u64>())
+                .help("How late the system may wake up the emulation, spinning for the rest, default: guessed for the system"),
+        )
+        .arg(
+            Arg::new("scaling")
+                .long("scaling")
+                .value_name("MODE")
+                .takes_value(true)
+                .possible_values(["integer", "fit"])
+                .help("Scale the game by whole numbers only with black borders (integer, default) or to fill the window (fit)"),
+        )
+        .arg(
+            Arg::new("aspect-ratio")
+                .long("aspect-ratio")
+                .help("Display the pixels 8:7 wide as on a TV"),
+        )
+        .arg(
+            Arg::new("crop-overscan")
+                .long("crop-overscan")
+                .help("Hide the 8 lines at the top and bottom of the frame that TVs did not show"),
+        )
+        .arg(
+            Arg::new("frame-blending")
+                .long("frame-blending")
+                .help("Blend each frame with the previous one as a CRT did, making flickering sprites transparent (B to toggle)"),
+        )
+        .arg(
+            Arg::new("fullscreen")
+                .long("fullscreen")
+                .help("Start in borderless fullscreen (F11 to switch)"),
+        )
+        .arg(
+            Arg::new("filter")
+                .long("filter")
+                .value_name("FILTER")
+                .takes_value(true)
+                .possible_values(["none", "scanlines", "crt", "ntsc"])
+                .help("Video filter: scanlines, crt (scanlines on a curved screen) or ntsc (color bleeding of the composite signal). F4 cycles the filters."),
+        )
+        .arg(
+            Arg::new("kiosk")
+                .long("kiosk")
+                .help("Run fullscreen for arcade cabinets and living-room PCs: only gamepads control the emulator, which closes by holding LB + RB + Start for 3 seconds"),
+        )
+        .arg(
+            Arg::new("attract")
+                .long("attract")
+                .value_name("MOVIE")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Play a movie written with --record-movie as a demo when nobody plays, can be given several times to play demos in rotation"),
+        )
+        .arg(
+            Arg::new("attract-idle")
+                .long("attract-idle")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(|seconds| seconds.parse::<u64>())
+                .help("Number of seconds without input before the demos start (default 60)"),
+        )
+        .arg(
+            Arg::new("theme")
+                .long("theme")
+                .value_name("THEME")
+                .takes_value(true)
+                .help("Colors of the pause menu and of the on-screen messages: dark (default), light or a theme file, reloaded when modified"),
+        )
+        .arg(
+            Arg::new("race")
+                .long("race")
+                .help("Run two instances of the game side by side for head-to-head races, the second controller playing on the right"),
+        )
+        .arg(
+            Arg::new("race-audio")
+                .long("race-audio")
+                .value_name("SIDE")
+                .takes_value(true)
+                .possible_values(["left", "right", "mix"])
+                .help("Choose the instance heard during a race (left, right or mix)"),
+        )
+        .arg(
+            Arg::new("record-movie")
+                .long("record-movie")
+                .visible_alias("record")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Write the inputs of both controllers at each frame to a movie file, to be played back with --play-movie or raced with --ghost"),
+        )
+        .arg(
+            Arg::new("play-movie")
+                .long("play-movie")
+                .visible_alias("play")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Drive the controllers with a movie written with --record-movie, from power on or from the same --state"),
+        )
+        .arg(
+            Arg::new("passthrough")
+                .long("passthrough")
+                .requires("play-movie")
+                .help("Show the buttons played by --play-movie on the frame, and press the keys of the first controller on the system keyboard for another emulator to play along (key-passthrough feature)"),
+        )
+        .arg(
+            Arg::new("ghost")
+                .long("ghost")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Play back a movie written with --record-movie as a translucent ghost over live play"),
+        )
+        .arg(
+            Arg::new("shared-frame")
+                .long("shared-frame")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Write each frame to a file that external tools can map in memory, e.g. /dev/shm/nesmulator-frame"),
+        )
+        .arg(
+            Arg::new("timer")
+                .long("timer")
+                .value_name("TRIGGERS")
+                .takes_value(true)
+                .help("Display a time trial timer started and stopped by button chords of the first controller, e.g. \"start=select+a,stop=select+b\""),
+        )
+        .arg(
+            Arg::new("trace")
+                .long("trace")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Write the time spent in each step of the frames to a chrome://tracing file, when built with the profiling feature"),
+        )
+        .arg(
+            Arg::new("rewind")
+                .long("rewind")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(|seconds| seconds.parse::<u64>())
+                .help("Keep the states of the last seconds of emulation in memory, to go back in time while Backspace is held"),
+        )
+        .arg(
+            Arg::new("rewind-interval")
+                .long("rewind-interval")
+                .value_name("FRAMES")
+                .takes_value(true)
+                .validator(|frames| frames.parse::<u32>())
+                .help("Number of frames between two states kept for the rewind (default 2)"),
+        )
+        .arg(
+            Arg::new("replay-buffer")
+                .long("replay-buffer")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(|seconds| seconds.parse::<u64>())
+                .help("Keep the inputs of the last seconds of play, written as a movie and a state with Y"),
+        )
+        .arg(
+            Arg::new("record-ui-events")
+                .long("record-ui-events")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Write the window events with their timestamps to a file, to reproduce GUI bugs"),
+        )
+        .arg(
+            Arg::new("replay-ui-events")
+                .long("replay-ui-events")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Apply the window events (resize, move, focus) of a file written with --record-ui-events"),
+        )
+        .arg(
+            Arg::new("screenshot-dir")
+                .long("screenshot-dir")
+                .value_name("DIR")
+                .takes_value(true)
+                .help("Specify the folder of the screenshots (G) and of the videos (V), default: screenshots next to the ROM"),
+        )
+        .arg(
+            Arg::new("reference")
+                .long("reference")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Load a 256x240 PNG screenshot to compare with the live frame (F6), e.g. a capture of real hardware"),
+        )
+        .arg(
+            Arg::new("reset-at")
+                .long("reset-at")
+                .value_name("TIMES")
+                .takes_value(true)
+                .help("Reset the NES at exact frames or cycles from the start of the emulation, e.g. \"frame:1234,cycle:5000000\""),
+        )
+        .arg(
+            Arg::new("no-gui")
+                .long("no-gui")
+                .help("Run the emulator without opening a window (use with --no-audio to run completely headless)"),
+        )
+        .arg(
+            Arg::new("control-socket")
+                .long("control-socket")
+                .value_name("ADDRESS")
+                .takes_value(true)
+                .help("Listen for commands of external tools on a TCP address, e.g. \"127.0.0.1:4370\""),
+        )
+        .arg(
+            Arg::new("event-hook")
+                .long("event-hook")
+                .value_name("EVENT=COMMAND")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help("Run a shell command on an event (game-loaded, state-saved, state-loaded, game-saved, bug-captured), can be given several times"),
+        )
+        .arg(
+            Arg::new("gamepad-hotkeys")
+                .long("gamepad-hotkeys")
+                .value_name("CHORDS")
+                .takes_value(true)
+                .help("Map emulator functions to gamepad button chords, e.g. \"save-state=back+rightshoulder,reset=back+leftshoulder\""),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Specify the TOML file the keyboard bindings and hotkeys are read from and written to by the binding wizard"),
+        )
+        .arg(
+            Arg::new("print-config")
+                .long("print-config")
+                .help("Print the effective configuration, from the defaults, the configuration file, the NESMULATOR_* environment variables and the options, with the source of each setting"),
+        )
+        .arg(
+            Arg::new("notes-file")
+                .long("notes-file")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Specify the file the known-issue notes of the ROMs are read from and written to"),
+        )
+        .arg(
+            Arg::new("add-note")
+                .long("add-note")
+                .value_name("TEXT")
+                .takes_value(true)
+                .help("Record a note about the ROM (e.g. \"flickers at level 3\"), displayed each time it is loaded"),
+        )
+        .arg(
+            Arg::new("no-audio")
+                .long("no-audio")
+                .help("Run the emulator without sound"),
+        )
+        .arg(
+            Arg::new("volume")
+                .long("volume")
+                .value_name("PERCENT")
+                .takes_value(true)
+                .validator(|percent| match percent.parse::<u8>() {
+                    Ok(percent) if percent <= 100 => Ok(()),
+                    _ => Err("must be between 0 and 100"),
+                })
+                .help("Volume of the sound, from 0 to 100 (default 100)"),
+        )
         .get_matches();
 
-    // Get all configuration informations
-    let rom_path = matches.value_of("game").unwrap();
-    let path_to_rom = Path::new(rom_path);
-    let path_to_rom = path_to_rom
-        .parent()
-        .unwrap()
-        .join(path_to_rom.file_stem().unwrap())
-        .with_extension("data");
-
-    let (state_path, load_state) = match matches.value_of("state") {
-        Some(path) => (String::from(path), true),
-        None => (
-            String::from(path_to_rom.with_extension("data").to_str().unwrap()),
-            false,
-        ),
-    };
-    let save_path = match matches.value_of("save") {
+    // Compare two states without running the emulator
+    if let Some(("diff-state", diff_matches)) = matches.subcommand() {
+        match diff_states(
+            diff_matches.value_of("a").unwrap(),
+            diff_matches.value_of("b").unwrap(),
+        ) {
+            Ok(report) => print!("{}", report),
+            Err(e) => {
+                eprintln!("Cannot compare the states: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    // Export the notes or the compatibility ratings without running the emulator
+    if let Some((name @ ("export-notes" | "export-compat"), export_matches)) = matches.subcommand()
+    {
+        let notes_path = export_matches
+            .value_of("notes-file")
+            .unwrap_or(DEFAULT_NOTES_PATH);
+        let table = if name == "export-notes" {
+            export_notes(notes_path)
+        } else {
+            export_compat(notes_path)
+        };
+        match table {
+            Ok(table) => print!("{}", table),
+            Err(e) => {
+                eprintln!("Cannot export the notes: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    // Change the play time limit without running the emulator
+    if let Some(("parental", parental_matches)) = matches.subcommand() {
+        let minutes = |name: &str| {
+            parental_matches
+                .value_of(name)
+                .map(|minutes| minutes.parse::<u64>().unwrap())
+        };
+        match configure_parental(
+            parental_matches.value_of("pin").unwrap(),
+            minutes("limit"),
+            minutes("extend"),
+        ) {
+            Ok(summary) => println!("{}", summary),
+            Err(e) => {
+                eprintln!("Cannot change the play time limit: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    // Render a frame of a ROM without running the emulator in a window
+    if let Some(("snap", snap_matches)) = matches.subcommand() {
+        let out = snap_matches.value_of("out").unwrap();
+        match snap(
+            snap_matches.value_of("game").unwrap(),
+            snap_matches.value_of("palette"),
+            snap_matches.value_of("frames").unwrap().parse().unwrap(),
+            out,
+        ) {
+            Ok(()) => println!("Frame written to {}", out),
+            Err(e) => {
+                eprintln!("Cannot render the ROM: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    // Capture the palette in the middle of the frames of a ROM without running the emulator
+    if let Some(("capture-palette", capture_matches)) = matches.subcommand() {
+        let out = capture_matches.value_of("out").unwrap();
+        match capture_palettes(
+            capture_matches.value_of("game").unwrap(),
+            capture_matches.value_of("palette"),
+            capture_matches
+                .value_of("scanline")
+                .unwrap()
+                .parse()
+                .unwrap(),
+            capture_matches.value_of("frames").unwrap().parse().unwrap(),
+            capture_matches.value_of("movie"),
+            out,
+        ) {
+            Ok(()) => println!("Palettes written to {}", out),
+            Err(e) => {
+                eprintln!("Cannot capture the palettes: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    // Play random inputs on a ROM to find the ones breaking the core
+    if let Some(("fuzz-input", fuzz_matches)) = matches.subcommand() {
+        let seed = match fuzz_matches.value_of("seed") {
+            Some(seed) => seed.parse().unwrap(),
+            None => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(1, |time| time.as_nanos() as u64),
+        };
+        let frames = fuzz_matches.value_of("frames").unwrap().parse().unwrap();
+        println!(
+            "Playing {} frames of random inputs with the seed {}",
+            frames, seed
+        );
+        match fuzz_input(
+            fuzz_matches.value_of("game").unwrap(),
+            fuzz_matches.value_of("palette"),
+            seed,
+            frames,
+            fuzz_matches.value_of("out").unwrap(),
+        ) {
+            Ok(None) => println!("No failure"),
+            Ok(Some(failure)) => {
+                println!(
+                    "Failure at frame {}: {}
+Movie reproducing it written to {}",
+                    failure.frame, failure.reason, failure.reproducer
+                );
+                exit(1);
+            }
+            Err(e) => {
+                eprintln!("Cannot fuzz the ROM: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    // Keyboard bindings of the first controller and of the hotkeys, and the settings of
+    // the setup wizard, asked on the first run before the configuration file is written
+    let config_path = match matches.value_of("config") {
         Some(path) => String::from(path),
-        None => String::from(path_to_rom.with_extension("sav").to_str().unwrap()),
+        None => String::from(default_config_path().to_string_lossy()),
     };
 
+    let first_run = !Path::new(&config_path).exists();
+    // The environment overrides the configuration file, and the options override both
+    let config = if first_run {
+        Ok(KeyBindings::default())
+    } else {
+        KeyBindings::load(&config_path)
+    }
+    .and_then(|mut bindings| bindings.apply_env().map(|()| bindings))
+    .map_err(FrontendError::Config);
+    let save_dir = config
+        .as_ref()
+        .ok()
+        .and_then(KeyBindings::save_dir)
+        .map(String::from);
+
+    // Print the effective settings and where they come from without running the emulator
+    if matches.is_present("print-config") {
+        let mut bindings = config.unwrap_or_else(|e| {
+            eprintln!("Invalid configuration: {}", e);
+            exit(1);
+        });
+        let mut command_line = vec![];
+        if let Some(merge) = matches
+            .value_of("input-merge")
+            .and_then(MergePolicy::from_name)
+        {
+            bindings.set_input_merge(merge);
+            command_line.push(("input", "merge"));
+        }
+        print!("{}", bindings.describe(&config_path, &command_line));
+        return;
+    }
+
+    // List the states of a ROM without running the emulator
+    if let Some(("states", states_matches)) = matches.subcommand() {
+        let rom_path = states_matches.value_of("game").unwrap();
+        match list_states(rom_path, save_dir.as_deref()) {
+            Ok(table) => print!("{}", table),
+            Err(e) => {
+                eprintln!("Cannot list the states: {}", e);
+                exit(1);
+            }
+        }
+        if states_matches.is_present("html") {
+            match export_states_page(rom_path, save_dir.as_deref()) {
+                Ok(page) => println!("Thumbnails written to {}", page.display()),
+                Err(e) => {
+                    eprintln!("Cannot write the page of the states: {}", e);
+                    exit(1);
+                }
+            }
+        }
+        return;
+    }
+
+    // Share the keys with other players without running the emulator
+    if let Some(("export-bindings", export_matches)) = matches.subcommand() {
+        let preset = export_matches.value_of("preset").unwrap();
+        let bindings = config.unwrap_or_else(|e| {
+            eprintln!("Invalid configuration file: {}", e);
+            exit(1);
+        });
+        match bindings.export_preset(preset) {
+            Ok(()) => println!("Bindings written to {}", preset),
+            Err(e) => {
+                eprintln!("Cannot write the preset: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(("import-bindings", import_matches)) = matches.subcommand() {
+        let preset = import_matches.value_of("preset").unwrap();
+        let mut bindings = config.unwrap_or_else(|e| {
+            eprintln!("Invalid configuration file: {}", e);
+            exit(1);
+        });
+        if let Err(e) = bindings.import_preset(preset) {
+            eprintln!("Invalid preset {}: {}", preset, e);
+            exit(1);
+        }
+        match bindings.save(&config_path) {
+            Ok(()) => println!("Bindings of {} written to {}", preset, config_path),
+            Err(e) => {
+                eprintln!("Cannot write the configuration file: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    // Show where the files are without running the emulator
+    if let Some(("paths", paths_matches)) = matches.subcommand() {
+        let rom_paths = paths_matches
+            .value_of("game")
+            .map(|path| rom_files(path, None, save_dir.as_deref()));
+        print!(
+            "{}",
+            describe_paths(
+                &config_path,
+                paths_matches
+                    .value_of("notes-file")
+                    .unwrap_or(DEFAULT_NOTES_PATH),
+                rom_paths.as_ref(),
+            )
+        );
+        return;
+    }
+
+    // Get all configuration informations
+    // Several ROM files, or a playlist, are played one after the other with the next and
+    // previous game hotkeys. Without a ROM file, the ROM is chosen in a picker or dropped on
+    // the window.
+    let games: Vec<&str> = matches.values_of("game").map_or(vec![], Iterator::collect);
+    let mut playlist_roms: Vec<String> = games
+        .iter()
+        .filter(|path| !Path::new(path).is_dir())
+        .map(|path| String::from(*path))
+        .collect();
+    if games.len() > 1 && playlist_roms.len() < games.len() {
+        eprintln!("A folder cannot be given with other games.");
+        exit(1);
+    }
+    if let Some(path) = matches.value_of("playlist") {
+        match Playlist::load(path) {
+            Ok(roms) => playlist_roms.extend(roms),
+            Err(e) => {
+                eprintln!("Cannot read the playlist {}: {}", path, e);
+                exit(1);
+            }
+        }
+    }
+    let mut playlist = Playlist::new(playlist_roms);
+    let game = games.first().copied();
+    let rom_path = playlist.current().map(String::from);
+    let rom_path = rom_path.as_deref();
+    if rom_path.is_none() {
+        // These options apply to the ROM given on the command line
+        let options = [
+            "state",
+            "save",
+            "cheats",
+            "no-gui",
+            "race",
+            "ghost",
+            "record-movie",
+            "play-movie",
+            "attract",
+        ];
+        if let Some(name) = options.iter().find(|name| matches.is_present(name)) {
+            eprintln!("--{} needs a ROM file.", name);
+            exit(1);
+        }
+    }
+    let mut rom_picker = match (rom_path, game.unwrap_or(".")) {
+        (Some(_), _) => None,
+        (None, dir) => match RomPicker::new(Path::new(dir)) {
+            Ok(picker) if !picker.is_empty() => Some(picker),
+            Ok(_) => None,
+            Err(e) => {
+                eprintln!("Cannot list the ROMs of {}: {}", dir, e);
+                None
+            }
+        },
+    };
+
+    // Files cannot be dropped on the window in the kiosk mode, the gamepads only drive the picker
+    if matches.is_present("kiosk") && rom_path.is_none() && rom_picker.is_none() {
+        eprintln!("--kiosk needs a ROM file or a folder holding ROMs.");
+        exit(1);
+    }
+
+    // Files of the ROM written next to it, the states and the save being given on the command line
+    let screenshot_dir = matches.value_of("screenshot-dir").map(String::from);
+    let load_state = matches.is_present("state");
+    let mut rom_paths =
+        rom_path.map(|path| rom_files(path, screenshot_dir.as_deref(), save_dir.as_deref()));
+    if let Some(rom_paths) = rom_paths.as_mut() {
+        if let Some(path) = matches.value_of("state") {
+            rom_paths.state = String::from(path);
+        }
+        if let Some(path) = matches.value_of("save") {
+            rom_paths.save = String::from(path);
+        }
+        if let Some(path) = matches.value_of("cheats") {
+            rom_paths.cheats = String::from(path);
+        }
+    }
+
     let palette_path = matches.value_of("palette");
     let display_cpu_logs = matches.is_present("log");
     let debug_level = matches.value_of("debug");
+    let enable_audio = !matches.is_present("no-audio");
+    let volume = matches
+        .value_of("volume")
+        .map(|percent| percent.parse::<u8>().unwrap())
+        .unwrap_or(100);
+    let no_gui = matches.is_present("no-gui");
+    let pacing_report_path = matches.value_of("pacing-report");
+    let trace_path = matches.value_of("trace");
+    let debug_panels = matches.value_of("debug-panels");
+    let gamepad_hotkeys = matches.value_of("gamepad-hotkeys");
+    let pause_when_minimized = matches.is_present("pause-when-minimized");
+    let skip_idle = matches.is_present("skip-idle");
+    // The option overrides the configuration file and the environment
+    let input_merge = matches
+        .value_of("input-merge")
+        .and_then(MergePolicy::from_name)
+        .unwrap_or_else(|| {
+            config
+                .as_ref()
+                .map_or(MergePolicy::Priority, KeyBindings::input_merge)
+        });
+    let turbo = matches
+        .value_of("turbo")
+        .and_then(Turbo::from_name)
+        .unwrap_or(Turbo::Off);
+    let refresh_rate = matches.value_of("refresh-rate");
+    let vrr = matches.is_present("vrr");
+    let black_frame_insertion = matches.is_present("black-frame-insertion");
+    let thread_tuning = ThreadTuning {
+        high_priority: matches.is_present("high-priority"),
+        core: matches
+            .value_of("pin-core")
+            .map(|core| core.parse().unwrap()),
+    };
+    let sync = matches
+        .value_of("sync")
+        .and_then(SyncStrategy::from_name)
+        .unwrap_or(if vrr {
+            SyncStrategy::AudioMaster
+        } else {
+            SyncStrategy::VideoMaster
+        });
+    let plain_sleep = matches.value_of("sleep") == Some("plain");
+    let sleep_accuracy = matches
+        .value_of("sleep-accuracy")
+        .map(|accuracy| Duration::from_micros(accuracy.parse().unwrap()));
+    // The logger is only set up once the emulation starts
+    if plain_sleep && sleep_accuracy.is_some() {
+        eprintln!("--sleep-accuracy has no effect with --sleep plain.");
+    }
+    let frame_sleep = FrameSleep::new(plain_sleep, sleep_accuracy);
+    let record_ui_events_path = matches.value_of("record-ui-events");
+    let replay_ui_events_path = matches.value_of("replay-ui-events");
+    let reset_at = matches.value_of("reset-at");
+    let log_panel_filter = matches.value_of("log-panel-filter");
+    let notes_path = matches.value_of("notes-file").unwrap_or(DEFAULT_NOTES_PATH);
+    let add_note = matches.value_of("add-note");
+    let race = matches.is_present("race");
+    let race_audio = matches.value_of("race-audio");
+    let ghost_path = matches.value_of("ghost");
+    let timer = matches.value_of("timer");
+    let reference_path = matches.value_of("reference");
+    let shared_frame_path = matches.value_of("shared-frame");
+    let record_movie_path = matches.value_of("record-movie");
+    let play_movie_path = matches.value_of("play-movie");
+    let passthrough_keys = matches.is_present("passthrough").then(|| {
+        config.as_ref().map_or_else(
+            |_| KeyBindings::default().controller_keys(),
+            KeyBindings::controller_keys,
+        )
+    });
+    // Movies only play back as recorded when the inputs are applied between frames
+    let deterministic = matches.is_present("deterministic")
+        || record_movie_path.is_some()
+        || play_movie_path.is_some();
+    let rewind_seconds = matches
+        .value_of("rewind")
+        .map(|seconds| seconds.parse::<u64>().unwrap());
+    let rewind_interval = matches
+        .value_of("rewind-interval")
+        .map(|frames| frames.parse::<u32>().unwrap());
+    let replay_seconds = matches
+        .value_of("replay-buffer")
+        .map(|seconds| seconds.parse::<u64>().unwrap());
+    let control_address = matches.value_of("control-socket");
+    let integer_scaling = matches.value_of("scaling") != Some("fit");
+    let aspect_correction = matches.is_present("aspect-ratio");
+    let crop_overscan = matches.is_present("crop-overscan");
+    let frame_blending = matches.is_present("frame-blending");
+    let fullscreen = matches.is_present("fullscreen");
+    let filter = matches
+        .value_of("filter")
+        .and_then(VideoFilter::from_name)
+        .unwrap_or(VideoFilter::None);
+    let theme = matches.value_of("theme");
+    let kiosk = matches.is_present("kiosk");
+    let attract_movies = matches
+        .values_of("attract")
+        .map(|movies| movies.collect())
+        .unwrap_or_default();
+    let attract_idle_seconds = matches
+        .value_of("attract-idle")
+        .map(|seconds| seconds.parse::<u64>().unwrap());
+    let event_hooks = matches
+        .values_of("event-hook")
+        .map(|hooks| hooks.collect())
+        .unwrap_or_default();
+    let monitor = matches
+        .value_of("monitor")
+        .map(|index| index.parse::<usize>().unwrap());
+
+    let nes_config = NESConfig {
+        rom: rom_paths.as_ref(),
+        palette_path,
+        load_state,
+        debug_level,
+        display_cpu_logs,
+        enable_audio,
+        volume,
+        pacing_report_path,
+        trace_path,
+        debug_panels,
+        gamepad_hotkeys,
+        pause_when_minimized,
+        skip_idle,
+        input_merge,
+        turbo,
+        refresh_rate,
+        vrr,
+        black_frame_insertion,
+        thread_tuning,
+        sync,
+        frame_sleep,
+        monitor,
+        record_ui_events_path,
+        replay_ui_events_path,
+        reset_at,
+        log_panel_filter,
+        notes_path,
+        config_path: &config_path,
+        add_note,
+        race,
+        race_audio,
+        record_movie_path,
+        play_movie_path,
+        passthrough_keys,
+        ghost_path,
+        timer,
+        reference_path,
+        shared_frame_path,
+        deterministic,
+        rewind_seconds,
+        rewind_interval,
+        replay_seconds,
+        control_address,
+        event_hooks,
+        integer_scaling,
+        aspect_correction,
+        crop_overscan,
+        frame_blending,
+        fullscreen,
+        window_scale: config
+            .as_ref()
+            .map_or(DEFAULT_WINDOW_SCALE, KeyBindings::window_scale),
+        filter,
+        theme,
+        kiosk,
+        attract_movies,
+        attract_idle_seconds,
+        retry_key: config.as_ref().map_or_else(
+            |_| KeyBindings::default().hotkey_name(Hotkey::Retry),
+            |bindings| bindings.hotkey_name(Hotkey::Retry),
+        ),
+    };
+    let (tx, rx): (mpsc::Sender<Message>, mpsc::Receiver<Message>) = mpsc::channel();
+
+    // The enabled cheats are applied once the ROM is loaded
+    let mut cheat_list = match rom_paths.as_ref().map(|rom| CheatList::load(&rom.cheats)) {
+        Some(Ok(list)) => Some(list),
+        Some(Err(e)) => {
+            eprintln!("Invalid cheats file: {}", e);
+            exit(1);
+        }
+        None => None,
+    };
+    if let Some(patches) = cheat_list.as_ref().map(CheatList::rom_patches) {
+        if !patches.is_empty() {
+            tx.send(Message::ApplyCheats(patches)).unwrap();
+        }
+    }
+
+    // Ctrl+C, SIGTERM and closing the console save the game before the emulation stops,
+    // the window then being closed as when quitting from the pause menu
+    let signal_tx = tx.clone();
+    let handler = ctrlc::set_handler(move || {
+        info!("Terminated, saving the game...");
+        // The emulation already stopped if the message cannot be sent
+        let _ = signal_tx.send(Message::Terminate);
+    });
+    // Printed as the logger is only set up once the emulation starts
+    if let Err(e) = handler {
+        eprintln!("Cannot handle the termination signals: {}", e);
+    }
+
+    // Without a window, the emulation runs on this thread until it is terminated
+    if no_gui {
+        if let Err(e) = run_headless(nes_config, rx) {
+            eprintln!("Cannot start the emulation: {}", e);
+            exit(1);
+        }
+        return;
+    }
 
     // Create the GUI for displaying the graphics
     let event_loop = EventLoop::new();
-    let (tx, rx): (mpsc::Sender<Message>, mpsc::Receiver<Message>) = mpsc::channel();
+    let mut handle = run(nes_config, &event_loop, rx).unwrap_or_else(|e| {
+        match e {
+            FrontendError::Rom(_) => eprintln!("Cannot start the emulation: {}", e),
+            _ => eprintln!("Cannot open the window: {}", e),
+        }
+        exit(1);
+    });
+    if let Some(list) = &cheat_list {
+        report_cheats(list, &handle);
+    }
+    if let Some(picker) = &rom_picker {
+        handle.set_prompt(Some(picker.prompt()));
+    }
+    // The hotkeys writing files are ignored by the emulation until a ROM is loaded
+    let mut rom_paths = rom_paths.unwrap_or_default();
 
-    run(
-        NESConfig {
-            rom_path,
-            palette_path,
-            state_path: &state_path,
-            load_state,
-            save_path: &save_path,
-            debug_level,
-            display_cpu_logs,
-        },
-        &event_loop,
-        rx,
-    );
+    let mut key_bindings = config.unwrap_or_else(|e| {
+        warn!("Invalid configuration file, using default bindings: {}", e);
+        handle.notify(OsdLevel::Warning, "Invalid configuration file");
+        KeyBindings::default()
+    });
+    create_save_dir(&key_bindings, &handle);
+    // The kiosk mode cannot answer the setup wizard, the default configuration is written
+    let mut setup_wizard = None;
+    if first_run && kiosk {
+        save_config(&key_bindings, &config_path, &handle);
+    } else if first_run {
+        let wizard = SetupWizard::new(&config_path);
+        handle.set_prompt(Some(wizard.prompt()));
+        setup_wizard = Some(wizard);
+    }
 
     // Run the event loop
     let mut palette_id = 0;
-    let mut speed = 1.0;
+    let mut volume = volume;
+    let mut minimized = false;
+    let mut binding_wizard: Option<BindingWizard> = None;
+    let mut rating_prompt = false;
+    let mut cheat_prompt = false;
     let mut input_helper = WinitInputHelper::new();
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Wait;
+        handle.record_ui_event(&event);
+
+        // The controller test of the pause menu lists the keys as the window reports them
+        if let Event::WindowEvent {
+            event: WindowEvent::KeyboardInput { input, .. },
+            ..
+        } = &event
+        {
+            let key = match input.virtual_keycode {
+                Some(key) => format!("Key {:?}", key),
+                None => format!("Scancode {}", input.scancode),
+            };
+            let change = match input.state {
+                ElementState::Pressed => "pressed",
+                ElementState::Released => "released",
+            };
+            send_message(
+                &tx,
+                Message::HostInput(format!("{} {}", key, change)),
+                control_flow,
+            );
+        }
+
+        // Present the frames sent by the emulation thread
+        match event {
+            Event::UserEvent(()) => {
+                // Quit was chosen in the pause menu
+                if handle.emulation_stopped() {
+                    info!("Closing application...");
+                    handle.join();
+                    exit(0);
+                }
+                // The gamepads drive the ROM picker, which is the only way to choose a game in
+                // the kiosk mode
+                let inputs = handle.picker_inputs();
+                if let Some(picker) = rom_picker.as_mut().filter(|_| setup_wizard.is_none()) {
+                    if let Some(rom) = pick_rom(picker, &inputs, &mut handle) {
+                        let rom = rom_files(
+                            &rom.to_string_lossy(),
+                            screenshot_dir.as_deref(),
+                            key_bindings.save_dir(),
+                        );
+                        cheat_list = load_rom(&rom, &tx, &handle, control_flow);
+                        rom_paths = rom;
+                        rom_picker = None;
+                        handle.set_prompt(None);
+                    }
+                }
+                handle.redraw();
+            }
+            Event::RedrawRequested(_) => handle.present(),
+            Event::MainEventsCleared => {
+                if let Some(next) = handle.replay_ui_events() {
+                    *control_flow = ControlFlow::WaitUntil(next);
+                }
+            }
+            _ => (),
+        }
+
+        // Play the ROM dropped on the window, replacing the current one
+        if let Event::WindowEvent {
+            event: WindowEvent::DroppedFile(path),
+            ..
+        } = &event
+        {
+            // Only the gamepads control the kiosk mode
+            if kiosk {
+                return;
+            }
+            if is_rom(path) {
+                let rom = rom_files(
+                    &path.to_string_lossy(),
+                    screenshot_dir.as_deref(),
+                    key_bindings.save_dir(),
+                );
+                cheat_list = load_rom(&rom, &tx, &handle, control_flow);
+                cheat_prompt = false;
+                rom_paths = rom;
+                if rom_picker.take().is_some() && setup_wizard.is_none() {
+                    handle.set_prompt(None);
+                }
+            } else {
+                handle.notify(OsdLevel::Warning, "Only .nes files can be played");
+            }
+            return;
+        }
 
-        if let Event::RedrawRequested(_) = event {
-            send_message(&tx, Message::DrawFrame, control_flow);
+        // Closing or resizing the debug window must not affect the main window
+        if let Event::WindowEvent { window_id, event } = &event {
+            if handle.is_debug_window(*window_id) {
+                match event {
+                    WindowEvent::CloseRequested => {
+                        send_message(&tx, Message::ToggleDebugWindow, control_flow);
+                        return;
+                    }
+                    WindowEvent::Resized(size) => {
+                        handle.resize_debug(size.width, size.height);
+                        return;
+                    }
+                    _ => (),
+                }
+            }
         }
 
         if input_helper.update(&event) {
-            // Close event
-            if input_helper.key_pressed(VirtualKeyCode::Escape) || input_helper.quit() {
+            // Escape opens the pause menu once a ROM is loaded, prompts handling it themselves.
+            // The kiosk mode is only closed with the gamepad exit chord.
+            let escape = input_helper.key_pressed(VirtualKeyCode::Escape)
+                && binding_wizard.is_none()
+                && setup_wizard.is_none()
+                && !rating_prompt
+                && !cheat_prompt
+                && rom_picker.is_none();
+            if escape && !kiosk && !rom_paths.rom.is_empty() {
+                send_message(&tx, Message::TogglePauseMenu, control_flow);
+            }
+            let close_requested = input_helper.quit() || (escape && rom_paths.rom.is_empty());
+            if close_requested && !kiosk {
                 *control_flow = ControlFlow::Exit;
                 send_message(&tx, Message::CloseApp, control_flow);
                 info!("Closing application...");
+                handle.join();
                 exit(0);
             }
             // Resize event
             if let Some(size) = input_helper.window_resized() {
-                send_message(
-                    &tx,
-                    Message::ResizeWindow(size.width, size.height),
-                    control_flow,
-                );
+                if handle.resize(size.width, size.height) != minimized {
+                    minimized = !minimized;
+                    send_message(&tx, Message::WindowMinimized(minimized), control_flow);
+                }
+            }
+            // Only the gamepads control the kiosk mode
+            if kiosk {
+                return;
+            }
+            // The setup wizard captures the keyboard until it is answered, Escape keeping the
+            // default configuration
+            if let Some(wizard) = &mut setup_wizard {
+                if input_helper.key_pressed(VirtualKeyCode::Left) {
+                    wizard.previous();
+                }
+                if input_helper.key_pressed(VirtualKeyCode::Right) {
+                    wizard.next();
+                }
+                let answers = if input_helper.key_pressed(VirtualKeyCode::Escape) {
+                    Some(KeyBindings::default())
+                } else if input_helper.key_pressed(VirtualKeyCode::Return) {
+                    wizard.confirm()
+                } else {
+                    None
+                };
+                match answers {
+                    Some(bindings) => {
+                        key_bindings = bindings;
+                        setup_wizard = None;
+                        handle.set_prompt(rom_picker.as_ref().map(RomPicker::prompt));
+                        handle.set_window_scale(key_bindings.window_scale());
+                        create_save_dir(&key_bindings, &handle);
+                        save_config(&key_bindings, &config_path, &handle);
+                    }
+                    None => handle.set_prompt(Some(wizard.prompt())),
+                }
+                send_message(&tx, Message::Input(0, 0), control_flow);
+                return;
+            }
+            // The binding wizard captures the keyboard while it runs
+            if let Some(wizard) = &mut binding_wizard {
+                if input_helper.key_pressed(VirtualKeyCode::Escape) {
+                    binding_wizard = None;
+                    handle.set_prompt(None);
+                    handle.notify(OsdLevel::Info, "Key binding cancelled");
+                } else if let Some(key) = pressed_key(|k| input_helper.key_pressed(k)) {
+                    match wizard.press(key) {
+                        Some(bindings) => {
+                            key_bindings = bindings;
+                            binding_wizard = None;
+                            handle.set_prompt(None);
+                            match key_bindings.save(&config_path) {
+                                Ok(()) => handle.notify(OsdLevel::Info, "Key bindings saved"),
+                                Err(e) => {
+                                    error!("Failed to save key bindings: {}", e);
+                                    handle.notify(OsdLevel::Error, "Failed to save key bindings");
+                                }
+                            }
+                        }
+                        None => handle.set_prompt(Some(wizard.prompt())),
+                    }
+                }
+                send_message(&tx, Message::Input(0, 0), control_flow);
+                return;
+            }
+            // Choosing the ROM to play, which cannot be escaped
+            if let Some(picker) = &mut rom_picker {
+                let inputs: Vec<PickerInput> = [
+                    (VirtualKeyCode::Left, PickerInput::Previous),
+                    (VirtualKeyCode::Right, PickerInput::Next),
+                    (VirtualKeyCode::Return, PickerInput::Play),
+                ]
+                .iter()
+                .filter(|(key, _)| input_helper.key_pressed(*key))
+                .map(|(_, input)| *input)
+                .collect();
+                if let Some(rom) = pick_rom(picker, &inputs, &mut handle) {
+                    let rom = rom_files(
+                        &rom.to_string_lossy(),
+                        screenshot_dir.as_deref(),
+                        key_bindings.save_dir(),
+                    );
+                    cheat_list = load_rom(&rom, &tx, &handle, control_flow);
+                    rom_paths = rom;
+                    rom_picker = None;
+                    handle.set_prompt(None);
+                }
+                return;
+            }
+            // Waiting for the compatibility rating of the game
+            if rating_prompt {
+                let rating = RATING_KEYS
+                    .iter()
+                    .find(|(key, _)| input_helper.key_pressed(*key))
+                    .map(|(_, rating)| *rating);
+                if let Some(rating) = rating {
+                    send_message(&tx, Message::RateCompatibility(rating), control_flow);
+                }
+                if rating.is_some() || input_helper.key_pressed(VirtualKeyCode::Escape) {
+                    rating_prompt = false;
+                    handle.set_prompt(None);
+                }
+                // The number keys must not load a state slot
+                return;
+            }
+            // Choosing the cheats to enable
+            if cheat_prompt {
+                if let Some(list) = &mut cheat_list {
+                    if input_helper.key_pressed(VirtualKeyCode::Left) {
+                        list.previous();
+                    }
+                    if input_helper.key_pressed(VirtualKeyCode::Right) {
+                        list.next();
+                    }
+                    if input_helper.key_pressed(VirtualKeyCode::Return) {
+                        match list.toggle_selected() {
+                            Ok(()) => {
+                                if let Err(e) = list.save() {
+                                    error!("Failed to save the cheats to {}: {}", list.path(), e);
+                                    handle.notify(OsdLevel::Error, "Failed to save the cheats");
+                                }
+                                send_message(
+                                    &tx,
+                                    Message::ApplyCheats(list.rom_patches()),
+                                    control_flow,
+                                );
+                            }
+                            Err(e) => handle.notify(OsdLevel::Warning, e),
+                        }
+                    }
+                    handle.set_prompt(Some(list.prompt()));
+                }
+                if input_helper.key_pressed(VirtualKeyCode::Escape) {
+                    cheat_prompt = false;
+                    handle.set_prompt(None);
+                }
+                return;
             }
             // Debug window
-            if input_helper.key_pressed(VirtualKeyCode::E) {
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::Debug)) {
                 send_message(&tx, Message::ToggleDebugWindow, control_flow);
             }
             // Reset
-            if input_helper.key_pressed(VirtualKeyCode::R) {
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::Reset)) {
                 send_message(&tx, Message::Reset, control_flow);
             }
             // Change debug palette
-            if input_helper.key_pressed(VirtualKeyCode::Left) {
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::PreviousPalette)) {
                 if palette_id == 0 {
                     palette_id = 7;
                 } else {
@@ -151,7 +1589,7 @@ fn main() {
                 }
                 send_message(&tx, Message::ChangePaletteId(palette_id), control_flow);
             }
-            if input_helper.key_pressed(VirtualKeyCode::Right) {
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::NextPalette)) {
                 if palette_id == 7 {
                     palette_id = 0;
                 } else {
@@ -160,53 +1598,298 @@ fn main() {
                 send_message(&tx, Message::ChangePaletteId(palette_id), control_flow);
             }
             // Change emulation speed
-            if input_helper.key_pressed(VirtualKeyCode::Up) {
-                speed += 0.5;
-                send_message(&tx, Message::ChangeEmulationSpeed(speed), control_flow);
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::SpeedUp)) {
+                send_message(&tx, Message::SpeedUp, control_flow);
+            }
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::SpeedDown)) {
+                send_message(&tx, Message::SpeedDown, control_flow);
+            }
+            // Change volume
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::VolumeUp)) {
+                volume = (volume + VOLUME_STEP).min(100);
+                send_message(&tx, Message::ChangeVolume(volume), control_flow);
             }
-            if input_helper.key_pressed(VirtualKeyCode::Down) {
-                speed -= 0.5;
-                send_message(&tx, Message::ChangeEmulationSpeed(speed), control_flow);
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::VolumeDown)) {
+                volume = volume.saturating_sub(VOLUME_STEP);
+                send_message(&tx, Message::ChangeVolume(volume), control_flow);
             }
             // Save state
-            if input_helper.key_pressed(VirtualKeyCode::M) {
-                send_message(&tx, Message::SaveState(state_path.clone()), control_flow);
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::SaveState)) {
+                send_message(
+                    &tx,
+                    Message::SaveState(rom_paths.state.clone()),
+                    control_flow,
+                );
+            }
+            // Save or load a state slot
+            for (slot, path) in rom_paths.state_slots.iter().enumerate() {
+                if input_helper.key_pressed(key_bindings.hotkey(Hotkey::StateSlot(slot))) {
+                    let message = if input_helper.held_shift() {
+                        Message::SaveState(path.clone())
+                    } else {
+                        Message::LoadState(path.clone())
+                    };
+                    send_message(&tx, message, control_flow);
+                }
+            }
+            // Go back in time while the rewind key is held
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::Rewind)) {
+                send_message(&tx, Message::StartRewind, control_flow);
+            }
+            if input_helper.key_released(key_bindings.hotkey(Hotkey::Rewind)) {
+                send_message(&tx, Message::StopRewind, control_flow);
+            }
+            // Pause, advance one frame while paused, or run as fast as possible while held
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::Pause)) {
+                send_message(&tx, Message::TogglePause, control_flow);
+            }
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::FrameAdvance)) {
+                send_message(&tx, Message::FrameAdvance, control_flow);
+            }
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::FastForward)) {
+                send_message(&tx, Message::FastForward(true), control_flow);
+            }
+            if input_helper.key_released(key_bindings.hotkey(Hotkey::FastForward)) {
+                send_message(&tx, Message::FastForward(false), control_flow);
+            }
+            // Fast-forward the still screens of the game or not
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::SkipIdle)) {
+                send_message(&tx, Message::ToggleSkipIdle, control_flow);
             }
             // Save game as in the original NES
-            if input_helper.key_pressed(VirtualKeyCode::L) {
-                send_message(&tx, Message::Save(save_path.clone()), control_flow);
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::Save)) {
+                send_message(&tx, Message::Save(rom_paths.save.clone()), control_flow);
             }
-            // Controller inputs
-            let mut input = 0;
-            if input_helper.key_held(VirtualKeyCode::Z) {
-                input |= ControllerInput::Up as u8;
+            // Pause menu
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::PauseMenu)) {
+                send_message(&tx, Message::TogglePauseMenu, control_flow);
+            }
+            // Retry the last failed save or capture
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::Retry)) {
+                send_message(&tx, Message::Retry, control_flow);
+            }
+            // Compare the frame with the reference screenshot
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::CompareReference)) {
+                handle.cycle_reference_mode();
+            }
+            // Display options
+            for (hotkey, option) in DISPLAY_HOTKEYS {
+                if input_helper.key_pressed(key_bindings.hotkey(hotkey)) {
+                    handle.toggle_display(option);
+                }
+            }
+            // Cycle the video filters
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::Filter)) {
+                send_message(&tx, Message::CycleFilter, control_flow);
+            }
+            // Move the window to the next monitor
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::NextMonitor)) {
+                handle.move_to_next_monitor();
+            }
+            // Start the key binding wizard
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::BindKeys)) {
+                let wizard = BindingWizard::new(key_bindings.clone());
+                handle.set_prompt(Some(wizard.prompt()));
+                binding_wizard = Some(wizard);
+                return;
+            }
+            // Rate the compatibility of the game
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::RateCompatibility)) {
+                rating_prompt = true;
+                handle.set_prompt(Some(String::from("Rate: 1 perfect, 2 playable, 3 broken")));
+            }
+            // Enable or disable the cheats of the game
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::Cheats)) {
+                match &cheat_list {
+                    Some(list) if !list.is_empty() => {
+                        cheat_prompt = true;
+                        handle.set_prompt(Some(list.prompt()));
+                    }
+                    _ => handle.notify(OsdLevel::Info, "No cheat for this game, see --cheats"),
+                }
+            }
+            // Capture a screenshot, state and context for bug reports
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::CaptureRepro)) {
+                send_message(
+                    &tx,
+                    Message::CaptureRepro(rom_paths.repro.clone()),
+                    control_flow,
+                );
             }
-            if input_helper.key_held(VirtualKeyCode::Q) {
-                input |= ControllerInput::Left as u8;
+            // Write the frame to the screenshots folder
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::Screenshot)) {
+                send_message(
+                    &tx,
+                    Message::Screenshot(rom_paths.screenshots.clone()),
+                    control_flow,
+                );
             }
-            if input_helper.key_held(VirtualKeyCode::S) {
-                input |= ControllerInput::Down as u8;
+            // Start or stop recording a video
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::Record)) {
+                send_message(
+                    &tx,
+                    Message::ToggleRecording(rom_paths.screenshots.clone()),
+                    control_flow,
+                );
             }
-            if input_helper.key_held(VirtualKeyCode::D) {
-                input |= ControllerInput::Right as u8;
+            // Write the last seconds of play as a movie
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::SaveReplay)) {
+                send_message(
+                    &tx,
+                    Message::SaveReplay(rom_paths.screenshots.clone()),
+                    control_flow,
+                );
+            }
+            // Keep the current state on the bookmark stack, or go back to the last one
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::PushBookmark)) {
+                send_message(&tx, Message::PushBookmark, control_flow);
+            }
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::PopBookmark)) {
+                send_message(&tx, Message::PopBookmark, control_flow);
             }
-            if input_helper.key_held(VirtualKeyCode::X) {
-                input |= ControllerInput::Start as u8;
+            // Browse the pattern tables seen since the game was loaded
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::PreviousChr)) {
+                send_message(&tx, Message::BrowseChrHistory(-1), control_flow);
             }
-            if input_helper.key_held(VirtualKeyCode::C) {
-                input |= ControllerInput::Select as u8;
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::NextChr)) {
+                send_message(&tx, Message::BrowseChrHistory(1), control_flow);
             }
-            if input_helper.key_held(VirtualKeyCode::I) {
-                input |= ControllerInput::A as u8;
+            // Show where the time of the frames goes
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::Profiler)) {
+                send_message(&tx, Message::ToggleProfiler, control_flow);
             }
-            if input_helper.key_held(VirtualKeyCode::O) {
-                input |= ControllerInput::B as u8;
+            // Switch to the previous or next game of the playlist
+            let offset = if input_helper.key_pressed(key_bindings.hotkey(Hotkey::PreviousGame)) {
+                Some(-1)
+            } else if input_helper.key_pressed(key_bindings.hotkey(Hotkey::NextGame)) {
+                Some(1)
+            } else {
+                None
+            };
+            if let Some(offset) = offset {
+                match playlist.switch(offset) {
+                    Some((rom, resume)) => {
+                        let rom =
+                            rom_files(rom, screenshot_dir.as_deref(), key_bindings.save_dir());
+                        send_message(&tx, Message::SwitchGame(rom.clone(), resume), control_flow);
+                        cheat_list = load_cheats(&rom, &tx, &handle, control_flow);
+                        cheat_prompt = false;
+                        rom_paths = rom;
+                        handle.notify(OsdLevel::Info, playlist.title());
+                    }
+                    None => handle.notify(OsdLevel::Warning, "No other game to switch to"),
+                }
+            }
+            // Use the current frame as the cover of the game
+            if input_helper.key_pressed(key_bindings.hotkey(Hotkey::SetCover)) {
+                send_message(
+                    &tx,
+                    Message::SetCover(rom_paths.cover.clone()),
+                    control_flow,
+                );
             }
+            // Controller inputs
+            let input = key_bindings.input(|key| input_helper.key_held(key));
             send_message(&tx, Message::Input(0, input), control_flow);
         }
     });
 }
 
+// Move the selection of the ROM picker, giving back the ROM chosen to be played
+fn pick_rom(
+    picker: &mut RomPicker,
+    inputs: &[PickerInput],
+    handle: &mut EmulatorHandle,
+) -> Option<PathBuf> {
+    for input in inputs {
+        match input {
+            PickerInput::Previous => picker.previous(),
+            PickerInput::Next => picker.next(),
+            PickerInput::Play => return picker.selected().map(Path::to_path_buf),
+        }
+        handle.set_prompt(Some(picker.prompt()));
+    }
+    None
+}
+
+// Play another ROM, returning its cheats
+fn load_rom(
+    rom: &RomPaths,
+    tx: &mpsc::Sender<Message>,
+    handle: &EmulatorHandle,
+    control_flow: &mut ControlFlow,
+) -> Option<CheatList> {
+    send_message(tx, Message::LoadRom(rom.clone()), control_flow);
+    load_cheats(rom, tx, handle, control_flow)
+}
+
+// Apply the cheats of the ROM just loaded, returning them
+fn load_cheats(
+    rom: &RomPaths,
+    tx: &mpsc::Sender<Message>,
+    handle: &EmulatorHandle,
+    control_flow: &mut ControlFlow,
+) -> Option<CheatList> {
+    let cheat_list = match CheatList::load(&rom.cheats) {
+        Ok(list) => list,
+        Err(e) => {
+            error!("Invalid cheats file {}: {}", rom.cheats, e);
+            handle.notify(OsdLevel::Error, "Invalid cheats file");
+            return None;
+        }
+    };
+    let patches = cheat_list.rom_patches();
+    if !patches.is_empty() {
+        send_message(tx, Message::ApplyCheats(patches), control_flow);
+    }
+    report_cheats(&cheat_list, handle);
+    Some(cheat_list)
+}
+
+// Log the enabled cheats of a ROM, and warn about the ones that cannot be applied
+fn report_cheats(cheat_list: &CheatList, handle: &EmulatorHandle) {
+    for cheat in cheat_list.cheats().iter().filter(|cheat| cheat.enabled) {
+        info!("Cheat enabled: {} {}", cheat.code, cheat.description);
+    }
+    for cheat in cheat_list.unsupported() {
+        warn!(
+            "RAM codes are not supported yet, ignoring the cheat {}.",
+            cheat.code
+        );
+        handle.notify(OsdLevel::Warning, "RAM codes are not supported yet");
+    }
+}
+
+// Files written next to a ROM or to the saves folder, the screenshots going to the folder
+// given on the command line
+fn rom_files(rom_path: &str, screenshot_dir: Option<&str>, save_dir: Option<&str>) -> RomPaths {
+    let mut rom_paths = RomPaths::new(rom_path, STATE_SLOTS, save_dir);
+    if let Some(dir) = screenshot_dir {
+        rom_paths.screenshots = String::from(dir);
+    }
+    rom_paths
+}
+
+fn save_config(key_bindings: &KeyBindings, config_path: &str, handle: &EmulatorHandle) {
+    match key_bindings.save(config_path) {
+        Ok(()) => info!("Configuration written to {}.", config_path),
+        Err(e) => {
+            error!("Failed to write the configuration: {}", e);
+            handle.notify(OsdLevel::Error, "Failed to write the configuration");
+        }
+    }
+}
+
+// The states and saves cannot be written to a folder that does not exist
+fn create_save_dir(key_bindings: &KeyBindings, handle: &EmulatorHandle) {
+    if let Some(dir) = key_bindings.save_dir() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            error!("Cannot create the saves folder {}: {}", dir, e);
+            handle.notify(OsdLevel::Error, "Cannot create the saves folder");
+        }
+    }
+}
+
 fn send_message(tx: &mpsc::Sender<Message>, message: Message, control_flow: &mut ControlFlow) {
     if tx.send(message).is_err() {
         error!("Receiving thread 'run_nes' panicked");
@@ -0,0 +1,319 @@
+use std::collections::VecDeque;
+
+use nesmulator_core::utils::{ARGBColor, ControllerInput};
+
+use crate::controls::Turbo;
+use crate::filter::VideoFilter;
+use crate::font::{self, GLYPH_HEIGHT};
+use crate::osd::blend;
+use crate::speed::{self, MAX_SPEED, MIN_SPEED};
+use crate::theme::Theme;
+
+const FRAME_WIDTH: usize = 256;
+// Space between two lines of text
+const LINE_SPACING: usize = 5;
+
+// Input source of the keyboard, after the controller ports
+pub const KEYBOARD: usize = 2;
+// Host events listed by the controller test
+const TEST_EVENTS: usize = 6;
+// Buttons shown by the controller test, in the order the NES reads them
+const TEST_BUTTONS: [(u8, &str); 8] = [
+    (ControllerInput::A as u8, "A"),
+    (ControllerInput::B as u8, "B"),
+    (ControllerInput::Select as u8, "Se"),
+    (ControllerInput::Start as u8, "St"),
+    (ControllerInput::Up as u8, "U"),
+    (ControllerInput::Down as u8, "D"),
+    (ControllerInput::Left as u8, "L"),
+    (ControllerInput::Right as u8, "R"),
+];
+
+// What the emulation thread must do after a menu input
+#[derive(Debug, PartialEq)]
+pub enum MenuAction {
+    Resume,
+    // Slot 0 is the quick state, the others the state slots
+    SaveState(usize),
+    LoadState(usize),
+    Reset,
+    ChangeEmulationSpeed(f64),
+    ToggleDebugWindow,
+    CycleFilter,
+    ToggleSwapAB,
+    CycleTurbo,
+    ToggleTiming,
+    Quit,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum MenuPage {
+    Main,
+    Settings,
+    // Buttons held on each input source and the last events of the keyboard and of the
+    // gamepads, to find why a button does not work
+    ControllerTest,
+}
+
+// Settings displayed by the menu, owned by the emulation state
+pub struct MenuSettings {
+    pub speed: f64,
+    pub debug: bool,
+    pub filter: VideoFilter,
+    // Number of state slots of the ROM, besides the quick state
+    pub state_slots: usize,
+    // Controls of the current game
+    pub swap_ab: bool,
+    pub turbo: Turbo,
+    // Whether the game runs at 50 frames per second
+    pub pal_timing: bool,
+    // Quit is not offered in kiosk mode
+    pub kiosk: bool,
+}
+
+// Full screen menu stopping the emulation, navigated with the controllers
+pub struct PauseMenu {
+    page: MenuPage,
+    cursor: usize,
+    // State slot saved or loaded, 0 being the quick state
+    slot: usize,
+    // Buttons held on each input source, to only react to new presses
+    previous_input: [u8; 3],
+    // Last host events, shown by the controller test
+    host_events: VecDeque<String>,
+}
+
+impl PauseMenu {
+    // The buttons held when opening the menu must be released before being used
+    pub fn new(held_input: [u8; 3]) -> Self {
+        PauseMenu {
+            page: MenuPage::Main,
+            cursor: 0,
+            slot: 0,
+            previous_input: held_input,
+            host_events: VecDeque::with_capacity(TEST_EVENTS),
+        }
+    }
+
+    // Record a key or a gamepad button pressed or released, as told by the host
+    pub fn host_input(&mut self, event: String) {
+        if self.page != MenuPage::ControllerTest || self.host_events.back() == Some(&event) {
+            return;
+        }
+        if self.host_events.len() == TEST_EVENTS {
+            self.host_events.pop_front();
+        }
+        self.host_events.push_back(event);
+    }
+
+    fn items(&self, settings: &MenuSettings) -> Vec<String> {
+        match self.page {
+            MenuPage::Main => {
+                let slot = match self.slot {
+                    0 => String::from("Slot: quick"),
+                    slot => format!("Slot: {}", slot),
+                };
+                [
+                    "Resume",
+                    "Save state",
+                    "Load state",
+                    &slot,
+                    "Reset",
+                    "Settings",
+                    "Controller test",
+                    "Quit",
+                ]
+                .iter()
+                .filter(|item| !(settings.kiosk && **item == "Quit"))
+                .map(|item| String::from(*item))
+                .collect()
+            }
+            MenuPage::Settings => vec![
+                format!("Speed: x{:.1}", settings.speed),
+                format!(
+                    "Debug window: {}",
+                    if settings.debug { "on" } else { "off" }
+                ),
+                format!("Filter: {}", settings.filter.name()),
+                format!("Swap A/B: {}", if settings.swap_ab { "on" } else { "off" }),
+                format!("Turbo: {}", settings.turbo.name()),
+                format!(
+                    "Timing: {}",
+                    if settings.pal_timing { "PAL" } else { "NTSC" }
+                ),
+                String::from("Back"),
+            ],
+            MenuPage::ControllerTest => {
+                let sources = ["Pad 1", "Pad 2", "Keys"];
+                let mut items: Vec<String> = sources
+                    .iter()
+                    .zip(self.previous_input.iter())
+                    .map(|(source, input)| format!("{:5} {}", source, buttons(*input)))
+                    .collect();
+                items.push(String::new());
+                items.extend(self.host_events.iter().cloned());
+                items.push(String::new());
+                items.push(String::from("Start+Select: back"));
+                items
+            }
+        }
+    }
+
+    // Handle the new state of the buttons of a controller port or of the keyboard
+    pub fn input(
+        &mut self,
+        source: usize,
+        input: u8,
+        settings: &MenuSettings,
+    ) -> Option<MenuAction> {
+        let previous = std::mem::replace(&mut self.previous_input[source], input);
+        let pressed = |button: ControllerInput| input & !previous & button as u8 != 0;
+
+        // Every button is being tested, only the chord goes back
+        if self.page == MenuPage::ControllerTest {
+            let chord = ControllerInput::Start as u8 | ControllerInput::Select as u8;
+            if input & chord == chord && previous & chord != chord {
+                return self.back();
+            }
+            return None;
+        }
+
+        let count = self.items(settings).len();
+        if pressed(ControllerInput::Up) {
+            self.cursor = (self.cursor + count - 1) % count;
+        } else if pressed(ControllerInput::Down) {
+            self.cursor = (self.cursor + 1) % count;
+        } else if pressed(ControllerInput::B) {
+            return self.back();
+        } else if pressed(ControllerInput::A) || pressed(ControllerInput::Start) {
+            return self.select(settings, true);
+        } else if pressed(ControllerInput::Left) && self.is_adjustable() {
+            return self.select(settings, false);
+        } else if pressed(ControllerInput::Right) && self.is_adjustable() {
+            return self.select(settings, true);
+        }
+        None
+    }
+
+    // Whether the selected item is a setting changed with Left and Right
+    fn is_adjustable(&self) -> bool {
+        self.page == MenuPage::Settings || (self.page == MenuPage::Main && self.cursor == 3)
+    }
+
+    fn back(&mut self) -> Option<MenuAction> {
+        match self.page {
+            MenuPage::Main => Some(MenuAction::Resume),
+            MenuPage::Settings => {
+                self.page = MenuPage::Main;
+                self.cursor = 5;
+                None
+            }
+            MenuPage::ControllerTest => {
+                self.page = MenuPage::Main;
+                self.cursor = 6;
+                None
+            }
+        }
+    }
+
+    // Activate the selected item, forward giving the direction of the speed and slot
+    // settings
+    fn select(&mut self, settings: &MenuSettings, forward: bool) -> Option<MenuAction> {
+        match (self.page, self.cursor) {
+            (MenuPage::Main, 0) => Some(MenuAction::Resume),
+            (MenuPage::Main, 1) => Some(MenuAction::SaveState(self.slot)),
+            (MenuPage::Main, 2) => Some(MenuAction::LoadState(self.slot)),
+            (MenuPage::Main, 3) => {
+                let count = settings.state_slots + 1;
+                self.slot = if forward {
+                    (self.slot + 1) % count
+                } else {
+                    (self.slot + count - 1) % count
+                };
+                None
+            }
+            (MenuPage::Main, 4) => Some(MenuAction::Reset),
+            (MenuPage::Main, 5) => {
+                self.page = MenuPage::Settings;
+                self.cursor = 0;
+                None
+            }
+            (MenuPage::Main, 6) => {
+                self.page = MenuPage::ControllerTest;
+                self.host_events.clear();
+                None
+            }
+            (MenuPage::Main, _) => Some(MenuAction::Quit),
+            (MenuPage::Settings, 0) => {
+                // The speed wraps around past the bounds
+                let speed = match (forward, settings.speed) {
+                    (true, speed) if speed >= MAX_SPEED => MIN_SPEED,
+                    (false, speed) if speed <= MIN_SPEED => MAX_SPEED,
+                    (forward, speed) => speed::next_speed(speed, forward),
+                };
+                Some(MenuAction::ChangeEmulationSpeed(speed))
+            }
+            (MenuPage::Settings, 1) => Some(MenuAction::ToggleDebugWindow),
+            (MenuPage::Settings, 2) => Some(MenuAction::CycleFilter),
+            (MenuPage::Settings, 3) => Some(MenuAction::ToggleSwapAB),
+            (MenuPage::Settings, 4) => Some(MenuAction::CycleTurbo),
+            (MenuPage::Settings, 5) => Some(MenuAction::ToggleTiming),
+            (MenuPage::Settings, _) | (MenuPage::ControllerTest, _) => self.back(),
+        }
+    }
+
+    // Shade the frame, or replace it with the background of the theme, and draw the menu
+    // items in its center
+    pub fn draw(&self, frame: &mut [ARGBColor], settings: &MenuSettings, theme: &Theme) {
+        match &theme.background {
+            Some(background) => frame.copy_from_slice(background),
+            None => {
+                for pixel in frame.iter_mut() {
+                    *pixel = blend(*pixel, theme.menu_shade);
+                }
+            }
+        }
+
+        let title = match self.page {
+            MenuPage::Main => "PAUSED",
+            MenuPage::Settings => "SETTINGS",
+            MenuPage::ControllerTest => "CONTROLLER TEST",
+        };
+        let items = self.items(settings);
+        let scale = theme.font_scale;
+        let line_height = GLYPH_HEIGHT * scale + LINE_SPACING;
+        let height = frame.len() / FRAME_WIDTH;
+        let top = height.saturating_sub((items.len() + 2) * line_height) / 2;
+        let mut draw = |text: &str, y: usize, color: ARGBColor| {
+            let x = FRAME_WIDTH.saturating_sub(font::text_width_scaled(text, scale)) / 2;
+            font::draw_text_scaled(frame, FRAME_WIDTH, x, y, text, color, scale);
+        };
+
+        draw(title, top, theme.text);
+        for (i, item) in items.iter().enumerate() {
+            let y = top + (i + 2) * line_height;
+            if i == self.cursor && self.page != MenuPage::ControllerTest {
+                draw(&format!("> {} <", item), y, theme.text);
+            } else {
+                draw(item, y, theme.dim_text);
+            }
+        }
+    }
+}
+
+// Names of the held buttons at fixed places, "-" for the released ones
+fn buttons(input: u8) -> String {
+    TEST_BUTTONS
+        .iter()
+        .map(
+            |(button, name)| {
+                if input & button != 0 {
+                    *name
+                } else {
+                    "-"
+                }
+            },
+        )
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
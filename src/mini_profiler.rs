@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+use nesmulator_core::utils::ARGBColor;
+
+use crate::font::{self, GLYPH_HEIGHT};
+use crate::osd::{argb, blend};
+
+const FRAME_WIDTH: usize = 256;
+const MARGIN: usize = 2;
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 2 * MARGIN;
+// Below the two lines of the timer, which shares the top right corner
+const TOP: usize = MARGIN + 2 * (LINE_HEIGHT + MARGIN);
+// Frames averaged for each refresh of the numbers, so that they can be read
+const AVERAGE_FRAMES: u32 = 30;
+
+// Parts of the emulation thread timed at each frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Subsystem {
+    // Clocks of the core
+    Emulation,
+    // Messages of the main thread, gamepads and control socket
+    Messages,
+    // Copy of the frame, overlays, recorders and sending it to the window
+    Frame,
+    // Pattern table history of the debugging window
+    Debug,
+    // Samples taken from the core and queued to the audio device
+    Audio,
+    // Wait for the next frame
+    Sleep,
+}
+
+impl Subsystem {
+    const ALL: [Subsystem; 6] = [
+        Subsystem::Emulation,
+        Subsystem::Messages,
+        Subsystem::Frame,
+        Subsystem::Debug,
+        Subsystem::Audio,
+        Subsystem::Sleep,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Subsystem::Emulation => "emulate",
+            Subsystem::Messages => "messages",
+            Subsystem::Frame => "frame",
+            Subsystem::Debug => "debug",
+            Subsystem::Audio => "audio",
+            Subsystem::Sleep => "sleep",
+        }
+    }
+
+    fn index(&self) -> usize {
+        Subsystem::ALL.iter().position(|s| s == self).unwrap()
+    }
+}
+
+// Time spent in each part of the emulation thread per frame, drawn on the frame while
+// enabled, to find where a stutter comes from without an external profiler. The times
+// are averaged over half a second, the sleep being what is left of the frame budget.
+pub struct MiniProfiler {
+    // Time spent in the current frame
+    current: [Duration; 6],
+    // Time spent since the last refresh of the averages
+    total: [Duration; 6],
+    frames: u32,
+    averages: [Duration; 6],
+}
+
+impl MiniProfiler {
+    pub fn new() -> Self {
+        MiniProfiler {
+            current: [Duration::ZERO; 6],
+            total: [Duration::ZERO; 6],
+            frames: 0,
+            averages: [Duration::ZERO; 6],
+        }
+    }
+
+    pub fn add(&mut self, subsystem: Subsystem, time: Duration) {
+        self.current[subsystem.index()] += time;
+    }
+
+    // Called at the end of each frame
+    pub fn end_frame(&mut self) {
+        for (total, current) in self.total.iter_mut().zip(self.current.iter_mut()) {
+            *total += *current;
+            *current = Duration::ZERO;
+        }
+        self.frames += 1;
+        if self.frames == AVERAGE_FRAMES {
+            for (average, total) in self.averages.iter_mut().zip(self.total.iter_mut()) {
+                *average = *total / AVERAGE_FRAMES;
+                *total = Duration::ZERO;
+            }
+            self.frames = 0;
+        }
+    }
+
+    // e.g. "emulate 9.81ms, messages 0.02ms, ..., sleep 6.20ms", logged when disabled
+    pub fn summary(&self) -> String {
+        Subsystem::ALL
+            .iter()
+            .map(|s| format!("{} {:.2}ms", s.name(), millis(self.averages[s.index()])))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    // Draw the averages in the top right corner of the frame, in yellow when the work of a
+    // frame takes longer than its target time
+    pub fn draw(&self, frame: &mut [ARGBColor], target_time: Duration) {
+        let background = argb(0xA0, 0x00, 0x00, 0x00);
+        let white = argb(0xFF, 0xFF, 0xFF, 0xFF);
+        let yellow = argb(0xFF, 0xFF, 0xD0, 0x40);
+        let busy: Duration = Subsystem::ALL
+            .iter()
+            .filter(|s| **s != Subsystem::Sleep)
+            .map(|s| self.averages[s.index()])
+            .sum();
+        let color = if busy > target_time { yellow } else { white };
+        let lines: Vec<String> = Subsystem::ALL
+            .iter()
+            .map(|s| {
+                format!(
+                    "{:<8} {:>5.2}ms",
+                    s.name(),
+                    millis(self.averages[s.index()])
+                )
+            })
+            .collect();
+        let box_width = lines
+            .iter()
+            .map(|text| font::text_width(text))
+            .max()
+            .unwrap_or(0)
+            + 2 * MARGIN;
+        let left = FRAME_WIDTH - MARGIN - box_width;
+        let bottom = TOP + lines.len() * (GLYPH_HEIGHT + MARGIN) + MARGIN;
+        for y in TOP..bottom {
+            for x in left..left + box_width {
+                let pixel = &mut frame[y * FRAME_WIDTH + x];
+                *pixel = blend(*pixel, background);
+            }
+        }
+        for (i, text) in lines.iter().enumerate() {
+            let top = TOP + MARGIN + i * (GLYPH_HEIGHT + MARGIN);
+            font::draw_text(frame, FRAME_WIDTH, left + MARGIN, top, text, color);
+        }
+    }
+}
+
+fn millis(time: Duration) -> f64 {
+    time.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,251 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+
+use log::warn;
+
+use crate::capture;
+use crate::input::{InputSource, SourceKind};
+
+// First line of the movie files, followed by the version of the format.
+// Files without it are the first movies, only holding the inputs of the first controller.
+const HEADER: &str = "nesmulator-movie";
+const VERSION: u32 = 1;
+
+// Where the emulation of a movie starts from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MovieStart {
+    PowerOn,
+    // CRC32 of the state file loaded
+    State(u32),
+    // Movies written before the header was added
+    Unknown,
+}
+
+impl MovieStart {
+    // The state file loaded before the recording, if any
+    pub fn new(state_path: Option<&str>) -> io::Result<Self> {
+        match state_path {
+            Some(path) => Ok(MovieStart::State(capture::crc32(&fs::read(path)?))),
+            None => Ok(MovieStart::PowerOn),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            MovieStart::PowerOn => String::from("power-on"),
+            MovieStart::State(crc32) => format!("state {:08X}", crc32),
+            MovieStart::Unknown => String::from("unknown"),
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.split_once(' ') {
+            None if name == "power-on" => Some(MovieStart::PowerOn),
+            Some(("state", crc32)) => u32::from_str_radix(crc32, 16).ok().map(MovieStart::State),
+            _ => None,
+        }
+    }
+}
+
+// Write the inputs of both controllers at the end of each frame, after a header:
+//   nesmulator-movie 1
+//   rom <CRC32 of the ROM>
+//   start power-on | start state <CRC32 of the state file>
+//   <input of the first controller> <input of the second controller>, in hexadecimal
+pub struct MovieRecorder {
+    file: File,
+}
+
+impl MovieRecorder {
+    pub fn new(path: &str, rom_crc32: u32, start: MovieStart) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        writeln!(file, "{} {}", HEADER, VERSION)?;
+        writeln!(file, "rom {:08X}", rom_crc32)?;
+        writeln!(file, "start {}", start.name())?;
+        Ok(MovieRecorder { file })
+    }
+
+    pub fn frame(&mut self, inputs: [u8; 2]) {
+        if let Err(e) = writeln!(self.file, "{:02X} {:02X}", inputs[0], inputs[1]) {
+            warn!("Failed to record the movie: {}", e);
+        }
+    }
+}
+
+// Inputs of a file written by MovieRecorder
+pub struct Movie {
+    inputs: Vec<[u8; 2]>,
+    // None for the movies written before the header was added
+    rom_crc32: Option<u32>,
+    start: MovieStart,
+}
+
+impl Movie {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Movie::parse(&content)
+    }
+
+    fn parse(content: &str) -> Result<Self, String> {
+        let mut lines = content.lines().enumerate().peekable();
+        let mut movie = Movie {
+            inputs: vec![],
+            rom_crc32: None,
+            start: MovieStart::Unknown,
+        };
+
+        if let Some(&(_, line)) = lines.peek().filter(|(_, l)| l.starts_with(HEADER)) {
+            let version = line[HEADER.len()..]
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid header: {:?}", line))?;
+            if version > VERSION {
+                return Err(format!(
+                    "Movie written by a newer version of the emulator (format {})",
+                    version
+                ));
+            }
+            lines.next();
+            for (number, line) in lines.by_ref().take(2) {
+                let invalid = || format!("Invalid header at line {}: {:?}", number + 1, line);
+                match line.split_once(' ') {
+                    Some(("rom", crc32)) => {
+                        movie.rom_crc32 =
+                            Some(u32::from_str_radix(crc32, 16).map_err(|_| invalid())?)
+                    }
+                    Some(("start", start)) => {
+                        movie.start = MovieStart::from_name(start).ok_or_else(invalid)?
+                    }
+                    _ => return Err(invalid()),
+                }
+            }
+        }
+
+        for (number, line) in lines {
+            let invalid = || format!("Invalid input at line {}: {:?}", number + 1, line);
+            let mut inputs = [0; 2];
+            let mut values = line.split_whitespace();
+            // The first movies only have the input of the first controller
+            for (i, value) in values.by_ref().take(2).enumerate() {
+                inputs[i] = u8::from_str_radix(value, 16).map_err(|_| invalid())?;
+            }
+            if values.next().is_some() || line.trim().is_empty() {
+                return Err(invalid());
+            }
+            movie.inputs.push(inputs);
+        }
+        Ok(movie)
+    }
+
+    // Input of the first controller for the given frame, None once the movie is over
+    pub fn input(&self, frame: usize) -> Option<u8> {
+        self.inputs(frame).map(|inputs| inputs[0])
+    }
+
+    // Inputs of both controllers for the given frame, None once the movie is over
+    pub fn inputs(&self, frame: usize) -> Option<[u8; 2]> {
+        self.inputs.get(frame).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    // Why the movie may not play back as recorded from the given ROM and start
+    pub fn check(&self, rom_crc32: u32, start: MovieStart) -> Vec<String> {
+        let mut problems = vec![];
+        if matches!(self.rom_crc32, Some(crc32) if crc32 != rom_crc32) {
+            problems.push(String::from("the movie was recorded with another ROM"));
+        }
+        match (self.start, start) {
+            (MovieStart::Unknown, _) => (),
+            (MovieStart::PowerOn, MovieStart::State(_)) => problems.push(String::from(
+                "the movie was recorded from power on, without --state",
+            )),
+            (MovieStart::State(_), MovieStart::PowerOn) => problems.push(String::from(
+                "the movie was recorded from a state, give it with --state",
+            )),
+            (MovieStart::State(a), MovieStart::State(b)) if a != b => {
+                problems.push(String::from("the movie was recorded from another state"))
+            }
+            _ => (),
+        }
+        problems
+    }
+}
+
+// Drive both controllers with the inputs of a movie, frame after frame
+pub struct MoviePlayer {
+    movie: Movie,
+    frame: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self {
+        MoviePlayer { movie, frame: 0 }
+    }
+}
+
+impl InputSource for MoviePlayer {
+    fn kind(&self) -> SourceKind {
+        SourceKind::Movie
+    }
+
+    fn input(&self, port: usize) -> Option<u8> {
+        self.movie.inputs(self.frame).map(|inputs| inputs[port])
+    }
+
+    fn next_frame(&mut self) -> bool {
+        self.frame += 1;
+        self.frame < self.movie.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_header() {
+        let movie = Movie::parse("nesmulator-movie 1\nrom 0A1B2C3D\nstart state DEADBEEF\n01 80\n")
+            .unwrap();
+        assert_eq!(movie.rom_crc32, Some(0x0A1B2C3D));
+        assert_eq!(movie.start, MovieStart::State(0xDEADBEEF));
+        assert_eq!(movie.inputs(0), Some([0x01, 0x80]));
+        assert_eq!(movie.len(), 1);
+    }
+
+    #[test]
+    fn reads_movies_without_header() {
+        let movie = Movie::parse("01\n02\n").unwrap();
+        assert_eq!(movie.rom_crc32, None);
+        assert_eq!(movie.start, MovieStart::Unknown);
+        assert_eq!(movie.inputs(1), Some([0x02, 0]));
+        assert!(movie.check(0x1234, MovieStart::PowerOn).is_empty());
+    }
+
+    #[test]
+    fn refuses_newer_versions() {
+        let error = Movie::parse("nesmulator-movie 2\nrom 00000000\nstart power-on\n")
+            .err()
+            .unwrap();
+        assert!(error.contains("newer version"));
+        assert!(Movie::parse("nesmulator-movie x\n").is_err());
+    }
+
+    #[test]
+    fn refuses_invalid_headers() {
+        assert!(Movie::parse("nesmulator-movie 1\nrom XYZ\nstart power-on\n").is_err());
+        assert!(Movie::parse("nesmulator-movie 1\nrom 00000000\nstart later\n").is_err());
+        assert!(
+            Movie::parse("nesmulator-movie 1\nrom 00000000\nstart power-on\n01 02 03\n").is_err()
+        );
+    }
+
+    #[test]
+    fn checks_the_rom_and_start() {
+        let movie = Movie::parse("nesmulator-movie 1\nrom 00001234\nstart power-on\n").unwrap();
+        assert!(movie.check(0x1234, MovieStart::PowerOn).is_empty());
+        assert_eq!(movie.check(0x4321, MovieStart::State(1)).len(), 2);
+    }
+}
@@ -0,0 +1,267 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::time::Duration;
+
+// Compatibility of a ROM with the emulator
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rating {
+    Perfect,
+    Playable,
+    Broken,
+}
+
+impl Rating {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rating::Perfect => "perfect",
+            Rating::Playable => "playable",
+            Rating::Broken => "broken",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [Rating::Perfect, Rating::Playable, Rating::Broken]
+            .iter()
+            .copied()
+            .find(|rating| rating.name() == name)
+    }
+}
+
+// Local database of notes about ROMs, identified by their CRC32.
+// Each line is "<crc32>\t<rom name>\t<kind>\t<text>", so that it can be shared as is.
+pub struct NotesDatabase {
+    records: Vec<Record>,
+}
+
+struct Record {
+    crc32: u32,
+    rom_name: String,
+    kind: String,
+    text: String,
+}
+
+impl NotesDatabase {
+    // A missing file is an empty database
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.to_string()),
+        };
+        let mut records = vec![];
+        for (number, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.splitn(4, '\t').collect();
+            let invalid = || format!("Invalid record at line {}: {:?}", number + 1, line);
+            if fields.len() != 4 {
+                return Err(invalid());
+            }
+            records.push(Record {
+                crc32: u32::from_str_radix(fields[0], 16).map_err(|_| invalid())?,
+                rom_name: String::from(fields[1]),
+                kind: String::from(fields[2]),
+                text: String::from(fields[3]),
+            });
+        }
+        Ok(NotesDatabase { records })
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let content: String = self
+            .records
+            .iter()
+            .map(|r| format!("{:08X}\t{}\t{}\t{}\n", r.crc32, r.rom_name, r.kind, r.text))
+            .collect();
+        fs::write(path, content)
+    }
+
+    pub fn add_note(&mut self, crc32: u32, rom_name: &str, text: &str) {
+        self.records.push(Record {
+            crc32,
+            rom_name: String::from(rom_name),
+            kind: String::from("note"),
+            // Tabs and line breaks would break the file format
+            text: text.replace(['\t', '\n', '\r'], " "),
+        });
+    }
+
+    // A ROM only has one rating, the last one given
+    pub fn set_rating(&mut self, crc32: u32, rom_name: &str, rating: Rating) {
+        self.records
+            .retain(|r| !(r.crc32 == crc32 && r.kind == "rating"));
+        self.records.push(Record {
+            crc32,
+            rom_name: String::from(rom_name),
+            kind: String::from("rating"),
+            text: String::from(rating.name()),
+        });
+    }
+
+    pub fn rating(&self, crc32: u32) -> Option<Rating> {
+        self.records
+            .iter()
+            .find(|r| r.crc32 == crc32 && r.kind == "rating")
+            .and_then(|r| Rating::from_name(&r.text))
+    }
+
+    // Best time of the time trial timer, stored in milliseconds
+    pub fn set_best_time(&mut self, crc32: u32, rom_name: &str, time: Duration) {
+        self.records
+            .retain(|r| !(r.crc32 == crc32 && r.kind == "best-time"));
+        self.records.push(Record {
+            crc32,
+            rom_name: String::from(rom_name),
+            kind: String::from("best-time"),
+            text: time.as_millis().to_string(),
+        });
+    }
+
+    pub fn best_time(&self, crc32: u32) -> Option<Duration> {
+        self.records
+            .iter()
+            .find(|r| r.crc32 == crc32 && r.kind == "best-time")
+            .and_then(|r| r.text.parse().ok())
+            .map(Duration::from_millis)
+    }
+
+    // Whether the idle waits of the ROM are fast-forwarded, when set for it
+    pub fn set_skip_idle(&mut self, crc32: u32, rom_name: &str, skip_idle: bool) {
+        self.set_switch(crc32, rom_name, "skip-idle", skip_idle);
+    }
+
+    pub fn skip_idle(&self, crc32: u32) -> Option<bool> {
+        self.switch(crc32, "skip-idle")
+    }
+
+    // Whether the debugging window was open when last playing the ROM
+    pub fn set_debug_window(&mut self, crc32: u32, rom_name: &str, open: bool) {
+        self.set_switch(crc32, rom_name, "debug-window", open);
+    }
+
+    pub fn debug_window(&self, crc32: u32) -> Option<bool> {
+        self.switch(crc32, "debug-window")
+    }
+
+    // Whether A and B are swapped for the ROM
+    pub fn set_swap_ab(&mut self, crc32: u32, rom_name: &str, swap_ab: bool) {
+        self.set_switch(crc32, rom_name, "swap-ab", swap_ab);
+    }
+
+    pub fn swap_ab(&self, crc32: u32) -> Option<bool> {
+        self.switch(crc32, "swap-ab")
+    }
+
+    // Name of the turbo mode of the ROM
+    pub fn set_turbo(&mut self, crc32: u32, rom_name: &str, turbo: &str) {
+        self.set_setting(crc32, rom_name, "turbo", turbo);
+    }
+
+    pub fn turbo(&self, crc32: u32) -> Option<&str> {
+        self.setting(crc32, "turbo")
+    }
+
+    // Whether the ROM runs at the frame rate of the PAL consoles
+    pub fn set_pal_timing(&mut self, crc32: u32, rom_name: &str, pal_timing: bool) {
+        self.set_switch(crc32, rom_name, "pal-timing", pal_timing);
+    }
+
+    pub fn pal_timing(&self, crc32: u32) -> Option<bool> {
+        self.switch(crc32, "pal-timing")
+    }
+
+    // Settings of a ROM that are on or off
+    fn set_switch(&mut self, crc32: u32, rom_name: &str, kind: &str, on: bool) {
+        self.set_setting(crc32, rom_name, kind, if on { "on" } else { "off" });
+    }
+
+    fn switch(&self, crc32: u32, kind: &str) -> Option<bool> {
+        match self.setting(crc32, kind) {
+            Some("on") => Some(true),
+            Some("off") => Some(false),
+            _ => None,
+        }
+    }
+
+    // A single record of each kind of setting is kept for a ROM
+    fn set_setting(&mut self, crc32: u32, rom_name: &str, kind: &str, value: &str) {
+        self.records
+            .retain(|r| !(r.crc32 == crc32 && r.kind == kind));
+        self.records.push(Record {
+            crc32,
+            rom_name: String::from(rom_name),
+            kind: String::from(kind),
+            text: String::from(value),
+        });
+    }
+
+    fn setting(&self, crc32: u32, kind: &str) -> Option<&str> {
+        self.records
+            .iter()
+            .find(|r| r.crc32 == crc32 && r.kind == kind)
+            .map(|r| r.text.as_str())
+    }
+
+    pub fn notes(&self, crc32: u32) -> Vec<&str> {
+        self.records
+            .iter()
+            .filter(|r| r.crc32 == crc32 && r.kind == "note")
+            .map(|r| r.text.as_str())
+            .collect()
+    }
+
+    // ROMs having records of the given kind, sorted by name
+    fn roms(&self, kind: &str) -> Vec<(u32, &str)> {
+        let mut roms: Vec<(u32, &str)> = vec![];
+        for record in self.records.iter().filter(|r| r.kind == kind) {
+            if !roms.iter().any(|(crc32, _)| *crc32 == record.crc32) {
+                roms.push((record.crc32, &record.rom_name));
+            }
+        }
+        roms.sort_by_key(|(_, name)| name.to_lowercase());
+        roms
+    }
+
+    fn joined_notes(&self, crc32: u32) -> String {
+        self.notes(crc32)
+            .iter()
+            .map(|note| escape_markdown(note))
+            .collect::<Vec<String>>()
+            .join("<br>")
+    }
+
+    // Markdown table of the notes, one row per ROM
+    pub fn export_notes(&self) -> String {
+        let mut table = String::from("| ROM | CRC32 | Notes |\n| --- | --- | --- |\n");
+        for (crc32, name) in self.roms("note") {
+            table.push_str(&format!(
+                "| {} | {:08X} | {} |\n",
+                escape_markdown(name),
+                crc32,
+                self.joined_notes(crc32)
+            ));
+        }
+        table
+    }
+
+    // Markdown table of the rated ROMs, for the compatibility list of the project
+    pub fn export_compat(&self) -> String {
+        let mut table =
+            String::from("| ROM | CRC32 | Rating | Notes |\n| --- | --- | --- | --- |\n");
+        for (crc32, name) in self.roms("rating") {
+            table.push_str(&format!(
+                "| {} | {:08X} | {} | {} |\n",
+                escape_markdown(name),
+                crc32,
+                self.rating(crc32).map_or("unknown", |rating| rating.name()),
+                self.joined_notes(crc32)
+            ));
+        }
+        table
+    }
+}
+
+fn escape_markdown(text: &str) -> String {
+    text.replace('|', "\\|")
+}
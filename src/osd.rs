@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use nesmulator_core::utils::ARGBColor;
+
+use crate::font::{self, GLYPH_HEIGHT};
+use crate::theme::Theme;
+
+const MAX_DISPLAYED_NOTIFICATIONS: usize = 4;
+const MARGIN: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OsdLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+impl OsdLevel {
+    fn background(&self, theme: &Theme) -> ARGBColor {
+        match self {
+            OsdLevel::Info => theme.info,
+            OsdLevel::Warning => theme.warning,
+            OsdLevel::Error => theme.error,
+        }
+    }
+
+    fn default_duration(&self) -> Duration {
+        match self {
+            OsdLevel::Info => Duration::from_secs(2),
+            OsdLevel::Warning => Duration::from_secs(4),
+            OsdLevel::Error => Duration::from_secs(6),
+        }
+    }
+}
+
+pub struct Notification {
+    level: OsdLevel,
+    text: String,
+    duration: Duration,
+}
+
+// Sending side of the on-screen display, can be cloned and given to any subsystem
+#[derive(Clone)]
+pub struct Osd {
+    tx: Sender<Notification>,
+}
+
+impl Osd {
+    pub fn notify(&self, level: OsdLevel, text: impl Into<String>) {
+        self.notify_for(level, text, level.default_duration());
+    }
+
+    pub fn notify_for(&self, level: OsdLevel, text: impl Into<String>, duration: Duration) {
+        // Nothing is displayed when running without a window
+        let _ = self.tx.send(Notification {
+            level,
+            text: text.into(),
+            duration,
+        });
+    }
+}
+
+// Create the on-screen display, returning the sender and the layer drawn over the frames
+pub fn osd() -> (Osd, OsdLayer) {
+    let (tx, rx) = mpsc::channel();
+    (
+        Osd { tx },
+        OsdLayer {
+            rx,
+            queued: VecDeque::new(),
+            displayed: vec![],
+            prompt: None,
+        },
+    )
+}
+
+pub struct OsdLayer {
+    rx: Receiver<Notification>,
+    queued: VecDeque<Notification>,
+    displayed: Vec<(Notification, Instant)>,
+    // Displayed above the notifications until it is cleared
+    prompt: Option<String>,
+}
+
+impl OsdLayer {
+    // Receive new notifications and replace the expired ones by queued ones
+    pub fn update(&mut self) {
+        self.queued.extend(self.rx.try_iter());
+        self.displayed
+            .retain(|(notification, shown_at)| shown_at.elapsed() < notification.duration);
+        while self.displayed.len() < MAX_DISPLAYED_NOTIFICATIONS {
+            match self.queued.pop_front() {
+                Some(notification) => self.displayed.push((notification, Instant::now())),
+                None => break,
+            }
+        }
+    }
+
+    pub fn set_prompt(&mut self, prompt: Option<String>) {
+        self.prompt = prompt;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.displayed.is_empty() && self.prompt.is_none()
+    }
+
+    // Draw the prompt and the displayed notifications in the top left corner of the frame
+    pub fn draw(&self, frame: &mut [ARGBColor], width: usize, theme: &Theme) {
+        let height = frame.len() / width;
+        let scale = theme.font_scale;
+        let notification_height = GLYPH_HEIGHT * scale + 2 * MARGIN;
+        let lines = self
+            .prompt
+            .iter()
+            .map(|prompt| (OsdLevel::Info, prompt))
+            .chain(self.displayed.iter().map(|(n, _)| (n.level, &n.text)));
+        for (i, (level, text)) in lines.enumerate() {
+            let top = MARGIN + i * (notification_height + MARGIN);
+            let box_width = (font::text_width_scaled(text, scale) + 2 * MARGIN).min(width - MARGIN);
+            if top + notification_height > height {
+                break;
+            }
+            let background = level.background(theme);
+            for y in top..top + notification_height {
+                for x in MARGIN..MARGIN + box_width {
+                    let pixel = &mut frame[y * width + x];
+                    *pixel = blend(*pixel, background);
+                }
+            }
+            font::draw_text_scaled(
+                frame,
+                width,
+                2 * MARGIN,
+                top + MARGIN,
+                text,
+                theme.text,
+                scale,
+            );
+        }
+    }
+}
+
+// Alpha blend a color over another one
+pub fn blend(under: ARGBColor, over: ARGBColor) -> ARGBColor {
+    let alpha = over.alpha as u16;
+    let mix = |u: u8, o: u8| ((o as u16 * alpha + u as u16 * (255 - alpha)) / 255) as u8;
+    argb(
+        under.alpha,
+        mix(under.red, over.red),
+        mix(under.green, over.green),
+        mix(under.blue, over.blue),
+    )
+}
+
+pub fn argb(alpha: u8, red: u8, green: u8, blue: u8) -> ARGBColor {
+    ARGBColor {
+        alpha,
+        red,
+        green,
+        blue,
+    }
+}
@@ -0,0 +1,213 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::thread;
+use std::time::Duration;
+
+use spin_sleep::SpinSleeper;
+
+// Frames late in a row before the emulation is considered too slow
+const LATE_FRAMES: u32 = 30;
+// Frames on time in a row before skipping fewer frames
+const ON_TIME_FRAMES: u32 = 120;
+// Most frames skipped between two presented frames, so that the game stays playable
+const MAX_FRAMESKIP: u32 = 3;
+// Weight of the last frame in the average of the speed reached while lagging
+const SPEED_SMOOTHING: f64 = 0.05;
+
+// How the emulation waits for the next frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameSleep {
+    // Sleep with the system, then spin for the last part, shorter than the accuracy of the
+    // system, which is precise but keeps a core busy
+    Spin(SpinSleeper),
+    // Sleep with the system only, for battery powered devices, the frames being less regular
+    Plain,
+}
+
+impl FrameSleep {
+    // accuracy is how late the sleep of the system can wake up, the default of spin_sleep
+    // for the system if not given
+    pub fn new(plain: bool, accuracy: Option<Duration>) -> Self {
+        match (plain, accuracy) {
+            (true, _) => FrameSleep::Plain,
+            (false, Some(accuracy)) => FrameSleep::Spin(SpinSleeper::new(
+                accuracy.as_nanos().min(u32::MAX as u128) as u32,
+            )),
+            (false, None) => FrameSleep::Spin(SpinSleeper::default()),
+        }
+    }
+
+    pub fn sleep(&self, duration: Duration) {
+        match self {
+            FrameSleep::Spin(sleeper) => sleeper.sleep(duration),
+            FrameSleep::Plain => thread::sleep(duration),
+        }
+    }
+}
+
+// Time spent in each step of a single frame
+pub struct FrameTiming {
+    pub emulate: Duration,
+    pub render: Duration,
+    pub sleep: Duration,
+    pub audio_queue_size: Option<u32>,
+    // Whether the frame was sent to the window or skipped
+    pub presented: bool,
+}
+
+// Per-frame timings collected during the session and written to a CSV file at exit
+pub struct PacingReport {
+    path: String,
+    frames: Vec<FrameTiming>,
+}
+
+impl PacingReport {
+    pub fn new(path: &str) -> Self {
+        PacingReport {
+            path: String::from(path),
+            frames: vec![],
+        }
+    }
+
+    pub fn record(&mut self, timing: FrameTiming) {
+        self.frames.push(timing);
+    }
+
+    pub fn write(&self) -> io::Result<&str> {
+        let mut writer = BufWriter::new(File::create(&self.path)?);
+        writeln!(
+            writer,
+            "frame,emulate_us,render_us,sleep_us,audio_queue_bytes,presented"
+        )?;
+        for (i, timing) in self.frames.iter().enumerate() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                i,
+                timing.emulate.as_micros(),
+                timing.render.as_micros(),
+                timing.sleep.as_micros(),
+                timing
+                    .audio_queue_size
+                    .map_or(String::new(), |size| size.to_string()),
+                timing.presented as u8,
+            )?;
+        }
+        writer.flush()?;
+        Ok(&self.path)
+    }
+}
+
+// Whether the emulation keeps up with its target frame time. When frames are late for
+// a while, some of them are not presented to save the time of their rendering, more of
+// them while it stays late, and the sound is slowed down to the speed actually reached.
+#[derive(Default)]
+pub struct LagMonitor {
+    late_in_a_row: u32,
+    on_time_in_a_row: u32,
+    // Frames skipped after each presented one, 0 when keeping up
+    frameskip: u32,
+    // Frames skipped since the last presented one
+    skipped_in_a_row: u32,
+    // Average of the ratio of the target time to the time taken, while lagging
+    reached_speed: f64,
+    // Statistics of the session
+    frames: u64,
+    late_frames: u64,
+    skipped_frames: u64,
+    worst_time: Duration,
+    // Gap between the time between the start of two frames and their target time
+    total_jitter: Duration,
+    worst_jitter: Duration,
+    paced_frames: u64,
+}
+
+// Change of the lagging state of the emulation
+#[derive(Debug, PartialEq)]
+pub enum LagChange {
+    Lagging,
+    Recovered,
+}
+
+impl LagMonitor {
+    pub fn is_lagging(&self) -> bool {
+        self.frameskip > 0
+    }
+
+    // Count the time a frame took to emulate and render, without the sleep
+    pub fn frame(&mut self, busy: Duration, target: Duration) -> Option<LagChange> {
+        self.frames += 1;
+        self.worst_time = self.worst_time.max(busy);
+        let was_lagging = self.is_lagging();
+        if busy > target {
+            self.late_frames += 1;
+            self.late_in_a_row += 1;
+            self.on_time_in_a_row = 0;
+            if self.late_in_a_row >= LATE_FRAMES {
+                self.late_in_a_row = 0;
+                self.frameskip = (self.frameskip + 1).min(MAX_FRAMESKIP);
+            }
+        } else {
+            self.on_time_in_a_row += 1;
+            self.late_in_a_row = 0;
+            if self.on_time_in_a_row >= ON_TIME_FRAMES {
+                self.on_time_in_a_row = 0;
+                self.frameskip = self.frameskip.saturating_sub(1);
+            }
+        }
+        let speed = target.as_secs_f64() / busy.as_secs_f64().max(f64::EPSILON);
+        self.reached_speed = if was_lagging {
+            self.reached_speed * (1.0 - SPEED_SMOOTHING) + speed.min(1.0) * SPEED_SMOOTHING
+        } else {
+            1.0
+        };
+        match (was_lagging, self.is_lagging()) {
+            (false, true) => Some(LagChange::Lagging),
+            (true, false) => Some(LagChange::Recovered),
+            _ => None,
+        }
+    }
+
+    // Count the time from the start of a frame to the start of the next one, the sleep
+    // included, to tell how regular the frames are
+    pub fn frame_period(&mut self, period: Duration, target: Duration) {
+        let jitter = period.abs_diff(target);
+        self.total_jitter += jitter;
+        self.worst_jitter = self.worst_jitter.max(jitter);
+        self.paced_frames += 1;
+    }
+
+    // Whether the next frame must be skipped instead of presented
+    pub fn skip_frame(&mut self) -> bool {
+        if self.skipped_in_a_row < self.frameskip {
+            self.skipped_in_a_row += 1;
+            self.skipped_frames += 1;
+            true
+        } else {
+            self.skipped_in_a_row = 0;
+            false
+        }
+    }
+
+    // Part of the target speed reached, 1 when keeping up
+    pub fn reached_speed(&self) -> f64 {
+        if self.is_lagging() {
+            self.reached_speed
+        } else {
+            1.0
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let average_jitter = self.total_jitter.as_secs_f64() / self.paced_frames.max(1) as f64;
+        format!(
+            "{} late frames and {} skipped frames out of {}, the slowest taking {:.1}ms, frame time jitter of {:.2}ms on average and {:.1}ms at most",
+            self.late_frames,
+            self.skipped_frames,
+            self.frames,
+            self.worst_time.as_secs_f64() * 1000.0,
+            average_jitter * 1000.0,
+            self.worst_jitter.as_secs_f64() * 1000.0
+        )
+    }
+}
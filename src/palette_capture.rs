@@ -0,0 +1,76 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::emulator::HeadlessEmulator;
+use crate::movie::Movie;
+
+// Clocks of the core per scanline, one per PPU cycle
+const SCANLINE_CLOCKS: u64 = 341;
+const VISIBLE_LINES: u16 = 240;
+// The core hands the frame once the visible lines are drawn, so the post-render and
+// vertical blank lines come before the first line of the next frame
+const LINES_BEFORE_FRAME: u64 = 262 - VISIBLE_LINES as u64;
+const PALETTE_ENTRIES: usize = 32;
+
+// Write the colors of the palette RAM at the start of a scanline of each frame to a CSV
+// file, one row per frame, to compare the games changing the palette in the middle of
+// the frame with reference emulators. The inputs of a movie are played if given, so that
+// the games can be brought to the screen to check.
+pub fn capture_palettes(
+    rom_path: &str,
+    palette_path: Option<&str>,
+    scanline: u16,
+    frames: u64,
+    movie_path: Option<&str>,
+    out: &str,
+) -> Result<(), String> {
+    if scanline >= VISIBLE_LINES {
+        return Err(format!("the scanline must be below {}", VISIBLE_LINES));
+    }
+    let movie = movie_path.map(Movie::load).transpose()?;
+    let mut emulator = HeadlessEmulator::new(palette_path);
+    emulator.load_rom(rom_path)?;
+    let file = File::create(out).map_err(|e| e.to_string())?;
+    let mut writer = BufWriter::new(file);
+    let error = |e: std::io::Error| e.to_string();
+
+    write!(writer, "frame,scanline").map_err(error)?;
+    for entry in 0..PALETTE_ENTRIES {
+        write!(writer, ",{:02X}", entry).map_err(error)?;
+    }
+    writeln!(writer).map_err(error)?;
+
+    let clocks = (LINES_BEFORE_FRAME + scanline as u64) * SCANLINE_CLOCKS;
+    for frame in 0..=frames {
+        let inputs = movie
+            .as_ref()
+            .and_then(|m| m.inputs(frame as usize))
+            .unwrap_or([0; 2]);
+        for (port, input) in inputs.iter().enumerate() {
+            emulator.set_input(port, *input)?;
+        }
+        // The first frame is only run to know where the frames start
+        if frame == 0 {
+            emulator.step_frame()?;
+            continue;
+        }
+        if emulator.step_frame_within(clocks)? {
+            return Err(format!(
+                "frame {} ended before scanline {}",
+                frame, scanline
+            ));
+        }
+        write!(writer, "{},{}", frame, scanline).map_err(error)?;
+        for color in emulator.palette()? {
+            write!(
+                writer,
+                ",{:02X}{:02X}{:02X}",
+                color.red, color.green, color.blue
+            )
+            .map_err(error)?;
+        }
+        writeln!(writer).map_err(error)?;
+        emulator.step_frame()?;
+    }
+    writer.flush().map_err(error)
+}
@@ -0,0 +1,464 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use log::warn;
+use nesmulator_core::utils::ARGBColor;
+use sha2::Sha256;
+
+use crate::font::{self, GLYPH_HEIGHT};
+use crate::hex::{from_hex, to_hex};
+use crate::osd::blend;
+use crate::theme::Theme;
+
+const FRAME_WIDTH: usize = 256;
+// Time between two writes of the played time, which also reads the changes of the limit
+const SYNC_TIME: Duration = Duration::from_secs(10);
+// Time between two checks for more play time once the limit is reached
+pub const LOCKED_CHECK_TIME: Duration = Duration::from_secs(1);
+// Longer times between two frames are pauses, which are not counted
+const MAX_FRAME_TIME: Duration = Duration::from_millis(100);
+// The player is warned when this much time is left
+const WARNING_TIME: Duration = Duration::from_secs(5 * 60);
+// Rounds of PBKDF2 hashing the PIN, so that trying every PIN of 6 digits takes days
+const PIN_HASH_ROUNDS: u32 = 600_000;
+
+// Salted hash of the PIN protecting the limit
+#[derive(Clone)]
+struct PinHash {
+    salt: [u8; 16],
+    hash: [u8; 32],
+}
+
+impl PinHash {
+    fn new(pin: &str) -> Result<Self, String> {
+        let mut salt = [0; 16];
+        getrandom::getrandom(&mut salt).map_err(|e| e.to_string())?;
+        Ok(PinHash {
+            salt,
+            hash: hash_pin(pin, &salt),
+        })
+    }
+
+    fn matches(&self, pin: &str) -> bool {
+        hash_pin(pin, &self.salt) == self.hash
+    }
+}
+
+fn hash_pin(pin: &str, salt: &[u8]) -> [u8; 32] {
+    let mut hash = [0; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(pin.as_bytes(), salt, PIN_HASH_ROUNDS, &mut hash);
+    hash
+}
+
+// Check of the other settings of the file, keyed by the random key written next to it so
+// that the emulator can write the played time without the PIN. It tells the file was
+// edited by hand, unless the key was read too.
+fn seal(key: &[u8], settings: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(settings.as_bytes());
+    to_hex(&mac.finalize().into_bytes())
+}
+
+fn from_hex_array<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    from_hex(hex)?.try_into().ok()
+}
+
+// Key of the seal, written once with the first PIN. Its folder must only be writable by the
+// administrators, so that the player can neither delete it nor the limits.
+fn key_path(path: &Path) -> PathBuf {
+    path.with_file_name("parental.key")
+}
+
+fn load_key(path: &Path) -> Result<Option<Vec<u8>>, String> {
+    let path = key_path(path);
+    match fs::read(&path) {
+        Ok(key) => Ok(Some(key)),
+        Err(_) if !path.exists() => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn create_key(path: &Path) -> Result<Vec<u8>, String> {
+    let mut key = vec![0; 32];
+    getrandom::getrandom(&mut key).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(key_path(path))
+        .map_err(|e| e.to_string())?;
+    file.write_all(&key).map_err(|e| e.to_string())?;
+    Ok(key)
+}
+
+// Daily play time limit and time played today, shared by all the instances of the emulator
+#[derive(Default)]
+struct Limits {
+    daily_limit: Option<Duration>,
+    pin: Option<PinHash>,
+    key: Option<Vec<u8>>,
+    // Days since the Unix epoch, in UTC
+    day: u64,
+    played: Duration,
+    // Whether the file was changed or deleted without the PIN, its seal not matching the
+    // settings
+    edited: bool,
+}
+
+impl Limits {
+    // There is no limit until the first PIN is given, which writes the key
+    fn load(path: &Path) -> Result<Self, String> {
+        let key = load_key(path)?;
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(_) if !path.exists() => {
+                return Ok(Limits {
+                    day: today(),
+                    edited: key.is_some(),
+                    key,
+                    ..Limits::default()
+                })
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        let value = text.parse::<toml::Value>().map_err(|e| e.to_string())?;
+        let integer = |name: &str| value.get(name).and_then(|v| v.as_integer());
+        let string = |name: &str| value.get(name).and_then(|v| v.as_str());
+        let pin = match (
+            string("pin-salt").and_then(from_hex_array),
+            string("pin-hash").and_then(from_hex_array),
+        ) {
+            (Some(salt), Some(hash)) => Some(PinHash { salt, hash }),
+            _ => None,
+        };
+        let mut limits = Limits {
+            daily_limit: integer("daily-limit").map(|m| Duration::from_secs(m as u64 * 60)),
+            pin,
+            key,
+            day: integer("day").unwrap_or(0) as u64,
+            played: Duration::from_secs(integer("played").unwrap_or(0) as u64),
+            edited: false,
+        };
+        limits.edited = match (&limits.pin, &limits.key) {
+            (Some(_), Some(key)) => string("seal") != Some(seal(key, &limits.settings()).as_str()),
+            _ => true,
+        };
+        // The played time is counted again each day
+        if limits.day != today() {
+            limits.day = today();
+            limits.played = Duration::ZERO;
+        }
+        Ok(limits)
+    }
+
+    // The strictest limit when the file was edited, until the PIN is given again
+    fn effective_limit(&self) -> Option<Duration> {
+        if self.edited {
+            Some(Duration::ZERO)
+        } else {
+            self.daily_limit
+        }
+    }
+
+    // Settings covered by the seal, as written in the file
+    fn settings(&self) -> String {
+        let mut text = String::new();
+        if let Some(limit) = self.daily_limit {
+            text += &format!("daily-limit = {}\n", limit.as_secs() / 60);
+        }
+        text += &format!("day = {}\nplayed = {}\n", self.day, self.played.as_secs());
+        text
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let mut text = self.settings();
+        if let (Some(pin), Some(key)) = (&self.pin, &self.key) {
+            text += &format!(
+                "pin-salt = \"{}\"\npin-hash = \"{}\"\nseal = \"{}\"\n",
+                to_hex(&pin.salt),
+                to_hex(&pin.hash),
+                seal(key, &self.settings())
+            );
+        }
+        fs::write(path, text).map_err(|e| e.to_string())?;
+        // Every player writes the played time, but cannot delete the file
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = fs::set_permissions(path, fs::Permissions::from_mode(0o666));
+        }
+        Ok(())
+    }
+}
+
+// In a folder of the system rather than of the user, which the environment cannot move
+#[cfg(windows)]
+const PARENTAL_DIR: &str = r"C:\ProgramData\nesmulator";
+#[cfg(not(windows))]
+const PARENTAL_DIR: &str = "/var/lib/nesmulator";
+
+pub fn default_path() -> PathBuf {
+    Path::new(PARENTAL_DIR).join("parental.toml")
+}
+
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / 86_400
+}
+
+// Change the daily limit in minutes (0 to remove it) or give more play time for today.
+// The first PIN given protects the next changes. Returns a summary of the limits.
+pub fn configure(
+    path: &Path,
+    pin: &str,
+    daily_limit: Option<u64>,
+    extra_time: Option<u64>,
+) -> Result<String, String> {
+    let mut limits = Limits::load(path)?;
+    match &limits.pin {
+        Some(hash) if !hash.matches(pin) => return Err(String::from("Wrong PIN")),
+        Some(_) => (),
+        None if limits.edited => {
+            return Err(format!(
+                "The PIN was removed from {}, delete {} to set a new one",
+                path.display(),
+                key_path(path).display()
+            ))
+        }
+        None => limits.pin = Some(PinHash::new(pin)?),
+    }
+    if limits.key.is_none() {
+        limits.key = Some(create_key(path)?);
+    }
+    // The limit of an edited file cannot be trusted
+    if limits.edited && daily_limit.is_none() {
+        return Err(String::from(
+            "The limits were edited without the PIN, give the daily limit again with --limit",
+        ));
+    }
+    limits.edited = false;
+    if let Some(minutes) = daily_limit {
+        limits.daily_limit = (minutes > 0).then(|| Duration::from_secs(minutes * 60));
+    }
+    if let Some(minutes) = extra_time {
+        limits.played = limits
+            .played
+            .saturating_sub(Duration::from_secs(minutes * 60));
+    }
+    limits.save(path)?;
+    Ok(match limits.daily_limit {
+        Some(limit) => format!(
+            "Daily limit: {} minutes, {} minutes played today",
+            limit.as_secs() / 60,
+            limits.played.as_secs() / 60
+        ),
+        None => String::from("No daily limit"),
+    })
+}
+
+// Count the play time of the session against the daily limit
+pub struct PlayTimer {
+    path: PathBuf,
+    daily_limit: Option<Duration>,
+    // Played today when last synchronized with the file
+    played: Duration,
+    unsynced: Duration,
+    last_frame: Instant,
+    synced_at: Instant,
+    warned: bool,
+}
+
+impl PlayTimer {
+    // None when there is no daily limit
+    pub fn new(path: &Path) -> Result<Option<Self>, String> {
+        let limits = Limits::load(path)?;
+        if limits.edited {
+            warn!(
+                "{} was edited without the PIN, no play time is left until the limit is given again.",
+                path.display()
+            );
+        }
+        if limits.effective_limit().is_none() {
+            return Ok(None);
+        }
+        Ok(Some(PlayTimer {
+            path: path.to_path_buf(),
+            daily_limit: limits.effective_limit(),
+            played: limits.played,
+            unsynced: Duration::ZERO,
+            last_frame: Instant::now(),
+            synced_at: Instant::now(),
+            warned: false,
+        }))
+    }
+
+    pub fn remaining(&self) -> Option<Duration> {
+        self.daily_limit
+            .map(|limit| limit.saturating_sub(self.played + self.unsynced))
+    }
+
+    pub fn is_time_up(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    // Count the time since the last frame. Returns whether the player must be warned
+    // that the time is almost up.
+    pub fn frame(&mut self) -> bool {
+        let elapsed = self.last_frame.elapsed();
+        self.last_frame = Instant::now();
+        if elapsed < MAX_FRAME_TIME {
+            self.unsynced += elapsed;
+        }
+        if self.synced_at.elapsed() >= SYNC_TIME || self.is_time_up() {
+            self.sync();
+        }
+        let warn = !self.warned && matches!(self.remaining(), Some(r) if r <= WARNING_TIME);
+        self.warned |= warn;
+        warn
+    }
+
+    // Write the played time, and read the limit and the time played by other instances
+    pub fn sync(&mut self) {
+        self.synced_at = Instant::now();
+        let mut limits = match Limits::load(&self.path) {
+            Ok(limits) => limits,
+            Err(e) => {
+                warn!("Cannot read the play time limits: {}", e);
+                return;
+            }
+        };
+        limits.played += self.unsynced;
+        self.unsynced = Duration::ZERO;
+        // An edited file is left as is, writing it would seal the changes
+        if !limits.edited {
+            if let Err(e) = limits.save(&self.path) {
+                warn!("Cannot save the play time: {}", e);
+            }
+        }
+        self.daily_limit = limits.effective_limit();
+        self.played = limits.played;
+        if !matches!(self.remaining(), Some(r) if r <= WARNING_TIME) {
+            self.warned = false;
+        }
+    }
+
+    // Once the time is up, read the file again in case more time was given
+    pub fn check_unlocked(&mut self) -> bool {
+        self.sync();
+        self.last_frame = Instant::now();
+        !self.is_time_up()
+    }
+}
+
+// Shade the frame and tell that the play time is over
+pub fn draw_notice(frame: &mut [ARGBColor], theme: &Theme) {
+    for pixel in frame.iter_mut() {
+        *pixel = blend(*pixel, theme.menu_shade);
+    }
+    let lines = [
+        ("TIME IS UP", theme.text),
+        ("See you tomorrow!", theme.text),
+        ("Session saved", theme.dim_text),
+    ];
+    let scale = theme.font_scale;
+    let line_height = GLYPH_HEIGHT * scale + 5;
+    let height = frame.len() / FRAME_WIDTH;
+    let top = height.saturating_sub((lines.len() + 1) * line_height) / 2;
+    for (i, (text, color)) in lines.iter().enumerate() {
+        let x = FRAME_WIDTH.saturating_sub(font::text_width_scaled(text, scale)) / 2;
+        let y = top + (i + (i > 0) as usize) * line_height;
+        font::draw_text_scaled(frame, FRAME_WIDTH, x, y, text, *color, scale);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    // Folder of the files of a test, removed when dropped
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "nesmulator-parental-{}-{}",
+                name,
+                process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            TestDir(dir)
+        }
+
+        fn path(&self) -> PathBuf {
+            self.0.join("parental.toml")
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn no_limit_until_a_pin_is_given() {
+        let dir = TestDir::new("none");
+        let limits = Limits::load(&dir.path()).unwrap();
+        assert!(!limits.edited);
+        assert_eq!(limits.effective_limit(), None);
+        assert_eq!(limits.day, today());
+    }
+
+    #[test]
+    fn seals_the_limits() {
+        let dir = TestDir::new("seal");
+        let path = dir.path();
+        configure(&path, "1234", Some(30), None).unwrap();
+        let limits = Limits::load(&path).unwrap();
+        assert!(!limits.edited);
+        assert_eq!(limits.effective_limit(), Some(Duration::from_secs(30 * 60)));
+        assert_eq!(
+            configure(&path, "4321", Some(0), None),
+            Err(String::from("Wrong PIN"))
+        );
+
+        // The played time is written without the PIN and stays sealed
+        let mut timer = PlayTimer::new(&path).unwrap().unwrap();
+        timer.unsynced = Duration::from_secs(60);
+        timer.sync();
+        let limits = Limits::load(&path).unwrap();
+        assert!(!limits.edited);
+        assert_eq!(limits.played, Duration::from_secs(60));
+
+        // The key is not in the file
+        let key = fs::read(key_path(&path)).unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains(&to_hex(&key)));
+    }
+
+    #[test]
+    fn locks_edited_or_deleted_limits() {
+        let dir = TestDir::new("edited");
+        let path = dir.path();
+        configure(&path, "1234", Some(30), None).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::write(&path, text.replace("daily-limit = 30", "daily-limit = 300")).unwrap();
+        let limits = Limits::load(&path).unwrap();
+        assert!(limits.edited);
+        assert_eq!(limits.effective_limit(), Some(Duration::ZERO));
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            Limits::load(&path).unwrap().effective_limit(),
+            Some(Duration::ZERO)
+        );
+    }
+}
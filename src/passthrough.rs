@@ -0,0 +1,167 @@
+use log::{info, warn};
+use nesmulator_core::utils::ARGBColor;
+
+use crate::font::{self, GLYPH_HEIGHT};
+use crate::input::PORTS;
+use crate::osd::{argb, blend};
+
+const FRAME_WIDTH: usize = 256;
+const FRAME_HEIGHT: usize = 240;
+const MARGIN: usize = 2;
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 2 * MARGIN;
+// Lines hidden by the overscan crop, kept clear so that the buttons stay visible
+const OVERSCAN_LINES: usize = 8;
+// Labels of the buttons in the order of their bits, A being the lowest
+const BUTTON_LABELS: [&str; 8] = ["A", "B", "Se", "St", "U", "D", "L", "R"];
+
+// Buttons played by a movie shown on the frame and pressed as keys of the system, so that
+// another emulator given the same keys plays along, e.g. to check that both emulators
+// play the movie the same way. The keys are only pressed when built with the
+// key-passthrough feature.
+pub struct MoviePassthrough {
+    // Key name of each button of the first controller
+    keys: Vec<(u8, &'static str)>,
+    #[cfg(feature = "key-passthrough")]
+    keyboard: Option<SystemKeyboard>,
+    // Buttons whose key is pressed
+    held: u8,
+}
+
+impl MoviePassthrough {
+    pub fn new(keys: Vec<(u8, &'static str)>) -> Self {
+        #[cfg(not(feature = "key-passthrough"))]
+        warn!("Built without the key-passthrough feature, the movie inputs are only shown.");
+        MoviePassthrough {
+            keys,
+            #[cfg(feature = "key-passthrough")]
+            keyboard: None,
+            held: 0,
+        }
+    }
+
+    // Press and release the keys of the buttons of the first controller that changed
+    pub fn frame(&mut self, input: u8) {
+        if input == self.held {
+            return;
+        }
+        for (button, key) in self.keys.clone() {
+            let pressed = input & button != 0;
+            if pressed != (self.held & button != 0) {
+                self.press(key, pressed);
+            }
+        }
+        self.held = input;
+    }
+
+    // The keys are released when the movie is over
+    pub fn release(&mut self) {
+        self.frame(0);
+    }
+
+    #[cfg(feature = "key-passthrough")]
+    fn press(&mut self, key: &str, pressed: bool) {
+        // The connection to the system is opened on the emulation thread
+        if self.keyboard.is_none() {
+            match SystemKeyboard::new() {
+                Ok(keyboard) => {
+                    info!("Pressing the keys of the movie buttons.");
+                    self.keyboard = Some(keyboard);
+                }
+                Err(e) => {
+                    warn!("Cannot press the keys of the system: {}", e);
+                    self.keys.clear();
+                    return;
+                }
+            }
+        }
+        if let Some(keyboard) = self.keyboard.as_mut() {
+            if let Err(e) = keyboard.press(key, pressed) {
+                warn!("Cannot press {}: {}", key, e);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "key-passthrough"))]
+    fn press(&mut self, _key: &str, _pressed: bool) {}
+}
+
+impl Drop for MoviePassthrough {
+    fn drop(&mut self) {
+        if self.held != 0 {
+            info!("Releasing the keys of the movie buttons.");
+            self.release();
+        }
+    }
+}
+
+#[cfg(feature = "key-passthrough")]
+struct SystemKeyboard {
+    enigo: enigo::Enigo,
+}
+
+#[cfg(feature = "key-passthrough")]
+impl SystemKeyboard {
+    fn new() -> Result<Self, String> {
+        let enigo = enigo::Enigo::new(&enigo::Settings::default()).map_err(|e| e.to_string())?;
+        Ok(SystemKeyboard { enigo })
+    }
+
+    fn press(&mut self, key: &str, pressed: bool) -> Result<(), String> {
+        use enigo::{Direction, Key, Keyboard};
+
+        let key = match key {
+            "Up" => Key::UpArrow,
+            "Down" => Key::DownArrow,
+            "Left" => Key::LeftArrow,
+            "Right" => Key::RightArrow,
+            "Space" => Key::Space,
+            "Return" => Key::Return,
+            "Tab" => Key::Tab,
+            "Backspace" => Key::Backspace,
+            "LControl" | "RControl" => Key::Control,
+            "LAlt" | "RAlt" => Key::Alt,
+            "Comma" => Key::Unicode(','),
+            "Period" => Key::Unicode('.'),
+            "Minus" => Key::Unicode('-'),
+            "Equals" => Key::Unicode('='),
+            _ if key.len() == 1 => Key::Unicode(key.to_ascii_lowercase().chars().next().unwrap()),
+            _ => return Err(String::from("key not supported by the passthrough")),
+        };
+        let direction = if pressed {
+            Direction::Press
+        } else {
+            Direction::Release
+        };
+        self.enigo.key(key, direction).map_err(|e| e.to_string())
+    }
+}
+
+// Draw the buttons held on each port driven by the movie in the bottom left corner of the
+// frame, the held ones in white
+pub fn draw_inputs(frame: &mut [ARGBColor], inputs: [Option<u8>; PORTS]) {
+    let background = argb(0xA0, 0x00, 0x00, 0x00);
+    let white = argb(0xFF, 0xFF, 0xFF, 0xFF);
+    let grey = argb(0xFF, 0x50, 0x50, 0x50);
+    let box_width = BUTTON_LABELS
+        .iter()
+        .map(|label| font::text_width(label) + MARGIN)
+        .sum::<usize>()
+        + MARGIN;
+    let rows: Vec<u8> = inputs.iter().flatten().copied().collect();
+    let bottom = FRAME_HEIGHT - OVERSCAN_LINES - MARGIN;
+    for (i, input) in rows.iter().enumerate() {
+        let top = bottom - (rows.len() - i) * (LINE_HEIGHT + MARGIN) + MARGIN;
+        for y in top..top + LINE_HEIGHT {
+            for x in MARGIN..MARGIN + box_width {
+                let pixel = &mut frame[y * FRAME_WIDTH + x];
+                *pixel = blend(*pixel, background);
+            }
+        }
+        let mut x = 2 * MARGIN;
+        for (bit, label) in BUTTON_LABELS.iter().enumerate() {
+            let color = if input & (1 << bit) != 0 { white } else { grey };
+            font::draw_text(frame, FRAME_WIDTH, x, top + MARGIN, label, color);
+            x += font::text_width(label) + MARGIN;
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use crate::parental;
+use crate::rom::RomPaths;
+
+// Where the emulator reads and writes its files, logged at startup and printed by the
+// paths subcommand so that nobody has to guess where a state went
+pub fn describe_paths(config_path: &str, notes_path: &str, rom: Option<&RomPaths>) -> String {
+    let mut rows = vec![
+        ("Configuration", String::from(config_path)),
+        ("Notes", String::from(notes_path)),
+        (
+            "Play time limit",
+            parental::default_path().to_string_lossy().into_owned(),
+        ),
+    ];
+    if let Some(rom) = rom {
+        let slots = match (rom.state_slots.first(), rom.state_slots.last()) {
+            (Some(first), Some(last)) => format!("{} to {}", first, file_name(last)),
+            _ => String::from("-"),
+        };
+        rows.extend([
+            ("ROM", rom.rom.clone()),
+            ("Quick state", rom.state.clone()),
+            ("State slots", slots),
+            ("Save", rom.save.clone()),
+            ("Cheats", rom.cheats.clone()),
+            ("Session", rom.session.clone()),
+            ("Screenshots", rom.screenshots.clone()),
+            ("Cover", rom.cover.clone()),
+            ("Bug reports", rom.repro.clone()),
+        ]);
+        if rom.save_fallback {
+            rows.push((
+                "Note",
+                String::from("the folder of the ROM is read-only, the saves go to the data folder"),
+            ));
+        }
+    }
+    let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    rows.iter()
+        .map(|(name, path)| format!("{:width$}  {}\n", name, path, width = width))
+        .collect()
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map_or(String::from(path), |name| {
+            name.to_string_lossy().into_owned()
+        })
+}
@@ -0,0 +1,43 @@
+use log::warn;
+#[cfg(feature = "profiling")]
+use {log::info, std::fs::File, tracing_subscriber::layer::SubscriberExt};
+
+// Trace of the main and emulation loops written with --trace, opened in chrome://tracing
+// or Perfetto. The functions of each frame are recorded as spans, the gaps between them on
+// the emulation thread being the time spent emulating. They are only compiled with the
+// profiling feature.
+pub struct TraceCapture {
+    #[cfg(feature = "profiling")]
+    _guard: tracing_chrome::FlushGuard,
+}
+
+// Record the spans to the file until the capture is dropped
+#[cfg(feature = "profiling")]
+pub fn start_capture(path: &str) -> Option<TraceCapture> {
+    let file = match File::create(path) {
+        Ok(file) => file,
+        Err(e) => {
+            warn!("Cannot write the trace to {}: {}", path, e);
+            return None;
+        }
+    };
+    let (layer, guard) = tracing_chrome::ChromeLayerBuilder::new()
+        .writer(file)
+        .build();
+    let subscriber = tracing_subscriber::registry().with(layer);
+    if let Err(e) = tracing::subscriber::set_global_default(subscriber) {
+        warn!("Cannot record the trace: {}", e);
+        return None;
+    }
+    info!("Recording a trace to {}.", path);
+    Some(TraceCapture { _guard: guard })
+}
+
+#[cfg(not(feature = "profiling"))]
+pub fn start_capture(path: &str) -> Option<TraceCapture> {
+    warn!(
+        "Built without the profiling feature, no trace is written to {}.",
+        path
+    );
+    None
+}
@@ -0,0 +1,72 @@
+use numpy::{PyArray1, PyArray3};
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+
+use crate::emulator::HeadlessEmulator;
+
+fn to_py_err(e: String) -> PyErr {
+    PyRuntimeError::new_err(e)
+}
+
+// Python wrapper of HeadlessEmulator
+#[pyclass(name = "Emulator")]
+struct PyEmulator {
+    inner: HeadlessEmulator,
+}
+
+#[pymethods]
+impl PyEmulator {
+    #[new]
+    #[args(palette = "None")]
+    fn new(palette: Option<&str>) -> Self {
+        PyEmulator {
+            inner: HeadlessEmulator::new(palette),
+        }
+    }
+
+    fn load_rom(&mut self, path: &str) -> PyResult<()> {
+        self.inner.load_rom(path).map_err(to_py_err)
+    }
+
+    fn step_frame(&mut self) -> PyResult<()> {
+        self.inner.step_frame().map_err(to_py_err)
+    }
+
+    // Last frame as a (240, 256, 3) RGB array
+    fn get_frame<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray3<u8>> {
+        let mut data = Vec::with_capacity(61_440 * 3);
+        for color in self.inner.frame() {
+            data.extend_from_slice(&[color.red, color.green, color.blue]);
+        }
+        PyArray1::from_vec(py, data).reshape([240, 256, 3])
+    }
+
+    #[getter]
+    fn frame_count(&self) -> u64 {
+        self.inner.frame_count()
+    }
+
+    // Buttons held on a controller port, as the bits of ControllerInput
+    // (A = 0x01, B = 0x02, Select = 0x04, Start = 0x08, Up, Down, Left, Right = 0x80)
+    fn set_input(&mut self, port: usize, buttons: u8) -> PyResult<()> {
+        self.inner.set_input(port, buttons).map_err(to_py_err)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn save_state(&mut self, path: &str) -> PyResult<()> {
+        self.inner.save_state(path).map_err(to_py_err)
+    }
+
+    fn load_state(&mut self, path: &str) -> PyResult<()> {
+        self.inner.load_state(path).map_err(to_py_err)
+    }
+}
+
+#[pymodule]
+fn nesmulator_gui(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyEmulator>()?;
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use nesmulator_core::utils::ARGBColor;
+
+use crate::backend::EmulatorCore;
+
+const FRAME_WIDTH: usize = 256;
+const FRAME_HEIGHT: usize = 240;
+
+// Which instance is heard during a race
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RaceAudio {
+    Left,
+    Right,
+    Mix,
+}
+
+impl RaceAudio {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "left" => Some(RaceAudio::Left),
+            "right" => Some(RaceAudio::Right),
+            "mix" => Some(RaceAudio::Mix),
+            _ => None,
+        }
+    }
+}
+
+// Two instances of the same ROM running side by side for head-to-head races.
+// Each instance is displayed at half the horizontal resolution, the first controller port
+// drives the left instance and the second port the right one.
+pub struct Race<C> {
+    left: C,
+    right: C,
+    right_frame: Box<[ARGBColor; 61_440]>,
+    audio: RaceAudio,
+}
+
+impl<C: EmulatorCore> Race<C> {
+    pub fn set_audio(&mut self, audio: RaceAudio) {
+        self.audio = audio;
+    }
+}
+
+impl<C: EmulatorCore> EmulatorCore for Race<C> {
+    fn new(palette_path: Option<&str>, display_cpu_logs: bool) -> Self {
+        Race {
+            left: C::new(palette_path, display_cpu_logs),
+            right: C::new(palette_path, display_cpu_logs),
+            right_frame: Box::new([ARGBColor::black(); 61_440]),
+            audio: RaceAudio::Mix,
+        }
+    }
+
+    fn insert_cartdrige(&mut self, rom_path: &str) -> Result<(), String> {
+        self.left.insert_cartdrige(rom_path)?;
+        self.right.insert_cartdrige(rom_path)
+    }
+
+    // Both players start from the same state
+    fn load_state(&mut self, state_path: &str, rom_path: &str) -> Result<(), String> {
+        self.left.load_state(state_path, rom_path)?;
+        self.right.load_state(state_path, rom_path)
+    }
+
+    // Only the state of the left player is saved
+    fn save_state(&mut self, path: &str) -> Result<(), String> {
+        self.left.save_state(path)
+    }
+
+    fn load_save(&mut self, path: &str) -> Result<(), String> {
+        self.left.load_save(path)?;
+        self.right.load_save(path)
+    }
+
+    fn save(&mut self, path: &str) -> Result<(), String> {
+        self.left.save(path)
+    }
+
+    fn clock(&mut self) {
+        self.left.clock();
+        self.right.clock();
+    }
+
+    fn reset(&mut self) {
+        self.left.reset();
+        self.right.reset();
+    }
+
+    fn input(&mut self, port: usize, input: u8) -> Result<(), String> {
+        match port {
+            0 => self.left.input(0, input),
+            1 => self.right.input(0, input),
+            _ => Ok(()),
+        }
+    }
+
+    // The instances run in lockstep, the left one decides when a frame is ready
+    fn get_frame_buffer(&mut self) -> Option<[ARGBColor; 61_440]> {
+        if let Some(frame) = self.right.get_frame_buffer() {
+            *self.right_frame = frame;
+        }
+        let left = self.left.get_frame_buffer()?;
+
+        let mut frame = [ARGBColor::black(); 61_440];
+        let half = FRAME_WIDTH / 2;
+        for y in 0..FRAME_HEIGHT {
+            let row = y * FRAME_WIDTH;
+            for x in 0..half {
+                frame[row + x] = left[row + 2 * x];
+                frame[row + half + x] = self.right_frame[row + 2 * x];
+            }
+        }
+        Some(frame)
+    }
+
+    fn get_pattern_tables(&self) -> Result<[Vec<ARGBColor>; 2], String> {
+        self.left.get_pattern_tables()
+    }
+
+    fn get_palette_colors(&self) -> Result<Vec<ARGBColor>, String> {
+        self.left.get_palette_colors()
+    }
+
+    fn set_debug_palette_id(&mut self, id: u8) -> Result<(), String> {
+        self.left.set_debug_palette_id(id)?;
+        self.right.set_debug_palette_id(id)
+    }
+
+    fn get_one_frame_duration(&self) -> Duration {
+        self.left.get_one_frame_duration()
+    }
+
+    fn produce_samples(&mut self, produce: bool) {
+        self.left.produce_samples(produce);
+        self.right.produce_samples(produce);
+    }
+
+    // Samples of both instances are always taken so that they do not pile up
+    fn get_samples(&mut self) -> Vec<f32> {
+        let left = self.left.get_samples();
+        let right = self.right.get_samples();
+        match self.audio {
+            RaceAudio::Left => left,
+            RaceAudio::Right => right,
+            RaceAudio::Mix => left
+                .iter()
+                .zip(right.iter())
+                .map(|(l, r)| (l + r) / 2.0)
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use log::{error, info};
+use nesmulator_core::utils::ARGBColor;
+
+use crate::capture::{FRAME_HEIGHT, FRAME_WIDTH};
+
+const SAMPLE_RATE: u32 = 44100;
+// The frames are scaled with nearest neighbour filtering, keeping the pixels sharp once
+// the colors are subsampled by the encoder
+const VIDEO_SCALE: u32 = 3;
+
+enum RecordedData {
+    Frame(Vec<u8>),
+    Samples(Vec<f32>),
+}
+
+// Record the frames and the sound of the emulation to a video file, encoded by ffmpeg.
+// The frames and the samples are written from another thread, so that a slow encoder
+// does not slow down the emulation. The video is encoded while recording and the sound
+// is written to a WAV file, both being muxed in the final file once the recording stops.
+pub struct VideoRecorder {
+    path: PathBuf,
+    sender: Sender<RecordedData>,
+    thread: JoinHandle<()>,
+}
+
+impl VideoRecorder {
+    // frame_rate is the number of frames per second of emulated time
+    pub fn new(path: &Path, frame_rate: f64) -> Result<Self, String> {
+        let video_path = path.with_extension("video.mkv");
+        let audio_path = path.with_extension("audio.wav");
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let encoder = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+            .args(["-s", &format!("{}x{}", FRAME_WIDTH, FRAME_HEIGHT)])
+            .args(["-r", &frame_rate.to_string(), "-i", "-"])
+            .args([
+                "-vf",
+                &format!("scale=iw*{0}:ih*{0}:flags=neighbor", VIDEO_SCALE),
+            ])
+            .args(["-c:v", "libx264", "-pix_fmt", "yuv420p", "-crf", "18"])
+            .arg(&video_path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("cannot run ffmpeg ({}), is it installed?", e))?;
+        let wav = WavWriter::new(&audio_path).map_err(|e| e.to_string())?;
+
+        let (sender, receiver) = mpsc::channel();
+        let output_path = path.to_path_buf();
+        let thread = thread::spawn(move || {
+            let result = write_data(receiver, encoder, wav)
+                .and_then(|()| mux(&video_path, &audio_path, &output_path));
+            let _ = fs::remove_file(&video_path);
+            let _ = fs::remove_file(&audio_path);
+            match result {
+                Ok(()) => info!("Video saved to {}.", output_path.display()),
+                Err(e) => error!("Failed to record the video: {}", e),
+            }
+        });
+        Ok(VideoRecorder {
+            path: path.to_path_buf(),
+            sender,
+            thread,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn frame(&mut self, frame: &[ARGBColor]) {
+        let mut data = Vec::with_capacity(frame.len() * 4);
+        for color in frame.iter() {
+            data.extend_from_slice(&[color.red, color.green, color.blue, color.alpha]);
+        }
+        let _ = self.sender.send(RecordedData::Frame(data));
+    }
+
+    pub fn samples(&mut self, samples: &[f32]) {
+        let _ = self.sender.send(RecordedData::Samples(samples.to_vec()));
+    }
+
+    // The video is finished in the background, wait for the returned thread before exiting
+    pub fn stop(self) -> JoinHandle<()> {
+        drop(self.sender);
+        self.thread
+    }
+}
+
+// Write the data received until the recording stops, then wait for the video encoder
+fn write_data(
+    receiver: Receiver<RecordedData>,
+    mut encoder: Child,
+    mut wav: WavWriter,
+) -> Result<(), String> {
+    let mut stdin = encoder.stdin.take().ok_or("no input for ffmpeg")?;
+    for data in receiver.iter() {
+        match data {
+            RecordedData::Frame(frame) => stdin.write_all(&frame),
+            RecordedData::Samples(samples) => wav.write(&samples),
+        }
+        .map_err(|e| e.to_string())?;
+    }
+    drop(stdin);
+    wav.finish().map_err(|e| e.to_string())?;
+    let status = encoder.wait().map_err(|e| e.to_string())?;
+    if !status.success() {
+        return Err(format!("ffmpeg failed to encode the frames ({})", status));
+    }
+    Ok(())
+}
+
+fn mux(video_path: &Path, audio_path: &Path, output_path: &Path) -> Result<(), String> {
+    let status = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-i"])
+        .arg(video_path)
+        .arg("-i")
+        .arg(audio_path)
+        .args(["-c:v", "copy", "-c:a", "aac", "-shortest"])
+        .arg(output_path)
+        .stdin(Stdio::null())
+        .status()
+        .map_err(|e| e.to_string())?;
+    if !status.success() {
+        return Err(format!("ffmpeg failed to mux the video ({})", status));
+    }
+    Ok(())
+}
+
+// 16 bits mono WAV file, whose sizes are written once finished
+struct WavWriter {
+    file: BufWriter<File>,
+    data_size: u32,
+}
+
+impl WavWriter {
+    fn new(path: &Path) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(b"RIFF")?;
+        file.write_all(&0_u32.to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16_u32.to_le_bytes())?;
+        // PCM format, 1 channel
+        file.write_all(&1_u16.to_le_bytes())?;
+        file.write_all(&1_u16.to_le_bytes())?;
+        file.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        // Bytes per second and per sample, bits per sample
+        file.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?;
+        file.write_all(&2_u16.to_le_bytes())?;
+        file.write_all(&16_u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0_u32.to_le_bytes())?;
+        Ok(WavWriter { file, data_size: 0 })
+    }
+
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        for sample in samples.iter() {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_size += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(36 + self.data_size).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&self.data_size.to_le_bytes())?;
+        self.file.flush()
+    }
+}
@@ -0,0 +1,106 @@
+use std::fs::File;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use nesmulator_core::utils::ARGBColor;
+
+use crate::capture::{FRAME_HEIGHT, FRAME_WIDTH};
+use crate::osd::argb;
+
+// Time each image is displayed in blink mode
+const BLINK_TIME: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, PartialEq)]
+enum CompareMode {
+    Off,
+    // Alternate between the live frame and the reference
+    Blink,
+    // Highlight the pixels that differ from the reference
+    Difference,
+}
+
+// Reference screenshot compared with the live frame, e.g. a capture of another emulator
+// or of real hardware
+pub struct ReferenceOverlay {
+    image: Vec<ARGBColor>,
+    mode: CompareMode,
+    since: Instant,
+}
+
+impl ReferenceOverlay {
+    // Load a 256x240 PNG file
+    pub fn load(path: &str) -> Result<Self, String> {
+        Ok(ReferenceOverlay {
+            image: read_frame_png(path)?,
+            mode: CompareMode::Off,
+            since: Instant::now(),
+        })
+    }
+
+    // Switch to the next comparison mode, returns its name
+    pub fn cycle_mode(&mut self) -> &'static str {
+        self.since = Instant::now();
+        let (mode, name) = match self.mode {
+            CompareMode::Off => (CompareMode::Blink, "Reference: blink"),
+            CompareMode::Blink => (CompareMode::Difference, "Reference: difference"),
+            CompareMode::Difference => (CompareMode::Off, "Reference: off"),
+        };
+        self.mode = mode;
+        name
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.mode != CompareMode::Off
+    }
+
+    pub fn draw(&self, frame: &mut [ARGBColor]) {
+        match self.mode {
+            CompareMode::Off => (),
+            CompareMode::Blink => {
+                let reference = (self.since.elapsed().as_millis() / BLINK_TIME.as_millis()) % 2;
+                if reference == 1 {
+                    frame.copy_from_slice(&self.image);
+                }
+            }
+            CompareMode::Difference => {
+                // Identical pixels are dimmed so that the differences stand out
+                for (pixel, reference) in frame.iter_mut().zip(self.image.iter()) {
+                    let same = pixel.red == reference.red
+                        && pixel.green == reference.green
+                        && pixel.blue == reference.blue;
+                    *pixel = if same {
+                        argb(0xFF, pixel.red / 4, pixel.green / 4, pixel.blue / 4)
+                    } else {
+                        argb(0xFF, 0xFF, 0x00, 0xFF)
+                    };
+                }
+            }
+        }
+    }
+}
+
+// Read a 256x240 PNG file as the colors of its pixels
+pub fn read_frame_png(path: impl AsRef<Path>) -> Result<Vec<ARGBColor>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).map_err(|e| e.to_string())?;
+    if info.width != FRAME_WIDTH || info.height != FRAME_HEIGHT {
+        return Err(format!(
+            "The image must be {}x{}, got {}x{}",
+            FRAME_WIDTH, FRAME_HEIGHT, info.width, info.height
+        ));
+    }
+
+    let channels = info.color_type.samples();
+    Ok(data[..info.buffer_size()]
+        .chunks(channels)
+        .map(|pixel| match pixel {
+            [gray] | [gray, _] => argb(0xFF, *gray, *gray, *gray),
+            [red, green, blue, ..] => argb(0xFF, *red, *green, *blue),
+            _ => argb(0xFF, 0x00, 0x00, 0x00),
+        })
+        .collect())
+}
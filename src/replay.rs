@@ -0,0 +1,94 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+
+use crate::backend::EmulatorCore;
+use crate::capture;
+use crate::movie::{MovieRecorder, MovieStart};
+use crate::snapshot::ScratchFile;
+
+// Frames between two states of the replay buffer, 10 seconds
+const SEGMENT_FRAMES: usize = 600;
+
+// State of the NES followed by the inputs of both controllers at each frame from it
+struct Segment {
+    state: Vec<u8>,
+    inputs: Vec<[u8; 2]>,
+}
+
+// Inputs of the last seconds of play and a state every 10 seconds, to write them as a
+// movie after the fact, see --replay-buffer. The replay starts from the oldest state
+// kept, so it holds between the given length and 10 seconds more.
+pub struct ReplayBuffer {
+    segments: VecDeque<Segment>,
+    length_frames: usize,
+    scratch: ScratchFile,
+}
+
+impl ReplayBuffer {
+    pub fn new(length_frames: usize) -> Self {
+        ReplayBuffer {
+            segments: VecDeque::new(),
+            length_frames,
+            scratch: ScratchFile::new("replay"),
+        }
+    }
+
+    // Called at the end of each frame with the inputs it was played with
+    pub fn frame(&mut self, nes: &mut impl EmulatorCore, inputs: [u8; 2]) -> Result<(), String> {
+        match self.segments.back_mut() {
+            Some(segment) if segment.inputs.len() < SEGMENT_FRAMES => {
+                segment.inputs.push(inputs);
+                return Ok(());
+            }
+            Some(segment) => segment.inputs.push(inputs),
+            None => (),
+        }
+        self.segments.push_back(Segment {
+            state: self.scratch.save(nes)?,
+            inputs: vec![],
+        });
+        // The oldest segment is dropped once the others cover the length of the buffer
+        while self.segments.len() > 1
+            && self
+                .segments
+                .iter()
+                .skip(1)
+                .map(|s| s.inputs.len())
+                .sum::<usize>()
+                >= self.length_frames
+        {
+            self.segments.pop_front();
+        }
+        Ok(())
+    }
+
+    // Start again from the next frame, when the game was reset or a state was loaded as
+    // the inputs cannot replay it
+    pub fn clear(&mut self) {
+        self.segments.clear();
+    }
+
+    // Write the oldest state kept and the inputs played since to <path>.data and
+    // <path>.movie, to be played with --state and --play-movie
+    pub fn write(&self, path: &Path, rom_crc32: u32) -> Result<usize, String> {
+        let first = self
+            .segments
+            .front()
+            .ok_or("Nothing was played since the game started")?;
+        let state_path = path.with_extension("data");
+        fs::write(&state_path, &first.state).map_err(|e| e.to_string())?;
+        let mut recorder = MovieRecorder::new(
+            &path.with_extension("movie").to_string_lossy(),
+            rom_crc32,
+            MovieStart::State(capture::crc32(&first.state)),
+        )
+        .map_err(|e| e.to_string())?;
+        let mut frames = 0;
+        for inputs in self.segments.iter().flat_map(|s| s.inputs.iter()) {
+            recorder.frame(*inputs);
+            frames += 1;
+        }
+        Ok(frames)
+    }
+}
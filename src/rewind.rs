@@ -0,0 +1,98 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::backend::EmulatorCore;
+use crate::snapshot::ScratchFile;
+
+// Frames between two snapshots when --rewind-interval is not given
+pub const DEFAULT_INTERVAL: u32 = 2;
+// Memory the snapshots can take, whatever the length of the buffer
+const MAX_BUFFER_SIZE: usize = 64 * 1024 * 1024;
+// Time a snapshot can take before the snapshots are spaced out, to keep the frame pacing
+const SNAPSHOT_BUDGET: Duration = Duration::from_millis(2);
+const MAX_INTERVAL: u32 = 60;
+
+// Ring buffer of the recent states of the NES, popped to go back in time
+pub struct RewindBuffer {
+    snapshots: VecDeque<Vec<u8>>,
+    // Total size of the snapshots in bytes
+    size: usize,
+    length: Duration,
+    frame_duration: Duration,
+    // Frames between two snapshots
+    interval: u32,
+    frames_since_snapshot: u32,
+    scratch: ScratchFile,
+}
+
+impl RewindBuffer {
+    // Keep the states of the last length of emulation, one every interval frames
+    pub fn new(length: Duration, frame_duration: Duration, interval: u32) -> Self {
+        RewindBuffer {
+            snapshots: VecDeque::new(),
+            size: 0,
+            length,
+            frame_duration,
+            interval: interval.max(1),
+            frames_since_snapshot: 0,
+            scratch: ScratchFile::new("rewind"),
+        }
+    }
+
+    // Called at each frame, takes a snapshot every interval frames
+    pub fn frame(&mut self, nes: &mut impl EmulatorCore) -> Result<(), String> {
+        self.frames_since_snapshot += 1;
+        if self.frames_since_snapshot < self.interval {
+            return Ok(());
+        }
+        self.frames_since_snapshot = 0;
+
+        let start = Instant::now();
+        let snapshot = self.scratch.save(nes)?;
+        self.size += snapshot.len();
+        self.snapshots.push_back(snapshot);
+        while self.snapshots.len() > self.max_snapshots() || self.size > MAX_BUFFER_SIZE {
+            match self.snapshots.pop_front() {
+                Some(oldest) => self.size -= oldest.len(),
+                None => break,
+            }
+        }
+
+        if start.elapsed() > SNAPSHOT_BUDGET && self.interval < MAX_INTERVAL {
+            self.interval = (self.interval * 2).min(MAX_INTERVAL);
+            debug!(
+                "Rewind snapshot took {:?}, now taking one every {} frames.",
+                start.elapsed(),
+                self.interval
+            );
+        }
+        Ok(())
+    }
+
+    // Restore the most recent snapshot, returns false if there is none left
+    pub fn rewind(&mut self, nes: &mut impl EmulatorCore, rom_path: &str) -> Result<bool, String> {
+        let snapshot = match self.snapshots.pop_back() {
+            Some(snapshot) => snapshot,
+            None => return Ok(false),
+        };
+        self.size -= snapshot.len();
+        self.frames_since_snapshot = 0;
+        self.scratch.load(nes, &snapshot, rom_path)?;
+        Ok(true)
+    }
+
+    // Forget the snapshots, e.g. of the previous game
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.size = 0;
+        self.frames_since_snapshot = 0;
+    }
+
+    // Number of snapshots covering the length of the buffer
+    fn max_snapshots(&self) -> usize {
+        let snapshot_duration = self.frame_duration.as_secs_f64() * self.interval as f64;
+        ((self.length.as_secs_f64() / snapshot_duration).ceil() as usize).max(1)
+    }
+}
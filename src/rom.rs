@@ -0,0 +1,327 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use nesmulator_core::utils::ARGBColor;
+
+use crate::capture;
+use crate::font::{self, GLYPH_HEIGHT};
+use crate::theme::Theme;
+
+const FRAME_WIDTH: usize = 256;
+
+// Files of a ROM written next to it, or to the saves folder
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RomPaths {
+    pub rom: String,
+    // <ROM>.data
+    pub state: String,
+    // <ROM>.sav
+    pub save: String,
+    // <ROM>.cht, the cheats and whether they are enabled
+    pub cheats: String,
+    // <ROM>-slot<N>.data
+    pub state_slots: Vec<String>,
+    // <ROM>-session.data, written when the emulator is terminated by a signal
+    pub session: String,
+    // <ROM>-repro folder of the bug report captures
+    pub repro: String,
+    // <ROM>.png
+    pub cover: String,
+    // screenshots folder, shared by the ROMs of the folder
+    pub screenshots: String,
+    // Whether the states, the save and the cheats are written to the data folder as the
+    // folder of the ROM is read-only, their names then ending with the CRC32 of the folder
+    // of the ROM, e.g. <ROM>-1a2b3c4d.sav
+    pub save_fallback: bool,
+}
+
+impl RomPaths {
+    // The states, the save and the cheats are written to save_dir if any, or to the data
+    // folder when the folder of the ROM is read-only, e.g. a network share
+    pub fn new(rom_path: &str, state_slots: usize, save_dir: Option<&str>) -> Self {
+        let rom = Path::new(rom_path);
+        let dir = rom.parent().unwrap_or_else(|| Path::new(""));
+        let fallback_dir = match save_dir {
+            None if !is_writable(dir) => Some(default_data_dir().join("saves")),
+            _ => None,
+        };
+        if let Some(fallback_dir) = &fallback_dir {
+            // Writing the files reports the error if this fails
+            let _ = fs::create_dir_all(fallback_dir);
+        }
+        let save_dir = match &fallback_dir {
+            Some(fallback_dir) => fallback_dir.as_path(),
+            None => save_dir.map_or(dir, Path::new),
+        };
+        let stem = rom
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // The data folder is shared by the read-only folders, which can hold ROMs with the
+        // same name, e.g. game.nes on two network shares
+        let save_stem = match &fallback_dir {
+            Some(_) => format!("{}-{:08x}", stem, folder_crc32(dir)),
+            None => stem.clone(),
+        };
+        let path = |name: String| dir.join(name).to_string_lossy().into_owned();
+        let save_path = |name: String| save_dir.join(name).to_string_lossy().into_owned();
+        RomPaths {
+            rom: String::from(rom_path),
+            state: save_path(format!("{}.data", save_stem)),
+            save: save_path(format!("{}.sav", save_stem)),
+            cheats: save_path(format!("{}.cht", save_stem)),
+            state_slots: (1..=state_slots)
+                .map(|slot| save_path(format!("{}-slot{}.data", save_stem, slot)))
+                .collect(),
+            session: save_path(format!("{}-session.data", save_stem)),
+            repro: path(format!("{}-repro", stem)),
+            cover: path(format!("{}.png", stem)),
+            screenshots: path(String::from("screenshots")),
+            save_fallback: fallback_dir.is_some(),
+        }
+    }
+}
+
+// CRC32 of the absolute path of a folder, which stays the same from one run to the next
+fn folder_crc32(dir: &Path) -> u32 {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    capture::crc32(dir.to_string_lossy().as_bytes())
+}
+
+// $XDG_DATA_HOME/nesmulator, ~/.local/share/nesmulator or %APPDATA%\nesmulator
+fn default_data_dir() -> PathBuf {
+    let data_dir = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("share")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from));
+    match data_dir {
+        Some(dir) => dir.join("nesmulator"),
+        None => PathBuf::from("nesmulator-data"),
+    }
+}
+
+// Whether a file can be created in the folder, its permissions not telling it for
+// network shares and read-only file systems
+fn is_writable(dir: &Path) -> bool {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let probe = dir.join(".nesmulator-write-test");
+    match OpenOptions::new().write(true).create_new(true).open(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            true
+        }
+        // A probe left by a crash still means the folder is writable
+        Err(e) => e.kind() == std::io::ErrorKind::AlreadyExists,
+    }
+}
+
+// Whether a file dropped on the window or found by the picker is a NES ROM
+pub fn is_rom(path: &Path) -> bool {
+    matches!(path.extension(), Some(extension) if extension.eq_ignore_ascii_case("nes"))
+}
+
+// Input device declared by the header of a NES 2.0 ROM in place of the standard
+// controllers, e.g. a Zapper or a Four Score, read from the default expansion device byte
+pub fn expansion_device(path: &str) -> Option<&'static str> {
+    let mut header = [0; 16];
+    File::open(path).ok()?.read_exact(&mut header).ok()?;
+    if &header[0..4] != b"NES\x1a" || header[7] & 0x0C != 0x08 {
+        return None;
+    }
+    match header[15] & 0x3F {
+        0x02 => Some("NES Four Score"),
+        0x03 => Some("Famicom Four Players Adapter"),
+        0x07 => Some("Vs. Zapper"),
+        0x08 => Some("Zapper"),
+        0x09 => Some("two Zappers"),
+        0x0A => Some("Hyper Shot light gun"),
+        0x0B => Some("Power Pad side A"),
+        0x0C => Some("Power Pad side B"),
+        0x0D => Some("Family Trainer side A"),
+        0x0E => Some("Family Trainer side B"),
+        0x0F => Some("Arkanoid Vaus controller"),
+        0x10 => Some("Famicom Arkanoid Vaus controller"),
+        0x11 => Some("two Arkanoid Vaus controllers and a Data Recorder"),
+        0x12 => Some("Konami Hyper Shot controller"),
+        0x13 => Some("Pachinko controller"),
+        0x14 => Some("Exciting Boxing punching bag"),
+        0x15 => Some("Jissen Mahjong controller"),
+        0x16 => Some("Party Tap"),
+        0x17 => Some("Oeka Kids tablet"),
+        0x18 => Some("Barcode Battler"),
+        0x19 => Some("Miracle Piano keyboard"),
+        0x1A => Some("Pokkun Moguraa mat"),
+        0x1B => Some("Top Rider bicycle"),
+        0x1D => Some("Famicom 3D System"),
+        0x1E => Some("Doremikko keyboard"),
+        0x1F => Some("R.O.B. Gyro Set"),
+        0x23 => Some("Family BASIC keyboard and Data Recorder"),
+        // Unspecified, the standard controllers, the Vs. System controllers or a device the
+        // GUI does not name
+        _ => None,
+    }
+}
+
+// Gamepad buttons of the ROM picker, the emulation thread polling the gamepads
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PickerInput {
+    Previous,
+    Next,
+    Play,
+}
+
+// Choose one of the ROMs of a folder, shown as a prompt when the emulator starts without one
+pub struct RomPicker {
+    roms: Vec<PathBuf>,
+    selected: usize,
+}
+
+impl RomPicker {
+    pub fn new(dir: &Path) -> Result<Self, String> {
+        let mut roms: Vec<PathBuf> = fs::read_dir(dir)
+            .map_err(|e| e.to_string())?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| is_rom(path))
+            .collect();
+        roms.sort();
+        Ok(RomPicker { roms, selected: 0 })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    pub fn previous(&mut self) {
+        if !self.roms.is_empty() {
+            self.selected = (self.selected + self.roms.len() - 1) % self.roms.len();
+        }
+    }
+
+    pub fn next(&mut self) {
+        if !self.roms.is_empty() {
+            self.selected = (self.selected + 1) % self.roms.len();
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Path> {
+        self.roms.get(self.selected).map(PathBuf::as_path)
+    }
+
+    pub fn prompt(&self) -> String {
+        match self.selected() {
+            Some(rom) => format!(
+                "< {} > {}/{}, Enter or A to play",
+                rom.file_name().unwrap_or_default().to_string_lossy(),
+                self.selected + 1,
+                self.roms.len()
+            ),
+            None => String::from("No ROM in this folder"),
+        }
+    }
+}
+
+// ROMs given on the command line or in a playlist file, played one after the other with
+// the next and previous game hotkeys, e.g. to judge the games of a jam or for a marathon
+pub struct Playlist {
+    roms: Vec<String>,
+    current: usize,
+    // Whether each ROM was left during this run, so that it is resumed where it was
+    played: Vec<bool>,
+}
+
+impl Playlist {
+    pub fn new(roms: Vec<String>) -> Self {
+        Playlist {
+            played: vec![false; roms.len()],
+            roms,
+            current: 0,
+        }
+    }
+
+    // One ROM per line, relative to the folder of the playlist, the empty lines and the
+    // ones starting with # being skipped
+    pub fn load(path: &str) -> Result<Vec<String>, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        let roms: Vec<String> = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| dir.join(line).to_string_lossy().into_owned())
+            .collect();
+        if roms.is_empty() {
+            return Err(String::from("no ROM in the playlist"));
+        }
+        Ok(roms)
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.roms.get(self.current).map(String::as_str)
+    }
+
+    // Leave the current ROM for the one offset positions away, returning it and whether
+    // it was played before
+    pub fn switch(&mut self, offset: isize) -> Option<(&str, bool)> {
+        if self.roms.len() < 2 {
+            return None;
+        }
+        self.played[self.current] = true;
+        let len = self.roms.len() as isize;
+        self.current = (self.current as isize + offset).rem_euclid(len) as usize;
+        Some((&self.roms[self.current], self.played[self.current]))
+    }
+
+    // e.g. "Game 2/5: Jam Entry"
+    pub fn title(&self) -> String {
+        let name = self
+            .current()
+            .and_then(|rom| Path::new(rom).file_stem())
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        format!("Game {}/{}: {}", self.current + 1, self.roms.len(), name)
+    }
+}
+
+// Tell how to load a ROM, drawn while none is loaded
+pub fn draw_notice(frame: &mut [ARGBColor], theme: &Theme) {
+    let lines = [
+        ("NO ROM LOADED", theme.text),
+        ("Drop a .nes file on the window", theme.dim_text),
+    ];
+    let scale = theme.font_scale;
+    let line_height = GLYPH_HEIGHT * scale + 5;
+    let height = frame.len() / FRAME_WIDTH;
+    let top = height.saturating_sub(lines.len() * line_height) / 2;
+    for (i, (text, color)) in lines.iter().enumerate() {
+        let x = FRAME_WIDTH.saturating_sub(font::text_width_scaled(text, scale)) / 2;
+        font::draw_text_scaled(
+            frame,
+            FRAME_WIDTH,
+            x,
+            top + i * line_height,
+            text,
+            *color,
+            scale,
+        );
+    }
+}
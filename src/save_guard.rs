@@ -0,0 +1,133 @@
+use std::fs;
+
+use log::info;
+
+use crate::backend::EmulatorCore;
+use crate::snapshot::ScratchFile;
+use crate::Message;
+
+// Frames the battery RAM must stay unchanged for a save to be written
+const SETTLE_FRAMES: u32 = 10;
+// Frames between two reads of the battery RAM, which goes through a file
+const CHECK_FRAMES: u32 = 5;
+// After that many checks of held saves, about 5 seconds, they are written anyway
+const MAX_CHECKS: u32 = 60;
+
+// Games writing their save over several frames leave the battery RAM inconsistent in
+// between, so a save or a state requested while the battery RAM changed within the last
+// SETTLE_FRAMES frames waits for it to stay unchanged. The core only gives the battery RAM
+// through a file, which is read every CHECK_FRAMES frames to know when it last changed.
+pub struct SaveGuard {
+    scratch: ScratchFile,
+    // Battery RAM at the last check, None before the first one
+    battery: Option<Vec<u8>>,
+    // Whether the game has no battery RAM, which is then no longer read
+    no_battery: bool,
+    frames_since_check: u32,
+    // Frames the battery RAM has stayed unchanged at the last check
+    unchanged_frames: u32,
+    // Saves waiting for the battery RAM to settle, in the order they were requested
+    held: Vec<Message>,
+    checks: u32,
+}
+
+impl SaveGuard {
+    pub fn new() -> Self {
+        SaveGuard {
+            scratch: ScratchFile::with_extension("battery", "sav"),
+            battery: None,
+            no_battery: false,
+            frames_since_check: 0,
+            unchanged_frames: 0,
+            held: vec![],
+            checks: 0,
+        }
+    }
+
+    // Forget the battery RAM of the previous game, whose held saves were written first
+    pub fn clear(&mut self) {
+        self.battery = None;
+        self.no_battery = false;
+        self.frames_since_check = 0;
+        self.unchanged_frames = 0;
+        self.held.clear();
+        self.checks = 0;
+    }
+
+    // Hold a save until the battery RAM settles. It is given back to be written right away
+    // when the battery RAM did not change recently, or when the game has none.
+    pub fn hold(&mut self, nes: &mut impl EmulatorCore, message: Message) -> Option<Message> {
+        if self.no_battery {
+            return Some(message);
+        }
+        self.check(nes);
+        if self.held.is_empty() && (self.no_battery || self.unchanged_frames >= SETTLE_FRAMES) {
+            return Some(message);
+        }
+        if self.held.is_empty() {
+            info!("The game is writing its save, waiting for it to finish.");
+            self.checks = 0;
+        }
+        self.held.push(message);
+        None
+    }
+
+    // Give back the held saves without waiting, e.g. before loading another state
+    pub fn take(&mut self) -> Vec<Message> {
+        std::mem::take(&mut self.held)
+    }
+
+    // Called at the end of each frame. Gives back the held saves once the battery RAM
+    // did not change for SETTLE_FRAMES frames, with whether it settled or the guard gave up.
+    pub fn frame(&mut self, nes: &mut impl EmulatorCore) -> Option<(Vec<Message>, bool)> {
+        if self.no_battery {
+            return None;
+        }
+        self.frames_since_check += 1;
+        if self.frames_since_check < CHECK_FRAMES {
+            return None;
+        }
+        self.check(nes);
+        if self.held.is_empty() {
+            return None;
+        }
+        if self.unchanged_frames >= SETTLE_FRAMES {
+            return Some((self.take(), true));
+        }
+        self.checks += 1;
+        if self.checks >= MAX_CHECKS {
+            return Some((self.take(), false));
+        }
+        None
+    }
+
+    // Read the battery RAM and count for how long it did not change
+    fn check(&mut self, nes: &mut impl EmulatorCore) {
+        let battery = match battery(&self.scratch, nes) {
+            Some(battery) => battery,
+            None => {
+                self.no_battery = true;
+                return;
+            }
+        };
+        match &self.battery {
+            Some(previous) if *previous != battery => self.unchanged_frames = 0,
+            _ => {
+                self.unchanged_frames = self
+                    .unchanged_frames
+                    .saturating_add(self.frames_since_check)
+            }
+        }
+        self.battery = Some(battery);
+        self.frames_since_check = 0;
+    }
+}
+
+// Content of the battery RAM, None if the game has none
+fn battery(scratch: &ScratchFile, nes: &mut impl EmulatorCore) -> Option<Vec<u8>> {
+    let path = scratch.path().to_string_lossy();
+    nes.save(&path).ok()?;
+    fs::read(scratch.path())
+        .ok()
+        .filter(|battery| !battery.is_empty())
+}
@@ -0,0 +1,29 @@
+// Moment of the emulation, counted from its start
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmulationTime {
+    // Right after the given frame was produced, the first frame being 1
+    Frame(u64),
+    // Right after the given number of NES clocks
+    Cycle(u64),
+}
+
+// Parse times written as "frame:N,cycle:N"
+pub fn parse_times(spec: &str) -> Result<Vec<EmulationTime>, String> {
+    let mut times = vec![];
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (unit, value) = entry.split_once(':').ok_or(format!(
+            "Invalid time {:?}, expected frame:N or cycle:N",
+            entry
+        ))?;
+        let value = value
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid number in {:?}", entry))?;
+        match unit.trim() {
+            "frame" => times.push(EmulationTime::Frame(value)),
+            "cycle" => times.push(EmulationTime::Cycle(value)),
+            unit => return Err(format!("Unknown time unit {:?}", unit)),
+        }
+    }
+    Ok(times)
+}
@@ -0,0 +1,81 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+
+use log::warn;
+use nesmulator_core::utils::ARGBColor;
+
+use crate::capture::{FRAME_HEIGHT, FRAME_WIDTH};
+
+const MAGIC: &[u8; 4] = b"NESF";
+const VERSION: u32 = 1;
+const SEQUENCE_OFFSET: u64 = 16;
+const HEADER_SIZE: usize = 32;
+
+// Latest frame written to a file that external tools can map in memory,
+// e.g. in /dev/shm on Linux. All numbers are little endian:
+//   0  magic "NESF"
+//   4  u32 version
+//   8  u32 width
+//   12 u32 height
+//   16 u64 sequence, odd while a frame is being written
+//   24 u64 frame number
+//   32 width * height RGBA pixels
+// Readers copy the frame, then check that the sequence is even and did not change.
+pub struct SharedFrame {
+    file: File,
+    sequence: u64,
+    data: Vec<u8>,
+}
+
+impl SharedFrame {
+    pub fn new(path: &str) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let pixels = (FRAME_WIDTH * FRAME_HEIGHT) as usize * 4;
+        file.set_len((HEADER_SIZE + pixels) as u64)?;
+
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&FRAME_WIDTH.to_le_bytes());
+        header.extend_from_slice(&FRAME_HEIGHT.to_le_bytes());
+        header.extend_from_slice(&0_u64.to_le_bytes());
+        header.extend_from_slice(&0_u64.to_le_bytes());
+        file.write_all(&header)?;
+
+        Ok(SharedFrame {
+            file,
+            sequence: 0,
+            data: Vec::with_capacity(8 + pixels),
+        })
+    }
+
+    pub fn write(&mut self, frame: &[ARGBColor], frame_number: u64) {
+        if let Err(e) = self.try_write(frame, frame_number) {
+            warn!("Failed to write the shared frame: {}", e);
+        }
+    }
+
+    fn try_write(&mut self, frame: &[ARGBColor], frame_number: u64) -> io::Result<()> {
+        self.data.clear();
+        self.data.extend_from_slice(&frame_number.to_le_bytes());
+        for color in frame.iter() {
+            self.data
+                .extend_from_slice(&[color.red, color.green, color.blue, color.alpha]);
+        }
+
+        self.write_sequence()?;
+        self.file.write_all(&self.data)?;
+        self.write_sequence()
+    }
+
+    fn write_sequence(&mut self) -> io::Result<()> {
+        self.sequence += 1;
+        self.file.seek(SeekFrom::Start(SEQUENCE_OFFSET))?;
+        self.file.write_all(&self.sequence.to_le_bytes())
+    }
+}
@@ -0,0 +1,99 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use log::warn;
+
+use crate::backend::EmulatorCore;
+use crate::hex::to_hex;
+
+// Attempts at creating a folder with a random name that does not exist yet
+const DIR_ATTEMPTS: usize = 16;
+
+// The core only saves its state to files, so the states kept in memory go through a
+// scratch file in the temporary folder, removed when dropped. The file is in a folder of
+// its own with a random name, only open to the user, so that another user of the computer
+// cannot replace it by a link to one of the files of the user, which the core would write.
+pub struct ScratchFile {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl ScratchFile {
+    pub fn new(name: &str) -> Self {
+        ScratchFile::with_extension(name, "data")
+    }
+
+    pub fn with_extension(name: &str, extension: &str) -> Self {
+        let dir = private_dir(name);
+        ScratchFile {
+            path: dir.join(format!("{}.{}", name, extension)),
+            dir,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Current state of the NES
+    pub fn save(&self, nes: &mut impl EmulatorCore) -> Result<Vec<u8>, String> {
+        nes.save_state(&self.path.to_string_lossy())?;
+        fs::read(&self.path).map_err(|e| e.to_string())
+    }
+
+    pub fn load(
+        &self,
+        nes: &mut impl EmulatorCore,
+        snapshot: &[u8],
+        rom_path: &str,
+    ) -> Result<(), String> {
+        fs::write(&self.path, snapshot).map_err(|e| e.to_string())?;
+        nes.load_state(&self.path.to_string_lossy(), rom_path)
+    }
+}
+
+impl Drop for ScratchFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+        let _ = fs::remove_dir(&self.dir);
+    }
+}
+
+// A new folder, whose files cannot be written when it could not be created
+fn private_dir(name: &str) -> PathBuf {
+    let mut dir = env::temp_dir();
+    for _ in 0..DIR_ATTEMPTS {
+        let mut random = [0; 8];
+        let suffix = match getrandom::getrandom(&mut random) {
+            Ok(()) => to_hex(&random),
+            // Still created by this process only, another process taking it first
+            Err(_) => process::id().to_string(),
+        };
+        dir = env::temp_dir().join(format!("nesmulator-{}-{}", name, suffix));
+        match create_private_dir(&dir) {
+            Ok(()) => return dir,
+            // Taken by another process, another name is drawn
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                warn!("Cannot create the scratch folder of the {}: {}", name, e);
+                return dir;
+            }
+        }
+    }
+    warn!("Cannot create the scratch folder of the {}", name);
+    dir
+}
+
+// The temporary folder of Windows is already only open to the user
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
@@ -0,0 +1,26 @@
+// Speeds chosen with the speed keys and in the pause menu, from slow motion to
+// fast-forward. Below a tenth of the normal speed, a frame takes most of a second and the
+// pacing cannot tell a slow frame from a stuck one.
+const SPEED_STEPS: [f64; 11] = [0.1, 0.25, 0.5, 0.75, 1.0, 1.5, 2.0, 3.0, 4.0, 8.0, 16.0];
+pub const MIN_SPEED: f64 = SPEED_STEPS[0];
+pub const MAX_SPEED: f64 = SPEED_STEPS[SPEED_STEPS.len() - 1];
+// Above it, the sound is muted as the resampling only leaves noise of it
+pub const MUTED_SPEED: f64 = 4.0;
+
+// Next speed step faster or slower than the speed, staying at the bounds
+pub fn next_speed(speed: f64, faster: bool) -> f64 {
+    let next = if faster {
+        SPEED_STEPS.iter().find(|step| **step > speed)
+    } else {
+        SPEED_STEPS.iter().rev().find(|step| **step < speed)
+    };
+    next.copied().unwrap_or_else(|| clamp_speed(speed))
+}
+
+pub fn clamp_speed(speed: f64) -> f64 {
+    if speed.is_nan() {
+        1.0
+    } else {
+        speed.clamp(MIN_SPEED, MAX_SPEED)
+    }
+}
@@ -0,0 +1,53 @@
+use nesmulator_core::utils::ARGBColor;
+
+use crate::font::{self, GLYPH_HEIGHT};
+use crate::osd::{argb, blend};
+
+const FRAME_WIDTH: usize = 256;
+const FRAME_HEIGHT: usize = 240;
+const MARGIN: usize = 2;
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 2 * MARGIN;
+// Lines hidden by the overscan crop, kept clear so that the indicator stays visible
+const OVERSCAN_LINES: usize = 8;
+
+// Text telling that the game does not run at normal speed, e.g. ">> x2" while
+// fast-forwarding, "> x0.5" in slow motion or "<<" while rewinding
+pub fn text(speed: f64, rewinding: bool) -> Option<String> {
+    // The analog fast-forward of the gamepads gives any speed
+    let rounded = (speed * 100.0).round() / 100.0;
+    if rewinding {
+        Some(String::from("<<"))
+    } else if speed.is_infinite() {
+        Some(String::from(">> MAX"))
+    } else if speed > 1.0 {
+        Some(format!(">> x{}", rounded))
+    } else if speed < 1.0 {
+        Some(format!("> x{}", rounded))
+    } else {
+        None
+    }
+}
+
+// Draw the indicator in the bottom right corner of the frame, which the on-screen
+// messages and the timer leave free
+pub fn draw(frame: &mut [ARGBColor], text: &str) {
+    let background = argb(0xA0, 0x00, 0x00, 0x00);
+    let yellow = argb(0xFF, 0xFF, 0xD0, 0x40);
+    let box_width = font::text_width(text) + 2 * MARGIN;
+    let left = FRAME_WIDTH - MARGIN - box_width;
+    let top = FRAME_HEIGHT - OVERSCAN_LINES - MARGIN - LINE_HEIGHT;
+    for y in top..top + LINE_HEIGHT {
+        for x in left..left + box_width {
+            let pixel = &mut frame[y * FRAME_WIDTH + x];
+            *pixel = blend(*pixel, background);
+        }
+    }
+    font::draw_text(
+        frame,
+        FRAME_WIDTH,
+        left + MARGIN,
+        top + MARGIN,
+        text,
+        yellow,
+    );
+}
@@ -0,0 +1,73 @@
+use std::fs;
+use std::io;
+use std::ops::Range;
+
+// Differences closer than this are reported as a single region
+const MAX_GAP: usize = 8;
+// Bytes of a region are only printed when it is small enough
+const MAX_PRINTED_BYTES: usize = 16;
+
+// Byte ranges that differ between two state files. The part of the longest file
+// that is missing from the other one is reported as a difference.
+pub fn diff_states(path_a: &str, path_b: &str) -> io::Result<String> {
+    let a = fs::read(path_a)?;
+    let b = fs::read(path_b)?;
+    let regions = differing_regions(&a, &b);
+
+    let mut report = format!(
+        "{}: {} bytes\n{}: {} bytes\n",
+        path_a,
+        a.len(),
+        path_b,
+        b.len()
+    );
+    if regions.is_empty() {
+        report.push_str("The states are identical.\n");
+        return Ok(report);
+    }
+    let differing_bytes: usize = regions.iter().map(|r| r.len()).sum();
+    report.push_str(&format!(
+        "{} differing regions ({} bytes):\n",
+        regions.len(),
+        differing_bytes
+    ));
+    for region in regions {
+        report.push_str(&format!(
+            "  0x{:06X}..0x{:06X} ({} bytes)\n",
+            region.start,
+            region.end,
+            region.len()
+        ));
+        if region.len() <= MAX_PRINTED_BYTES {
+            report.push_str(&format!("    a: {}\n", hex(&a, &region)));
+            report.push_str(&format!("    b: {}\n", hex(&b, &region)));
+        }
+    }
+    Ok(report)
+}
+
+fn differing_regions(a: &[u8], b: &[u8]) -> Vec<Range<usize>> {
+    let mut regions: Vec<Range<usize>> = vec![];
+    for i in 0..a.len().max(b.len()) {
+        if a.get(i) == b.get(i) {
+            continue;
+        }
+        match regions.last_mut() {
+            Some(region) if i - region.end <= MAX_GAP => region.end = i + 1,
+            _ => regions.push(i..i + 1),
+        }
+    }
+    regions
+}
+
+// Bytes of a region, "--" marking the bytes past the end of the file
+fn hex(bytes: &[u8], region: &Range<usize>) -> String {
+    region
+        .clone()
+        .map(|i| match bytes.get(i) {
+            Some(byte) => format!("{:02X}", byte),
+            None => String::from("--"),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
@@ -0,0 +1,242 @@
+use std::cmp::Reverse;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use nesmulator_core::utils::ARGBColor;
+
+use crate::capture::{self, FRAME_HEIGHT, FRAME_WIDTH};
+use crate::rom::RomPaths;
+
+// A state file of a ROM, with what is known about it
+struct StateInfo {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    frame: Option<u64>,
+    thumbnail: Option<PathBuf>,
+}
+
+// Files written next to a state when it is saved: <STATE>.png and <STATE>.toml
+fn thumbnail_path(state_path: &Path) -> PathBuf {
+    let mut path = state_path.as_os_str().to_owned();
+    path.push(".png");
+    PathBuf::from(path)
+}
+
+fn metadata_path(state_path: &Path) -> PathBuf {
+    let mut path = state_path.as_os_str().to_owned();
+    path.push(".toml");
+    PathBuf::from(path)
+}
+
+// Write the frame and the frame number of a saved state, for the states subcommand
+pub fn write_metadata(
+    state_path: &str,
+    frame: &[ARGBColor],
+    frame_number: u64,
+) -> Result<(), String> {
+    let state_path = Path::new(state_path);
+    capture::write_screenshot(
+        &thumbnail_path(state_path),
+        frame,
+        FRAME_WIDTH,
+        FRAME_HEIGHT,
+    )
+    .map_err(|e| e.to_string())?;
+    fs::write(
+        metadata_path(state_path),
+        format!("frame = {}\n", frame_number),
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn read_state(path: PathBuf) -> StateInfo {
+    let frame = fs::read_to_string(metadata_path(&path))
+        .ok()
+        .and_then(|text| text.parse::<toml::Value>().ok())
+        .and_then(|value| value.get("frame").and_then(|f| f.as_integer()))
+        .map(|frame| frame as u64);
+    let thumbnail = Some(thumbnail_path(&path)).filter(|p| p.exists());
+    StateInfo {
+        modified: fs::metadata(&path).and_then(|m| m.modified()).ok(),
+        path,
+        frame,
+        thumbnail,
+    }
+}
+
+// States of a bug report capture, with the frame number of its context.json
+fn read_repro_state(dir: &Path) -> StateInfo {
+    let frame = fs::read_to_string(dir.join("context.json"))
+        .ok()
+        .and_then(|json| {
+            let value = json.split("\"frame\":").nth(1)?;
+            value.split(',').next()?.trim().parse::<u64>().ok()
+        });
+    let path = dir.join("state.data");
+    StateInfo {
+        modified: fs::metadata(&path).and_then(|m| m.modified()).ok(),
+        path,
+        frame,
+        thumbnail: Some(dir.join("screenshot.png")).filter(|p| p.exists()),
+    }
+}
+
+// The state of the ROM, its slots and the states of its bug reports, newest first, from the
+// folder the emulator writes them to
+fn find_states(rom_path: &str, save_dir: Option<&str>) -> Result<Vec<StateInfo>, String> {
+    let rom_paths = RomPaths::new(rom_path, 0, save_dir);
+    let state_path = Path::new(&rom_paths.state);
+    let stem = state_path
+        .file_stem()
+        .ok_or("Invalid ROM path")?
+        .to_string_lossy()
+        .into_owned();
+    let save_dir = state_path
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+
+    let mut states = vec![];
+    for entry in fs::read_dir(save_dir).map_err(|e| e.to_string())?.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_slot = matches!(
+            name.strip_prefix(&format!("{}-slot", stem))
+                .and_then(|rest| rest.strip_suffix(".data")),
+            Some(slot) if slot.parse::<u32>().is_ok()
+        );
+        let is_quick_or_session =
+            name == format!("{}.data", stem) || name == format!("{}-session.data", stem);
+        if is_quick_or_session || is_slot {
+            states.push(read_state(entry.path()));
+        }
+    }
+    if let Ok(captures) = fs::read_dir(&rom_paths.repro) {
+        for entry in captures.flatten() {
+            if entry.path().join("state.data").exists() {
+                states.push(read_repro_state(&entry.path()));
+            }
+        }
+    }
+    states.sort_by_key(|state| Reverse(state.modified));
+    Ok(states)
+}
+
+// Time of a file as "YYYY-MM-DD HH:MM:SS" in UTC
+fn format_time(time: Option<SystemTime>) -> String {
+    let seconds = match time.and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+        Some(duration) => duration.as_secs(),
+        None => return String::from("-"),
+    };
+    // Civil date from the number of days since 1970-01-01, by Howard Hinnant
+    let days = (seconds / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3_600 % 24,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// Table of the states of a ROM, for the terminal
+pub fn list_states(rom_path: &str, save_dir: Option<&str>) -> Result<String, String> {
+    let states = find_states(rom_path, save_dir)?;
+    if states.is_empty() {
+        return Ok(String::from("No state found for this ROM.\n"));
+    }
+    let rows: Vec<[String; 4]> = states
+        .iter()
+        .map(|state| {
+            [
+                state.path.display().to_string(),
+                format_time(state.modified),
+                state.frame.map_or(String::from("-"), |f| f.to_string()),
+                state
+                    .thumbnail
+                    .as_ref()
+                    .map_or(String::from("-"), |t| t.display().to_string()),
+            ]
+        })
+        .collect();
+    let header = ["State", "Saved (UTC)", "Frame", "Thumbnail"];
+    let mut widths = header.map(str::len);
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let mut table = String::new();
+    let mut add_row = |cells: [&str; 4]| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        table += line.join("  ").trim_end();
+        table.push('\n');
+    };
+    add_row(header);
+    for row in rows.iter() {
+        add_row([&row[0], &row[1], &row[2], &row[3]]);
+    }
+    Ok(table)
+}
+
+// Write a page with the thumbnails of the states in the temporary folder, returns its path
+pub fn export_states_page(rom_path: &str, save_dir: Option<&str>) -> Result<PathBuf, String> {
+    let states = find_states(rom_path, save_dir)?;
+    let name = Path::new(rom_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>States of {0}</title></head>\n<body>\n<h1>States of {0}</h1>\n",
+        escape_html(&name)
+    );
+    for state in states.iter() {
+        html += "<figure>\n";
+        if let Some(thumbnail) = &state.thumbnail {
+            let thumbnail = fs::canonicalize(thumbnail).unwrap_or_else(|_| thumbnail.clone());
+            html += &format!(
+                "<img src=\"file://{}\" width=\"256\" height=\"240\">\n",
+                escape_html(&thumbnail.to_string_lossy())
+            );
+        }
+        html += &format!(
+            "<figcaption>{}<br>{} UTC, frame {}</figcaption>\n</figure>\n",
+            escape_html(&state.path.display().to_string()),
+            format_time(state.modified),
+            state
+                .frame
+                .map_or(String::from("unknown"), |f| f.to_string())
+        );
+    }
+    html += "</body>\n</html>\n";
+    let path = env::temp_dir().join(format!("nesmulator-states-{}.html", name));
+    fs::write(&path, html).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
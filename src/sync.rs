@@ -0,0 +1,43 @@
+// How the emulation and the sound are kept together, each strategy trading something off
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SyncStrategy {
+    // The emulation waits for the audio device, so the sound never crackles but the frames
+    // follow the clock of the audio device instead of the monitor
+    AudioMaster,
+    // The emulation is paced by the frame timer and the sound is slightly resampled to
+    // follow it, without an audible change of pitch
+    VideoMaster,
+    // The emulation is paced by the frame timer and the sound is played as produced,
+    // dropping samples when the buffer is full, for the lowest CPU usage
+    Off,
+}
+
+impl SyncStrategy {
+    const ALL: [SyncStrategy; 3] = [
+        SyncStrategy::AudioMaster,
+        SyncStrategy::VideoMaster,
+        SyncStrategy::Off,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SyncStrategy::AudioMaster => "audio-master",
+            SyncStrategy::VideoMaster => "video-master",
+            SyncStrategy::Off => "off",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        SyncStrategy::ALL.iter().copied().find(|s| s.name() == name)
+    }
+
+    // Whether the emulation waits for the audio device instead of the frame timer
+    pub fn is_audio_paced(&self) -> bool {
+        *self == SyncStrategy::AudioMaster
+    }
+
+    // Whether the sound is resampled to follow the speed of the emulation
+    pub fn resamples_audio(&self) -> bool {
+        *self == SyncStrategy::VideoMaster
+    }
+}
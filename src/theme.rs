@@ -0,0 +1,158 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use nesmulator_core::utils::ARGBColor;
+
+use crate::osd::argb;
+use crate::reference;
+
+// Time between two checks of the theme file
+const RELOAD_CHECK_TIME: Duration = Duration::from_secs(1);
+const MAX_FONT_SCALE: usize = 3;
+
+const DARK_THEME: &str = include_str!("../themes/dark.toml");
+const LIGHT_THEME: &str = include_str!("../themes/light.toml");
+
+// Colors, text size and background of the pause menu and of the on-screen display
+pub struct Theme {
+    pub text: ARGBColor,
+    pub dim_text: ARGBColor,
+    pub menu_shade: ARGBColor,
+    pub info: ARGBColor,
+    pub warning: ARGBColor,
+    pub error: ARGBColor,
+    pub font_scale: usize,
+    // 256x240 image replacing the game behind the pause menu
+    pub background: Option<Vec<ARGBColor>>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::parse(DARK_THEME, None).expect("Invalid dark theme")
+    }
+}
+
+impl Theme {
+    // Themes bundled with the emulator
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Theme::default()),
+            "light" => Some(Theme::parse(LIGHT_THEME, None).expect("Invalid light theme")),
+            _ => None,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Theme::parse(&text, path.parent())
+    }
+
+    // Entries missing from the file keep the color of the dark theme
+    fn parse(text: &str, dir: Option<&Path>) -> Result<Self, String> {
+        let value = text.parse::<toml::Value>().map_err(|e| e.to_string())?;
+        let table = value.as_table().ok_or("Invalid theme file")?;
+        let color = |name: &str, default: ARGBColor| match table.get(name) {
+            Some(value) => value
+                .as_str()
+                .and_then(parse_color)
+                .ok_or(format!("Invalid color for {}: {}", name, value)),
+            None => Ok(default),
+        };
+        let font_scale = match table.get("font-scale") {
+            Some(value) => match value.as_integer() {
+                Some(scale) if (1..=MAX_FONT_SCALE as i64).contains(&scale) => scale as usize,
+                _ => return Err(format!("Invalid font scale: {}", value)),
+            },
+            None => 1,
+        };
+        let background = match table.get("background-image") {
+            Some(value) => {
+                let file = value.as_str().ok_or("Invalid background image")?;
+                let path = dir.unwrap_or_else(|| Path::new("")).join(file);
+                let image = reference::read_frame_png(&path)
+                    .map_err(|e| format!("Cannot load {}: {}", path.display(), e))?;
+                Some(image)
+            }
+            None => None,
+        };
+
+        Ok(Theme {
+            text: color("text", argb(0xFF, 0xFF, 0xFF, 0xFF))?,
+            dim_text: color("dim-text", argb(0xFF, 0xA0, 0xA0, 0xA0))?,
+            menu_shade: color("menu-shade", argb(0xB0, 0x00, 0x00, 0x00))?,
+            info: color("info", argb(0xC0, 0x20, 0x20, 0x20))?,
+            warning: color("warning", argb(0xC0, 0x80, 0x60, 0x00))?,
+            error: color("error", argb(0xC0, 0x90, 0x10, 0x10))?,
+            font_scale,
+            background,
+        })
+    }
+}
+
+// Parse a color written "#RRGGBB" or "#AARRGGBB"
+fn parse_color(text: &str) -> Option<ARGBColor> {
+    let hex = text.strip_prefix('#')?;
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    let byte = |shift: u32| (value >> shift) as u8;
+    match hex.len() {
+        6 => Some(argb(0xFF, byte(16), byte(8), byte(0))),
+        8 => Some(argb(byte(24), byte(16), byte(8), byte(0))),
+        _ => None,
+    }
+}
+
+// Theme used by both the GUI and the emulation thread, replaced when its file is reloaded
+#[derive(Clone, Default)]
+pub struct SharedTheme {
+    current: Arc<RwLock<Arc<Theme>>>,
+}
+
+impl SharedTheme {
+    pub fn get(&self) -> Arc<Theme> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn set(&self, theme: Theme) {
+        *self.current.write().unwrap() = Arc::new(theme);
+    }
+}
+
+// Reload a theme file when it is modified, to edit a theme while playing
+pub struct ThemeWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    checked_at: Instant,
+    theme: SharedTheme,
+}
+
+impl ThemeWatcher {
+    pub fn new(path: &str, theme: SharedTheme) -> Self {
+        let path = PathBuf::from(path);
+        ThemeWatcher {
+            modified: modified_time(&path),
+            path,
+            checked_at: Instant::now(),
+            theme,
+        }
+    }
+
+    // Returns the result of the reload if the file changed since the last check
+    pub fn reload_if_changed(&mut self) -> Option<Result<(), String>> {
+        if self.checked_at.elapsed() < RELOAD_CHECK_TIME {
+            return None;
+        }
+        self.checked_at = Instant::now();
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(Theme::load(&self.path).map(|theme| self.theme.set(theme)))
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
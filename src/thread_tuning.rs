@@ -0,0 +1,83 @@
+use log::{info, warn};
+
+// Niceness given to the emulation thread with --high-priority, raising it above the
+// other programs without starving the system
+#[cfg(all(feature = "thread-tuning", target_os = "linux"))]
+const HIGH_PRIORITY_NICE: i32 = -10;
+
+// Scheduling of the emulation thread, for the systems where other programs make it
+// stutter. Only available when built with the thread-tuning feature, and the priority
+// only on Linux.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadTuning {
+    pub high_priority: bool,
+    // Core the thread is pinned to
+    pub core: Option<usize>,
+}
+
+impl ThreadTuning {
+    // Apply to the calling thread, logging what was applied
+    pub fn apply(&self) {
+        if self.high_priority {
+            match raise_priority() {
+                Ok(nice) => info!("Emulation thread priority raised (nice {}).", nice),
+                Err(e) => warn!("Cannot raise the priority of the emulation thread: {}", e),
+            }
+        }
+        if let Some(core) = self.core {
+            match pin_to_core(core) {
+                Ok(()) => info!("Emulation thread pinned to core {}.", core),
+                Err(e) => warn!("Cannot pin the emulation thread to core {}: {}", core, e),
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "thread-tuning", target_os = "linux"))]
+fn raise_priority() -> Result<i32, String> {
+    // On Linux, the niceness of a single thread is set through its thread id
+    let result = unsafe {
+        libc::setpriority(
+            libc::PRIO_PROCESS,
+            libc::gettid() as libc::id_t,
+            HIGH_PRIORITY_NICE,
+        )
+    };
+    if result == 0 {
+        Ok(HIGH_PRIORITY_NICE)
+    } else {
+        Err(format!(
+            "{} (raising it needs root or the CAP_SYS_NICE capability)",
+            std::io::Error::last_os_error()
+        ))
+    }
+}
+
+#[cfg(all(feature = "thread-tuning", not(target_os = "linux")))]
+fn raise_priority() -> Result<i32, String> {
+    Err(String::from("only supported on Linux"))
+}
+
+#[cfg(feature = "thread-tuning")]
+fn pin_to_core(core: usize) -> Result<(), String> {
+    let core_id = core_affinity::get_core_ids()
+        .ok_or("the cores cannot be listed")?
+        .into_iter()
+        .find(|id| id.id == core)
+        .ok_or("no such core")?;
+    if core_affinity::set_for_current(core_id) {
+        Ok(())
+    } else {
+        Err(String::from("refused by the system"))
+    }
+}
+
+#[cfg(not(feature = "thread-tuning"))]
+fn raise_priority() -> Result<i32, String> {
+    Err(String::from("built without the thread-tuning feature"))
+}
+
+#[cfg(not(feature = "thread-tuning"))]
+fn pin_to_core(_core: usize) -> Result<(), String> {
+    Err(String::from("built without the thread-tuning feature"))
+}
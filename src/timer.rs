@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use nesmulator_core::utils::ARGBColor;
+
+use crate::bindings::BUTTONS;
+use crate::font::{self, GLYPH_HEIGHT};
+use crate::osd::{argb, blend};
+
+const FRAME_WIDTH: usize = 256;
+const MARGIN: usize = 2;
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 2 * MARGIN;
+
+// Parse "start=<chord>,stop=<chord>", chords being controller buttons joined with '+',
+// e.g. "start=select+a,stop=select+b"
+pub fn parse_triggers(spec: &str) -> Result<(u8, u8), String> {
+    let mut start = None;
+    let mut stop = None;
+    for item in spec.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+        let (name, chord) = item
+            .split_once('=')
+            .ok_or_else(|| format!("Expected <start|stop>=<buttons>, got {:?}", item))?;
+        let mut buttons = 0;
+        for button in chord.split('+').map(str::trim) {
+            buttons |= BUTTONS
+                .iter()
+                .find(|(_, b)| *b == button)
+                .map(|(bit, _)| *bit)
+                .ok_or_else(|| format!("Unknown button: {:?}", button))?;
+        }
+        match name.trim() {
+            "start" => start = Some(buttons),
+            "stop" => stop = Some(buttons),
+            n => return Err(format!("Unknown trigger: {:?}", n)),
+        }
+    }
+    match (start, stop) {
+        (Some(start), Some(stop)) => Ok((start, stop)),
+        _ => Err(String::from(
+            "Both the start and stop triggers must be given",
+        )),
+    }
+}
+
+// Format a time as m:ss.cc
+pub fn format_time(time: Duration) -> String {
+    let centiseconds = time.as_millis() / 10;
+    format!(
+        "{}:{:02}.{:02}",
+        centiseconds / 6000,
+        centiseconds / 100 % 60,
+        centiseconds % 100
+    )
+}
+
+// Timer started and stopped by button chords of the first controller, counting emulated frames
+// so that the time does not depend on the emulation speed
+pub struct TimeTrial {
+    start: u8,
+    stop: u8,
+    previous_input: u8,
+    frame_duration: Duration,
+    // Frame the running timer started at
+    started_at: Option<u64>,
+    last_time: Option<Duration>,
+    best_time: Option<Duration>,
+}
+
+impl TimeTrial {
+    pub fn new(
+        (start, stop): (u8, u8),
+        frame_duration: Duration,
+        best_time: Option<Duration>,
+    ) -> Self {
+        TimeTrial {
+            start,
+            stop,
+            previous_input: 0,
+            frame_duration,
+            started_at: None,
+            last_time: None,
+            best_time,
+        }
+    }
+
+    // Handle the input of the given frame, returns the time when the timer stops.
+    // Pressing the start chord while the timer runs restarts it.
+    pub fn frame(&mut self, frame: u64, input: u8) -> Option<Duration> {
+        let pressed = |chord: u8| input & chord == chord && self.previous_input & chord != chord;
+        let (start, stop) = (pressed(self.start), pressed(self.stop));
+        self.previous_input = input;
+
+        if start {
+            self.started_at = Some(frame);
+            None
+        } else if let (true, Some(started_at)) = (stop, self.started_at) {
+            self.started_at = None;
+            let time = self.frame_duration * (frame - started_at) as u32;
+            self.last_time = Some(time);
+            Some(time)
+        } else {
+            None
+        }
+    }
+
+    // Stop the timer and use the best time of another game
+    pub fn change_game(&mut self, best_time: Option<Duration>) {
+        self.previous_input = 0;
+        self.started_at = None;
+        self.last_time = None;
+        self.best_time = best_time;
+    }
+
+    // Returns true if the time is the new best time
+    pub fn is_best_time(&mut self, time: Duration) -> bool {
+        let best = match self.best_time {
+            Some(best) => time < best,
+            None => true,
+        };
+        if best {
+            self.best_time = Some(time);
+            return true;
+        }
+        false
+    }
+
+    // Draw the current and best times in the top right corner of the frame
+    pub fn draw(&self, frame: &mut [ARGBColor], frame_count: u64) {
+        let current = match self.started_at {
+            Some(started_at) => Some(self.frame_duration * (frame_count - started_at) as u32),
+            None => self.last_time,
+        };
+        let lines = [
+            current.map(format_time),
+            self.best_time
+                .map(|best| format!("Best {}", format_time(best))),
+        ];
+        let background = argb(0xA0, 0x00, 0x00, 0x00);
+        let white = argb(0xFF, 0xFF, 0xFF, 0xFF);
+        for (i, text) in lines.iter().flatten().enumerate() {
+            let box_width = font::text_width(text) + 2 * MARGIN;
+            let left = FRAME_WIDTH - MARGIN - box_width;
+            let top = MARGIN + i * (LINE_HEIGHT + MARGIN);
+            for y in top..top + LINE_HEIGHT {
+                for x in left..left + box_width {
+                    let pixel = &mut frame[y * FRAME_WIDTH + x];
+                    *pixel = blend(*pixel, background);
+                }
+            }
+            font::draw_text(frame, FRAME_WIDTH, left + MARGIN, top + MARGIN, text, white);
+        }
+    }
+}
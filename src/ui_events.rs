@@ -0,0 +1,144 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use log::warn;
+use winit::event::WindowEvent;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UiWindow {
+    Main,
+    Debug,
+}
+
+impl UiWindow {
+    fn name(&self) -> &'static str {
+        match self {
+            UiWindow::Main => "main",
+            UiWindow::Debug => "debug",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "main" => Some(UiWindow::Main),
+            "debug" => Some(UiWindow::Debug),
+            _ => None,
+        }
+    }
+}
+
+// Window manager events that can be applied again to the windows when replaying
+#[derive(Debug, PartialEq)]
+pub enum UiAction {
+    Resized(u32, u32),
+    Moved(i32, i32),
+    Focused(bool),
+}
+
+// Write the window events with the time they happened, one per line.
+// Replayable events are written as "<ms> <window> <action> <args>",
+// the others as "<ms> <window> event <debug output>" for reading only.
+pub struct UiEventRecorder {
+    file: File,
+    start: Instant,
+}
+
+impl UiEventRecorder {
+    pub fn new(path: &str) -> io::Result<Self> {
+        Ok(UiEventRecorder {
+            file: File::create(path)?,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, window: UiWindow, event: &WindowEvent) {
+        let description = match event {
+            WindowEvent::Resized(size) => format!("resized {} {}", size.width, size.height),
+            WindowEvent::Moved(position) => format!("moved {} {}", position.x, position.y),
+            WindowEvent::Focused(focused) => format!("focused {}", focused),
+            WindowEvent::ScaleFactorChanged {
+                scale_factor,
+                new_inner_size,
+            } => format!(
+                "scale-factor {} {} {}",
+                scale_factor, new_inner_size.width, new_inner_size.height
+            ),
+            // The mouse moves are too frequent to be of any use
+            WindowEvent::CursorMoved { .. } => return,
+            event => format!("event {:?}", event),
+        };
+        // Written right away as the application exits without unwinding
+        if let Err(e) = writeln!(
+            self.file,
+            "{} {} {}",
+            self.start.elapsed().as_millis(),
+            window.name(),
+            description
+        ) {
+            warn!("Failed to record UI event: {}", e);
+        }
+    }
+}
+
+// Replayable events of a recording, applied at the time they happened
+pub struct UiEventReplay {
+    events: VecDeque<(Duration, UiWindow, UiAction)>,
+    start: Instant,
+}
+
+impl UiEventReplay {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut events = VecDeque::new();
+        for (number, line) in content.lines().enumerate() {
+            let invalid = || format!("Invalid UI event at line {}: {:?}", number + 1, line);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 3 {
+                continue;
+            }
+            let time = Duration::from_millis(fields[0].parse().map_err(|_| invalid())?);
+            let window = UiWindow::from_name(fields[1]).ok_or_else(invalid)?;
+            let action = match (fields[2], &fields[3..]) {
+                ("resized", [width, height]) => UiAction::Resized(
+                    width.parse().map_err(|_| invalid())?,
+                    height.parse().map_err(|_| invalid())?,
+                ),
+                ("moved", [x, y]) => UiAction::Moved(
+                    x.parse().map_err(|_| invalid())?,
+                    y.parse().map_err(|_| invalid())?,
+                ),
+                ("focused", [focused]) => {
+                    UiAction::Focused(focused.parse().map_err(|_| invalid())?)
+                }
+                // Scale factor changes and input events cannot be reproduced
+                _ => continue,
+            };
+            events.push_back((time, window, action));
+        }
+        Ok(UiEventReplay {
+            events,
+            start: Instant::now(),
+        })
+    }
+
+    // Time of the next event to replay, None once the replay is over
+    pub fn next_time(&self) -> Option<Instant> {
+        self.events.front().map(|(time, _, _)| self.start + *time)
+    }
+
+    // Events whose time has come
+    pub fn due(&mut self) -> Vec<(UiWindow, UiAction)> {
+        let elapsed = self.start.elapsed();
+        let mut due = vec![];
+        while let Some((time, _, _)) = self.events.front() {
+            if *time > elapsed {
+                break;
+            }
+            let (_, window, action) = self.events.pop_front().unwrap();
+            due.push((window, action));
+        }
+        due
+    }
+}